@@ -1,15 +1,22 @@
 use std::fmt::Display;
 
+use schemars::{
+    gen::SchemaGenerator,
+    schema::{RootSchema, SchemaObject},
+    JsonSchema,
+};
 use serde::{Deserialize, Serialize};
 
 use crate::{
     endpoint::{EndpointId, Label, LabelledEndpointId, Labels},
     error, events,
-    serial::{SerialMessage, SerialMessageBytes},
+    metrics::ByteCounts,
+    serial::{Direction, FlowControlSchema, PortSettings, SerialMessage, SerialMessageBytes},
+    user::User,
 };
 
 /// Actions user can ask of the server.
-#[derive(Debug, Serialize, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone, JsonSchema)]
 pub enum Action {
     /// Start controlling the given endpoint.
     ///
@@ -23,19 +30,137 @@ pub enum Action {
     /// Start controlling any endpoint matching the given labels.
     ControlAny(Labels),
 
+    /// Like [`Action::ControlAny`], but if no endpoint matches the labels (yet),
+    /// wait for a matching one to appear instead of answering with
+    /// [`error::Error::NoMatchingEndpoints`].
+    ///
+    /// The user is sent [`Sync::ControlDeferred`] right away,
+    /// then control is requested once a matching endpoint appears.
+    /// If none appears within the timeout, the user is sent
+    /// [`error::Error::NoMatchingEndpoints`] after all.
+    ControlAnyWhenAvailable {
+        /// The labels an endpoint must have.
+        labels: Labels,
+
+        /// How long to wait for a matching endpoint.
+        timeout: std::time::Duration,
+    },
+
     /// Start observing the given endpoint.
     ///
     /// The user may only read output from the given endpoint.
     ///
     /// There may be many concurrent observers,
     /// but only a single controller.
-    Observe(EndpointId),
+    Observe {
+        /// The endpoint to observe.
+        endpoint: EndpointId,
+
+        /// Also receive the messages put on wire by whoever controls the endpoint,
+        /// not just the ones received from wire.
+        #[serde(default)]
+        include_writes: bool,
+
+        /// If the endpoint is already observed, confirm it again instead of
+        /// answering with [`error::Error::SuperfluousRequest`].
+        /// The existing observation is kept as is, so messages are still only received once.
+        #[serde(default)]
+        idempotent: bool,
+
+        /// Stop observing after this many messages were received.
+        /// The user is then sent [`Async::EndpointClosed`].
+        /// Does not apply to an observation which already exists, see `idempotent`.
+        #[serde(default)]
+        limit: Option<usize>,
+
+        /// If the endpoint does not exist (yet), wait for it to appear instead of
+        /// answering with [`error::Error::NoSuchEndpoint`].
+        /// The user is sent [`Sync::ObservationDeferred`] right away,
+        /// then [`Sync::Observing`] once the endpoint appears.
+        /// Mocks are not created by a deferred observe.
+        #[serde(default)]
+        deferred: bool,
+
+        /// Start by receiving the messages the endpoint recently saw,
+        /// instead of only the ones arriving from now on.
+        #[serde(default)]
+        replay: bool,
+
+        /// Start by receiving the recently seen messages newer than this,
+        /// like `replay` but leaving out what the user already saw, e.g. when reconnecting.
+        #[serde(default)]
+        since: Option<chrono::DateTime<chrono::Utc>>,
+
+        /// Collapse identical consecutive messages into a single [`Async::RepeatedMessage`],
+        /// instead of receiving each of them.
+        /// Useful for slowly changing status lines.
+        #[serde(default)]
+        dedup: bool,
+    },
+
+    /// Start observing the given endpoint without keeping it alive.
+    ///
+    /// Transient mock endpoints are removed when no user controls, queues for,
+    /// or (strongly) observes them, regardless of weak observers.
+    /// Weak observers are sent [`Async::EndpointClosed`] when that happens.
+    ObserveWeak(EndpointId),
+
+    /// Start observing the given endpoint, while keeping anyone else from observing it.
+    ///
+    /// Fails with [`error::Error::EndpointBusy`] if someone else already observes it.
+    /// While held, others trying to observe it get the same error.
+    /// The hold ends when the user disconnects.
+    ObserveExclusive(EndpointId),
+
+    /// Start observing every endpoint matching the given labels.
+    ///
+    /// Unlike [`Action::ControlAny`], which picks one matching endpoint,
+    /// all endpoints matching when the request arrives are observed,
+    /// since any number of users may observe an endpoint.
+    /// Endpoints appearing later are not observed.
+    ///
+    /// Either all matching endpoints are observed, or none are,
+    /// e.g. if one of them is already observed by this user.
+    /// The user is sent [`Sync::ObservingAny`].
+    ObserveAny(Labels),
+
+    /// Stop observing the given endpoint, which also ends an exclusive hold.
+    /// Transient mock endpoints are removed if nobody else uses them.
+    ///
+    /// Always succeeds, even if the endpoint was not observed.
+    Unobserve(EndpointId),
 
     /// Put this message on the wire for the given endpoint.
-    Write((EndpointId, SerialMessage)),
+    Write {
+        /// The endpoint to write to.
+        endpoint: EndpointId,
+
+        /// The message to put on wire.
+        message: SerialMessage,
+
+        /// Echoed back as is in [`Sync::WriteOk`],
+        /// such that pipelined writes can be matched with their responses.
+        /// Should the write fail, its error is tagged as by [`Action::Correlated`].
+        #[serde(default)]
+        correlation_id: Option<String>,
+    },
 
     /// Put these bytes on the wire for the given endpoint.
-    WriteBytes((EndpointId, SerialMessageBytes)),
+    WriteBytes {
+        /// The endpoint to write to.
+        endpoint: EndpointId,
+
+        /// The bytes to put on wire.
+        bytes: SerialMessageBytes,
+
+        /// See the same field of [`Action::Write`].
+        #[serde(default)]
+        correlation_id: Option<String>,
+    },
+
+    /// Check whether writing to the given endpoint would be allowed,
+    /// without putting anything on the wire.
+    CanWrite(EndpointId),
 
     /// Start receiving events.
     ///
@@ -45,27 +170,352 @@ pub enum Action {
     /// - Endpoint queue updates
     /// and more.
     ObserveEvents,
+
+    /// Start receiving the events of the given endpoint, such as it going idle.
+    /// See [`events::endpoint::Event`].
+    ObserveEndpointEvents(EndpointId),
+
+    /// Start receiving every message put on or received from wire by the given endpoints,
+    /// whoever controls them.
+    /// The messages are sent as [`Async::Traffic`].
+    ///
+    /// Unlike [`Action::ObserveEvents`], no other events are sent.
+    ObserveMessages(Vec<EndpointId>),
+
+    /// Change the settings of a controlled TTY endpoint without closing it.
+    ///
+    /// Settings which are not given are left as they are.
+    /// Observers of the endpoint keep observing.
+    /// Mock endpoints have no such settings and cannot be reconfigured.
+    Reconfigure {
+        /// The endpoint to reconfigure.
+        endpoint: EndpointId,
+
+        /// The new baud rate, if it should change.
+        baud: Option<u32>,
+
+        /// The new flow control, if it should change.
+        #[schemars(with = "Option<FlowControlSchema>")]
+        flow_control: Option<serialport::FlowControl>,
+    },
+
+    /// Discard what is buffered on a controlled endpoint, e.g. before a critical command.
+    ///
+    /// For TTY endpoints the OS buffers are cleared.
+    /// For mock endpoints, messages written but not yet looped back are dropped.
+    /// At least one of the buffers must be chosen.
+    Flush {
+        /// The endpoint to flush.
+        endpoint: EndpointId,
+
+        /// Discard what is waiting to be put on the wire.
+        tx: bool,
+
+        /// Discard what was received from the wire but not yet read.
+        rx: bool,
+    },
+
+    /// Run a macro from the server configuration on a controlled endpoint.
+    RunMacro {
+        /// The endpoint to run the macro on.
+        endpoint: EndpointId,
+
+        /// The name of the macro.
+        name: String,
+    },
+
+    /// Whether macros configured to run on control should be skipped
+    /// when this user is granted control from now on.
+    /// They are not skipped by default.
+    SkipOnControlMacros(bool),
+
+    /// Whether messages of endpoints observed from now on refer to the endpoint
+    /// by a short [`EndpointHandle`] instead of by its full id.
+    /// See [`Async::EndpointHandle`] and [`Async::CompactMessage`].
+    /// Messages carry the full id by default.
+    CompactEndpointIds(bool),
+
+    /// List the endpoints the server knows of, along with their descriptions.
+    ListEndpoints,
+
+    /// List the distinct labels of the endpoints the server knows of,
+    /// along with how many endpoints carry each.
+    /// Useful for offering what to [`Action::ControlAny`].
+    ListLabels,
+
+    /// Hand control of an endpoint directly to another connected user,
+    /// without them having to queue for it.
+    ///
+    /// If the endpoint is part of a group, control of the whole group is handed over.
+    TransferControl {
+        /// The controlled endpoint to hand over.
+        endpoint: EndpointId,
+
+        /// The name of the user receiving control.
+        to_user: String,
+    },
+
+    /// Stop controlling the given endpoint, so that the next user in queue gets it.
+    /// Observing the endpoint is not affected.
+    ///
+    /// If the endpoint is part of a group, control of the whole group is released.
+    Release(EndpointId),
+
+    /// Stop waiting in any queue for control, e.g. because the user gave up waiting.
+    /// Endpoints already controlled are not affected.
+    ///
+    /// Control may have been granted just before the server received this,
+    /// in which case the user is not queued and keeps control.
+    CancelQueue,
+
+    /// Get the most recent messages the endpoint received from wire, oldest first.
+    /// Neither control nor observing is required, and nothing is observed afterwards.
+    ///
+    /// The server only remembers a limited number of messages per endpoint,
+    /// so fewer than `count` may be returned.
+    RecentMessages {
+        /// The endpoint to get messages of.
+        endpoint: EndpointId,
+
+        /// How many of the most recent messages to get at most.
+        count: usize,
+    },
+
+    /// Send a notice to everyone observing the endpoint, e.g. that the board will be reset.
+    /// Observers receive it as [`Async::Notice`], told apart from what the device sent.
+    ///
+    /// Only users configured as admins may send notices,
+    /// see [`crate::config::Config::admins`].
+    Notice {
+        /// The endpoint whose observers are told.
+        endpoint: EndpointId,
+
+        /// What to tell them.
+        text: String,
+    },
+
+    /// Take an endpoint out of rotation, or put it back.
+    /// Users may not start controlling or observing a disabled endpoint,
+    /// and it is listed as disabled.
+    /// The user is sent [`Sync::EndpointEnabled`].
+    ///
+    /// Only users configured as admins may do this,
+    /// see [`crate::config::Config::admins`].
+    SetEndpointEnabled {
+        /// The endpoint to enable or disable.
+        endpoint: EndpointId,
+
+        /// Whether users may use the endpoint.
+        enabled: bool,
+
+        /// When disabling, whether the users controlling the endpoint lose control.
+        /// They are then sent [`Async::ControlRevoked`].
+        /// Otherwise they keep control until they release it.
+        #[serde(default)]
+        release_holders: bool,
+    },
+
+    /// Check that a controlled endpoint's device is responsive:
+    /// Put the probe on wire, then wait for the expected message from wire.
+    ///
+    /// The user is sent [`Sync::Pong`] if it arrives in time,
+    /// else [`error::Error::Timeout`].
+    PingEndpoint {
+        /// The endpoint to ping.
+        endpoint: EndpointId,
+
+        /// What to put on wire.
+        probe: SerialMessageBytes,
+
+        /// The message from wire telling the device is responsive.
+        expect: SerialMessageBytes,
+
+        /// How long to wait for the expected message.
+        timeout: std::time::Duration,
+    },
+
+    /// Get the server's current time.
+    /// Lets clients on other hosts compute how far off their clock is,
+    /// such that timestamps from the server can be shown consistently.
+    ServerTime,
+
+    /// Get the server's view of this connection:
+    /// The session, who the user is, where it connected from and what it holds.
+    /// Helps troubleshooting proxies, or finding out which of several sessions is which.
+    WhoAmI,
+
+    /// Perform the given action.
+    /// Should it fail, the error is wrapped in [`error::Error::Correlated`] with the correlation id,
+    /// such that pipelined actions can be matched with their failures.
+    ///
+    /// Only errors are tagged, successful responses are as usual.
+    /// Errors are tagged once: The id of the outermost correlated action is used,
+    /// over the id of nested ones or that of a [`Action::Write`] it wraps.
+    Correlated {
+        /// Tags the error of the action, if it fails.
+        correlation_id: String,
+
+        /// The action to perform.
+        action: Box<Action>,
+    },
+}
+
+/// The flags of [`Action::Observe`], all off by default.
+///
+/// Lets the observe constructors only name the flag they turn on.
+#[derive(Debug, Default)]
+struct ObserveFlags {
+    include_writes: bool,
+    idempotent: bool,
+    limit: Option<usize>,
+    deferred: bool,
+    replay: bool,
+    since: Option<chrono::DateTime<chrono::Utc>>,
+    dedup: bool,
+}
+
+impl ObserveFlags {
+    fn observe(self, id: &EndpointId) -> Action {
+        let Self {
+            include_writes,
+            idempotent,
+            limit,
+            deferred,
+            replay,
+            since,
+            dedup,
+        } = self;
+
+        Action::Observe {
+            endpoint: id.clone(),
+            include_writes,
+            idempotent,
+            limit,
+            deferred,
+            replay,
+            since,
+            dedup,
+        }
+    }
 }
 
 impl Display for Action {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             Action::Control(e) => write!(f, "control: {e}"),
-            Action::Observe(e) => write!(f, "observe: {e}"),
-            Action::Write((e, msg)) => {
-                write!(f, "write: {e}, msg: {msg}")
+            Action::Observe {
+                endpoint,
+                include_writes,
+                idempotent,
+                limit,
+                deferred,
+                replay,
+                since,
+                dedup,
+            } => {
+                write!(f, "observe: {endpoint}")?;
+                if *include_writes {
+                    write!(f, " (including writes)")?;
+                }
+                if *idempotent {
+                    write!(f, " (idempotent)")?;
+                }
+                if let Some(limit) = limit {
+                    write!(f, " (limit: {limit})")?;
+                }
+                if *deferred {
+                    write!(f, " (deferred)")?;
+                }
+                if *replay {
+                    write!(f, " (replaying recent)")?;
+                }
+                if let Some(since) = since {
+                    write!(f, " (replaying since {since})")?;
+                }
+                if *dedup {
+                    write!(f, " (deduplicated)")?;
+                }
+                Ok(())
+            }
+            Action::ObserveWeak(e) => write!(f, "observe weak: {e}"),
+            Action::ObserveExclusive(e) => write!(f, "observe exclusive: {e}"),
+            Action::ObserveAny(labels) => write!(f, "observe any: {labels}"),
+            Action::Unobserve(e) => write!(f, "unobserve: {e}"),
+            Action::Write {
+                endpoint, message, ..
+            } => {
+                write!(f, "write: {endpoint}, msg: {message}")
             }
             Action::ControlAny(labels) => {
                 write!(f, "control any: {labels}")
             }
-            Action::WriteBytes((e, bytes)) => {
+            Action::ControlAnyWhenAvailable { labels, timeout } => {
+                write!(
+                    f,
+                    "control any when available: {labels}, timeout: {timeout:?}"
+                )
+            }
+            Action::WriteBytes {
+                endpoint, bytes, ..
+            } => {
                 write!(
                     f,
-                    "write: {e}, msg: [{:?}]..",
+                    "write: {endpoint}, msg: [{:?}]..",
                     &bytes[0..bytes.len().min(16)]
                 )
             }
+            Action::CanWrite(e) => write!(f, "can write: {e}"),
             Action::ObserveEvents => write!(f, "observe events"),
+            Action::ObserveEndpointEvents(e) => write!(f, "observe endpoint events: {e}"),
+            Action::ObserveMessages(endpoints) => {
+                write!(f, "observe messages:")?;
+                for endpoint in endpoints {
+                    write!(f, " {endpoint}")?;
+                }
+                Ok(())
+            }
+            Action::Reconfigure {
+                endpoint,
+                baud,
+                flow_control,
+            } => write!(
+                f,
+                "reconfigure: {endpoint}, baud: {baud:?}, flow control: {flow_control:?}"
+            ),
+            Action::Flush { endpoint, tx, rx } => {
+                write!(f, "flush: {endpoint}, tx: {tx}, rx: {rx}")
+            }
+            Action::RunMacro { endpoint, name } => write!(f, "run macro: {name} on {endpoint}"),
+            Action::SkipOnControlMacros(skip) => write!(f, "skip on control macros: {skip}"),
+            Action::CompactEndpointIds(compact) => write!(f, "compact endpoint ids: {compact}"),
+            Action::ListEndpoints => write!(f, "list endpoints"),
+            Action::ListLabels => write!(f, "list labels"),
+            Action::TransferControl { endpoint, to_user } => {
+                write!(f, "transfer control: {endpoint} to {to_user}")
+            }
+            Action::Release(e) => write!(f, "release: {e}"),
+            Action::CancelQueue => write!(f, "cancel queue"),
+            Action::RecentMessages { endpoint, count } => {
+                write!(f, "recent messages: {endpoint}, count: {count}")
+            }
+            Action::Notice { endpoint, text } => write!(f, "notice: {text} on {endpoint}"),
+            Action::SetEndpointEnabled {
+                endpoint,
+                enabled,
+                release_holders,
+            } => write!(
+                f,
+                "set endpoint enabled: {endpoint}, enabled: {enabled}, release holders: {release_holders}"
+            ),
+            Action::PingEndpoint {
+                endpoint, timeout, ..
+            } => write!(f, "ping endpoint: {endpoint}, timeout: {timeout:?}"),
+            Action::ServerTime => write!(f, "server time"),
+            Action::WhoAmI => write!(f, "who am i"),
+            Action::Correlated {
+                correlation_id,
+                action,
+            } => write!(f, "{action} ({correlation_id})"),
         }
     }
 }
@@ -106,14 +556,113 @@ impl Action {
         Self::control_any(&["my-label", "blue-device"])
     }
 
+    /// Create a control any action which waits for a matching endpoint to appear.
+    pub fn control_any_when_available<S: AsRef<str>>(
+        labels: &[S],
+        timeout: std::time::Duration,
+    ) -> Self {
+        Self::ControlAnyWhenAvailable {
+            labels: labels.iter().map(Label::new).collect(),
+            timeout,
+        }
+    }
+
+    /// An example of waiting up to a minute for a matching endpoint to control.
+    pub fn example_control_any_when_available() -> Self {
+        Self::control_any_when_available(&["blue-device"], std::time::Duration::from_secs(60))
+    }
+
     /// Create an observe action.
     pub fn observe(id: &EndpointId) -> Self {
-        Self::Observe(id.clone())
+        ObserveFlags::default().observe(id)
+    }
+
+    /// Create an observe action which is confirmed again if the endpoint is already observed.
+    pub fn observe_idempotent(id: &EndpointId) -> Self {
+        ObserveFlags {
+            idempotent: true,
+            ..Default::default()
+        }
+        .observe(id)
+    }
+
+    /// Create an observe action which waits for the endpoint to appear if it does not exist.
+    pub fn observe_deferred(id: &EndpointId) -> Self {
+        ObserveFlags {
+            deferred: true,
+            ..Default::default()
+        }
+        .observe(id)
+    }
+
+    /// An example of observing a TTY which may not be plugged in yet.
+    pub fn example_observe_deferred() -> Self {
+        Self::observe_deferred(&EndpointId::tty("/dev/ttyUSB0"))
+    }
+
+    /// Create an observe action which stops after the given number of messages.
+    pub fn observe_limited(id: &EndpointId, limit: usize) -> Self {
+        ObserveFlags {
+            limit: Some(limit),
+            ..Default::default()
+        }
+        .observe(id)
+    }
+
+    /// An example of capturing just the next few messages of a TTY.
+    pub fn example_observe_limited() -> Self {
+        Self::observe_limited(&EndpointId::tty("/dev/ttyACM123"), 5)
+    }
+
+    /// Create an observe action which starts with the messages the endpoint recently saw.
+    pub fn observe_replayed(id: &EndpointId) -> Self {
+        ObserveFlags {
+            replay: true,
+            ..Default::default()
+        }
+        .observe(id)
+    }
+
+    /// An example of observing a TTY, catching up on what it recently received.
+    pub fn example_observe_replayed() -> Self {
+        Self::observe_replayed(&EndpointId::tty("/dev/ttyACM123"))
+    }
+
+    /// Create an observe action which starts with the recently seen messages newer than `since`.
+    pub fn observe_since(id: &EndpointId, since: chrono::DateTime<chrono::Utc>) -> Self {
+        ObserveFlags {
+            since: Some(since),
+            ..Default::default()
+        }
+        .observe(id)
+    }
+
+    /// An example of observing a TTY again after a disconnect,
+    /// catching up on what it received in the meantime.
+    pub fn example_observe_since() -> Self {
+        Self::observe_since(
+            &EndpointId::tty("/dev/ttyACM123"),
+            chrono::Utc::now() - chrono::Duration::minutes(5),
+        )
+    }
+
+    /// Create an observe action which collapses identical consecutive messages.
+    pub fn observe_deduplicated(id: &EndpointId) -> Self {
+        ObserveFlags {
+            dedup: true,
+            ..Default::default()
+        }
+        .observe(id)
+    }
+
+    /// An example of observing a TTY which repeats a status line.
+    pub fn example_observe_deduplicated() -> Self {
+        Self::observe_deduplicated(&EndpointId::tty("/dev/ttyACM123"))
     }
 
     /// Create an observe TTY action.
     pub fn observe_tty(path: &str) -> Self {
-        Self::Observe(EndpointId::tty(path))
+        Self::observe(&EndpointId::tty(path))
     }
 
     /// An example of requesting to observe a TTY.
@@ -123,7 +672,7 @@ impl Action {
 
     /// Create an observe mock action.
     pub fn observe_mock(name: &str) -> Self {
-        Self::Observe(EndpointId::mock(name))
+        Self::observe(&EndpointId::mock(name))
     }
 
     /// An example of requesting to observe a mock.
@@ -131,27 +680,135 @@ impl Action {
         Self::observe_mock("some-mock")
     }
 
+    /// An example of requesting to observe the ports of a composite endpoint.
+    pub fn example_observe_composite() -> Self {
+        Self::observe(&EndpointId::composite("nrf5340-dk"))
+    }
+
+    /// Create an observe action which also sees what is put on wire.
+    pub fn observe_including_writes(id: &EndpointId) -> Self {
+        ObserveFlags {
+            include_writes: true,
+            ..Default::default()
+        }
+        .observe(id)
+    }
+
+    /// An example of observing both directions of a TTY.
+    pub fn example_observe_including_writes() -> Self {
+        Self::observe_including_writes(&EndpointId::tty("/dev/ttyACM123"))
+    }
+
+    /// Create a weak observe action.
+    pub fn observe_weak(id: &EndpointId) -> Self {
+        Self::ObserveWeak(id.clone())
+    }
+
+    /// An example of weakly observing a mock.
+    pub fn example_observe_weak() -> Self {
+        Self::observe_weak(&EndpointId::mock("some-mock"))
+    }
+
+    /// Create an exclusive observe action.
+    pub fn observe_exclusive(id: &EndpointId) -> Self {
+        Self::ObserveExclusive(id.clone())
+    }
+
+    /// An example of observing a TTY without anyone else reading along.
+    pub fn example_observe_exclusive() -> Self {
+        Self::observe_exclusive(&EndpointId::tty("/dev/ttyACM0"))
+    }
+
+    /// Create an action observing every endpoint matching the labels.
+    pub fn observe_any<S: AsRef<str>>(labels: &[S]) -> Self {
+        Self::ObserveAny(labels.iter().map(Label::new).collect())
+    }
+
+    /// An example of observing every matching endpoint.
+    pub fn example_observe_any() -> Self {
+        Self::observe_any(&["blue-device"])
+    }
+
+    /// Create an unobserve action.
+    pub fn unobserve(id: &EndpointId) -> Self {
+        Self::Unobserve(id.clone())
+    }
+
+    /// An example of no longer observing a TTY.
+    pub fn example_unobserve() -> Self {
+        Self::unobserve(&EndpointId::tty("/dev/ttyACM0"))
+    }
+
     /// Create a write action.
     pub fn write(id: &EndpointId, message: SerialMessage) -> Self {
-        Self::Write((id.clone(), message))
+        Self::Write {
+            endpoint: id.clone(),
+            message,
+            correlation_id: None,
+        }
+    }
+
+    /// Create a write action, the response of which carries the given correlation id.
+    pub fn write_correlated(id: &EndpointId, message: SerialMessage, correlation_id: &str) -> Self {
+        Self::Write {
+            endpoint: id.clone(),
+            message,
+            correlation_id: Some(correlation_id.into()),
+        }
     }
 
     /// An example of a write message to a TTY endpoint.
     pub fn example_write() -> Self {
-        Self::Write((EndpointId::tty("/dev/ttyACMx"), "This is a message".into()))
+        Self::write(&EndpointId::tty("/dev/ttyACMx"), "This is a message".into())
+    }
+
+    /// An example of a write message tagged with a correlation id.
+    pub fn example_write_correlated() -> Self {
+        Self::write_correlated(
+            &EndpointId::tty("/dev/ttyACMx"),
+            "This is a message".into(),
+            "cmd-42",
+        )
     }
 
     /// Create a write bytes action.
     pub fn write_bytes(id: &EndpointId, bytes: SerialMessageBytes) -> Self {
-        Self::WriteBytes((id.clone(), bytes))
+        Self::WriteBytes {
+            endpoint: id.clone(),
+            bytes,
+            correlation_id: None,
+        }
+    }
+
+    /// Create a write bytes action, the response of which carries the given correlation id.
+    pub fn write_bytes_correlated(
+        id: &EndpointId,
+        bytes: SerialMessageBytes,
+        correlation_id: &str,
+    ) -> Self {
+        Self::WriteBytes {
+            endpoint: id.clone(),
+            bytes,
+            correlation_id: Some(correlation_id.into()),
+        }
     }
 
     /// An example of a writing bytes to a mock endpoint.
     pub fn example_write_bytes() -> Self {
-        Self::WriteBytes((
-            EndpointId::mock("/mock/ttyACMx"),
+        Self::write_bytes(
+            &EndpointId::mock("/mock/ttyACMx"),
             b"This is a message".to_vec(),
-        ))
+        )
+    }
+
+    /// Create a can write action.
+    pub fn can_write(id: &EndpointId) -> Self {
+        Self::CanWrite(id.clone())
+    }
+
+    /// An example of checking whether a TTY endpoint may be written to.
+    pub fn example_can_write() -> Self {
+        Self::can_write(&EndpointId::tty("/dev/ttyACM0"))
     }
 
     /// Create an observe events action.
@@ -164,96 +821,907 @@ impl Action {
         Self::observe_events()
     }
 
-    /// Turn an action into serialized json.
-    pub fn serialize(&self) -> String {
-        serde_json::to_string(self).expect("Should serialize well")
+    /// Create an observe endpoint events action.
+    pub fn observe_endpoint_events(id: &EndpointId) -> Self {
+        Self::ObserveEndpointEvents(id.clone())
     }
-}
 
-/// A response type of "sync nature"- a direct response to a request.
-#[derive(Debug, Serialize, Deserialize, PartialEq, Eq)]
-pub enum Sync {
-    /// The write action was successful.
-    WriteOk,
+    /// An example of observing the events of a TTY endpoint.
+    pub fn example_observe_endpoint_events() -> Self {
+        Self::observe_endpoint_events(&EndpointId::tty("/dev/ttyACM0"))
+    }
 
-    /// Now observing the following endpoint.
-    Observing(LabelledEndpointId),
+    /// Create an observe messages action.
+    pub fn observe_messages(ids: &[EndpointId]) -> Self {
+        Self::ObserveMessages(ids.to_vec())
+    }
 
-    /// Now receiving events.
-    ObservingEvents,
+    /// An example of observing the messages of two TTY endpoints.
+    pub fn example_observe_messages() -> Self {
+        Self::observe_messages(&[
+            EndpointId::tty("/dev/ttyACM0"),
+            EndpointId::tty("/dev/ttyACM1"),
+        ])
+    }
 
-    /// The requested endpoint was busy.
-    /// When available, access is granted and
-    /// [`Response::ControlGranted(_)`] is sent.
-    ControlQueue(Vec<LabelledEndpointId>),
+    /// Create a reconfigure action.
+    pub fn reconfigure(
+        id: &EndpointId,
+        baud: Option<u32>,
+        flow_control: Option<serialport::FlowControl>,
+    ) -> Self {
+        Self::Reconfigure {
+            endpoint: id.clone(),
+            baud,
+            flow_control,
+        }
+    }
 
-    /// The requested endpoint is now exclusively in use by the user.
-    /// Writing to this endpoint is now possible.
-    ControlGranted(Vec<LabelledEndpointId>),
-}
+    /// An example of switching a TTY endpoint to a different baud rate.
+    pub fn example_reconfigure() -> Self {
+        Self::reconfigure(&EndpointId::tty("/dev/ttyACM0"), Some(57_600), None)
+    }
 
-/// An async response type- might originate on the server side at any time.
-#[derive(Debug, Serialize, Deserialize, PartialEq, Eq)]
-pub enum Async {
-    /// An endpoint sent a message.
-    Message {
-        /// Which endpoint sent a message.
-        endpoint: LabelledEndpointId,
+    /// Create a flush action.
+    pub fn flush(id: &EndpointId, tx: bool, rx: bool) -> Self {
+        Self::Flush {
+            endpoint: id.clone(),
+            tx,
+            rx,
+        }
+    }
 
-        /// The message contents.
-        message: SerialMessageBytes,
-    },
+    /// An example of discarding what a TTY endpoint has received but not yet been read.
+    pub fn example_flush() -> Self {
+        Self::flush(&EndpointId::tty("/dev/ttyACM0"), false, true)
+    }
 
-    /// An event.
-    Event(events::TimestampedEvent),
-}
+    /// Create a run macro action.
+    pub fn run_macro(id: &EndpointId, name: &str) -> Self {
+        Self::RunMacro {
+            endpoint: id.clone(),
+            name: name.into(),
+        }
+    }
 
-/// Responses the server will send to connected users.
-#[derive(Debug, Serialize, Deserialize, PartialEq, Eq)]
-pub enum Response {
-    /// A synchronous response in the sense that it's sent directly after a user
-    /// request.
-    Sync(Sync),
+    /// An example of running a macro on a TTY endpoint.
+    pub fn example_run_macro() -> Self {
+        Self::run_macro(&EndpointId::tty("/dev/ttyACM0"), "init")
+    }
 
-    /// An async message- the server might send this at any time and not in response to any
-    /// particular request.
-    Async(Async),
-}
+    /// Create a skip on control macros action.
+    pub fn skip_on_control_macros(skip: bool) -> Self {
+        Self::SkipOnControlMacros(skip)
+    }
 
-impl Response {
-    pub(crate) fn write_ok() -> Self {
-        Self::Sync(Sync::WriteOk)
+    /// An example of opting out of on control macros.
+    pub fn example_skip_on_control_macros() -> Self {
+        Self::skip_on_control_macros(true)
     }
 
-    /// An example of a message write OK response.
-    pub fn example_write_ok() -> Self {
-        Self::write_ok()
+    /// Create a compact endpoint ids action.
+    pub fn compact_endpoint_ids(compact: bool) -> Self {
+        Self::CompactEndpointIds(compact)
     }
 
-    pub(crate) fn observing_events() -> Self {
-        Self::Sync(Sync::ObservingEvents)
+    /// An example of opting into compact endpoint ids.
+    pub fn example_compact_endpoint_ids() -> Self {
+        Self::compact_endpoint_ids(true)
     }
 
-    /// An example of an observe events OK response.
-    pub fn example_observing_events() -> Self {
-        Self::observing_events()
+    /// Create a list endpoints action.
+    pub fn list_endpoints() -> Self {
+        Self::ListEndpoints
     }
 
-    pub(crate) fn message(endpoint: LabelledEndpointId, message: SerialMessageBytes) -> Self {
-        Self::Async(Async::Message { endpoint, message })
+    /// An example of a list endpoints action.
+    pub fn example_list_endpoints() -> Self {
+        Self::list_endpoints()
     }
 
-    /// An example of a new message response. These are async and might appear at any time after a user has
-    /// started observing the related endpoint.
-    pub fn example_new_message() -> Self {
-        Self::message(
-            LabelledEndpointId::new(&EndpointId::tty("COM0")),
-            "Hello World!".into(),
-        )
+    /// Create a list labels action.
+    pub fn list_labels() -> Self {
+        Self::ListLabels
     }
 
-    pub(crate) fn observing(id: LabelledEndpointId) -> Self {
-        Self::Sync(Sync::Observing(id))
+    /// An example of a list labels action.
+    pub fn example_list_labels() -> Self {
+        Self::list_labels()
+    }
+
+    /// Create a transfer control action.
+    pub fn transfer_control(id: &EndpointId, to_user: &str) -> Self {
+        Self::TransferControl {
+            endpoint: id.clone(),
+            to_user: to_user.into(),
+        }
+    }
+
+    /// An example of handing a controlled TTY endpoint to another user.
+    pub fn example_transfer_control() -> Self {
+        Self::transfer_control(&EndpointId::tty("/dev/ttyACM0"), "127.0.0.1:51234")
+    }
+
+    /// Create a release action.
+    pub fn release(id: &EndpointId) -> Self {
+        Self::Release(id.clone())
+    }
+
+    /// An example of releasing control of a TTY endpoint.
+    pub fn example_release() -> Self {
+        Self::release(&EndpointId::tty("/dev/ttyACM0"))
+    }
+
+    /// Create a cancel queue action.
+    pub fn cancel_queue() -> Self {
+        Self::CancelQueue
+    }
+
+    /// An example of no longer waiting for control.
+    pub fn example_cancel_queue() -> Self {
+        Self::cancel_queue()
+    }
+
+    /// Create a recent messages action.
+    pub fn recent_messages(id: &EndpointId, count: usize) -> Self {
+        Self::RecentMessages {
+            endpoint: id.clone(),
+            count,
+        }
+    }
+
+    /// An example of getting the recent messages of an endpoint.
+    pub fn example_recent_messages() -> Self {
+        Self::recent_messages(&EndpointId::tty("/dev/ttyACM0"), 10)
+    }
+
+    /// Create a notice action.
+    pub fn notice(id: &EndpointId, text: &str) -> Self {
+        Self::Notice {
+            endpoint: id.clone(),
+            text: text.into(),
+        }
+    }
+
+    /// An example of telling observers about an upcoming reset.
+    pub fn example_notice() -> Self {
+        Self::notice(
+            &EndpointId::tty("/dev/ttyACM0"),
+            "Board will reset in 5 minutes",
+        )
+    }
+
+    /// Create an action enabling or disabling an endpoint.
+    pub fn set_endpoint_enabled(id: &EndpointId, enabled: bool, release_holders: bool) -> Self {
+        Self::SetEndpointEnabled {
+            endpoint: id.clone(),
+            enabled,
+            release_holders,
+        }
+    }
+
+    /// An example of taking a board out of rotation for maintenance.
+    pub fn example_set_endpoint_enabled() -> Self {
+        Self::set_endpoint_enabled(&EndpointId::tty("/dev/ttyACM0"), false, true)
+    }
+
+    /// Create a ping endpoint action.
+    pub fn ping_endpoint(
+        id: &EndpointId,
+        probe: &[u8],
+        expect: &[u8],
+        timeout: std::time::Duration,
+    ) -> Self {
+        Self::PingEndpoint {
+            endpoint: id.clone(),
+            probe: probe.to_vec(),
+            expect: expect.to_vec(),
+            timeout,
+        }
+    }
+
+    /// An example of checking a device answers `AT` with `OK` within a second.
+    pub fn example_ping_endpoint() -> Self {
+        Self::ping_endpoint(
+            &EndpointId::tty("/dev/ttyACM0"),
+            b"AT",
+            b"OK",
+            std::time::Duration::from_secs(1),
+        )
+    }
+
+    /// Create a server time action.
+    pub fn server_time() -> Self {
+        Self::ServerTime
+    }
+
+    /// An example of asking for the server's time.
+    pub fn example_server_time() -> Self {
+        Self::server_time()
+    }
+
+    /// Create a who am I action.
+    pub fn who_am_i() -> Self {
+        Self::WhoAmI
+    }
+
+    /// An example of asking for the server's view of the connection.
+    pub fn example_who_am_i() -> Self {
+        Self::who_am_i()
+    }
+
+    /// Wrap an action such that its error, if any, carries the given correlation id.
+    pub fn correlated(self, correlation_id: &str) -> Self {
+        Self::Correlated {
+            correlation_id: correlation_id.into(),
+            action: Box::new(self),
+        }
+    }
+
+    /// An example of releasing a TTY, tagged with a correlation id.
+    pub fn example_correlated() -> Self {
+        Self::example_release().correlated("release-7")
+    }
+
+    /// An example of every kind of action, see the `example_*` constructors.
+    pub fn examples() -> Vec<Self> {
+        vec![
+            Self::example_control_mock(),
+            Self::example_control_tty(),
+            Self::example_control_any(),
+            Self::example_control_any_when_available(),
+            Self::example_observe_deferred(),
+            Self::example_observe_limited(),
+            Self::example_observe_replayed(),
+            Self::example_observe_since(),
+            Self::example_observe_deduplicated(),
+            Self::example_observe_tty(),
+            Self::example_observe_mock(),
+            Self::example_observe_composite(),
+            Self::example_observe_including_writes(),
+            Self::example_observe_weak(),
+            Self::example_observe_exclusive(),
+            Self::example_observe_any(),
+            Self::example_unobserve(),
+            Self::example_write(),
+            Self::example_write_correlated(),
+            Self::example_write_bytes(),
+            Self::example_can_write(),
+            Self::example_observe_events(),
+            Self::example_observe_endpoint_events(),
+            Self::example_observe_messages(),
+            Self::example_reconfigure(),
+            Self::example_flush(),
+            Self::example_run_macro(),
+            Self::example_skip_on_control_macros(),
+            Self::example_compact_endpoint_ids(),
+            Self::example_list_endpoints(),
+            Self::example_list_labels(),
+            Self::example_transfer_control(),
+            Self::example_release(),
+            Self::example_cancel_queue(),
+            Self::example_recent_messages(),
+            Self::example_notice(),
+            Self::example_set_endpoint_enabled(),
+            Self::example_ping_endpoint(),
+            Self::example_server_time(),
+            Self::example_who_am_i(),
+            Self::example_correlated(),
+        ]
+    }
+
+    /// The action without any [`Action::Correlated`] wrapping it.
+    pub(crate) fn uncorrelated(self) -> Self {
+        match self {
+            Action::Correlated { action, .. } => action.uncorrelated(),
+            action => action,
+        }
+    }
+
+    /// The correlation id the action was given, if any.
+    pub(crate) fn correlation_id(&self) -> Option<&str> {
+        match self {
+            Action::Write { correlation_id, .. } | Action::WriteBytes { correlation_id, .. } => {
+                correlation_id.as_deref()
+            }
+            Action::Correlated { correlation_id, .. } => Some(correlation_id),
+            _ => None,
+        }
+    }
+
+    /// Whether this action controls endpoints or affects what is on wire,
+    /// which a read-only server does not allow.
+    pub(crate) fn controls_endpoints(&self) -> bool {
+        match self {
+            Action::Control(_)
+            | Action::ControlAny(_)
+            | Action::ControlAnyWhenAvailable { .. }
+            | Action::Write { .. }
+            | Action::WriteBytes { .. }
+            | Action::Reconfigure { .. }
+            | Action::Flush { .. }
+            | Action::RunMacro { .. }
+            | Action::TransferControl { .. }
+            | Action::SetEndpointEnabled { .. }
+            | Action::PingEndpoint { .. } => true,
+            Action::Correlated { action, .. } => action.controls_endpoints(),
+            _ => false,
+        }
+    }
+
+    /// How many bytes this action puts on wire, if it writes anything.
+    pub(crate) fn message_len(&self) -> Option<usize> {
+        match self {
+            Action::Write { message, .. } => Some(message.as_str().len()),
+            Action::WriteBytes { bytes, .. } => Some(bytes.len()),
+            Action::PingEndpoint { probe, .. } => Some(probe.len()),
+            Action::Correlated { action, .. } => action.message_len(),
+            _ => None,
+        }
+    }
+
+    /// Turn an action into serialized json.
+    pub fn serialize(&self) -> String {
+        serde_json::to_string(self).expect("Should serialize well")
+    }
+
+    /// Turn an action into the compact binary encoding (MessagePack).
+    /// Only servers supporting [`Capability::BinaryFrames`] understand this.
+    pub fn serialize_binary(&self) -> Vec<u8> {
+        rmp_serde::to_vec_named(self).expect("Should serialize well")
+    }
+
+    /// Read an action from the compact binary encoding (MessagePack).
+    pub fn deserialize_binary(bytes: &[u8]) -> Result<Self, error::Error> {
+        rmp_serde::from_slice(bytes).map_err(|e| error::Error::BadBinary(e.to_string()))
+    }
+}
+
+/// An endpoint as listed by the server.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, JsonSchema)]
+pub struct ListedEndpoint {
+    /// The endpoint and its labels.
+    pub id: LabelledEndpointId,
+
+    /// A human readable description from the server configuration, if any.
+    pub description: Option<String>,
+
+    /// The settings a serial port actually uses, which may differ from the configured ones.
+    /// `None` for mocks.
+    pub settings: Option<PortSettings>,
+
+    /// What the endpoint prints when waiting for input, if configured.
+    /// See [`crate::client::ControlledEndpoint::request_response_until_prompt`].
+    #[serde(default)]
+    pub prompt: Option<String>,
+
+    /// Where the other end of a pseudo-terminal endpoint can be opened,
+    /// e.g. `/dev/pts/3`.
+    /// `None` for anything but pseudo-terminals.
+    #[serde(default)]
+    pub device: Option<String>,
+
+    /// When the endpoint was opened, or created for mocks.
+    pub opened_at: chrono::DateTime<chrono::Utc>,
+
+    /// Whether an admin took the endpoint out of rotation.
+    /// See [`Action::SetEndpointEnabled`].
+    #[serde(default)]
+    pub disabled: bool,
+
+    /// How many bytes the endpoint saw since it was opened.
+    #[serde(default)]
+    pub bytes: ByteCounts,
+}
+
+/// A label as listed by the server.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, JsonSchema)]
+pub struct ListedLabel {
+    /// The label.
+    pub label: Label,
+
+    /// How many endpoints carry the label.
+    pub endpoints: usize,
+}
+
+/// An endpoint a user was queued for, along with who is in the way.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, JsonSchema)]
+pub struct QueuedOn {
+    /// The endpoint queued for.
+    pub endpoint: LabelledEndpointId,
+
+    /// The name of the user controlling the endpoint, if any.
+    pub controller: Option<String>,
+
+    /// How many users were already queued for the endpoint.
+    /// This is an estimate: users ahead may leave, or get a different endpoint instead.
+    pub position: usize,
+}
+
+/// An endpoint matching the labels of an [`Action::ControlAny`] which could not be queued for.
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, JsonSchema)]
+pub struct NotQueuedOn {
+    /// The endpoint not queued for.
+    pub endpoint: LabelledEndpointId,
+
+    /// Why it could not be queued for, e.g. because the user already controls it.
+    pub error: error::Error,
+}
+
+/// A short stand-in for an observed endpoint, see [`Action::CompactEndpointIds`].
+///
+/// Handles are assigned per connection and never reused on it.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Hash, JsonSchema)]
+pub struct EndpointHandle(pub u32);
+
+impl Display for EndpointHandle {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "#{}", self.0)
+    }
+}
+
+/// A response type of "sync nature"- a direct response to a request.
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, JsonSchema)]
+pub enum Sync {
+    /// The write action was successful.
+    WriteOk {
+        /// The correlation id the write was given, if any.
+        #[serde(default)]
+        correlation_id: Option<String>,
+    },
+
+    /// Now observing the following endpoint.
+    Observing(LabelledEndpointId),
+
+    /// Now observing each port of the composite.
+    /// Messages are tagged with the port they came from.
+    ObservingComposite((LabelledEndpointId, Vec<LabelledEndpointId>)),
+
+    /// Now observing each endpoint which matched the labels.
+    ObservingAny((Labels, Vec<LabelledEndpointId>)),
+
+    /// Now receiving events.
+    ObservingEvents,
+
+    /// The requested endpoint was busy.
+    /// When available, access is granted and
+    /// [`Response::ControlGranted(_)`] is sent.
+    ControlQueue {
+        /// The endpoints queued for.
+        queued_on: Vec<QueuedOn>,
+
+        /// Endpoints matching the labels of an [`Action::ControlAny`]
+        /// which could not be queued for, and why.
+        #[serde(default)]
+        not_queued_on: Vec<NotQueuedOn>,
+    },
+
+    /// The requested endpoint is now exclusively in use by the user.
+    /// Writing to this endpoint is now possible.
+    ControlGranted(Vec<LabelledEndpointId>),
+
+    /// The endpoint now uses the requested settings.
+    Reconfigured(LabelledEndpointId),
+
+    /// The requested buffers of the endpoint were discarded.
+    Flushed(LabelledEndpointId),
+
+    /// The macro with the given name has run to completion on the endpoint.
+    MacroDone((LabelledEndpointId, String)),
+
+    /// Whether on control macros are now skipped for this user.
+    SkippingOnControlMacros(bool),
+
+    /// Whether messages of endpoints observed from now on refer to them by handle.
+    CompactingEndpointIds(bool),
+
+    /// The endpoints the server knows of.
+    Endpoints(Vec<ListedEndpoint>),
+
+    /// The distinct labels of the endpoints the server knows of, sorted by label.
+    Labels(Vec<ListedLabel>),
+
+    /// Control of the endpoint was handed to the user with the given name.
+    /// The user handing it over no longer controls it.
+    ControlTransferred((LabelledEndpointId, String)),
+
+    /// The user no longer controls the endpoint(s).
+    Released(Vec<LabelledEndpointId>),
+
+    /// The user is no longer queued for the endpoint(s).
+    /// Empty if the user was not queued for anything.
+    QueueCancelled(Vec<LabelledEndpointId>),
+
+    /// The most recent messages the endpoint received from wire, oldest first.
+    RecentMessages((LabelledEndpointId, Vec<SerialMessage>)),
+
+    /// The server's time when handling the request.
+    ServerTime(chrono::DateTime<chrono::Utc>),
+
+    /// The server's view of the connection.
+    Connection(ConnectionInfo),
+
+    /// The user is allowed to write to the endpoint.
+    /// Nothing was written.
+    WriteAllowed(LabelledEndpointId),
+
+    /// The user is now receiving events of the given endpoint.
+    ObservingEndpointEvents(LabelledEndpointId),
+
+    /// The user is now receiving the messages of the given endpoints.
+    ObservingMessages(Vec<LabelledEndpointId>),
+
+    /// The endpoint to observe does not exist yet.
+    /// [`Sync::Observing`] is sent once it appears.
+    ObservationDeferred(EndpointId),
+
+    /// No endpoint matches the labels to control yet.
+    /// Control is requested once one appears, see [`Action::ControlAnyWhenAvailable`].
+    ControlDeferred(Labels),
+
+    /// The user no longer observes the endpoint.
+    /// No messages of it are sent after this.
+    Unobserved(LabelledEndpointId),
+
+    /// The notice was sent to the endpoint's observers, of which there were this many.
+    NoticeSent((LabelledEndpointId, usize)),
+
+    /// The endpoint was enabled or disabled.
+    EndpointEnabled {
+        /// The endpoint.
+        endpoint: LabelledEndpointId,
+
+        /// Whether users may now use it.
+        enabled: bool,
+    },
+
+    /// The endpoint answered a ping, this long after the probe was put on wire.
+    Pong((LabelledEndpointId, std::time::Duration)),
+}
+
+/// Features a server may support.
+/// Clients may check these before asking for something an older server does not know of.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Hash, JsonSchema)]
+pub enum Capability {
+    /// Connections may be encrypted.
+    Tls,
+
+    /// Messages may be compressed.
+    Compression,
+
+    /// Macros from the server configuration may be run.
+    /// See [`Action::RunMacro`].
+    Macros,
+
+    /// Users may authenticate.
+    Auth,
+
+    /// Requests and responses may use binary websocket frames with a compact encoding
+    /// instead of text frames with json.
+    /// The server answers in binary once a client has sent a binary request.
+    BinaryFrames,
+}
+
+/// The version of the wire protocol spoken by this crate.
+/// Bumped whenever a change would make older peers misunderstand the messages.
+pub const PROTOCOL_VERSION: u32 = 3;
+
+/// The path clients connect to the server's websocket at, unless configured otherwise.
+/// See [`crate::config::Config::websocket_path`].
+pub const DEFAULT_WEBSOCKET_PATH: &str = "/client";
+
+/// The HTTP header a client may use to tell the server which
+/// [`PROTOCOL_VERSION`] it speaks when upgrading to a websocket.
+/// Clients not sending it are assumed to be compatible.
+pub const PROTOCOL_VERSION_HEADER: &str = "serial-keel-protocol";
+
+/// The HTTP header a client may use to name its session when upgrading to a websocket.
+/// The name shows up alongside the user in events.
+pub const SESSION_TAG_HEADER: &str = "serial-keel-session-tag";
+
+/// The HTTP header a client may use to choose its username when upgrading to a websocket.
+/// Clients not sending it are named by the address they connect from.
+/// See [`crate::user::User::validate_name`] for which names are accepted.
+pub const USERNAME_HEADER: &str = "serial-keel-username";
+
+/// What the server tells each client about itself as soon as the client connects.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, JsonSchema)]
+pub struct ServerInfo {
+    /// The version of the server.
+    pub version: semver::Version,
+
+    /// The wire protocol version of the server.
+    /// See [`PROTOCOL_VERSION`].
+    pub protocol: u32,
+
+    /// The features the server supports.
+    pub capabilities: Vec<Capability>,
+}
+
+impl ServerInfo {
+    /// Information about this server.
+    pub(crate) fn current() -> Self {
+        Self {
+            version: semver::Version::parse(env!("CARGO_PKG_VERSION"))
+                .expect("The server version should always be semver parsable!"),
+            protocol: PROTOCOL_VERSION,
+            capabilities: vec![Capability::Macros, Capability::BinaryFrames],
+        }
+    }
+
+    /// Whether the server supports the given capability.
+    pub fn supports(&self, capability: Capability) -> bool {
+        self.capabilities.contains(&capability)
+    }
+}
+
+/// The server's view of a connection, see [`Action::WhoAmI`].
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, JsonSchema)]
+pub struct ConnectionInfo {
+    /// Tells connections apart in the server logs, even ones of the same user.
+    pub session: u64,
+
+    /// Who the server takes the user to be.
+    pub user: User,
+
+    /// Where the connection came from as far as the server can tell,
+    /// i.e. the last proxy in between if any.
+    /// `None` for clients connecting in-process.
+    pub remote: Option<String>,
+
+    /// When the user connected.
+    pub connected_at: chrono::DateTime<chrono::Utc>,
+
+    /// The endpoints the user observes.
+    pub observing: Vec<LabelledEndpointId>,
+
+    /// The endpoints the user controls.
+    pub controlling: Vec<LabelledEndpointId>,
+}
+
+/// An async response type- might originate on the server side at any time.
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, JsonSchema)]
+pub enum Async {
+    /// An endpoint sent a message.
+    Message {
+        /// Which endpoint sent a message.
+        endpoint: LabelledEndpointId,
+
+        /// Whether the message was received from wire, or put on wire by a controller.
+        /// The latter is only seen when observing with writes included.
+        #[serde(default)]
+        direction: Direction,
+
+        /// The message contents.
+        message: SerialMessageBytes,
+    },
+
+    /// An endpoint sent the same message several times in a row.
+    /// Only seen when observing deduplicated, see [`Action::observe_deduplicated`].
+    RepeatedMessage {
+        /// Which endpoint sent the messages.
+        endpoint: LabelledEndpointId,
+
+        /// Whether the messages were received from wire, or put on wire by a controller.
+        #[serde(default)]
+        direction: Direction,
+
+        /// The message contents.
+        message: SerialMessageBytes,
+
+        /// How many times in a row the message was seen.
+        repeats: usize,
+    },
+
+    /// An event.
+    Event(events::TimestampedEvent),
+
+    /// An event of an endpoint the user observes the events of.
+    EndpointEvent(events::endpoint::TimestampedEndpointEvent),
+
+    /// A message of an endpoint the user observes the messages of.
+    Traffic(events::message::TimestampedMessage),
+
+    /// The server introducing itself.
+    /// This is always the first message on a new connection.
+    Handshake(ServerInfo),
+
+    /// The message of the day configured on the server.
+    /// Sent right after the handshake, if there is one.
+    Banner(String),
+
+    /// An observed endpoint went away, or the observation reached its limit.
+    /// No more messages will arrive from it.
+    EndpointClosed((LabelledEndpointId, CloseReason)),
+
+    /// A notice about an observed endpoint, sent by an admin and not by the device.
+    /// See [`Action::Notice`].
+    Notice {
+        /// Which endpoint the notice is about.
+        endpoint: LabelledEndpointId,
+
+        /// The notice.
+        text: String,
+    },
+
+    /// The user was too slow reading an observed endpoint, so the server skipped messages of it.
+    /// The observation goes on with the messages which follow.
+    MessagesSkipped {
+        /// The endpoint messages were skipped of.
+        endpoint: LabelledEndpointId,
+
+        /// How many messages were skipped.
+        skipped: u64,
+    },
+
+    /// An admin disabled an endpoint the user controlled, so the user no longer does.
+    /// Holds every endpoint released along with it.
+    /// See [`Action::SetEndpointEnabled`].
+    ControlRevoked(Vec<LabelledEndpointId>),
+
+    /// Messages of the endpoint refer to it by the given handle from now on.
+    /// Sent before the endpoint is confirmed observed,
+    /// and only after opting in by [`Action::CompactEndpointIds`].
+    EndpointHandle((EndpointHandle, LabelledEndpointId)),
+
+    /// Like [`Async::Message`], but the endpoint is referred to by handle.
+    /// See [`Async::EndpointHandle`] for which endpoint it is.
+    CompactMessage {
+        /// The handle of the endpoint which sent a message.
+        handle: EndpointHandle,
+
+        /// Whether the message was received from wire, or put on wire by a controller.
+        #[serde(default)]
+        direction: Direction,
+
+        /// The message contents.
+        message: SerialMessageBytes,
+    },
+}
+
+/// Why no more messages arrive from an observed endpoint.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, JsonSchema)]
+pub enum CloseReason {
+    /// The endpoint went away, e.g. a weakly observed transient mock was removed
+    /// or the device of a serial port disappeared.
+    Unavailable,
+
+    /// The observation received as many messages as its limit allowed.
+    /// See the `limit` of [`Action::Observe`].
+    LimitReached,
+
+    /// The connection to the server ended, e.g. because the server shut down.
+    /// Never sent by the server, clients tell this when their connection ends.
+    Disconnected,
+}
+
+impl Display for CloseReason {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CloseReason::Unavailable => write!(f, "endpoint unavailable"),
+            CloseReason::LimitReached => write!(f, "observe limit reached"),
+            CloseReason::Disconnected => write!(f, "disconnected from server"),
+        }
+    }
+}
+
+/// Responses the server will send to connected users.
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, JsonSchema)]
+pub enum Response {
+    /// A synchronous response in the sense that it's sent directly after a user
+    /// request.
+    Sync(Sync),
+
+    /// An async message- the server might send this at any time and not in response to any
+    /// particular request.
+    Async(Async),
+}
+
+impl Response {
+    pub(crate) fn write_ok(correlation_id: Option<String>) -> Self {
+        Self::Sync(Sync::WriteOk { correlation_id })
+    }
+
+    /// An example of a message write OK response.
+    pub fn example_write_ok() -> Self {
+        Self::write_ok(None)
+    }
+
+    /// An example of a message write OK response to a write with a correlation id.
+    pub fn example_write_ok_correlated() -> Self {
+        Self::write_ok(Some("cmd-42".into()))
+    }
+
+    pub(crate) fn observing_events() -> Self {
+        Self::Sync(Sync::ObservingEvents)
+    }
+
+    /// An example of an observe events OK response.
+    pub fn example_observing_events() -> Self {
+        Self::observing_events()
+    }
+
+    pub(crate) fn message(
+        endpoint: LabelledEndpointId,
+        direction: Direction,
+        message: SerialMessageBytes,
+    ) -> Self {
+        Self::Async(Async::Message {
+            endpoint,
+            direction,
+            message,
+        })
+    }
+
+    pub(crate) fn compact_message(
+        handle: EndpointHandle,
+        direction: Direction,
+        message: SerialMessageBytes,
+    ) -> Self {
+        Self::Async(Async::CompactMessage {
+            handle,
+            direction,
+            message,
+        })
+    }
+
+    /// An example of a new message response referring to the endpoint by handle.
+    pub fn example_compact_message() -> Self {
+        Self::compact_message(
+            EndpointHandle(0),
+            Direction::FromWire,
+            "Hello World!".into(),
+        )
+    }
+
+    pub(crate) fn endpoint_handle(handle: EndpointHandle, endpoint: LabelledEndpointId) -> Self {
+        Self::Async(Async::EndpointHandle((handle, endpoint)))
+    }
+
+    /// An example of an endpoint being assigned a handle.
+    pub fn example_endpoint_handle() -> Self {
+        Self::endpoint_handle(
+            EndpointHandle(0),
+            LabelledEndpointId::new(&EndpointId::tty("COM0")),
+        )
+    }
+
+    /// An example of a new message response. These are async and might appear at any time after a user has
+    /// started observing the related endpoint.
+    pub fn example_new_message() -> Self {
+        Self::message(
+            LabelledEndpointId::new(&EndpointId::tty("COM0")),
+            Direction::FromWire,
+            "Hello World!".into(),
+        )
+    }
+
+    pub(crate) fn repeated_message(
+        endpoint: LabelledEndpointId,
+        direction: Direction,
+        message: SerialMessageBytes,
+        repeats: usize,
+    ) -> Self {
+        Self::Async(Async::RepeatedMessage {
+            endpoint,
+            direction,
+            message,
+            repeats,
+        })
+    }
+
+    /// An example of a message seen several times in a row by a deduplicating observer.
+    pub fn example_repeated_message() -> Self {
+        Self::repeated_message(
+            LabelledEndpointId::new(&EndpointId::tty("COM0")),
+            Direction::FromWire,
+            "Battery: 87%".into(),
+            12,
+        )
+    }
+
+    pub(crate) fn observing(id: LabelledEndpointId) -> Self {
+        Self::Sync(Sync::Observing(id))
     }
 
     /// An example of a new message response.
@@ -264,6 +1732,45 @@ impl Response {
         ))
     }
 
+    pub(crate) fn observing_composite(
+        composite: LabelledEndpointId,
+        ports: Vec<LabelledEndpointId>,
+    ) -> Self {
+        Self::Sync(Sync::ObservingComposite((composite, ports)))
+    }
+
+    /// An example of observing the ports of a composite endpoint.
+    pub fn example_observing_composite() -> Self {
+        Self::observing_composite(
+            LabelledEndpointId::new(&EndpointId::composite("nrf5340-dk")),
+            vec![
+                LabelledEndpointId::new(&EndpointId::tty("/dev/ttyACM0")),
+                LabelledEndpointId::new(&EndpointId::tty("/dev/ttyACM1")),
+            ],
+        )
+    }
+
+    pub(crate) fn observing_any(labels: Labels, endpoints: Vec<LabelledEndpointId>) -> Self {
+        Self::Sync(Sync::ObservingAny((labels, endpoints)))
+    }
+
+    /// An example of observing every endpoint matching labels.
+    pub fn example_observing_any() -> Self {
+        Self::observing_any(
+            Labels::from_iter(["blue-device"]),
+            vec![
+                LabelledEndpointId::new_with_labels(
+                    &EndpointId::tty("/dev/ttyACM0"),
+                    &["blue-device"],
+                ),
+                LabelledEndpointId::new_with_labels(
+                    &EndpointId::tty("/dev/ttyACM1"),
+                    &["blue-device"],
+                ),
+            ],
+        )
+    }
+
     pub(crate) fn control_granted(granted: Vec<LabelledEndpointId>) -> Self {
         Self::Sync(Sync::ControlGranted(granted))
     }
@@ -279,32 +1786,502 @@ impl Response {
         ])
     }
 
-    pub(crate) fn control_queue(queued_on: Vec<LabelledEndpointId>) -> Self {
-        Self::Sync(Sync::ControlQueue(queued_on))
+    pub(crate) fn control_queue(queued_on: Vec<QueuedOn>, not_queued_on: Vec<NotQueuedOn>) -> Self {
+        Self::Sync(Sync::ControlQueue {
+            queued_on,
+            not_queued_on,
+        })
     }
 
     /// An example response when the user gets queued instead of being granted access.
     pub fn example_control_queue() -> Self {
-        Self::control_queue(vec![
-            LabelledEndpointId::new(&EndpointId::tty("COM0")),
-            LabelledEndpointId::new_with_labels(
-                &EndpointId::tty("/dev/ttyACMx"),
-                &["device-type-1", "server-room-foo"],
-            ),
+        Self::control_queue(
+            vec![
+                QueuedOn {
+                    endpoint: LabelledEndpointId::new(&EndpointId::tty("COM0")),
+                    controller: Some("127.0.0.1:51234".into()),
+                    position: 0,
+                },
+                QueuedOn {
+                    endpoint: LabelledEndpointId::new_with_labels(
+                        &EndpointId::tty("/dev/ttyACMx"),
+                        &["device-type-1", "server-room-foo"],
+                    ),
+                    controller: Some("127.0.0.1:51235".into()),
+                    position: 2,
+                },
+            ],
+            vec![NotQueuedOn {
+                endpoint: LabelledEndpointId::new_with_labels(
+                    &EndpointId::tty("/dev/ttyACMy"),
+                    &["device-type-1", "server-room-foo"],
+                ),
+                error: error::Error::SuperfluousRequest(format!(
+                    "User {} is already queued or already has control over {}.",
+                    "127.0.0.1:51236", "/dev/ttyACMy"
+                )),
+            }],
+        )
+    }
+
+    pub(crate) fn handshake() -> Self {
+        Self::Async(Async::Handshake(ServerInfo::current()))
+    }
+
+    /// An example of the handshake sent when connecting.
+    pub fn example_handshake() -> Self {
+        Self::handshake()
+    }
+
+    pub(crate) fn banner(banner: String) -> Self {
+        Self::Async(Async::Banner(banner))
+    }
+
+    /// An example of the banner sent after the handshake.
+    pub fn example_banner() -> Self {
+        Self::banner("Maintenance at 18:00".into())
+    }
+
+    pub(crate) fn notice(endpoint: LabelledEndpointId, text: String) -> Self {
+        Self::Async(Async::Notice { endpoint, text })
+    }
+
+    /// An example of a notice about an observed endpoint.
+    pub fn example_notice() -> Self {
+        Self::notice(
+            LabelledEndpointId::new(&EndpointId::tty("/dev/ttyACM0")),
+            "Board will reset in 5 minutes".into(),
+        )
+    }
+
+    pub(crate) fn messages_skipped(endpoint: LabelledEndpointId, skipped: u64) -> Self {
+        Self::Async(Async::MessagesSkipped { endpoint, skipped })
+    }
+
+    /// An example of messages of an observed endpoint having been skipped.
+    pub fn example_messages_skipped() -> Self {
+        Self::messages_skipped(
+            LabelledEndpointId::new(&EndpointId::tty("/dev/ttyACM0")),
+            42,
+        )
+    }
+
+    pub(crate) fn notice_sent(id: LabelledEndpointId, observers: usize) -> Self {
+        Self::Sync(Sync::NoticeSent((id, observers)))
+    }
+
+    /// An example of a notice having been sent.
+    pub fn example_notice_sent() -> Self {
+        Self::notice_sent(LabelledEndpointId::new(&EndpointId::tty("/dev/ttyACM0")), 2)
+    }
+
+    pub(crate) fn endpoint_enabled(endpoint: LabelledEndpointId, enabled: bool) -> Self {
+        Self::Sync(Sync::EndpointEnabled { endpoint, enabled })
+    }
+
+    /// An example of an endpoint having been disabled.
+    pub fn example_endpoint_enabled() -> Self {
+        Self::endpoint_enabled(
+            LabelledEndpointId::new(&EndpointId::tty("/dev/ttyACM0")),
+            false,
+        )
+    }
+
+    pub(crate) fn control_revoked(endpoints: Vec<LabelledEndpointId>) -> Self {
+        Self::Async(Async::ControlRevoked(endpoints))
+    }
+
+    /// An example of losing control of an endpoint an admin disabled.
+    pub fn example_control_revoked() -> Self {
+        Self::control_revoked(vec![LabelledEndpointId::new(&EndpointId::tty(
+            "/dev/ttyACM0",
+        ))])
+    }
+
+    pub(crate) fn pong(id: LabelledEndpointId, round_trip: std::time::Duration) -> Self {
+        Self::Sync(Sync::Pong((id, round_trip)))
+    }
+
+    /// An example of an endpoint having answered a ping.
+    pub fn example_pong() -> Self {
+        Self::pong(
+            LabelledEndpointId::new(&EndpointId::tty("/dev/ttyACM0")),
+            std::time::Duration::from_millis(12),
+        )
+    }
+
+    pub(crate) fn endpoint_closed(id: LabelledEndpointId, reason: CloseReason) -> Self {
+        Self::Async(Async::EndpointClosed((id, reason)))
+    }
+
+    /// An example of a weakly observed endpoint being removed.
+    pub fn example_endpoint_closed() -> Self {
+        Self::endpoint_closed(
+            LabelledEndpointId::new(&EndpointId::mock("some-mock")),
+            CloseReason::Unavailable,
+        )
+    }
+
+    pub(crate) fn reconfigured(id: LabelledEndpointId) -> Self {
+        Self::Sync(Sync::Reconfigured(id))
+    }
+
+    /// An example of a reconfigure OK response.
+    pub fn example_reconfigured() -> Self {
+        Self::reconfigured(LabelledEndpointId::new(&EndpointId::tty("/dev/ttyACM0")))
+    }
+
+    pub(crate) fn flushed(id: LabelledEndpointId) -> Self {
+        Self::Sync(Sync::Flushed(id))
+    }
+
+    /// An example of a flush OK response.
+    pub fn example_flushed() -> Self {
+        Self::flushed(LabelledEndpointId::new(&EndpointId::tty("/dev/ttyACM0")))
+    }
+
+    pub(crate) fn macro_done(id: LabelledEndpointId, name: String) -> Self {
+        Self::Sync(Sync::MacroDone((id, name)))
+    }
+
+    /// An example of a macro done response.
+    pub fn example_macro_done() -> Self {
+        Self::macro_done(
+            LabelledEndpointId::new(&EndpointId::tty("/dev/ttyACM0")),
+            "init".into(),
+        )
+    }
+
+    pub(crate) fn skipping_on_control_macros(skip: bool) -> Self {
+        Self::Sync(Sync::SkippingOnControlMacros(skip))
+    }
+
+    /// An example of a response to opting out of on control macros.
+    pub fn example_skipping_on_control_macros() -> Self {
+        Self::skipping_on_control_macros(true)
+    }
+
+    pub(crate) fn compacting_endpoint_ids(compact: bool) -> Self {
+        Self::Sync(Sync::CompactingEndpointIds(compact))
+    }
+
+    /// An example of a response to opting into compact endpoint ids.
+    pub fn example_compacting_endpoint_ids() -> Self {
+        Self::compacting_endpoint_ids(true)
+    }
+
+    pub(crate) fn endpoints(endpoints: Vec<ListedEndpoint>) -> Self {
+        Self::Sync(Sync::Endpoints(endpoints))
+    }
+
+    pub(crate) fn labels(labels: Vec<ListedLabel>) -> Self {
+        Self::Sync(Sync::Labels(labels))
+    }
+
+    /// An example of a list labels response.
+    pub fn example_labels() -> Self {
+        Self::labels(vec![
+            ListedLabel {
+                label: Label::new("nrf52"),
+                endpoints: 3,
+            },
+            ListedLabel {
+                label: Label::new("lab=oslo"),
+                endpoints: 1,
+            },
+        ])
+    }
+
+    pub(crate) fn control_transferred(id: LabelledEndpointId, to_user: String) -> Self {
+        Self::Sync(Sync::ControlTransferred((id, to_user)))
+    }
+
+    pub(crate) fn released(ids: Vec<LabelledEndpointId>) -> Self {
+        Self::Sync(Sync::Released(ids))
+    }
+
+    /// An example of a released response.
+    pub fn example_released() -> Self {
+        Self::released(vec![LabelledEndpointId::new(&EndpointId::tty(
+            "/dev/ttyACM0",
+        ))])
+    }
+
+    pub(crate) fn queue_cancelled(ids: Vec<LabelledEndpointId>) -> Self {
+        Self::Sync(Sync::QueueCancelled(ids))
+    }
+
+    /// An example of a response to no longer waiting for control.
+    pub fn example_queue_cancelled() -> Self {
+        Self::queue_cancelled(vec![LabelledEndpointId::new_with_labels(
+            &EndpointId::tty("/dev/ttyACM0"),
+            &["nrf52"],
+        )])
+    }
+
+    pub(crate) fn recent_messages(id: LabelledEndpointId, messages: Vec<SerialMessage>) -> Self {
+        Self::Sync(Sync::RecentMessages((id, messages)))
+    }
+
+    /// An example of a response with the recent messages of an endpoint.
+    pub fn example_recent_messages() -> Self {
+        Self::recent_messages(
+            LabelledEndpointId::new(&EndpointId::tty("/dev/ttyACM0")),
+            vec!["Booting...".into(), "Ready".into()],
+        )
+    }
+
+    pub(crate) fn server_time(now: chrono::DateTime<chrono::Utc>) -> Self {
+        Self::Sync(Sync::ServerTime(now))
+    }
+
+    /// An example of a response with the server's time.
+    pub fn example_server_time() -> Self {
+        Self::server_time(chrono::Utc::now())
+    }
+
+    pub(crate) fn connection(info: ConnectionInfo) -> Self {
+        Self::Sync(Sync::Connection(info))
+    }
+
+    /// An example of a response with the server's view of the connection.
+    pub fn example_connection() -> Self {
+        Self::connection(ConnectionInfo {
+            session: 7,
+            user: User::new("lab-runner"),
+            remote: Some("127.0.0.1:51234".into()),
+            connected_at: chrono::Utc::now(),
+            observing: vec![LabelledEndpointId::new(&EndpointId::tty("/dev/ttyACM0"))],
+            controlling: vec![LabelledEndpointId::new(&EndpointId::tty("/dev/ttyACM0"))],
+        })
+    }
+
+    pub(crate) fn write_allowed(id: LabelledEndpointId) -> Self {
+        Self::Sync(Sync::WriteAllowed(id))
+    }
+
+    /// An example of a write allowed response.
+    pub fn example_write_allowed() -> Self {
+        Self::write_allowed(LabelledEndpointId::new(&EndpointId::tty("/dev/ttyACM0")))
+    }
+
+    pub(crate) fn observing_endpoint_events(id: LabelledEndpointId) -> Self {
+        Self::Sync(Sync::ObservingEndpointEvents(id))
+    }
+
+    pub(crate) fn observing_messages(endpoints: Vec<LabelledEndpointId>) -> Self {
+        Self::Sync(Sync::ObservingMessages(endpoints))
+    }
+
+    pub(crate) fn observation_deferred(id: EndpointId) -> Self {
+        Self::Sync(Sync::ObservationDeferred(id))
+    }
+
+    /// An example of an observation waiting for its endpoint to appear.
+    pub fn example_observation_deferred() -> Self {
+        Self::observation_deferred(EndpointId::tty("/dev/ttyUSB0"))
+    }
+
+    pub(crate) fn control_deferred(labels: Labels) -> Self {
+        Self::Sync(Sync::ControlDeferred(labels))
+    }
+
+    /// An example of control waiting for a matching endpoint to appear.
+    pub fn example_control_deferred() -> Self {
+        Self::control_deferred(Labels::from_iter(["blue-device"]))
+    }
+
+    pub(crate) fn unobserved(id: LabelledEndpointId) -> Self {
+        Self::Sync(Sync::Unobserved(id))
+    }
+
+    /// An example of a response to no longer observing an endpoint.
+    pub fn example_unobserved() -> Self {
+        Self::unobserved(LabelledEndpointId::new(&EndpointId::tty("/dev/ttyACM0")))
+    }
+
+    /// An example of an observe endpoint events OK response.
+    pub fn example_observing_endpoint_events() -> Self {
+        Self::observing_endpoint_events(LabelledEndpointId::new(&EndpointId::tty("/dev/ttyACM0")))
+    }
+
+    /// An example of an observe messages OK response.
+    pub fn example_observing_messages() -> Self {
+        Self::observing_messages(vec![
+            LabelledEndpointId::new(&EndpointId::tty("/dev/ttyACM0")),
+            LabelledEndpointId::new(&EndpointId::tty("/dev/ttyACM1")),
+        ])
+    }
+
+    /// An example of an event, as seen when observing events.
+    pub fn example_event() -> Self {
+        Self::Async(Async::Event(events::TimestampedEvent::new_user_event(
+            &User::new("127.0.0.1:51234"),
+            events::user::Event::Connected,
+        )))
+    }
+
+    pub(crate) fn endpoint_event(event: events::endpoint::TimestampedEndpointEvent) -> Self {
+        Self::Async(Async::EndpointEvent(event))
+    }
+
+    /// An example of an endpoint going idle.
+    pub fn example_endpoint_event() -> Self {
+        Self::endpoint_event(events::endpoint::TimestampedEndpointEvent {
+            endpoint: LabelledEndpointId::new(&EndpointId::tty("/dev/ttyACM0")),
+            event: events::endpoint::Event::Idle,
+            timestamp: chrono::Utc::now(),
+        })
+    }
+
+    pub(crate) fn traffic(message: events::message::TimestampedMessage) -> Self {
+        Self::Async(Async::Traffic(message))
+    }
+
+    /// An example of a message received from wire, as seen when observing messages.
+    pub fn example_traffic() -> Self {
+        Self::traffic(events::message::TimestampedMessage {
+            endpoint: LabelledEndpointId::new(&EndpointId::tty("/dev/ttyACM0")),
+            direction: Direction::FromWire,
+            message: b"Booting...".to_vec(),
+            timestamp: chrono::Utc::now(),
+        })
+    }
+
+    /// An example of a control transferred response.
+    pub fn example_control_transferred() -> Self {
+        Self::control_transferred(
+            LabelledEndpointId::new(&EndpointId::tty("/dev/ttyACM0")),
+            "127.0.0.1:51234".into(),
+        )
+    }
+
+    /// An example of a list endpoints response.
+    pub fn example_endpoints() -> Self {
+        Self::endpoints(vec![
+            ListedEndpoint {
+                id: LabelledEndpointId::new_with_labels(
+                    &EndpointId::tty("/dev/ttyACM0"),
+                    &["nrf52"],
+                ),
+                description: Some("Lab bench 1, nRF52840 DK".into()),
+                prompt: Some("uart:~$ ".into()),
+                settings: Some(PortSettings {
+                    baud: 115_200,
+                    flow_control: serialport::FlowControl::None,
+                }),
+                device: None,
+                opened_at: chrono::Utc::now(),
+                disabled: false,
+                bytes: ByteCounts::default(),
+            },
+            ListedEndpoint {
+                id: LabelledEndpointId::new(&EndpointId::mock("some-mock")),
+                description: None,
+                prompt: None,
+                settings: None,
+                device: None,
+                opened_at: chrono::Utc::now(),
+                disabled: false,
+                bytes: ByteCounts::default(),
+            },
         ])
     }
+
+    /// An example of every kind of response, see the `example_*` constructors.
+    pub fn examples() -> Vec<Self> {
+        vec![
+            Self::example_write_ok(),
+            Self::example_write_ok_correlated(),
+            Self::example_write_allowed(),
+            Self::example_observing_events(),
+            Self::example_new_message(),
+            Self::example_repeated_message(),
+            Self::example_endpoint_handle(),
+            Self::example_compact_message(),
+            Self::example_event(),
+            Self::example_observing(),
+            Self::example_observing_composite(),
+            Self::example_observing_any(),
+            Self::example_control_granted(),
+            Self::example_control_queue(),
+            Self::example_handshake(),
+            Self::example_banner(),
+            Self::example_notice(),
+            Self::example_messages_skipped(),
+            Self::example_notice_sent(),
+            Self::example_endpoint_enabled(),
+            Self::example_control_revoked(),
+            Self::example_pong(),
+            Self::example_endpoint_closed(),
+            Self::example_reconfigured(),
+            Self::example_flushed(),
+            Self::example_macro_done(),
+            Self::example_skipping_on_control_macros(),
+            Self::example_compacting_endpoint_ids(),
+            Self::example_labels(),
+            Self::example_released(),
+            Self::example_queue_cancelled(),
+            Self::example_recent_messages(),
+            Self::example_server_time(),
+            Self::example_connection(),
+            Self::example_observation_deferred(),
+            Self::example_control_deferred(),
+            Self::example_unobserved(),
+            Self::example_observing_endpoint_events(),
+            Self::example_endpoint_event(),
+            Self::example_observing_messages(),
+            Self::example_traffic(),
+            Self::example_control_transferred(),
+            Self::example_endpoints(),
+        ]
+    }
 }
 
 impl Display for Response {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
-            Response::Sync(Sync::WriteOk) => write!(f, "Write ok"),
+            Response::Sync(Sync::WriteOk {
+                correlation_id: None,
+            }) => write!(f, "Write ok"),
+            Response::Sync(Sync::WriteOk {
+                correlation_id: Some(id),
+            }) => write!(f, "Write ok ({id})"),
             Response::Sync(Sync::ObservingEvents) => write!(f, "User events subscription ok"),
             Response::Sync(Sync::Observing(id)) => write!(f, "Observing {id}"),
-            Response::Sync(Sync::ControlQueue(ids)) => {
+            Response::Sync(Sync::ObservingComposite((composite, ports))) => {
+                write!(f, "Observing {composite} through ")?;
+                for port in ports {
+                    write!(f, "{port} ")?;
+                }
+                Ok(())
+            }
+            Response::Sync(Sync::ObservingAny((labels, endpoints))) => {
+                write!(f, "Observing {labels} on ")?;
+                for endpoint in endpoints {
+                    write!(f, "{endpoint} ")?;
+                }
+                Ok(())
+            }
+            Response::Sync(Sync::ControlQueue {
+                queued_on,
+                not_queued_on,
+            }) => {
                 write!(f, "In control queue for ")?;
-                for id in ids {
-                    write!(f, "{id}")?;
+                for QueuedOn {
+                    endpoint,
+                    controller,
+                    position,
+                } in queued_on
+                {
+                    write!(f, "{endpoint} (#{position}")?;
+                    if let Some(controller) = controller {
+                        write!(f, ", held by {controller}")?;
+                    }
+                    write!(f, ")")?;
+                }
+                for NotQueuedOn { endpoint, error } in not_queued_on {
+                    write!(f, ", not for {endpoint} ({error})")?;
                 }
                 Ok(())
             }
@@ -315,15 +2292,179 @@ impl Display for Response {
                 }
                 Ok(())
             }
-            Response::Async(Async::Message { endpoint, message }) => write!(
+            Response::Sync(Sync::Reconfigured(id)) => write!(f, "Reconfigured {id}"),
+            Response::Sync(Sync::Flushed(id)) => write!(f, "Flushed {id}"),
+            Response::Sync(Sync::MacroDone((id, name))) => write!(f, "Ran macro {name} on {id}"),
+            Response::Sync(Sync::SkippingOnControlMacros(skip)) => {
+                write!(f, "Skipping on control macros: {skip}")
+            }
+            Response::Sync(Sync::CompactingEndpointIds(compact)) => {
+                write!(f, "Compacting endpoint ids: {compact}")
+            }
+            Response::Sync(Sync::ControlTransferred((id, to_user))) => {
+                write!(f, "Control of {id} transferred to {to_user}")
+            }
+            Response::Sync(Sync::Released(ids)) => {
+                write!(f, "Released ")?;
+                for id in ids {
+                    write!(f, "{id}")?;
+                }
+                Ok(())
+            }
+            Response::Sync(Sync::QueueCancelled(ids)) => {
+                write!(f, "No longer queued for ")?;
+                for id in ids {
+                    write!(f, "{id}")?;
+                }
+                Ok(())
+            }
+            Response::Sync(Sync::RecentMessages((id, messages))) => {
+                write!(f, "{} recent messages of {id}", messages.len())
+            }
+            Response::Sync(Sync::ServerTime(now)) => write!(f, "Server time: {now}"),
+            Response::Sync(Sync::Connection(info)) => write!(
+                f,
+                "Session {} of {}, observing {} and controlling {} endpoint(s)",
+                info.session,
+                info.user,
+                info.observing.len(),
+                info.controlling.len()
+            ),
+            Response::Sync(Sync::WriteAllowed(id)) => write!(f, "Write allowed for {id}"),
+            Response::Sync(Sync::ObservingEndpointEvents(id)) => {
+                write!(f, "Observing events of {id}")
+            }
+            Response::Sync(Sync::ObservingMessages(endpoints)) => {
+                write!(f, "Observing messages of ")?;
+                for endpoint in endpoints {
+                    write!(f, "{endpoint} ")?;
+                }
+                Ok(())
+            }
+            Response::Sync(Sync::Unobserved(id)) => write!(f, "No longer observing {id}"),
+            Response::Sync(Sync::NoticeSent((id, observers))) => {
+                write!(f, "Notice sent to {observers} observer(s) of {id}")
+            }
+            Response::Sync(Sync::EndpointEnabled { endpoint, enabled }) => {
+                if *enabled {
+                    write!(f, "Enabled {endpoint}")
+                } else {
+                    write!(f, "Disabled {endpoint}")
+                }
+            }
+            Response::Sync(Sync::Pong((id, round_trip))) => {
+                write!(f, "Pong from {id} after {round_trip:?}")
+            }
+            Response::Sync(Sync::ObservationDeferred(id)) => {
+                write!(f, "Observing {id} once it appears")
+            }
+            Response::Sync(Sync::ControlDeferred(labels)) => {
+                write!(f, "Controlling any of {labels} once one appears")
+            }
+            Response::Sync(Sync::Endpoints(endpoints)) => {
+                write!(f, "Endpoints:")?;
+                for endpoint in endpoints {
+                    write!(f, " {}", endpoint.id)?;
+                }
+                Ok(())
+            }
+            Response::Sync(Sync::Labels(labels)) => {
+                write!(f, "Labels:")?;
+                for ListedLabel { label, endpoints } in labels {
+                    write!(f, " {label} ({endpoints})")?;
+                }
+                Ok(())
+            }
+            Response::Async(Async::Message {
+                endpoint,
+                direction,
+                message,
+            }) => write!(
                 f,
-                "Message from {endpoint}: `[{:?}..]`",
+                "Message {direction} {endpoint}: `[{:?}..]`",
                 &message[..message.len().min(32)]
             ),
+            Response::Async(Async::RepeatedMessage {
+                endpoint,
+                direction,
+                message,
+                repeats,
+            }) => write!(
+                f,
+                "Message {direction} {endpoint} (x{repeats}): `[{:?}..]`",
+                &message[..message.len().min(32)]
+            ),
+            Response::Async(Async::CompactMessage {
+                handle,
+                direction,
+                message,
+            }) => write!(
+                f,
+                "Message {direction} {handle}: `[{:?}..]`",
+                &message[..message.len().min(32)]
+            ),
+            Response::Async(Async::EndpointHandle((handle, endpoint))) => {
+                write!(f, "Endpoint {endpoint} is {handle}")
+            }
             Response::Async(Async::Event(event)) => write!(f, "UserEvent: `[{event}..]`",),
+            Response::Async(Async::EndpointEvent(event)) => write!(f, "EndpointEvent: {event}"),
+            Response::Async(Async::Traffic(message)) => write!(f, "Traffic: {message}"),
+            Response::Async(Async::EndpointClosed((id, reason))) => {
+                write!(f, "Endpoint {id} closed: {reason}")
+            }
+            Response::Async(Async::Handshake(info)) => write!(
+                f,
+                "Server version {} (protocol {}), capabilities: {:?}",
+                info.version, info.protocol, info.capabilities
+            ),
+            Response::Async(Async::Banner(banner)) => write!(f, "Banner: {banner}"),
+            Response::Async(Async::Notice { endpoint, text }) => {
+                write!(f, "Notice about {endpoint}: {text}")
+            }
+            Response::Async(Async::MessagesSkipped { endpoint, skipped }) => {
+                write!(f, "Skipped {skipped} message(s) of {endpoint}")
+            }
+            Response::Async(Async::ControlRevoked(endpoints)) => {
+                write!(f, "Control revoked of ")?;
+                for endpoint in endpoints {
+                    write!(f, "{endpoint} ")?;
+                }
+                Ok(())
+            }
         }
     }
 }
 
 /// A fallible response.
 pub type ResponseResult = Result<Response, error::Error>;
+
+/// Turn a response into the compact binary encoding (MessagePack).
+pub fn serialize_response_binary(response: &ResponseResult) -> Vec<u8> {
+    rmp_serde::to_vec_named(response).expect("Should serialize well")
+}
+
+/// Read a response from the compact binary encoding (MessagePack).
+pub fn deserialize_response_binary(bytes: &[u8]) -> Result<ResponseResult, error::Error> {
+    rmp_serde::from_slice(bytes).map_err(|e| error::Error::BadBinary(e.to_string()))
+}
+
+/// The JSON schema of what is sent over the websocket:
+/// Either an [`Action`] from a client, or a [`ResponseResult`] from the server.
+///
+/// Lets clients in other languages validate or generate their wire types.
+pub fn schema() -> RootSchema {
+    let mut generator = SchemaGenerator::default();
+
+    let mut schema = SchemaObject::default();
+    schema.metadata().title = Some("Serial Keel wire types".into());
+    schema.subschemas().one_of = Some(vec![
+        generator.subschema_for::<Action>(),
+        generator.subschema_for::<ResponseResult>(),
+    ]);
+
+    RootSchema {
+        meta_schema: generator.settings().meta_schema.clone(),
+        definitions: generator.take_definitions(),
+        schema,
+    }
+}
@@ -9,6 +9,7 @@ use crate::{
     config::Config,
     endpoint::{EndpointId, LabelledEndpointId},
     error,
+    serial::Direction,
 };
 
 /// The command line interface for serial keel.
@@ -32,17 +33,34 @@ pub struct Cli {
     #[arg(long, default_value = "logs")]
     pub file_dir: PathBuf,
 
+    /// Do not open any serial ports, even if the configuration file lists some.
+    /// Only mock endpoints are set up.
+    #[arg(long)]
+    pub no_serial: bool,
+
     /// Subcommands
     #[command(subcommand)]
     pub command: Option<Commands>,
 }
 
+impl Cli {
+    /// Let the command line override the configuration.
+    pub fn apply_overrides(&self, config: &mut Config) {
+        if self.no_serial {
+            config.remove_serial_ports();
+        }
+    }
+}
+
 /// Commands available in the command line interface.
 #[derive(Subcommand)]
 pub enum Commands {
     /// Examples for user convenience.
     #[clap(subcommand)]
     Examples(Examples),
+
+    /// Print the JSON schema of the requests and responses sent over the websocket.
+    Schema,
 }
 
 /// Helpful examples for users.
@@ -62,6 +80,9 @@ pub enum Examples {
     /// Examples relating to server-to-user responses.
     #[clap(subcommand)]
     Response(Response),
+
+    /// Show every example request, response and error as JSON.
+    All,
 }
 
 /// Examples of requests a user may send to the server.
@@ -76,20 +97,119 @@ pub enum Request {
     /// Show an example JSON request of controlling any endpoint matching the provided label(s).
     ControlAny,
 
+    /// Show an example JSON request of controlling any matching endpoint, waiting for one to appear.
+    ControlAnyWhenAvailable,
+
     /// Show an example JSON request of observing a specific TTY endpoint.
     ObserveTty,
 
     /// Show an example JSON request of observing a specific mock endpoint.
     ObserveMock,
 
+    /// Show an example JSON request of observing each port of a composite endpoint.
+    ObserveComposite,
+
+    /// Show an example JSON request of observing both what a TTY receives and what is written to it.
+    ObserveIncludingWrites,
+
+    /// Show an example JSON request of observing only the next few messages of a TTY.
+    ObserveLimited,
+
+    /// Show an example JSON request of observing a TTY which may not be plugged in yet.
+    ObserveDeferred,
+
+    /// Show an example JSON request of observing a TTY, starting with what it recently received.
+    ObserveReplayed,
+
+    /// Show an example JSON request of observing a TTY, starting with what it received since some time.
+    ObserveSince,
+
+    /// Show an example JSON request of observing a TTY with identical consecutive messages collapsed.
+    ObserveDeduplicated,
+
     /// Show an example JSON request of writing a message to an endpoint.
     WriteMessage,
 
     /// Show an example JSON request of writing bytes to an endpoint.
     WriteMessageBytes,
 
+    /// Show an example JSON request of writing a message tagged with a correlation id.
+    WriteMessageCorrelated,
+
+    /// Show an example JSON request of checking whether an endpoint may be written to.
+    CanWrite,
+
     /// Show an example JSON request of observing all events from all sources.
     ObserveEvents,
+
+    /// Show an example JSON request of observing the events of a single endpoint.
+    ObserveEndpointEvents,
+
+    /// Show an example JSON request of observing every message of some endpoints.
+    ObserveMessages,
+
+    /// Show an example JSON request of changing the baud rate of a controlled TTY endpoint.
+    Reconfigure,
+
+    /// Show an example JSON request of discarding what a controlled endpoint has buffered.
+    Flush,
+
+    /// Show an example JSON request of running a configured macro on a controlled endpoint.
+    RunMacro,
+
+    /// Show an example JSON request of opting out of macros run when gaining control.
+    SkipOnControlMacros,
+
+    /// Show an example JSON request of opting into endpoints being referred to by handles.
+    CompactEndpointIds,
+
+    /// Show an example JSON request of listing the endpoints a server knows of.
+    ListEndpoints,
+
+    /// Show an example JSON request of listing the labels of the endpoints a server knows of.
+    ListLabels,
+
+    /// Show an example JSON request of observing a mock endpoint without keeping it alive.
+    ObserveWeak,
+
+    /// Show an example JSON request of observing a TTY while keeping others from observing it.
+    ObserveExclusive,
+
+    /// Show an example JSON request of observing every endpoint matching labels.
+    ObserveAny,
+
+    /// Show an example JSON request of no longer observing an endpoint.
+    Unobserve,
+
+    /// Show an example JSON request of handing a controlled endpoint to another user.
+    TransferControl,
+
+    /// Show an example JSON request of no longer controlling an endpoint.
+    Release,
+
+    /// Show an example JSON request of no longer waiting for control.
+    CancelQueue,
+
+    /// Show an example JSON request of getting the recent messages of an endpoint.
+    RecentMessages,
+
+    /// Show an example JSON request of an admin sending a notice to the observers of an endpoint.
+    Notice,
+
+    /// Show an example JSON request of an admin disabling an endpoint.
+    SetEndpointEnabled,
+
+    /// Show an example JSON request of checking a controlled endpoint's device is responsive.
+    PingEndpoint,
+
+    /// Show an example JSON request of asking for the server's time.
+    ServerTime,
+
+    /// Show an example JSON request of asking for the server's view of the connection.
+    WhoAmI,
+
+    /// Show an example JSON request of an action tagged with a correlation id.
+    Correlated,
 }
 
 /// Examples of responses a user might see from a server.
@@ -98,20 +218,128 @@ pub enum Response {
     /// Show an example JSON response of a confirmation that writing a message was ok.
     WriteOk,
 
+    /// Show an example JSON response of a confirmation that writing a message with a correlation id was ok.
+    WriteOkCorrelated,
+
+    /// Show an example JSON response of a confirmation that writing would be allowed.
+    WriteAllowed,
+
     /// Show an example JSON response of a confirmation that the user is now observing all events.
     ObservingEvents,
 
     /// Show an example JSON response of a new message received.
     NewMessage,
 
+    /// Show an example JSON response of a message received several times in a row.
+    RepeatedMessage,
+
+    /// Show an example JSON response of an observed endpoint being assigned a handle.
+    EndpointHandle,
+
+    /// Show an example JSON response of a new message received, referring to the endpoint by handle.
+    CompactMessage,
+
+    /// Show an example JSON response of a confirmation that the user now observes an endpoint's events.
+    ObservingEndpointEvents,
+
+    /// Show an example JSON response of an event of an observed endpoint.
+    EndpointEvent,
+
+    /// Show an example JSON response of a confirmation that the user now observes endpoints' messages.
+    ObservingMessages,
+
+    /// Show an example JSON response of a message of an endpoint whose messages are observed.
+    Traffic,
+
+    /// Show an example JSON response of an observation waiting for its endpoint to appear.
+    ObservationDeferred,
+
+    /// Show an example JSON response of control waiting for a matching endpoint to appear.
+    ControlDeferred,
+
+    /// Show an example JSON response to no longer observing an endpoint.
+    Unobserved,
+
     /// Show an example JSON response to successfully starting to observe an endpoint.
     Observing,
 
+    /// Show an example JSON response to successfully starting to observe the ports of a composite endpoint.
+    ObservingComposite,
+
+    /// Show an example JSON response to successfully starting to observe every endpoint matching labels.
+    ObservingAny,
+
     /// Show an example JSON response to granted control.
     ControlGranted,
 
     /// Show an example JSON response to being queued for control.
     ControlQueued,
+
+    /// Show an example JSON response to successfully reconfiguring an endpoint.
+    Reconfigured,
+
+    /// Show an example JSON response to successfully flushing an endpoint.
+    Flushed,
+
+    /// Show an example JSON response to a macro having run.
+    MacroDone,
+
+    /// Show an example JSON response to opting out of macros run when gaining control.
+    SkippingOnControlMacros,
+
+    /// Show an example JSON response to opting into endpoints being referred to by handles.
+    CompactingEndpointIds,
+
+    /// Show an example JSON response of the endpoints a server knows of.
+    Endpoints,
+
+    /// Show an example JSON response of the labels of the endpoints a server knows of.
+    Labels,
+
+    /// Show an example JSON response to handing a controlled endpoint to another user.
+    ControlTransferred,
+
+    /// Show an example JSON response to no longer controlling an endpoint.
+    Released,
+
+    /// Show an example JSON response to no longer waiting for control.
+    QueueCancelled,
+
+    /// Show an example JSON response with the recent messages of an endpoint.
+    RecentMessagesSnapshot,
+
+    /// Show an example JSON response with the server's time.
+    ServerTimeNow,
+
+    /// Show an example JSON response with the server's view of the connection.
+    Connection,
+
+    /// Show an example JSON response of a weakly observed endpoint being removed.
+    EndpointClosed,
+
+    /// Show an example JSON response of the handshake the server sends on connecting.
+    Handshake,
+
+    /// Show an example JSON response of the banner the server may send after the handshake.
+    Banner,
+
+    /// Show an example JSON response of a notice an admin sent about an observed endpoint.
+    NoticeReceived,
+
+    /// Show an example JSON response of messages skipped because the user read too slowly.
+    MessagesSkipped,
+
+    /// Show an example JSON response of a notice having been sent.
+    NoticeSent,
+
+    /// Show an example JSON response of an endpoint having been disabled.
+    EndpointEnabled,
+
+    /// Show an example JSON response of losing control of an endpoint an admin disabled.
+    ControlRevoked,
+
+    /// Show an example JSON response of an endpoint having answered a ping.
+    Pong,
 }
 
 /// Handle subcommands.
@@ -156,29 +384,62 @@ pub fn handle_command(command: Commands) {
         c("The user also wants access to any endpoint matching a few labels");
         req(Action::control_any(&["fast", "secure"]));
         c("Two endpoints matched, neither were available, therefore queued");
-        resp_ok(actions::Response::control_queue(vec![lei_1, lei_2.clone()]));
+        resp_ok(actions::Response::control_queue(
+            vec![
+                actions::QueuedOn {
+                    endpoint: lei_1,
+                    controller: Some("127.0.0.1:51234".into()),
+                    position: 0,
+                },
+                actions::QueuedOn {
+                    endpoint: lei_2.clone(),
+                    controller: Some("127.0.0.1:51235".into()),
+                    position: 1,
+                },
+            ],
+            vec![],
+        ));
         c("The user sits around and waits for another response");
         c("Some time passes.. Then one is available");
         resp_ok(actions::Response::control_granted(vec![lei_2]));
         c("");
         c("The user wants to know about messages received too, so they observe an endpoint");
-        req(Action::Observe(lei_0.clone().into()));
+        req(Action::observe(&lei_0.clone().into()));
         resp_ok(actions::Response::observing(lei_0.clone()));
         c("");
         c("Messages might now appear at any time on that endpoint");
         c("The server does not assume an encoding for the message, so the user should decode it if it's e.g. utf-8");
         resp_ok(actions::Response::message(
             lei_0.clone(),
+            Direction::FromWire,
             "Hello, world".into(),
         ));
         c("");
         c("Since the user controls a few endpoints, the user may write to those at any time");
-        req(Action::Write((lei_0.into(), "Hi there, endpoint!".into())));
-        resp_ok(actions::Response::write_ok());
+        req(Action::write(&lei_0.into(), "Hi there, endpoint!".into()));
+        resp_ok(actions::Response::write_ok(None));
         c("");
         c("The user leaves and the endpoints they controlled are then available for others");
     }
 
+    fn print_all() {
+        for action in Action::examples() {
+            println!("// Request: {action}");
+            print_request(action);
+        }
+
+        for response in actions::Response::examples() {
+            println!("// Response: {response}");
+            print_ok_response(response);
+        }
+
+        for error in error::Error::examples() {
+            println!("// Error: {error}");
+            let response: actions::ResponseResult = Err(error);
+            println!("{}", serde_json::to_string_pretty(&response).unwrap());
+        }
+    }
+
     fn print_request(req: impl Serialize) {
         println!("{}", serde_json::to_string_pretty(&req).unwrap());
     }
@@ -193,9 +454,16 @@ pub fn handle_command(command: Commands) {
     use Response::*;
 
     match command {
+        Commands::Schema => {
+            println!(
+                "{}",
+                serde_json::to_string_pretty(&actions::schema()).unwrap()
+            );
+        }
         Commands::Examples(example) => match example {
             Examples::Config => print_config(),
             Examples::Session => print_session(),
+            Examples::All => print_all(),
             Examples::Request(ControlMock) => {
                 print_request(Action::example_control_mock());
             }
@@ -205,39 +473,284 @@ pub fn handle_command(command: Commands) {
             Examples::Request(ControlAny) => {
                 print_request(Action::example_control_any());
             }
+            Examples::Request(ControlAnyWhenAvailable) => {
+                print_request(Action::example_control_any_when_available());
+            }
             Examples::Request(ObserveTty) => {
                 print_request(Action::example_observe_tty());
             }
             Examples::Request(ObserveMock) => {
                 print_request(Action::example_observe_mock());
             }
+            Examples::Request(ObserveComposite) => {
+                print_request(Action::example_observe_composite());
+            }
+            Examples::Request(ObserveIncludingWrites) => {
+                print_request(Action::example_observe_including_writes());
+            }
+            Examples::Request(ObserveLimited) => {
+                print_request(Action::example_observe_limited());
+            }
+            Examples::Request(ObserveDeferred) => {
+                print_request(Action::example_observe_deferred());
+            }
+            Examples::Request(ObserveReplayed) => {
+                print_request(Action::example_observe_replayed());
+            }
+            Examples::Request(ObserveSince) => {
+                print_request(Action::example_observe_since());
+            }
+            Examples::Request(ObserveDeduplicated) => {
+                print_request(Action::example_observe_deduplicated());
+            }
             Examples::Request(WriteMessage) => {
                 print_request(Action::example_write());
             }
             Examples::Request(WriteMessageBytes) => {
                 print_request(Action::example_write_bytes());
             }
+            Examples::Request(WriteMessageCorrelated) => {
+                print_request(Action::example_write_correlated());
+            }
+            Examples::Request(CanWrite) => {
+                print_request(Action::example_can_write());
+            }
             Examples::Request(ObserveEvents) => {
                 print_request(Action::example_observe_events());
             }
+            Examples::Request(ObserveEndpointEvents) => {
+                print_request(Action::example_observe_endpoint_events());
+            }
+            Examples::Request(ObserveMessages) => {
+                print_request(Action::example_observe_messages());
+            }
+            Examples::Request(Reconfigure) => {
+                print_request(Action::example_reconfigure());
+            }
+            Examples::Request(Flush) => {
+                print_request(Action::example_flush());
+            }
+            Examples::Request(RunMacro) => {
+                print_request(Action::example_run_macro());
+            }
+            Examples::Request(SkipOnControlMacros) => {
+                print_request(Action::example_skip_on_control_macros());
+            }
+            Examples::Request(CompactEndpointIds) => {
+                print_request(Action::example_compact_endpoint_ids());
+            }
+            Examples::Request(ListEndpoints) => {
+                print_request(Action::example_list_endpoints());
+            }
+            Examples::Request(ListLabels) => {
+                print_request(Action::example_list_labels());
+            }
+            Examples::Request(ObserveWeak) => {
+                print_request(Action::example_observe_weak());
+            }
+            Examples::Request(ObserveExclusive) => {
+                print_request(Action::example_observe_exclusive());
+            }
+            Examples::Request(ObserveAny) => {
+                print_request(Action::example_observe_any());
+            }
+            Examples::Request(Unobserve) => {
+                print_request(Action::example_unobserve());
+            }
+            Examples::Request(TransferControl) => {
+                print_request(Action::example_transfer_control());
+            }
+            Examples::Request(Release) => {
+                print_request(Action::example_release());
+            }
+            Examples::Request(CancelQueue) => {
+                print_request(Action::example_cancel_queue());
+            }
+            Examples::Request(RecentMessages) => {
+                print_request(Action::example_recent_messages());
+            }
+            Examples::Request(Notice) => {
+                print_request(Action::example_notice());
+            }
+            Examples::Request(SetEndpointEnabled) => {
+                print_request(Action::example_set_endpoint_enabled());
+            }
+            Examples::Request(PingEndpoint) => {
+                print_request(Action::example_ping_endpoint());
+            }
+            Examples::Request(ServerTime) => {
+                print_request(Action::example_server_time());
+            }
+            Examples::Request(WhoAmI) => {
+                print_request(Action::example_who_am_i());
+            }
+            Examples::Request(Correlated) => {
+                print_request(Action::example_correlated());
+            }
             Examples::Response(WriteOk) => {
                 print_ok_response(actions::Response::example_write_ok());
             }
+            Examples::Response(WriteOkCorrelated) => {
+                print_ok_response(actions::Response::example_write_ok_correlated());
+            }
+            Examples::Response(WriteAllowed) => {
+                print_ok_response(actions::Response::example_write_allowed());
+            }
             Examples::Response(ObservingEvents) => {
                 print_ok_response(actions::Response::example_observing_events());
             }
             Examples::Response(NewMessage) => {
                 print_ok_response(actions::Response::example_new_message());
             }
+            Examples::Response(RepeatedMessage) => {
+                print_ok_response(actions::Response::example_repeated_message());
+            }
+            Examples::Response(EndpointHandle) => {
+                print_ok_response(actions::Response::example_endpoint_handle());
+            }
+            Examples::Response(CompactMessage) => {
+                print_ok_response(actions::Response::example_compact_message());
+            }
+            Examples::Response(ObservingEndpointEvents) => {
+                print_ok_response(actions::Response::example_observing_endpoint_events());
+            }
+            Examples::Response(EndpointEvent) => {
+                print_ok_response(actions::Response::example_endpoint_event());
+            }
+            Examples::Response(ObservingMessages) => {
+                print_ok_response(actions::Response::example_observing_messages());
+            }
+            Examples::Response(Traffic) => {
+                print_ok_response(actions::Response::example_traffic());
+            }
+            Examples::Response(ObservationDeferred) => {
+                print_ok_response(actions::Response::example_observation_deferred());
+            }
+            Examples::Response(ControlDeferred) => {
+                print_ok_response(actions::Response::example_control_deferred());
+            }
+            Examples::Response(Unobserved) => {
+                print_ok_response(actions::Response::example_unobserved());
+            }
             Examples::Response(Observing) => {
                 print_ok_response(actions::Response::example_observing());
             }
+            Examples::Response(ObservingComposite) => {
+                print_ok_response(actions::Response::example_observing_composite());
+            }
+            Examples::Response(ObservingAny) => {
+                print_ok_response(actions::Response::example_observing_any());
+            }
             Examples::Response(ControlGranted) => {
                 print_ok_response(actions::Response::example_control_granted());
             }
             Examples::Response(ControlQueued) => {
                 print_ok_response(actions::Response::example_control_queue());
             }
+            Examples::Response(Reconfigured) => {
+                print_ok_response(actions::Response::example_reconfigured());
+            }
+            Examples::Response(Flushed) => {
+                print_ok_response(actions::Response::example_flushed());
+            }
+            Examples::Response(MacroDone) => {
+                print_ok_response(actions::Response::example_macro_done());
+            }
+            Examples::Response(SkippingOnControlMacros) => {
+                print_ok_response(actions::Response::example_skipping_on_control_macros());
+            }
+            Examples::Response(CompactingEndpointIds) => {
+                print_ok_response(actions::Response::example_compacting_endpoint_ids());
+            }
+            Examples::Response(Endpoints) => {
+                print_ok_response(actions::Response::example_endpoints());
+            }
+            Examples::Response(Labels) => {
+                print_ok_response(actions::Response::example_labels());
+            }
+            Examples::Response(EndpointClosed) => {
+                print_ok_response(actions::Response::example_endpoint_closed());
+            }
+            Examples::Response(ControlTransferred) => {
+                print_ok_response(actions::Response::example_control_transferred());
+            }
+            Examples::Response(Released) => {
+                print_ok_response(actions::Response::example_released());
+            }
+            Examples::Response(QueueCancelled) => {
+                print_ok_response(actions::Response::example_queue_cancelled());
+            }
+            Examples::Response(RecentMessagesSnapshot) => {
+                print_ok_response(actions::Response::example_recent_messages());
+            }
+            Examples::Response(ServerTimeNow) => {
+                print_ok_response(actions::Response::example_server_time());
+            }
+            Examples::Response(Connection) => {
+                print_ok_response(actions::Response::example_connection());
+            }
+            Examples::Response(Handshake) => {
+                print_ok_response(actions::Response::example_handshake());
+            }
+            Examples::Response(Banner) => {
+                print_ok_response(actions::Response::example_banner());
+            }
+            Examples::Response(NoticeReceived) => {
+                print_ok_response(actions::Response::example_notice());
+            }
+            Examples::Response(MessagesSkipped) => {
+                print_ok_response(actions::Response::example_messages_skipped());
+            }
+            Examples::Response(NoticeSent) => {
+                print_ok_response(actions::Response::example_notice_sent());
+            }
+            Examples::Response(EndpointEnabled) => {
+                print_ok_response(actions::Response::example_endpoint_enabled());
+            }
+            Examples::Response(ControlRevoked) => {
+                print_ok_response(actions::Response::example_control_revoked());
+            }
+            Examples::Response(Pong) => {
+                print_ok_response(actions::Response::example_pong());
+            }
         },
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn no_serial_overrides_config() {
+        let mut config = Config::example();
+        assert!(config
+            .endpoints
+            .iter()
+            .any(|endpoint| matches!(endpoint.id, EndpointId::Tty(_))));
+
+        let cli = Cli::try_parse_from(["serial-keel", "--no-serial"]).unwrap();
+        cli.apply_overrides(&mut config);
+
+        assert!(config
+            .endpoints
+            .iter()
+            .chain(config.groups.iter().flat_map(|group| &group.endpoints))
+            .all(|endpoint| matches!(endpoint.id, EndpointId::Mock(_))));
+
+        // The mocks are kept
+        assert!(!config.endpoints.is_empty());
+        assert!(!config.groups.is_empty());
+    }
+
+    #[test]
+    fn serial_ports_kept_by_default() {
+        let mut config = Config::example();
+        let before = config.endpoints.len();
+
+        let cli = Cli::try_parse_from(["serial-keel"]).unwrap();
+        cli.apply_overrides(&mut config);
+
+        assert_eq!(config.endpoints.len(), before);
+    }
+}
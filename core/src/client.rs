@@ -2,26 +2,43 @@ use std::{
     borrow::BorrowMut,
     collections::HashMap,
     fmt::Display,
+    future::Future,
+    io::Write,
+    path::Path,
     pin::Pin,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex, MutexGuard,
+    },
     task::{Context, Poll},
+    time::{Duration, Instant},
 };
 
 use futures::{
     channel::{mpsc, oneshot},
     executor::block_on,
-    stream::BoxStream,
+    future,
+    stream::{BoxStream, SelectAll},
     Sink, SinkExt, Stream, StreamExt,
 };
-use tokio::net::TcpStream;
-use tokio_tungstenite::{MaybeTlsStream, WebSocketStream};
+use serde::Serialize;
+use tokio::{
+    io::{AsyncRead, AsyncReadExt, AsyncWriteExt},
+    net::TcpStream,
+};
+use tokio_tungstenite::{tungstenite::client::IntoClientRequest, MaybeTlsStream, WebSocketStream};
 use tracing::{debug, error, info, warn};
 
 use crate::{
-    actions::{self, Action, Async, Response, ResponseResult},
-    endpoint::{LabelledEndpointId, Labels},
+    actions::{
+        self, Action, Async, Capability, CloseReason, ConnectionInfo, EndpointHandle,
+        ListedEndpoint, ListedLabel, NotQueuedOn, QueuedOn, Response, ResponseResult, ServerInfo,
+    },
+    endpoint::{EndpointId, InternalEndpointInfo, Label, LabelledEndpointId, Labels},
     error::Error,
     events,
     serial::{SerialMessage, SerialMessageBytes},
+    user::User,
 };
 
 pub use chrono::{DateTime, Utc};
@@ -33,9 +50,61 @@ pub struct ClientHandle {
     tx: ClientHandleTx,
     rx: ClientHandleRx,
 
+    server_info: ServerInfo,
+
+    /// Who the server sees us as, if known.
+    user: Option<User>,
+
+    /// Shared with the client task, see [`ClientHandle::use_binary_frames`].
+    binary_frames: Arc<AtomicBool>,
+
+    /// Shared with the client task, see [`ClientHandle::with_traffic_log`].
+    traffic_log: TrafficLog,
+
+    /// Shared with the client task, see [`ClientHandle::banner`].
+    banner: Arc<Mutex<Option<String>>>,
+
+    /// The endpoints control was granted of, see [`ClientHandle::write_to_label`].
+    /// Shared with the client task, which forgets those whose control is revoked.
+    controlled: Arc<Mutex<Vec<LabelledEndpointId>>>,
+
     _cancel_rx: oneshot::Receiver<()>,
 }
 
+/// A change in what this client controls or is queued for.
+/// See [`ClientHandle::control_transitions`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ControlTransition {
+    /// Control was granted over these endpoints.
+    Granted(Vec<LabelledEndpointId>),
+
+    /// The client is now in queue for these endpoints.
+    Queued(Vec<LabelledEndpointId>),
+
+    /// Control over these endpoints was given up.
+    Released(Vec<LabelledEndpointId>),
+}
+
+impl ControlTransition {
+    fn from_event(event: events::user::Event) -> Option<Self> {
+        let into_ids = |endpoints: Vec<InternalEndpointInfo>| {
+            endpoints
+                .into_iter()
+                .map(LabelledEndpointId::from)
+                .collect()
+        };
+
+        match event {
+            events::user::Event::InControlOf(endpoints) => Some(Self::Granted(into_ids(endpoints))),
+            events::user::Event::InQueueFor(endpoints) => Some(Self::Queued(into_ids(endpoints))),
+            events::user::Event::NoLongerInControlOf(endpoints) => {
+                Some(Self::Released(into_ids(endpoints)))
+            }
+            _ => None,
+        }
+    }
+}
+
 /// A reader for user events.
 #[derive(Debug)]
 pub struct EventReader {
@@ -67,63 +136,606 @@ impl EventReader {
         }
     }
 
+    /// Consume events until one matches the predicate, or the timeout elapses.
+    /// Events not matching are discarded.
+    pub async fn wait_for<P>(
+        &mut self,
+        mut predicate: P,
+        timeout: Duration,
+    ) -> Result<events::TimestampedEvent, Error>
+    where
+        P: FnMut(&events::TimestampedEvent) -> bool,
+    {
+        let wait = async {
+            loop {
+                let event = self.next_event().await;
+                if predicate(&event) {
+                    return event;
+                }
+            }
+        };
+
+        tokio::time::timeout(timeout, wait)
+            .await
+            .map_err(|_| Error::Timeout(format!("No matching event within {timeout:?}")))
+    }
+
     /// TODO
     pub fn box_stream(&mut self) -> BoxStream<events::TimestampedEvent> {
         self.events.borrow_mut().boxed()
     }
 }
 
+/// A reader for the events related to users, leaving out the others.
+/// See [`ClientHandle::observe_user_events`].
+#[derive(Debug)]
+pub struct UserEventReader {
+    events: EventReader,
+}
+
+impl UserEventReader {
+    /// Make a new user event reader, reading from the given events.
+    pub fn new(events: EventReader) -> Self {
+        Self { events }
+    }
+
+    /// Await the next user event.
+    /// Other events are discarded.
+    pub async fn next_user_event(&mut self) -> events::user::TimestampedUserEvent {
+        loop {
+            if let Some(event) =
+                events::user::TimestampedUserEvent::from_event(self.events.next_event().await)
+            {
+                return event;
+            }
+        }
+    }
+
+    /// Get the next user event if there is one.
+    /// Other events are discarded.
+    pub fn try_next_user_event(&mut self) -> Option<events::user::TimestampedUserEvent> {
+        while let Some(event) = self.events.try_next_event() {
+            if let Some(event) = events::user::TimestampedUserEvent::from_event(event) {
+                return Some(event);
+            }
+        }
+
+        None
+    }
+}
+
+/// A reader for the events of a single endpoint.
+#[derive(Debug)]
+pub struct EndpointEventReader {
+    endpoint_id: LabelledEndpointId,
+
+    /// Events can be awaited here.
+    events: mpsc::UnboundedReceiver<events::endpoint::TimestampedEndpointEvent>,
+}
+
+impl EndpointEventReader {
+    fn new(
+        id: LabelledEndpointId,
+        rx: mpsc::UnboundedReceiver<events::endpoint::TimestampedEndpointEvent>,
+    ) -> Self {
+        Self {
+            endpoint_id: id,
+            events: rx,
+        }
+    }
+
+    /// Await the next event of the endpoint.
+    pub async fn next_event(
+        &mut self,
+    ) -> Result<events::endpoint::TimestampedEndpointEvent, Error> {
+        self.events.next().await.ok_or_else(|| {
+            Error::InternalIssue(format!("No more events of endpoint {}", self.endpoint_id()))
+        })
+    }
+
+    /// Get the next event if there is one.
+    ///
+    /// Once no more events arrive, e.g. because the connection closed, this fails.
+    pub fn try_next_event(
+        &mut self,
+    ) -> Result<Option<events::endpoint::TimestampedEndpointEvent>, Error> {
+        match self.events.try_next() {
+            Ok(Some(event)) => Ok(Some(event)),
+            Ok(None) => Err(Error::InternalIssue(format!(
+                "No more events of endpoint {}",
+                self.endpoint_id()
+            ))),
+            Err(_) => Ok(None),
+        }
+    }
+
+    /// Borrow the [`LabelledEndpointId`].
+    pub fn endpoint_id(&self) -> &LabelledEndpointId {
+        &self.endpoint_id
+    }
+}
+
+/// A reader for every message of some endpoints, whoever put them on wire.
+#[derive(Debug)]
+pub struct MessageReader {
+    endpoints: Vec<LabelledEndpointId>,
+
+    /// Messages can be awaited here.
+    messages: mpsc::UnboundedReceiver<events::message::TimestampedMessage>,
+}
+
+impl MessageReader {
+    fn new(
+        endpoints: Vec<LabelledEndpointId>,
+        rx: mpsc::UnboundedReceiver<events::message::TimestampedMessage>,
+    ) -> Self {
+        Self {
+            endpoints,
+            messages: rx,
+        }
+    }
+
+    /// Await the next message of any of the endpoints.
+    pub async fn next_message(&mut self) -> Result<events::message::TimestampedMessage, Error> {
+        self.messages
+            .next()
+            .await
+            .ok_or_else(|| Error::InternalIssue("No more messages".into()))
+    }
+
+    /// Get the next message if there is one.
+    ///
+    /// Once no more messages arrive, e.g. because the connection closed, this fails.
+    pub fn try_next_message(
+        &mut self,
+    ) -> Result<Option<events::message::TimestampedMessage>, Error> {
+        match self.messages.try_next() {
+            Ok(Some(message)) => Ok(Some(message)),
+            Ok(None) => Err(Error::InternalIssue("No more messages".into())),
+            Err(_) => Ok(None),
+        }
+    }
+
+    /// The endpoints whose messages are read.
+    pub fn endpoints(&self) -> &[LabelledEndpointId] {
+        &self.endpoints
+    }
+}
+
+/// Stops observing an endpoint when dropped.
+#[derive(Debug)]
+struct Observation {
+    id: EndpointId,
+    actions: mpsc::UnboundedSender<Action>,
+}
+
+impl Drop for Observation {
+    fn drop(&mut self) {
+        // The client may be gone already, in which case so is the observation.
+        if self
+            .actions
+            .unbounded_send(Action::unobserve(&self.id))
+            .is_err()
+        {
+            debug!(id = %self.id, "Client gone, not unobserving");
+        }
+    }
+}
+
+/// Something an [`EndpointReader`] received.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ReadItem {
+    /// A message of the endpoint.
+    Message(SerialMessageBytes),
+
+    /// A message the endpoint sent several times in a row.
+    /// Only read when observing deduplicated, see [`ClientHandle::observe_deduplicated`].
+    Repeated {
+        /// The message contents.
+        message: SerialMessageBytes,
+
+        /// How many times in a row the message was seen.
+        repeats: usize,
+    },
+
+    /// A notice sent to the observers of the endpoint, not something the endpoint itself sent.
+    /// See [`ClientHandle::notice`].
+    Notice(String),
+}
+
+impl ReadItem {
+    fn message(&self) -> Option<&[u8]> {
+        match self {
+            ReadItem::Message(message) | ReadItem::Repeated { message, .. } => Some(message),
+            ReadItem::Notice(_) => None,
+        }
+    }
+
+    fn into_message(self) -> Option<SerialMessageBytes> {
+        match self {
+            ReadItem::Message(message) | ReadItem::Repeated { message, .. } => Some(message),
+            ReadItem::Notice(_) => None,
+        }
+    }
+}
+
 /// A reader for an endpoint.
+///
+/// The endpoint is observed for as long as the reader lives.
+/// Dropping the reader (or anything made from it, such as [`EndpointReader::into_stream`])
+/// tells the server to stop observing it.
 #[derive(Debug)]
 pub struct EndpointReader {
     endpoint_id: LabelledEndpointId,
 
-    /// Messages can be awaited here.
-    messages: mpsc::UnboundedReceiver<SerialMessageBytes>,
+    /// Messages and notices can be awaited here.
+    items: mpsc::UnboundedReceiver<ReadItem>,
+
+    /// Why the messages ended, if the server said.
+    closed: oneshot::Receiver<CloseReason>,
+
+    /// Taken from `closed` once the messages ended.
+    close_reason: Option<CloseReason>,
+
+    observation: Observation,
+}
+
+/// The client task's end of an [`EndpointReader`].
+#[derive(Debug)]
+struct ReaderSender {
+    items: mpsc::UnboundedSender<ReadItem>,
+    closed: oneshot::Sender<CloseReason>,
 }
 
 impl EndpointReader {
-    fn new(id: LabelledEndpointId, rx: mpsc::UnboundedReceiver<SerialMessageBytes>) -> Self {
-        Self {
+    fn new(id: LabelledEndpointId, actions: mpsc::UnboundedSender<Action>) -> (ReaderSender, Self) {
+        let (items_tx, items) = mpsc::unbounded();
+        let (closed_tx, closed) = oneshot::channel();
+
+        let reader = Self {
+            observation: Observation {
+                id: id.id.clone(),
+                actions,
+            },
             endpoint_id: id,
-            messages: rx,
+            items,
+            closed,
+            close_reason: None,
+        };
+
+        (
+            ReaderSender {
+                items: items_tx,
+                closed: closed_tx,
+            },
+            reader,
+        )
+    }
+
+    /// Why no more messages arrive, once that is the case.
+    fn ended(&mut self) -> Error {
+        let reason = *self.close_reason.get_or_insert_with(|| {
+            match self.closed.try_recv() {
+                Ok(Some(reason)) => reason,
+                // The server did not say, so the connection to it is gone.
+                Ok(None) | Err(oneshot::Canceled) => CloseReason::Disconnected,
+            }
+        });
+
+        Error::ObservationEnded {
+            endpoint: self.endpoint_id.id.clone(),
+            reason,
         }
     }
 
+    /// Stop observing the endpoint.
+    /// This is the same as dropping the reader.
+    pub fn close(self) {
+        drop(self)
+    }
+
     /// Await the next message from the endpoint.
+    /// Notices are skipped, see [`EndpointReader::next_item`] to get those too.
+    ///
+    /// Once no more messages arrive, fails with [`Error::ObservationEnded`] telling why.
     pub async fn next_message(&mut self) -> Result<SerialMessage, Error> {
-        let message = self.messages.next().await.ok_or_else(|| {
-            Error::InternalIssue(format!(
-                "No more messages on endpoint {}",
-                self.endpoint_id()
-            ))
-        })?;
-
-        Ok(String::from_utf8_lossy(&message).into())
+        loop {
+            if let Some(message) = self.next_item().await?.into_message() {
+                return Ok(String::from_utf8_lossy(&message).into());
+            }
+        }
     }
 
     /// Get the next message if there is one.
-    pub fn try_next_message(&mut self) -> Option<SerialMessage> {
-        match self.messages.try_next() {
-            Ok(Some(message)) => Some(String::from_utf8_lossy(&message).into()),
-            Ok(None) => panic!("Endpoint closed"),
-            Err(_) => None,
+    /// Notices are skipped.
+    ///
+    /// Once no more messages arrive, fails with [`Error::ObservationEnded`] telling why.
+    pub fn try_next_message(&mut self) -> Result<Option<SerialMessage>, Error> {
+        loop {
+            match self.items.try_next() {
+                Ok(Some(item)) => {
+                    if let Some(message) = item.into_message() {
+                        return Ok(Some(String::from_utf8_lossy(&message).into()));
+                    }
+                }
+                Ok(None) => return Err(self.ended()),
+                Err(_) => return Ok(None),
+            }
+        }
+    }
+
+    /// Await whatever the endpoint's observers are sent next, be it a message or a notice.
+    ///
+    /// Once nothing more arrives, fails with [`Error::ObservationEnded`] telling why.
+    pub async fn next_item(&mut self) -> Result<ReadItem, Error> {
+        match self.items.next().await {
+            Some(item) => Ok(item),
+            None => Err(self.ended()),
         }
     }
 
+    /// Take all messages received so far without waiting for more.
+    /// Useful for discarding whatever a noisy endpoint has buffered before sending a command.
+    pub fn drain(&mut self) -> Vec<SerialMessage> {
+        std::iter::from_fn(|| self.try_next_message().ok().flatten()).collect()
+    }
+
     /// Borrow the [`LabelledEndpointId`].
     pub fn endpoint_id(&self) -> &LabelledEndpointId {
         &self.endpoint_id
     }
 
     /// Use the reader exclusively as a stream of messages.
+    /// Notices are skipped.
     pub fn stream(&mut self) -> impl Stream<Item = SerialMessageBytes> + '_ {
-        &mut self.messages
+        (&mut self.items).filter_map(|item| future::ready(item.into_message()))
     }
 
     /// Turn the reader into a stream of messages.
+    /// Notices are skipped.
+    /// The endpoint is observed until the stream is dropped.
     pub fn into_stream(self) -> impl Stream<Item = SerialMessageBytes> {
-        self.messages
+        let observation = self.observation;
+
+        self.items.filter_map(move |item| {
+            // Owned by the stream, such that dropping it stops the observation.
+            let _ = &observation;
+            future::ready(item.into_message())
+        })
+    }
+
+    /// Append every message received from now on to the file at the given path, one per line.
+    /// The messages are still yielded by this reader as usual.
+    /// If `timestamps` is set each line is prefixed by when the message was received.
+    ///
+    /// The file is created if it does not exist.
+    /// Use the returned [`TeeHandle`] to flush or stop writing to the file.
+    pub async fn tee_to_file<P: AsRef<Path>>(
+        &mut self,
+        path: P,
+        timestamps: bool,
+    ) -> Result<TeeHandle, Error> {
+        let path = path.as_ref();
+        let file = tokio::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .await
+            .map_err(|e| {
+                Error::BadUsage(format!(
+                    "Could not open `{}` for teeing: {e}",
+                    path.display()
+                ))
+            })?;
+
+        let (items_tx, items_rx) = mpsc::unbounded();
+        let mut items = std::mem::replace(&mut self.items, items_rx);
+        let (control_tx, mut control_rx) = mpsc::unbounded();
+
+        tokio::spawn(async move {
+            let mut file = Some(tokio::io::BufWriter::new(file));
+
+            loop {
+                tokio::select! {
+                    item = items.next() => {
+                        let Some(item) = item else {
+                            break;
+                        };
+
+                        // Notices are not the endpoint's output, so they are not teed.
+                        if let (Some(writer), Some(message)) = (&mut file, item.message()) {
+                            if let Err(e) = write_tee_line(writer, message, timestamps).await {
+                                warn!(?e, "Could not write to tee file, no longer teeing");
+                                file = None;
+                            }
+                        }
+
+                        // The consumer not listening does not stop the tee.
+                        let _ = items_tx.unbounded_send(item);
+                    }
+                    Some(control) = control_rx.next() => match control {
+                        TeeControl::Flush(reply) => {
+                            let _ = reply.send(flush_tee(&mut file).await);
+                        }
+                        TeeControl::Stop(reply) => {
+                            let _ = reply.send(flush_tee(&mut file).await);
+                            file = None;
+                        }
+                    },
+                }
+            }
+
+            if let Err(e) = flush_tee(&mut file).await {
+                warn!(?e, "Could not flush tee file");
+            }
+        });
+
+        Ok(TeeHandle {
+            control: control_tx,
+        })
+    }
+
+    fn into_tagged_stream(self) -> BoxStream<'static, (LabelledEndpointId, SerialMessageBytes)> {
+        let id = self.endpoint_id;
+        let observation = self.observation;
+
+        self.items
+            .filter_map(move |item| {
+                // Owned by the stream, such that dropping it stops the observation.
+                let _ = &observation;
+                future::ready(item.into_message().map(|message| (id.clone(), message)))
+            })
+            .boxed()
+    }
+}
+
+async fn write_tee_line(
+    writer: &mut tokio::io::BufWriter<tokio::fs::File>,
+    message: &[u8],
+    timestamp: bool,
+) -> std::io::Result<()> {
+    if timestamp {
+        let now = chrono::Utc::now().to_rfc3339();
+        writer.write_all(format!("[{now}] ").as_bytes()).await?;
+    }
+
+    writer.write_all(message).await?;
+
+    if !message.ends_with(b"\n") {
+        writer.write_all(b"\n").await?;
+    }
+
+    Ok(())
+}
+
+async fn flush_tee(file: &mut Option<tokio::io::BufWriter<tokio::fs::File>>) -> Result<(), Error> {
+    match file {
+        Some(writer) => writer
+            .flush()
+            .await
+            .map_err(|e| Error::InternalIssue(format!("Could not flush tee file: {e}"))),
+        None => Ok(()),
+    }
+}
+
+#[derive(Debug)]
+enum TeeControl {
+    Flush(oneshot::Sender<Result<(), Error>>),
+    Stop(oneshot::Sender<Result<(), Error>>),
+}
+
+/// Controls the file an [`EndpointReader`] tees messages into.
+/// See [`EndpointReader::tee_to_file`].
+///
+/// Dropping the handle does not stop the tee.
+#[derive(Debug)]
+pub struct TeeHandle {
+    control: mpsc::UnboundedSender<TeeControl>,
+}
+
+impl TeeHandle {
+    async fn request(
+        &self,
+        control: impl FnOnce(oneshot::Sender<Result<(), Error>>) -> TeeControl,
+    ) -> Result<(), Error> {
+        let (tx, rx) = oneshot::channel();
+
+        self.control
+            .unbounded_send(control(tx))
+            .map_err(|_| Error::BadUsage("The endpoint is closed, nothing is teed".into()))?;
+
+        rx.await
+            .map_err(|_| Error::InternalIssue("The tee task went away".into()))?
+    }
+
+    /// Make sure all messages received so far are written to the file.
+    pub async fn flush(&self) -> Result<(), Error> {
+        self.request(TeeControl::Flush).await
+    }
+
+    /// Flush, then stop writing messages to the file.
+    /// The reader keeps yielding messages.
+    pub async fn stop(self) -> Result<(), Error> {
+        self.request(TeeControl::Stop).await
+    }
+}
+
+/// Stops the callback of [`ClientHandle::observe_with_callback`].
+///
+/// The endpoint is observed for as long as the handle lives,
+/// so dropping it stops the callback too.
+#[derive(Debug)]
+pub struct CallbackHandle {
+    task: tokio::task::JoinHandle<()>,
+}
+
+impl CallbackHandle {
+    /// Stop calling the callback and stop observing the endpoint.
+    /// This is the same as dropping the handle.
+    pub fn stop(self) {
+        drop(self)
+    }
+
+    /// Whether the callback will not be called anymore,
+    /// e.g. because the observation ended.
+    pub fn is_finished(&self) -> bool {
+        self.task.is_finished()
+    }
+}
+
+impl Drop for CallbackHandle {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+/// A reader for several endpoints at once.
+/// Messages are yielded in the order they arrive, tagged with the endpoint they came from.
+pub struct MergedEndpointReader {
+    endpoint_ids: Vec<LabelledEndpointId>,
+
+    /// Messages from all endpoints can be awaited here.
+    messages: SelectAll<BoxStream<'static, (LabelledEndpointId, SerialMessageBytes)>>,
+}
+
+impl std::fmt::Debug for MergedEndpointReader {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("MergedEndpointReader")
+            .field("endpoint_ids", &self.endpoint_ids)
+            .finish_non_exhaustive()
+    }
+}
+
+impl MergedEndpointReader {
+    fn new(readers: Vec<EndpointReader>) -> Self {
+        let endpoint_ids = readers
+            .iter()
+            .map(|reader| reader.endpoint_id().clone())
+            .collect();
+
+        Self {
+            endpoint_ids,
+            messages: futures::stream::select_all(
+                readers.into_iter().map(EndpointReader::into_tagged_stream),
+            ),
+        }
+    }
+
+    /// Await the next message from any of the endpoints.
+    pub async fn next_message(&mut self) -> Result<(LabelledEndpointId, SerialMessage), Error> {
+        let (id, message) = self.messages.next().await.ok_or_else(|| {
+            Error::InternalIssue(format!(
+                "No more messages on endpoints {:?}",
+                self.endpoint_ids
+            ))
+        })?;
+
+        Ok((id, String::from_utf8_lossy(&message).into()))
+    }
+
+    /// Borrow the [`LabelledEndpointId`]s of the merged endpoints.
+    pub fn endpoint_ids(&self) -> &[LabelledEndpointId] {
+        &self.endpoint_ids
     }
 }
 
@@ -162,6 +774,57 @@ impl EndpointWriter {
             .map_err(|_| Error::BadUsage("Cannot write when the client has closed".into()))
     }
 
+    /// Run a macro from the server configuration on this endpoint.
+    pub async fn run_macro(&mut self, name: &str) -> Result<(), Error> {
+        self.messages
+            .send(Action::run_macro(&self.endpoint_id.id, name))
+            .await
+            .map_err(|_| Error::BadUsage("Cannot run a macro when the client has closed".into()))
+    }
+
+    /// Write bytes given as hex digits, e.g. `"DE AD BE EF"` or `"de:ad:be:ef"`.
+    /// Bytes may be separated by whitespace or colons.
+    pub async fn write_hex(&mut self, hex: &str) -> Result<(), Error> {
+        let bytes = parse_hex(hex)?;
+        self.write(bytes).await
+    }
+
+    /// Write everything the reader yields, in chunks of at most `chunk_size` bytes.
+    /// Useful for streaming large files without loading them into memory first.
+    ///
+    /// Returns the number of bytes written.
+    pub async fn write_from_reader<R>(
+        &mut self,
+        mut reader: R,
+        chunk_size: usize,
+    ) -> Result<usize, Error>
+    where
+        R: AsyncRead + Unpin,
+    {
+        if chunk_size == 0 {
+            return Err(Error::BadUsage("The chunk size must be non-zero".into()));
+        }
+
+        let mut chunk = vec![0; chunk_size];
+        let mut written = 0;
+
+        loop {
+            let read = reader
+                .read(&mut chunk)
+                .await
+                .map_err(|e| Error::BadUsage(format!("Could not read from the reader: {e}")))?;
+
+            if read == 0 {
+                break;
+            }
+
+            self.write(&chunk[..read]).await?;
+            written += read;
+        }
+
+        Ok(written)
+    }
+
     /// Borrow the [`LabelledEndpointId`].
     pub fn endpoint_id(&self) -> &LabelledEndpointId {
         &self.endpoint_id
@@ -181,7 +844,58 @@ impl EndpointWriter {
     pub fn into_sink(self) -> impl Sink<Action> {
         self.messages
     }
-}
+
+    /// Consume the writer as a sink of payloads, each written to this endpoint.
+    ///
+    /// Allows forwarding a stream straight to a device.
+    pub fn into_bytes_sink(self) -> impl Sink<SerialMessageBytes, Error = Error> {
+        let id = self.endpoint_id.id;
+
+        self.messages
+            .sink_map_err(|_| Error::BadUsage("Cannot write when the client has closed".into()))
+            .with(move |bytes: SerialMessageBytes| {
+                future::ready(Ok(Action::write_bytes(&id, bytes)))
+            })
+    }
+
+    /// Consume the writer as a sink of lines, each written followed by a newline.
+    pub fn into_line_sink(self) -> impl Sink<String, Error = Error> {
+        self.into_bytes_sink()
+            .with(|line: String| future::ready(Ok(format!("{line}\n").into_bytes())))
+    }
+}
+
+/// Parse hex digits into bytes.
+/// Each whitespace or colon separated chunk must hold whole bytes, i.e. an even number of digits.
+fn parse_hex(hex: &str) -> Result<Vec<u8>, Error> {
+    let bad_usage = |problem: String| Error::BadUsage(format!("Bad hex `{hex}`: {problem}"));
+
+    let mut bytes = vec![];
+
+    for chunk in hex
+        .split(|c: char| c.is_whitespace() || c == ':')
+        .filter(|chunk| !chunk.is_empty())
+    {
+        if !chunk.chars().all(|c| c.is_ascii_hexdigit()) {
+            return Err(bad_usage(format!("`{chunk}` is not hex")));
+        }
+
+        if chunk.len() % 2 != 0 {
+            return Err(bad_usage(format!("`{chunk}` has an odd number of digits")));
+        }
+
+        for pair in chunk.as_bytes().chunks(2) {
+            let pair = std::str::from_utf8(pair).expect("Hex digits are ascii");
+            bytes.push(u8::from_str_radix(pair, 16).expect("Checked to be hex digits"));
+        }
+    }
+
+    if bytes.is_empty() {
+        return Err(bad_usage("no bytes given".into()));
+    }
+
+    Ok(bytes)
+}
 
 /// A collection of [`EndpointWriter`].
 #[derive(Debug)]
@@ -202,6 +916,35 @@ impl From<Vec<EndpointWriter>> for EndpointWriters {
     }
 }
 
+/// How control was granted, for those wanting to know about contention.
+/// See e.g. [`ClientHandle::control_any_outcome`].
+#[derive(Debug)]
+pub struct ControlOutcome<W> {
+    /// The writers of the endpoints now controlled.
+    pub writers: W,
+
+    /// Whether someone else was controlling the endpoint(s), so the request was queued.
+    pub was_queued: bool,
+
+    /// How long it took from asking for control until it was granted.
+    pub waited: Duration,
+
+    /// Endpoints matching the labels which could not be queued for, and why.
+    /// Only told about when the request was queued.
+    pub not_queued_on: Vec<NotQueuedOn>,
+}
+
+impl<W> ControlOutcome<W> {
+    fn map<V>(self, f: impl FnOnce(W) -> V) -> ControlOutcome<V> {
+        ControlOutcome {
+            writers: f(self.writers),
+            was_queued: self.was_queued,
+            waited: self.waited,
+            not_queued_on: self.not_queued_on,
+        }
+    }
+}
+
 impl EndpointWriters {
     /// Remove the given [`EndpointWriter`] from self if an endpoint with a (super)set of the given labels is owned by us.
     pub fn remove_writer_with_labels(&mut self, labels: &Labels) -> Option<EndpointWriter> {
@@ -217,8 +960,128 @@ impl EndpointWriters {
     }
 }
 
+/// An endpoint which is both controlled and observed,
+/// so that it can be written to and read from through one handle.
+///
+/// ```no_run
+/// use serial_keel::{client::ClientHandle, endpoint::EndpointId};
+///
+/// # async fn example() -> Result<(), serial_keel::error::Error> {
+/// let mut client = ClientHandle::new("localhost", 3123).await?;
+/// let mut endpoint = client.control_and_observe(&EndpointId::mock("my-mock")).await?;
+///
+/// endpoint.write_line("Hello").await?;
+/// assert_eq!(endpoint.next_message().await?.as_str(), "Hello");
+///
+/// let _reader = endpoint.release(&mut client).await?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct ControlledEndpoint {
+    writer: EndpointWriter,
+    reader: EndpointReader,
+}
+
+impl ControlledEndpoint {
+    /// Bundle a writer and a reader of the same endpoint.
+    pub fn new(writer: EndpointWriter, reader: EndpointReader) -> Result<Self, Error> {
+        if writer.endpoint_id().id != reader.endpoint_id().id {
+            return Err(Error::BadUsage(format!(
+                "The writer of {} and the reader of {} are not for the same endpoint",
+                writer.endpoint_id(),
+                reader.endpoint_id()
+            )));
+        }
+
+        Ok(Self { writer, reader })
+    }
+
+    /// Write the line, followed by a newline.
+    pub async fn write_line(&mut self, line: &str) -> Result<(), Error> {
+        self.writer.write(format!("{line}\n")).await
+    }
+
+    /// Await the next message from the endpoint.
+    pub async fn next_message(&mut self) -> Result<SerialMessage, Error> {
+        self.reader.next_message().await
+    }
+
+    /// Discard anything received so far, write the request as a line,
+    /// and await the message which follows.
+    pub async fn request_response(&mut self, request: &str) -> Result<SerialMessage, Error> {
+        self.reader.drain();
+        self.write_line(request).await?;
+        self.next_message().await
+    }
+
+    /// Like [`Self::request_response`], but await every message up to the one ending with the prompt.
+    /// Whatever preceded the prompt in that message is kept, the prompt itself is not.
+    ///
+    /// Endpoints with a configured prompt list it, see [`ListedEndpoint::prompt`].
+    pub async fn request_response_until_prompt(
+        &mut self,
+        request: &str,
+        prompt: &str,
+    ) -> Result<Vec<SerialMessage>, Error> {
+        if prompt.is_empty() {
+            return Err(Error::BadUsage(
+                "The prompt to read until cannot be empty".into(),
+            ));
+        }
+
+        self.reader.drain();
+        self.write_line(request).await?;
+
+        let mut response = vec![];
+        loop {
+            let message = self.next_message().await?;
+
+            match message.as_str().strip_suffix(prompt) {
+                Some(before) => {
+                    if !before.is_empty() {
+                        response.push(before.into());
+                    }
+                    return Ok(response);
+                }
+                None => response.push(message),
+            }
+        }
+    }
+
+    /// Mutable borrow of the writer.
+    pub fn writer_mut(&mut self) -> &mut EndpointWriter {
+        &mut self.writer
+    }
+
+    /// Mutable borrow of the reader.
+    pub fn reader_mut(&mut self) -> &mut EndpointReader {
+        &mut self.reader
+    }
+
+    /// Borrow the [`LabelledEndpointId`].
+    pub fn endpoint_id(&self) -> &LabelledEndpointId {
+        self.writer.endpoint_id()
+    }
+
+    /// Stop controlling the endpoint, letting the next user in queue have it.
+    /// The endpoint is still observed through the returned reader.
+    pub async fn release(self, client: &mut ClientHandle) -> Result<EndpointReader, Error> {
+        client.release(self.writer).await?;
+        Ok(self.reader)
+    }
+}
+
+/// Where the client sends its frames.
+pub(crate) type FrameSink =
+    Pin<Box<dyn Sink<tungstenite::Message, Error = tungstenite::Error> + Send>>;
+
+/// Where the client receives frames from.
+pub(crate) type FrameStream = BoxStream<'static, Result<tungstenite::Message, tungstenite::Error>>;
+
 struct Client {
-    stream: WebSocketStream<MaybeTlsStream<TcpStream>>,
+    sink: FrameSink,
+    stream: FrameStream,
 
     /// Writes any non-async responses
     responses: mpsc::UnboundedSender<Result<ClientResponse, Error>>,
@@ -226,7 +1089,15 @@ struct Client {
     action_requests_tx: mpsc::UnboundedSender<Action>,
     action_requests_rx: mpsc::UnboundedReceiver<Action>,
 
-    endpoint_readers: HashMap<LabelledEndpointId, mpsc::UnboundedSender<SerialMessageBytes>>,
+    endpoint_readers: HashMap<LabelledEndpointId, ReaderSender>,
+
+    endpoint_event_readers: HashMap<
+        LabelledEndpointId,
+        mpsc::UnboundedSender<events::endpoint::TimestampedEndpointEvent>,
+    >,
+
+    message_readers:
+        HashMap<LabelledEndpointId, mpsc::UnboundedSender<events::message::TimestampedMessage>>,
 
     events_tx: mpsc::UnboundedSender<events::TimestampedEvent>,
 
@@ -234,14 +1105,85 @@ struct Client {
     // In that casses, it is owned by the handler.
     events_rx: Option<mpsc::UnboundedReceiver<events::TimestampedEvent>>,
 
+    /// Send actions in binary frames rather than text.
+    binary_frames: Arc<AtomicBool>,
+
+    traffic_log: TrafficLog,
+
+    /// The server's banner, once received.
+    banner: Arc<Mutex<Option<String>>>,
+
+    /// The endpoints the handle controls.
+    controlled: Arc<Mutex<Vec<LabelledEndpointId>>>,
+
     close: oneshot::Sender<()>,
 }
 
+/// Which way traffic in the [`TrafficLog`] went.
+#[derive(Debug, Clone, Copy, Serialize)]
+#[serde(rename_all = "lowercase")]
+enum TrafficDirection {
+    Outgoing,
+    Incoming,
+}
+
+#[derive(Debug, Serialize)]
+struct TrafficEntry<'a, T> {
+    direction: TrafficDirection,
+    timestamp: DateTime<Utc>,
+    message: &'a T,
+}
+
+/// Where the client writes the actions it sends and the responses it receives, if anywhere.
+#[derive(Clone, Default)]
+struct TrafficLog(Arc<Mutex<Option<Box<dyn Write + Send>>>>);
+
+impl std::fmt::Debug for TrafficLog {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("TrafficLog").finish_non_exhaustive()
+    }
+}
+
+impl TrafficLog {
+    fn set(&self, writer: Box<dyn Write + Send>) {
+        *self
+            .0
+            .lock()
+            .expect("Traffic log lock should not be poisoned") = Some(writer);
+    }
+
+    fn log<T: Serialize>(&self, direction: TrafficDirection, message: &T) {
+        let mut writer = self
+            .0
+            .lock()
+            .expect("Traffic log lock should not be poisoned");
+        let Some(writer) = writer.as_mut() else {
+            return;
+        };
+
+        let entry = TrafficEntry {
+            direction,
+            timestamp: Utc::now(),
+            message,
+        };
+
+        let written = serde_json::to_writer_pretty(&mut *writer, &entry)
+            .map_err(std::io::Error::from)
+            .and_then(|()| writeln!(writer))
+            .and_then(|()| writer.flush());
+
+        if let Err(e) = written {
+            warn!(?e, "Could not write to traffic log");
+        }
+    }
+}
+
 /// The response the client exposes through its API.
 #[derive(Debug)]
 pub enum ClientResponse {
     /// A requested write action was successful.
-    WriteOk,
+    /// Carries the write's correlation id, if it had one.
+    WriteOk(Option<String>),
 
     /// Now receiving events from the server.
     Events(EventReader),
@@ -249,27 +1191,130 @@ pub enum ClientResponse {
     /// Now observing the given endpoints.
     Observing(EndpointReader),
 
+    /// The given endpoint was already observed, and its existing reader keeps receiving.
+    AlreadyObserving(LabelledEndpointId),
+
+    /// Now observing each port of a composite endpoint.
+    ObservingComposite(MergedEndpointReader),
+
+    /// Now observing each endpoint which matched the labels.
+    ObservingAny(Vec<EndpointReader>),
+
+    /// Now receiving the events of the given endpoint.
+    ObservingEndpointEvents(EndpointEventReader),
+
+    /// Now receiving the messages of the given endpoints.
+    ObservingMessages(MessageReader),
+
     /// Now controlling the given endpoints.
     Controlling(Vec<EndpointWriter>),
 
-    /// Queued.
-    Queued,
+    /// Queued, along with who currently holds the endpoints,
+    /// and which matching endpoints could not be queued for.
+    Queued((Vec<QueuedOn>, Vec<NotQueuedOn>)),
+
+    /// The given endpoint now uses the requested settings.
+    Reconfigured(LabelledEndpointId),
+
+    /// The buffers of the endpoint were discarded.
+    Flushed(LabelledEndpointId),
+
+    /// The macro with the given name has run on the given endpoint.
+    MacroDone((LabelledEndpointId, String)),
+
+    /// Whether on control macros are now skipped.
+    SkippingOnControlMacros(bool),
+
+    /// Whether endpoints observed from now on have their messages sent with handles.
+    CompactingEndpointIds(bool),
+
+    /// The endpoints the server knows of.
+    Endpoints(Vec<ListedEndpoint>),
+
+    /// The distinct labels of the endpoints the server knows of.
+    Labels(Vec<ListedLabel>),
+
+    /// Control of the given endpoint was handed to the user with the given name.
+    ControlTransferred((LabelledEndpointId, String)),
+
+    /// The given endpoints are no longer controlled.
+    Released(Vec<LabelledEndpointId>),
+
+    /// The given endpoints are no longer queued for.
+    QueueCancelled(Vec<LabelledEndpointId>),
+
+    /// The most recent messages the given endpoint received, oldest first.
+    RecentMessages((LabelledEndpointId, Vec<SerialMessage>)),
+
+    /// The server's time.
+    ServerTime(DateTime<Utc>),
+
+    /// The server's view of the connection.
+    Connection(ConnectionInfo),
+
+    /// A notice was sent to this many observers of the endpoint.
+    NoticeSent((LabelledEndpointId, usize)),
+
+    /// The endpoint was enabled or disabled.
+    EndpointEnabled((LabelledEndpointId, bool)),
+
+    /// The endpoint answered a ping, this long after being probed.
+    Pong((LabelledEndpointId, Duration)),
+
+    /// Writing to the given endpoint would be allowed.
+    WriteAllowed(LabelledEndpointId),
+
+    /// The server introduced itself.
+    Handshake(ServerInfo),
+
+    /// The endpoint to observe does not exist yet.
+    /// [`ClientResponse::Observing`] follows once it does.
+    ObservationDeferred(EndpointId),
+
+    /// No endpoint matches the labels to control yet.
+    /// [`ClientResponse::Controlling`] or [`ClientResponse::Queued`] follows once one does.
+    ControlDeferred(Labels),
 }
 
 impl Client {
+    #[allow(clippy::too_many_arguments)]
     async fn handle_websocket_message(
         message: Result<tungstenite::protocol::Message, tungstenite::Error>,
-        endpoint_readers: &mut HashMap<
+        endpoint_readers: &mut HashMap<LabelledEndpointId, ReaderSender>,
+        endpoint_handles: &mut HashMap<EndpointHandle, LabelledEndpointId>,
+        endpoint_event_readers: &mut HashMap<
+            LabelledEndpointId,
+            mpsc::UnboundedSender<events::endpoint::TimestampedEndpointEvent>,
+        >,
+        message_readers: &mut HashMap<
             LabelledEndpointId,
-            mpsc::UnboundedSender<SerialMessageBytes>,
+            mpsc::UnboundedSender<events::message::TimestampedMessage>,
         >,
         responses: &mut mpsc::UnboundedSender<Result<ClientResponse, Error>>,
         actions_tx: mpsc::UnboundedSender<Action>,
         events_tx: &mut mpsc::UnboundedSender<events::TimestampedEvent>,
         events_rx: &mut Option<mpsc::UnboundedReceiver<events::TimestampedEvent>>,
+        traffic_log: &TrafficLog,
+        banner: &Mutex<Option<String>>,
+        controlled: &Mutex<Vec<LabelledEndpointId>>,
     ) {
-        let text = match message {
-            Ok(tungstenite::protocol::Message::Text(text)) => text,
+        let response: ResponseResult = match message {
+            Ok(tungstenite::protocol::Message::Text(text)) => match serde_json::from_str(&text) {
+                Ok(response) => response,
+                Err(e) => {
+                    error!(?e, ?text, "Could not deserialize message");
+                    return;
+                }
+            },
+            Ok(tungstenite::protocol::Message::Binary(bytes)) => {
+                match actions::deserialize_response_binary(&bytes) {
+                    Ok(response) => response,
+                    Err(e) => {
+                        error!(?e, "Could not deserialize binary message");
+                        return;
+                    }
+                }
+            }
             Err(e) => {
                 error!(?e, "Wrong thing");
                 return;
@@ -280,13 +1325,7 @@ impl Client {
             }
         };
 
-        let response: ResponseResult = match serde_json::from_str(&text) {
-            Ok(response) => response,
-            Err(e) => {
-                error!(?e, ?text, "Could not deserialize message");
-                return;
-            }
-        };
+        traffic_log.log(TrafficDirection::Incoming, &response);
 
         let response = match response {
             Ok(response) => response,
@@ -301,23 +1340,57 @@ impl Client {
         use actions::Sync::*;
         let response = match response {
             Response::Sync(response) => match response {
+                // Confirmed again by an idempotent observe.
+                Observing(id) if endpoint_readers.contains_key(&id) => {
+                    debug!(%id, "Already observing");
+                    ClientResponse::AlreadyObserving(id)
+                }
                 Observing(id) => {
-                    let (tx, rx) = mpsc::unbounded();
+                    let (tx, reader) = EndpointReader::new(id.clone(), actions_tx.clone());
+                    endpoint_readers.insert(id, tx);
+
+                    ClientResponse::Observing(reader)
+                }
+                ObservingComposite((composite, ports)) => {
+                    debug!(%composite, "Observing composite");
 
-                    let reader = EndpointReader::new(id.clone(), rx);
-                    if let Some(_already_exists) = endpoint_readers.insert(id.clone(), tx) {
-                        panic!("Bug! Endpoint {id} already observing");
+                    let mut readers = vec![];
+                    for id in ports {
+                        let (tx, reader) = EndpointReader::new(id.clone(), actions_tx.clone());
+
+                        if let Some(_already_exists) = endpoint_readers.insert(id.clone(), tx) {
+                            panic!("Bug! Endpoint {id} already observing");
+                        }
+                        readers.push(reader);
                     }
 
-                    ClientResponse::Observing(reader)
+                    ClientResponse::ObservingComposite(MergedEndpointReader::new(readers))
+                }
+                ObservingAny((labels, endpoints)) => {
+                    debug!(%labels, "Observing any");
+
+                    let mut readers = vec![];
+                    for id in endpoints {
+                        let (tx, reader) = EndpointReader::new(id.clone(), actions_tx.clone());
+
+                        if let Some(_already_exists) = endpoint_readers.insert(id.clone(), tx) {
+                            panic!("Bug! Endpoint {id} already observing");
+                        }
+                        readers.push(reader);
+                    }
+
+                    ClientResponse::ObservingAny(readers)
                 }
-                WriteOk => ClientResponse::WriteOk,
+                WriteOk { correlation_id } => ClientResponse::WriteOk(correlation_id),
                 ObservingEvents => ClientResponse::Events(EventReader::new(
                     events_rx
                         .take()
                         .expect("Should be able to take the events receiver"),
                 )),
-                ControlQueue(_) => ClientResponse::Queued,
+                ControlQueue {
+                    queued_on,
+                    not_queued_on,
+                } => ClientResponse::Queued((queued_on, not_queued_on)),
                 ControlGranted(ref ids) => {
                     let mut writers = vec![];
                     for id in ids {
@@ -325,6 +1398,56 @@ impl Client {
                     }
                     ClientResponse::Controlling(writers)
                 }
+                Reconfigured(id) => ClientResponse::Reconfigured(id),
+                Flushed(id) => ClientResponse::Flushed(id),
+                MacroDone(done) => ClientResponse::MacroDone(done),
+                SkippingOnControlMacros(skip) => ClientResponse::SkippingOnControlMacros(skip),
+                CompactingEndpointIds(compact) => ClientResponse::CompactingEndpointIds(compact),
+                Endpoints(endpoints) => ClientResponse::Endpoints(endpoints),
+                Labels(labels) => ClientResponse::Labels(labels),
+                ControlTransferred(transferred) => ClientResponse::ControlTransferred(transferred),
+                Released(ids) => ClientResponse::Released(ids),
+                QueueCancelled(ids) => ClientResponse::QueueCancelled(ids),
+                RecentMessages(recent) => ClientResponse::RecentMessages(recent),
+                ServerTime(now) => ClientResponse::ServerTime(now),
+                Connection(info) => ClientResponse::Connection(info),
+                WriteAllowed(id) => ClientResponse::WriteAllowed(id),
+                ObservationDeferred(id) => ClientResponse::ObservationDeferred(id),
+                ControlDeferred(labels) => ClientResponse::ControlDeferred(labels),
+                NoticeSent(sent) => ClientResponse::NoticeSent(sent),
+                EndpointEnabled { endpoint, enabled } => {
+                    ClientResponse::EndpointEnabled((endpoint, enabled))
+                }
+                Pong(pong) => ClientResponse::Pong(pong),
+                Unobserved(id) => {
+                    // Sent because a reader was dropped, so nobody waits for this.
+                    debug!(%id, "No longer observing");
+                    endpoint_readers.remove(&id);
+                    return;
+                }
+                ObservingEndpointEvents(id) => {
+                    let (tx, rx) = mpsc::unbounded();
+
+                    let reader = EndpointEventReader::new(id.clone(), rx);
+                    if let Some(_already_exists) = endpoint_event_readers.insert(id.clone(), tx) {
+                        panic!("Bug! Endpoint {id} events already observed");
+                    }
+
+                    ClientResponse::ObservingEndpointEvents(reader)
+                }
+                ObservingMessages(endpoints) => {
+                    let (tx, rx) = mpsc::unbounded();
+
+                    for endpoint in &endpoints {
+                        if let Some(_already_exists) =
+                            message_readers.insert(endpoint.clone(), tx.clone())
+                        {
+                            panic!("Bug! Endpoint {endpoint} messages already observed");
+                        }
+                    }
+
+                    ClientResponse::ObservingMessages(MessageReader::new(endpoints, rx))
+                }
             },
             Response::Async(Async::Event(user_event)) => {
                 debug!(?user_event, "Async response");
@@ -333,16 +1456,103 @@ impl Client {
                     .expect("Should be alive");
                 return;
             }
-            Response::Async(Async::Message { endpoint, message }) => {
-                let tx = endpoint_readers
-                    .get_mut(&endpoint)
-                    .expect("We will not be sent messages of endpoints we are not observing");
+            Response::Async(Async::Handshake(info)) => ClientResponse::Handshake(info),
+            Response::Async(Async::Banner(text)) => {
+                info!(%text, "Server banner");
+                *banner.lock().expect("Banner lock should not be poisoned") = Some(text);
+                return;
+            }
+            Response::Async(Async::EndpointEvent(event)) => {
+                let endpoint = event.endpoint.clone();
+                // The reader may have been dropped while the event was on its way.
+                let Some(tx) = endpoint_event_readers.get_mut(&endpoint) else {
+                    debug!(%endpoint, "Event of an endpoint without an `EndpointEventReader`, dropping it");
+                    return;
+                };
+
+                if tx.unbounded_send(event).is_err() {
+                    warn!(%endpoint, "Could not forward event to user's `EndpointEventReader`- likely they have dropped it.");
+                }
+                return;
+            }
+            Response::Async(Async::Traffic(message)) => {
+                let endpoint = message.endpoint.clone();
+                // The reader may have been dropped while the message was on its way.
+                let Some(tx) = message_readers.get_mut(&endpoint) else {
+                    debug!(%endpoint, "Message of an endpoint without a `MessageReader`, dropping it");
+                    return;
+                };
 
                 if tx.unbounded_send(message).is_err() {
-                    warn!(%endpoint, "Could not forward message to user's `EventReader`- likely they have dropped it.");
+                    warn!(%endpoint, "Could not forward message to user's `MessageReader`- likely they have dropped it.");
+                }
+                return;
+            }
+            Response::Async(Async::Notice { endpoint, text }) => {
+                debug!(%endpoint, %text, "Notice");
+                Self::forward_to_reader(endpoint_readers, &endpoint, ReadItem::Notice(text));
+                return;
+            }
+            Response::Async(Async::MessagesSkipped { endpoint, skipped }) => {
+                warn!(%endpoint, %skipped, "Read too slowly, the server skipped messages");
+                return;
+            }
+            Response::Async(Async::ControlRevoked(endpoints)) => {
+                // Writes to them fail from now on, telling the user why.
+                for endpoint in &endpoints {
+                    warn!(%endpoint, "Control revoked, the endpoint was disabled");
+                }
+                controlled
+                    .lock()
+                    .expect("Controlled lock should not be poisoned")
+                    .retain(|controlled| !endpoints.contains(controlled));
+                return;
+            }
+            Response::Async(Async::EndpointClosed((endpoint, reason))) => {
+                // Dropping the sender ends the user's reader.
+                match endpoint_readers.remove(&endpoint) {
+                    Some(tx) => {
+                        // The reader may be gone already.
+                        let _ = tx.closed.send(reason);
+                    }
+                    None => warn!(%endpoint, "Closed endpoint was not being observed"),
                 }
                 return;
             }
+            Response::Async(Async::Message {
+                endpoint, message, ..
+            }) => {
+                Self::forward_to_reader(endpoint_readers, &endpoint, ReadItem::Message(message));
+                return;
+            }
+            Response::Async(Async::RepeatedMessage {
+                endpoint,
+                message,
+                repeats,
+                ..
+            }) => {
+                Self::forward_to_reader(
+                    endpoint_readers,
+                    &endpoint,
+                    ReadItem::Repeated { message, repeats },
+                );
+                return;
+            }
+            Response::Async(Async::EndpointHandle((handle, endpoint))) => {
+                debug!(%handle, %endpoint, "Endpoint handle");
+                endpoint_handles.insert(handle, endpoint);
+                return;
+            }
+            Response::Async(Async::CompactMessage {
+                handle, message, ..
+            }) => {
+                let Some(endpoint) = endpoint_handles.get(&handle) else {
+                    warn!(%handle, "Message refers to an unknown endpoint handle");
+                    return;
+                };
+                Self::forward_to_reader(endpoint_readers, endpoint, ReadItem::Message(message));
+                return;
+            }
         };
 
         if let Err(e) = responses.send(Ok(response)).await {
@@ -350,18 +1560,45 @@ impl Client {
         }
     }
 
+    fn forward_to_reader(
+        endpoint_readers: &mut HashMap<LabelledEndpointId, ReaderSender>,
+        endpoint: &LabelledEndpointId,
+        item: ReadItem,
+    ) {
+        // Messages may still be on their way after a reader was dropped.
+        let Some(tx) = endpoint_readers.get_mut(endpoint) else {
+            debug!(%endpoint, "Message of an endpoint no longer observed");
+            return;
+        };
+
+        if tx.items.unbounded_send(item).is_err() {
+            warn!(%endpoint, "Could not forward message to user's `EventReader`- likely they have dropped it.");
+        }
+    }
+
     async fn run(self) {
-        let (mut ws_tx, mut ws_rx) = self.stream.split();
+        let (mut ws_tx, mut ws_rx) = (self.sink, self.stream);
 
         let mut actions_rx = self.action_requests_rx;
         let mut response_tx = self.responses;
 
+        let binary_frames = self.binary_frames;
+        let traffic_log = self.traffic_log;
+        let banner = self.banner;
+        let controlled = self.controlled;
+        let actions_traffic_log = traffic_log.clone();
+
         let actions_handle = tokio::spawn(async move {
             while let Some(action) = actions_rx.next().await {
-                if let Err(e) = ws_tx
-                    .send(tungstenite::Message::Text(action.serialize()))
-                    .await
-                {
+                actions_traffic_log.log(TrafficDirection::Outgoing, &action);
+
+                let message = if binary_frames.load(Ordering::Relaxed) {
+                    tungstenite::Message::Binary(action.serialize_binary())
+                } else {
+                    tungstenite::Message::Text(action.serialize())
+                };
+
+                if let Err(e) = ws_tx.send(message).await {
                     error!(?e, "Could not send message to server");
                     break;
                 }
@@ -369,6 +1606,9 @@ impl Client {
         });
 
         let mut endpoint_readers = self.endpoint_readers;
+        let mut endpoint_handles = HashMap::new();
+        let mut endpoint_event_readers = self.endpoint_event_readers;
+        let mut message_readers = self.message_readers;
         let mut user_events_tx = self.events_tx;
         let mut user_events_rx = self.events_rx;
 
@@ -382,10 +1622,16 @@ impl Client {
                 Self::handle_websocket_message(
                     ws_msg,
                     &mut endpoint_readers,
+                    &mut endpoint_handles,
+                    &mut endpoint_event_readers,
+                    &mut message_readers,
                     &mut response_tx,
                     actions_tx,
                     &mut user_events_tx,
                     &mut user_events_rx,
+                    &traffic_log,
+                    &banner,
+                    &controlled,
                 )
                 .await;
             }
@@ -409,81 +1655,363 @@ impl ClientHandleTx {
             .map_err(|e| Error::WebsocketIssue(e.to_string()))
     }
 
-    /// Send an [`Action`] to start observing a TTY endpoint with the given path.
-    pub async fn observe_tty(&mut self, tty: &str) -> Result<(), Error> {
-        self.send_or_ws_issue(Action::observe_tty(tty)).await
+    /// Send an [`Action`] to opt in or out of on control macros.
+    pub async fn skip_on_control_macros(&mut self, skip: bool) -> Result<(), Error> {
+        self.send_or_ws_issue(Action::skip_on_control_macros(skip))
+            .await
     }
 
-    /// Send an [`Action`] to start observing a mock endpoint with the given name.
-    pub async fn observe_mock(&mut self, name: &str) -> Result<(), Error> {
-        self.send_or_ws_issue(Action::observe_mock(name)).await
+    /// Send an [`Action`] to opt in or out of compact endpoint ids.
+    pub async fn compact_endpoint_ids(&mut self, compact: bool) -> Result<(), Error> {
+        self.send_or_ws_issue(Action::compact_endpoint_ids(compact))
+            .await
     }
 
-    /// Send an [`Action`] to start controlling a mock endpoint with the given name.
-    pub async fn control_mock(&mut self, name: &str) -> Result<(), Error> {
-        self.send_or_ws_issue(Action::control_mock(name)).await
+    /// Send an [`Action`] to hand control of the given endpoint to another user.
+    pub async fn transfer_control(&mut self, id: &EndpointId, to_user: &str) -> Result<(), Error> {
+        self.send_or_ws_issue(Action::transfer_control(id, to_user))
+            .await
     }
 
-    /// Send an [`Action`] to start controlling a tty endpoint with the given path.
-    pub async fn control_tty(&mut self, path: &str) -> Result<(), Error> {
-        self.send_or_ws_issue(Action::control_tty(path)).await
+    /// Send an [`Action`] to stop controlling the given endpoint.
+    pub async fn release(&mut self, id: &EndpointId) -> Result<(), Error> {
+        self.send_or_ws_issue(Action::release(id)).await
     }
 
-    /// Control any endpoint matching all the given labels.
-    pub async fn control_any<S: AsRef<str>>(&mut self, labels: &[S]) -> Result<(), Error> {
-        self.send_or_ws_issue(Action::control_any(labels)).await
+    /// Send an [`Action`] to stop waiting in any control queue.
+    pub async fn cancel_queue(&mut self) -> Result<(), Error> {
+        self.send_or_ws_issue(Action::cancel_queue()).await
     }
 
-    /// Start observing events.
-    pub async fn observe_events(&mut self) -> Result<(), Error> {
-        self.send_or_ws_issue(Action::ObserveEvents).await
+    /// Send an [`Action`] to get the given number of recent messages of the endpoint.
+    pub async fn recent_messages(&mut self, id: &EndpointId, count: usize) -> Result<(), Error> {
+        self.send_or_ws_issue(Action::recent_messages(id, count))
+            .await
     }
-}
 
-impl Sink<Action> for ClientHandleTx {
-    type Error = mpsc::SendError;
+    /// Send an [`Action`] to get the server's time.
+    pub async fn server_time(&mut self) -> Result<(), Error> {
+        self.send_or_ws_issue(Action::server_time()).await
+    }
 
-    fn poll_ready(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
-        self.0.poll_ready(cx)
+    /// Send an [`Action`] to get the server's view of the connection.
+    pub async fn who_am_i(&mut self) -> Result<(), Error> {
+        self.send_or_ws_issue(Action::who_am_i()).await
     }
 
-    fn start_send(mut self: Pin<&mut Self>, item: Action) -> Result<(), Self::Error> {
-        self.0.start_send_unpin(item)
+    /// Send an [`Action`] to send a notice to the observers of the given endpoint.
+    pub async fn notice(&mut self, id: &EndpointId, text: &str) -> Result<(), Error> {
+        self.send_or_ws_issue(Action::notice(id, text)).await
     }
 
-    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
-        self.0.poll_flush_unpin(cx)
+    /// Send an [`Action`] to enable or disable the given endpoint.
+    pub async fn set_endpoint_enabled(
+        &mut self,
+        id: &EndpointId,
+        enabled: bool,
+        release_holders: bool,
+    ) -> Result<(), Error> {
+        self.send_or_ws_issue(Action::set_endpoint_enabled(id, enabled, release_holders))
+            .await
     }
 
-    fn poll_close(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
-        self.0.poll_close_unpin(cx)
+    /// Send an [`Action`] to ping the given endpoint.
+    pub async fn ping_endpoint(
+        &mut self,
+        id: &EndpointId,
+        probe: &[u8],
+        expect: &[u8],
+        timeout: Duration,
+    ) -> Result<(), Error> {
+        self.send_or_ws_issue(Action::ping_endpoint(id, probe, expect, timeout))
+            .await
     }
-}
 
-/// The single receiver a client has for responses from the server.
-#[derive(Debug)]
-pub struct ClientHandleRx {
-    responses: mpsc::UnboundedReceiver<Result<ClientResponse, Error>>,
-}
+    /// Send an [`Action`] to check whether the given endpoint may be written to.
+    pub async fn can_write(&mut self, id: &EndpointId) -> Result<(), Error> {
+        self.send_or_ws_issue(Action::can_write(id)).await
+    }
 
-impl ClientHandleRx {
-    /// Await the next response from the transport.
-    pub async fn next_response(&mut self) -> Result<ClientResponse, Error> {
-        debug!("Waiting");
-        self.responses
-            .next()
-            .await
-            .ok_or_else(|| Error::WebsocketIssue("Next was None".into()))?
+    /// Send an [`Action`] to list the endpoints the server knows of.
+    pub async fn list_endpoints(&mut self) -> Result<(), Error> {
+        self.send_or_ws_issue(Action::list_endpoints()).await
     }
-}
 
-impl ClientHandle {
-    async fn new_stream(
-        address: &str,
+    /// Send an [`Action`] to list the labels of the endpoints the server knows of.
+    pub async fn list_labels(&mut self) -> Result<(), Error> {
+        self.send_or_ws_issue(Action::list_labels()).await
+    }
+
+    /// Send an [`Action`] to start observing the given endpoint.
+    pub async fn observe(&mut self, id: &EndpointId) -> Result<(), Error> {
+        self.send_or_ws_issue(Action::observe(id)).await
+    }
+
+    /// Send an [`Action`] to start observing the given endpoint, unless it already is.
+    pub async fn observe_idempotent(&mut self, id: &EndpointId) -> Result<(), Error> {
+        self.send_or_ws_issue(Action::observe_idempotent(id)).await
+    }
+
+    /// Send an [`Action`] to start observing the given endpoint for the given number of messages.
+    pub async fn observe_limited(&mut self, id: &EndpointId, limit: usize) -> Result<(), Error> {
+        self.send_or_ws_issue(Action::observe_limited(id, limit))
+            .await
+    }
+
+    /// Send an [`Action`] to start observing the given endpoint, starting with its recent messages.
+    pub async fn observe_replayed(&mut self, id: &EndpointId) -> Result<(), Error> {
+        self.send_or_ws_issue(Action::observe_replayed(id)).await
+    }
+
+    /// Send an [`Action`] to start observing the given endpoint,
+    /// starting with the recent messages newer than `since`.
+    pub async fn observe_since(
+        &mut self,
+        id: &EndpointId,
+        since: chrono::DateTime<chrono::Utc>,
+    ) -> Result<(), Error> {
+        self.send_or_ws_issue(Action::observe_since(id, since))
+            .await
+    }
+
+    /// Send an [`Action`] to start observing the given endpoint, collapsing identical consecutive messages.
+    pub async fn observe_deduplicated(&mut self, id: &EndpointId) -> Result<(), Error> {
+        self.send_or_ws_issue(Action::observe_deduplicated(id))
+            .await
+    }
+
+    /// Send an [`Action`] to start observing the given endpoint, even if it does not exist yet.
+    pub async fn observe_deferred(&mut self, id: &EndpointId) -> Result<(), Error> {
+        self.send_or_ws_issue(Action::observe_deferred(id)).await
+    }
+
+    /// Send an [`Action`] to start observing the given endpoint without keeping it alive.
+    pub async fn observe_weak(&mut self, id: &EndpointId) -> Result<(), Error> {
+        self.send_or_ws_issue(Action::observe_weak(id)).await
+    }
+
+    /// Send an [`Action`] to start observing the given endpoint while keeping others from observing it.
+    pub async fn observe_exclusive(&mut self, id: &EndpointId) -> Result<(), Error> {
+        self.send_or_ws_issue(Action::observe_exclusive(id)).await
+    }
+
+    /// Send an [`Action`] to start observing every endpoint matching the labels.
+    pub async fn observe_any<S: AsRef<str>>(&mut self, labels: &[S]) -> Result<(), Error> {
+        self.send_or_ws_issue(Action::observe_any(labels)).await
+    }
+
+    /// Send an [`Action`] to start observing a TTY endpoint with the given path.
+    pub async fn observe_tty(&mut self, tty: &str) -> Result<(), Error> {
+        self.send_or_ws_issue(Action::observe_tty(tty)).await
+    }
+
+    /// Send an [`Action`] to start observing a mock endpoint with the given name.
+    pub async fn observe_mock(&mut self, name: &str) -> Result<(), Error> {
+        self.send_or_ws_issue(Action::observe_mock(name)).await
+    }
+
+    /// Send an [`Action`] to start controlling the given endpoint.
+    pub async fn control(&mut self, id: &EndpointId) -> Result<(), Error> {
+        self.send_or_ws_issue(Action::control(id)).await
+    }
+
+    /// Send an [`Action`] to start controlling a mock endpoint with the given name.
+    pub async fn control_mock(&mut self, name: &str) -> Result<(), Error> {
+        self.send_or_ws_issue(Action::control_mock(name)).await
+    }
+
+    /// Send an [`Action`] to start controlling a tty endpoint with the given path.
+    pub async fn control_tty(&mut self, path: &str) -> Result<(), Error> {
+        self.send_or_ws_issue(Action::control_tty(path)).await
+    }
+
+    /// Control any endpoint matching all the given labels.
+    pub async fn control_any<S: AsRef<str>>(&mut self, labels: &[S]) -> Result<(), Error> {
+        self.send_or_ws_issue(Action::control_any(labels)).await
+    }
+
+    /// Send an [`Action`] to start controlling any endpoint(s) with the matching label(s),
+    /// waiting up to the timeout for a matching one to appear.
+    pub async fn control_any_when_available<S: AsRef<str>>(
+        &mut self,
+        labels: &[S],
+        timeout: Duration,
+    ) -> Result<(), Error> {
+        self.send_or_ws_issue(Action::control_any_when_available(labels, timeout))
+            .await
+    }
+
+    /// Start observing events.
+    pub async fn observe_events(&mut self) -> Result<(), Error> {
+        self.send_or_ws_issue(Action::ObserveEvents).await
+    }
+
+    /// Send an [`Action`] to start observing the events of the given endpoint.
+    pub async fn observe_endpoint_events(&mut self, id: &EndpointId) -> Result<(), Error> {
+        self.send_or_ws_issue(Action::observe_endpoint_events(id))
+            .await
+    }
+
+    /// Send an [`Action`] to start observing every message of the given endpoints.
+    pub async fn observe_messages(&mut self, ids: &[EndpointId]) -> Result<(), Error> {
+        self.send_or_ws_issue(Action::observe_messages(ids)).await
+    }
+
+    /// Send an [`Action`] to change the settings of a controlled endpoint.
+    pub async fn reconfigure(
+        &mut self,
+        id: &EndpointId,
+        baud: Option<u32>,
+        flow_control: Option<serialport::FlowControl>,
+    ) -> Result<(), Error> {
+        self.send_or_ws_issue(Action::reconfigure(id, baud, flow_control))
+            .await
+    }
+
+    /// Send an [`Action`] to discard what a controlled endpoint has buffered.
+    pub async fn flush(&mut self, id: &EndpointId, tx: bool, rx: bool) -> Result<(), Error> {
+        self.send_or_ws_issue(Action::flush(id, tx, rx)).await
+    }
+}
+
+impl Sink<Action> for ClientHandleTx {
+    type Error = mpsc::SendError;
+
+    fn poll_ready(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.0.poll_ready(cx)
+    }
+
+    fn start_send(mut self: Pin<&mut Self>, item: Action) -> Result<(), Self::Error> {
+        self.0.start_send_unpin(item)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.0.poll_flush_unpin(cx)
+    }
+
+    fn poll_close(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.0.poll_close_unpin(cx)
+    }
+}
+
+/// The single receiver a client has for responses from the server.
+#[derive(Debug)]
+pub struct ClientHandleRx {
+    responses: mpsc::UnboundedReceiver<Result<ClientResponse, Error>>,
+
+    /// For cleaning up after control requests which were given up on.
+    actions: mpsc::UnboundedSender<Action>,
+
+    /// How many queue cancellations are not yet confirmed.
+    /// Responses until then belong to the control request which was given up on.
+    cancelled_queues: usize,
+
+    /// Endpoints granted to a control request which was given up on,
+    /// and released again.
+    stale_grants: Vec<LabelledEndpointId>,
+}
+
+impl ClientHandleRx {
+    /// Await the next response from the transport.
+    pub async fn next_response(&mut self) -> Result<ClientResponse, Error> {
+        loop {
+            debug!("Waiting");
+            let response = self
+                .responses
+                .next()
+                .await
+                .ok_or_else(|| Error::WebsocketIssue("Next was None".into()))?;
+
+            if !self.is_leftover(&response) {
+                return response;
+            }
+        }
+    }
+
+    /// Stop waiting for control, see [`ClientHandle::control_any_future`].
+    fn give_up_control(&mut self) {
+        if self.actions.unbounded_send(Action::cancel_queue()).is_ok() {
+            self.cancelled_queues += 1;
+        }
+    }
+
+    /// Whether the response is left over from a control request which was given up on.
+    fn is_leftover(&mut self, response: &Result<ClientResponse, Error>) -> bool {
+        match response {
+            Ok(ClientResponse::Released(ids))
+                if self.stale_grants.iter().any(|stale| ids.contains(stale)) =>
+            {
+                self.stale_grants.retain(|stale| !ids.contains(stale));
+                true
+            }
+            _ if self.cancelled_queues == 0 => false,
+            Ok(ClientResponse::QueueCancelled(_)) => {
+                self.cancelled_queues -= 1;
+                true
+            }
+            Ok(ClientResponse::Controlling(writers)) => {
+                // Granted before the server saw the cancellation, so give it back.
+                if let Some(writer) = writers.first() {
+                    let id = writer.endpoint_id().clone();
+                    debug!(%id, "Releasing control nobody waits for anymore");
+
+                    if self.actions.unbounded_send(Action::release(&id.id)).is_ok() {
+                        self.stale_grants.push(id);
+                    }
+                }
+                true
+            }
+            // E.g. being queued, or the request failing.
+            _ => true,
+        }
+    }
+}
+
+/// Gives up waiting for control when dropped, unless control was granted.
+/// See [`ClientHandle::control_any_future`].
+struct ControlWait<'a> {
+    client: &'a mut ClientHandle,
+    waiting: bool,
+}
+
+impl Drop for ControlWait<'_> {
+    fn drop(&mut self) {
+        if self.waiting {
+            debug!("Control no longer waited for");
+            self.client.rx.give_up_control();
+        }
+    }
+}
+
+impl ClientHandle {
+    async fn new_stream(
+        address: &str,
         port: u16,
+        path: &str,
+        session_tag: Option<&str>,
+        username: Option<&str>,
     ) -> Result<WebSocketStream<MaybeTlsStream<TcpStream>>, Error> {
-        let (stream, _) =
-            tokio_tungstenite::connect_async(format!("ws://{address}:{port}/client")).await?;
+        let mut request = format!("ws://{address}:{port}{path}").into_client_request()?;
+        request.headers_mut().insert(
+            actions::PROTOCOL_VERSION_HEADER,
+            actions::PROTOCOL_VERSION.into(),
+        );
+        if let Some(tag) = session_tag {
+            let tag = tag.parse().map_err(|_| {
+                Error::BadUsage(format!("Session tag `{tag}` is not a valid header"))
+            })?;
+            request
+                .headers_mut()
+                .insert(actions::SESSION_TAG_HEADER, tag);
+        }
+        if let Some(name) = username {
+            let name = name
+                .parse()
+                .map_err(|_| Error::InvalidUsername(format!("`{name}` is not a valid header")))?;
+            request.headers_mut().insert(actions::USERNAME_HEADER, name);
+        }
+
+        let (stream, _) = tokio_tungstenite::connect_async(request).await?;
         Ok(stream)
     }
 
@@ -492,48 +2020,217 @@ impl ClientHandle {
         port: u16,
     ) -> Result<WebSocketStream<MaybeTlsStream<TcpStream>>, Error> {
         // Wraps the async function in a blocking call.
-        block_on(Self::new_stream(address, port))
+        block_on(Self::new_stream(
+            address,
+            port,
+            actions::DEFAULT_WEBSOCKET_PATH,
+            None,
+            None,
+        ))
+    }
+
+    async fn new_impl(
+        stream: WebSocketStream<MaybeTlsStream<TcpStream>>,
+        session_tag: Option<&str>,
+        username: Option<&str>,
+    ) -> Result<Self, Error> {
+        // Unless a name was chosen, the server names users by the address it sees them connect from.
+        let user = match (username, stream.get_ref()) {
+            (Some(name), _) => Some(User::new(name)),
+            (None, MaybeTlsStream::Plain(stream)) => stream
+                .local_addr()
+                .ok()
+                .map(|addr| User::new(&addr.to_string())),
+            _ => None,
+        }
+        .map(|user| match session_tag {
+            Some(tag) => user.with_session_tag(tag),
+            None => user,
+        });
+
+        let (sink, stream) = stream.split();
+        Self::from_frames(Box::pin(sink), stream.boxed(), user).await
     }
 
-    fn new_impl(stream: WebSocketStream<MaybeTlsStream<TcpStream>>) -> Result<Self, Error> {
+    /// Run a client over the given frames, e.g. when the server is in the same process.
+    pub(crate) async fn from_frames(
+        sink: FrameSink,
+        stream: FrameStream,
+        user: Option<User>,
+    ) -> Result<Self, Error> {
         let (action_tx, action_rx) = mpsc::unbounded();
         let (response_tx, response_rx) = mpsc::unbounded();
         let (user_events_tx, user_events_rx) = mpsc::unbounded();
 
         let (cancel_tx, cancel_rx) = oneshot::channel();
+        let binary_frames = Arc::new(AtomicBool::new(false));
+        let traffic_log = TrafficLog::default();
+        let banner = Arc::new(Mutex::new(None));
+        let controlled = Arc::new(Mutex::new(vec![]));
 
         let client = Client {
             responses: response_tx,
             action_requests_tx: action_tx.clone(),
             action_requests_rx: action_rx,
+            sink,
             stream,
             endpoint_readers: HashMap::new(),
+            endpoint_event_readers: HashMap::new(),
+            message_readers: HashMap::new(),
             events_tx: user_events_tx,
             events_rx: Some(user_events_rx),
+            binary_frames: binary_frames.clone(),
+            traffic_log: traffic_log.clone(),
+            banner: banner.clone(),
+            controlled: controlled.clone(),
             close: cancel_tx,
         };
 
         tokio::spawn(async move { client.run().await });
 
+        let mut rx = ClientHandleRx {
+            responses: response_rx,
+            actions: action_tx.clone(),
+            cancelled_queues: 0,
+            stale_grants: vec![],
+        };
+
+        let server_info = match rx.next_response().await {
+            Ok(ClientResponse::Handshake(info)) => info,
+            Ok(_) => {
+                return Err(Error::InternalIssue(
+                    "The server did not start with a handshake".into(),
+                ))
+            }
+            Err(e) => return Err(e),
+        };
+        debug!(?server_info, "Handshake");
+
         Ok(Self {
             tx: ClientHandleTx(action_tx),
-            rx: ClientHandleRx {
-                responses: response_rx,
-            },
+            rx,
+            server_info,
+            user,
+            binary_frames,
+            traffic_log,
+            banner,
+            controlled,
             _cancel_rx: cancel_rx,
         })
     }
 
     /// Create a new [`ClientHandle`] from the given address and port, connecting asynchronously.
     pub async fn new(address: &str, port: u16) -> Result<Self, Error> {
-        let stream = Self::new_stream(address, port).await?;
-        Self::new_impl(stream)
+        Self::new_with_path(address, port, actions::DEFAULT_WEBSOCKET_PATH).await
+    }
+
+    /// Create a new [`ClientHandle`] like [`ClientHandle::new`],
+    /// connecting to a server serving its websocket at the given path.
+    /// See [`crate::config::Config::websocket_path`].
+    pub async fn new_with_path(address: &str, port: u16, path: &str) -> Result<Self, Error> {
+        let stream = Self::new_stream(address, port, path, None, None).await?;
+        Self::new_impl(stream, None, None).await
+    }
+
+    /// Create a new [`ClientHandle`] like [`ClientHandle::new`],
+    /// naming the session so that it can be told apart in the server's events.
+    pub async fn new_with_session_tag(
+        address: &str,
+        port: u16,
+        session_tag: &str,
+    ) -> Result<Self, Error> {
+        let stream = Self::new_stream(
+            address,
+            port,
+            actions::DEFAULT_WEBSOCKET_PATH,
+            Some(session_tag),
+            None,
+        )
+        .await?;
+        Self::new_impl(stream, Some(session_tag), None).await
+    }
+
+    /// Create a new [`ClientHandle`] like [`ClientHandle::new`],
+    /// choosing the name the server knows this user by, e.g. in events.
+    ///
+    /// The server rejects names which are not valid, or in use by another client,
+    /// with [`Error::InvalidUsername`].
+    /// See [`User::validate_name`].
+    pub async fn new_with_username(
+        address: &str,
+        port: u16,
+        username: &str,
+    ) -> Result<Self, Error> {
+        let stream = Self::new_stream(
+            address,
+            port,
+            actions::DEFAULT_WEBSOCKET_PATH,
+            None,
+            Some(username),
+        )
+        .await?;
+        Self::new_impl(stream, None, Some(username)).await
     }
 
     /// Create a new [`ClientHandle`] from the given address and port.
     pub fn new_blocking(address: &str, port: u16) -> Result<Self, Error> {
         let stream = Self::new_stream_blocking(address, port)?;
-        Self::new_impl(stream)
+        block_on(Self::new_impl(stream, None, None))
+    }
+
+    /// What the server told about itself when connecting.
+    pub fn server_info(&self) -> &ServerInfo {
+        &self.server_info
+    }
+
+    /// The features the connected server supports.
+    pub fn server_capabilities(&self) -> &[Capability] {
+        &self.server_info.capabilities
+    }
+
+    /// The message of the day the server sent, if any.
+    ///
+    /// The server sends it right after the handshake, so it is known
+    /// once any response to a request has arrived.
+    pub fn banner(&self) -> Option<String> {
+        self.banner
+            .lock()
+            .expect("Banner lock should not be poisoned")
+            .clone()
+    }
+
+    fn controlled(&self) -> MutexGuard<'_, Vec<LabelledEndpointId>> {
+        self.controlled
+            .lock()
+            .expect("Controlled lock should not be poisoned")
+    }
+
+    /// Close the client once everything sent so far has been handled.
+    ///
+    /// Pending actions, such as writes through an [`EndpointWriter`], are flushed,
+    /// and their responses awaited, before the client stops.
+    /// Responses arriving in the meantime are discarded,
+    /// but if any of them is an error the first one is returned.
+    pub async fn close(mut self) -> Result<(), Error> {
+        // The server handles actions in order,
+        // so once this is answered everything sent before it has been too.
+        self.tx.list_endpoints().await?;
+
+        let mut failed = None;
+        loop {
+            match self.rx.next_response().await {
+                Ok(ClientResponse::Endpoints(_)) => break,
+                Ok(response) => debug!(?response, "Discarded while closing"),
+                Err(Error::WebsocketIssue(e)) => return Err(Error::WebsocketIssue(e)),
+                Err(e) => {
+                    warn!(?e, "Pending action failed while closing");
+                    failed.get_or_insert(e);
+                }
+            }
+        }
+
+        // Dropping the handle stops the client's tasks.
+        failed.map_or(Ok(()), Err)
     }
 
     async fn observe_response(&mut self) -> Result<EndpointReader, Error> {
@@ -544,6 +2241,60 @@ impl ClientHandle {
         }
     }
 
+    /// Send requests in binary websocket frames with a compact encoding instead of json text.
+    /// The server answers in kind after receiving the first one.
+    ///
+    /// Fails if the server does not support [`Capability::BinaryFrames`].
+    pub fn use_binary_frames(&mut self) -> Result<(), Error> {
+        if !self.server_info.supports(Capability::BinaryFrames) {
+            return Err(Error::BadUsage(
+                "The server does not support binary frames".into(),
+            ));
+        }
+
+        self.binary_frames.store(true, Ordering::Relaxed);
+        Ok(())
+    }
+
+    /// Log every action sent and response received from now on to the given writer,
+    /// as pretty JSON along with the direction and a timestamp.
+    /// Useful for debugging the protocol.
+    pub fn with_traffic_log<W>(self, writer: W) -> Self
+    where
+        W: Write + Send + 'static,
+    {
+        self.traffic_log.set(Box::new(writer));
+        self
+    }
+
+    /// Who the server sees this client as, if known.
+    pub fn user(&self) -> Option<&User> {
+        self.user.as_ref()
+    }
+
+    /// Start observing changes in what this client controls or is queued for.
+    ///
+    /// This uses the event subscription, so it cannot be combined with [`ClientHandle::observe_events`].
+    pub async fn control_transitions(
+        &mut self,
+    ) -> Result<impl Stream<Item = ControlTransition>, Error> {
+        let user = self.user.clone().ok_or_else(|| {
+            Error::BadUsage("Not known which user the server sees this client as".into())
+        })?;
+        let reader = self.observe_events().await?;
+
+        Ok(reader.events.filter_map(move |event| {
+            let transition = match event.inner {
+                events::Event::User(user_event) if user_event.user == user => {
+                    ControlTransition::from_event(user_event.event)
+                }
+                _ => None,
+            };
+
+            async move { transition }
+        }))
+    }
+
     async fn event_response(&mut self) -> Result<EventReader, Error> {
         match self.rx.next_response().await {
             Ok(ClientResponse::Events(reader)) => Ok(reader),
@@ -569,74 +2320,853 @@ impl ClientHandle {
         self.observe_response().await
     }
 
-    async fn wait_for_control(&mut self) -> Result<Vec<EndpointWriter>, Error> {
+    /// Start observing the given endpoint.
+    pub async fn observe(&mut self, id: &EndpointId) -> Result<EndpointReader, Error> {
+        self.tx.observe(id).await?;
+        self.observe_response().await
+    }
+
+    /// Start observing the given endpoint, unless this client already does.
+    ///
+    /// Gives `None` if it is already observed, in which case the existing reader keeps receiving.
+    pub async fn observe_idempotent(
+        &mut self,
+        id: &EndpointId,
+    ) -> Result<Option<EndpointReader>, Error> {
+        self.tx.observe_idempotent(id).await?;
+
         match self.rx.next_response().await {
-            Ok(ClientResponse::Controlling(endpoints)) => {
-                for endpoint in &endpoints {
-                    info!(%endpoint, "Granted");
-                }
-                Ok(endpoints)
-            }
-            Ok(ClientResponse::Queued) => {
-                info!("Queued");
-                let after_queue = self.rx.next_response().await?;
-                match after_queue {
-                    ClientResponse::Controlling(endpoints) => {
-                        for endpoint in &endpoints {
-                            info!(%endpoint, "Granted");
-                        }
-                        Ok(endpoints)
-                    }
-                    _ => unreachable!(),
-                }
+            Ok(ClientResponse::Observing(endpoint)) => Ok(Some(endpoint)),
+            Ok(ClientResponse::AlreadyObserving(id)) => {
+                debug!(%id, "Already observing");
+                Ok(None)
             }
             Ok(_) => unreachable!(),
             Err(e) => Err(e),
         }
     }
 
-    async fn wait_for_one_writer(&mut self) -> Result<EndpointWriter, Error> {
-        let mut writers = self.wait_for_control().await?;
-        let writer = writers.remove(0);
-        assert!(writers.is_empty());
+    /// Start observing the next `limit` messages of the given endpoint.
+    /// The reader ends after that many messages.
+    pub async fn observe_limited(
+        &mut self,
+        id: &EndpointId,
+        limit: usize,
+    ) -> Result<EndpointReader, Error> {
+        self.tx.observe_limited(id, limit).await?;
+        self.observe_response().await
+    }
 
-        Ok(writer)
+    /// Start observing the given endpoint.
+    /// The reader starts with the messages the endpoint recently received,
+    /// followed by new ones.
+    pub async fn observe_replayed(&mut self, id: &EndpointId) -> Result<EndpointReader, Error> {
+        self.tx.observe_replayed(id).await?;
+        self.observe_response().await
     }
 
-    /// Start controlling the mock with the given name.
-    pub async fn control_mock(&mut self, name: &str) -> Result<EndpointWriter, Error> {
-        self.tx.control_mock(name).await?;
-        self.wait_for_one_writer().await
+    /// Start observing the given endpoint.
+    /// The reader starts with the messages the endpoint recently received after `since`,
+    /// followed by new ones.
+    /// Useful to catch up after reconnecting, without seeing messages twice.
+    pub async fn observe_since(
+        &mut self,
+        id: &EndpointId,
+        since: chrono::DateTime<chrono::Utc>,
+    ) -> Result<EndpointReader, Error> {
+        self.tx.observe_since(id, since).await?;
+        self.observe_response().await
     }
 
-    /// Start controlling the mock with the given name.
-    pub async fn control_tty(&mut self, path: &str) -> Result<EndpointWriter, Error> {
-        self.tx.control_tty(path).await?;
-        self.wait_for_one_writer().await
+    /// Start observing the given endpoint.
+    /// Identical consecutive messages are read once,
+    /// [`EndpointReader::next_item`] tells how many times they were repeated.
+    pub async fn observe_deduplicated(&mut self, id: &EndpointId) -> Result<EndpointReader, Error> {
+        self.tx.observe_deduplicated(id).await?;
+        self.observe_response().await
     }
 
-    /// Start controlling any endpoint(s) with the matching label(s).
-    pub async fn control_any<S: AsRef<str>>(
-        &mut self,
-        labels: &[S],
-    ) -> Result<EndpointWriters, Error> {
-        self.tx.control_any(labels).await?;
-        self.wait_for_control().await.map(|ew| ew.into())
+    /// Start observing the given endpoint, waiting for it to appear if it does not exist yet.
+    ///
+    /// Nothing else can be done through this handle while waiting.
+    pub async fn observe_deferred(&mut self, id: &EndpointId) -> Result<EndpointReader, Error> {
+        self.tx.observe_deferred(id).await?;
+
+        match self.rx.next_response().await {
+            Ok(ClientResponse::Observing(endpoint)) => Ok(endpoint),
+            Ok(ClientResponse::ObservationDeferred(id)) => {
+                debug!(%id, "Waiting for endpoint to appear");
+                self.observe_response().await
+            }
+            Ok(_) => unreachable!(),
+            Err(e) => Err(e),
+        }
     }
 
-    /// Start observing events from the server.
-    pub async fn observe_events(&mut self) -> Result<EventReader, Error> {
-        self.tx.observe_events().await?;
-        self.event_response().await
+    /// Start observing the given endpoint without keeping it alive.
+    /// The reader ends when the endpoint is removed.
+    pub async fn observe_weak(&mut self, id: &EndpointId) -> Result<EndpointReader, Error> {
+        self.tx.observe_weak(id).await?;
+        self.observe_response().await
     }
 
-    /// Mutable borrow of the tx.
-    pub fn tx_mut(&mut self) -> &mut ClientHandleTx {
-        &mut self.tx
+    /// Start observing the given endpoint while keeping others from observing it.
+    /// Fails with [`Error::EndpointBusy`] if someone else observes it already.
+    pub async fn observe_exclusive(&mut self, id: &EndpointId) -> Result<EndpointReader, Error> {
+        self.tx.observe_exclusive(id).await?;
+        self.observe_response().await
     }
 
-    /// Mutable borrow of the rx.
-    pub fn rx_mut(&mut self) -> &mut ClientHandleRx {
-        &mut self.rx
+    /// Start observing every endpoint matching the labels, getting a reader for each.
+    /// Endpoints matching only later on are not observed.
+    /// Fails with [`Error::NoMatchingEndpoints`] if none match.
+    pub async fn observe_any<S: AsRef<str>>(
+        &mut self,
+        labels: &[S],
+    ) -> Result<Vec<EndpointReader>, Error> {
+        self.tx.observe_any(labels).await?;
+
+        match self.rx.next_response().await {
+            Ok(ClientResponse::ObservingAny(readers)) => Ok(readers),
+            Ok(_) => unreachable!(),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Start observing the given endpoint, calling `f` with each message in a task of its own.
+    /// The callback is called until the returned [`CallbackHandle`] is stopped,
+    /// or the observation ends.
+    pub async fn observe_with_callback<F>(
+        &mut self,
+        id: &EndpointId,
+        mut f: F,
+    ) -> Result<CallbackHandle, Error>
+    where
+        F: FnMut(SerialMessage) + Send + 'static,
+    {
+        let mut reader = self.observe(id).await?;
+
+        let task = tokio::spawn(async move {
+            while let Ok(message) = reader.next_message().await {
+                f(message);
+            }
+        });
+
+        Ok(CallbackHandle { task })
+    }
+
+    /// Start observing all the given endpoints, merged into a single reader.
+    pub async fn observe_many(
+        &mut self,
+        ids: &[EndpointId],
+    ) -> Result<MergedEndpointReader, Error> {
+        let mut readers = Vec::with_capacity(ids.len());
+
+        for id in ids {
+            readers.push(self.observe(id).await?);
+        }
+
+        Ok(MergedEndpointReader::new(readers))
+    }
+
+    async fn wait_for_control(&mut self) -> Result<Vec<EndpointWriter>, Error> {
+        self.wait_for_control_outcome(Instant::now())
+            .await
+            .map(|outcome| outcome.writers)
+    }
+
+    async fn wait_for_control_outcome(
+        &mut self,
+        asked_at: Instant,
+    ) -> Result<ControlOutcome<Vec<EndpointWriter>>, Error> {
+        let outcome = self.wait_for_control_granted(asked_at).await?;
+
+        self.controlled().extend(
+            outcome
+                .writers
+                .iter()
+                .map(|writer| writer.endpoint_id().clone()),
+        );
+
+        Ok(outcome)
+    }
+
+    async fn wait_for_control_granted(
+        &mut self,
+        asked_at: Instant,
+    ) -> Result<ControlOutcome<Vec<EndpointWriter>>, Error> {
+        let response = loop {
+            match self.rx.next_response().await {
+                Ok(ClientResponse::ControlDeferred(labels)) => {
+                    info!(%labels, "Waiting for a matching endpoint");
+                }
+                response => break response,
+            }
+        };
+
+        match response {
+            Ok(ClientResponse::Controlling(endpoints)) => {
+                for endpoint in &endpoints {
+                    info!(%endpoint, "Granted");
+                }
+                Ok(ControlOutcome {
+                    writers: endpoints,
+                    was_queued: false,
+                    waited: asked_at.elapsed(),
+                    not_queued_on: vec![],
+                })
+            }
+            Ok(ClientResponse::Queued((queued_on, not_queued_on))) => {
+                for QueuedOn {
+                    endpoint,
+                    controller,
+                    position,
+                } in &queued_on
+                {
+                    info!(%endpoint, ?controller, %position, "Queued");
+                }
+                for NotQueuedOn { endpoint, error } in &not_queued_on {
+                    warn!(%endpoint, %error, "Not queued");
+                }
+                let after_queue = self.rx.next_response().await?;
+                match after_queue {
+                    ClientResponse::Controlling(endpoints) => {
+                        for endpoint in &endpoints {
+                            info!(%endpoint, "Granted");
+                        }
+                        Ok(ControlOutcome {
+                            writers: endpoints,
+                            was_queued: true,
+                            waited: asked_at.elapsed(),
+                            not_queued_on,
+                        })
+                    }
+                    _ => unreachable!(),
+                }
+            }
+            Ok(_) => unreachable!(),
+            Err(e) => Err(e),
+        }
+    }
+
+    async fn wait_for_one_writer(&mut self) -> Result<EndpointWriter, Error> {
+        let mut writers = self.wait_for_control().await?;
+        let writer = writers.remove(0);
+        assert!(writers.is_empty());
+
+        Ok(writer)
+    }
+
+    /// Start controlling the given endpoint.
+    pub async fn control(&mut self, id: &EndpointId) -> Result<EndpointWriter, Error> {
+        self.tx.control(id).await?;
+        self.wait_for_one_writer().await
+    }
+
+    /// Start controlling and observing the given endpoint.
+    pub async fn control_and_observe(
+        &mut self,
+        id: &EndpointId,
+    ) -> Result<ControlledEndpoint, Error> {
+        let writer = self.control(id).await?;
+        let reader = self.observe(id).await?;
+
+        ControlledEndpoint::new(writer, reader)
+    }
+
+    /// Start controlling the mock with the given name.
+    pub async fn control_mock(&mut self, name: &str) -> Result<EndpointWriter, Error> {
+        self.tx.control_mock(name).await?;
+        self.wait_for_one_writer().await
+    }
+
+    /// Start controlling the mock with the given name, like [`ClientHandle::control_mock`],
+    /// telling whether control was granted right away or only after queueing.
+    pub async fn control_mock_outcome(
+        &mut self,
+        name: &str,
+    ) -> Result<ControlOutcome<EndpointWriter>, Error> {
+        let asked_at = Instant::now();
+        self.tx.control_mock(name).await?;
+
+        let outcome = self.wait_for_control_outcome(asked_at).await?;
+        Ok(outcome.map(|mut writers| {
+            let writer = writers.remove(0);
+            assert!(writers.is_empty());
+            writer
+        }))
+    }
+
+    /// Start controlling the TTY at the given path.
+    /// Fails with [`Error::NoSuchEndpoint`] if the server has no TTY there.
+    pub async fn control_tty(&mut self, path: &str) -> Result<EndpointWriter, Error> {
+        self.tx.control_tty(path).await?;
+        self.wait_for_one_writer().await
+    }
+
+    /// Start observing each port of the composite endpoint with the given name.
+    /// Messages are tagged with the port they came from.
+    pub async fn observe_composite(&mut self, name: &str) -> Result<MergedEndpointReader, Error> {
+        self.tx.observe(&EndpointId::composite(name)).await?;
+
+        match self.rx.next_response().await {
+            Ok(ClientResponse::ObservingComposite(reader)) => Ok(reader),
+            Ok(_) => unreachable!(),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Start controlling the composite endpoint with the given name.
+    /// A writer is given for each port, since writes must go to a specific port.
+    pub async fn control_composite(&mut self, name: &str) -> Result<EndpointWriters, Error> {
+        self.tx.control(&EndpointId::composite(name)).await?;
+
+        let writers = self.wait_for_control().await?;
+
+        Ok(writers
+            .into_iter()
+            .filter(|writer| writer.endpoint_id().id.as_composite().is_none())
+            .collect::<Vec<_>>()
+            .into())
+    }
+
+    /// Start controlling any endpoint(s) with the matching label(s).
+    pub async fn control_any<S: AsRef<str>>(
+        &mut self,
+        labels: &[S],
+    ) -> Result<EndpointWriters, Error> {
+        self.tx.control_any(labels).await?;
+        self.wait_for_control().await.map(|ew| ew.into())
+    }
+
+    /// Start controlling any endpoint(s) with the matching label(s), like [`ClientHandle::control_any`],
+    /// telling whether control was granted right away or only after queueing.
+    pub async fn control_any_outcome<S: AsRef<str>>(
+        &mut self,
+        labels: &[S],
+    ) -> Result<ControlOutcome<EndpointWriters>, Error> {
+        let asked_at = Instant::now();
+        self.tx.control_any(labels).await?;
+
+        let outcome = self.wait_for_control_outcome(asked_at).await?;
+        Ok(outcome.map(Into::into))
+    }
+
+    /// Start controlling any endpoint(s) with the matching label(s), like [`ClientHandle::control_any`].
+    ///
+    /// If no endpoint matches yet, wait up to the timeout for one to appear.
+    /// Fails with [`Error::NoMatchingEndpoints`] if none does.
+    pub async fn control_any_when_available<S: AsRef<str>>(
+        &mut self,
+        labels: &[S],
+        timeout: Duration,
+    ) -> Result<EndpointWriters, Error> {
+        self.tx.control_any_when_available(labels, timeout).await?;
+        self.wait_for_control().await.map(|ew| ew.into())
+    }
+
+    /// Start controlling any endpoint(s) with the matching label(s), like [`ClientHandle::control_any`].
+    ///
+    /// The future may be dropped before control is granted, e.g. when `tokio::select!`-ing
+    /// on it and a timeout. The client then leaves the queue,
+    /// and releases control granted just as the future was dropped.
+    pub fn control_any_future<'a, S: AsRef<str>>(
+        &'a mut self,
+        labels: &'a [S],
+    ) -> impl Future<Output = Result<EndpointWriters, Error>> + 'a {
+        async move {
+            self.tx.control_any(labels).await?;
+
+            let mut wait = ControlWait {
+                client: self,
+                waiting: true,
+            };
+            let writers = wait.client.wait_for_control().await;
+            wait.waiting = false;
+
+            writers.map(Into::into)
+        }
+    }
+
+    /// Start controlling any endpoint(s) with the matching label(s),
+    /// leaving the queue if control is not granted within the given time.
+    pub async fn control_any_with_timeout<S: AsRef<str>>(
+        &mut self,
+        labels: &[S],
+        timeout: Duration,
+    ) -> Result<EndpointWriters, Error> {
+        tokio::time::timeout(timeout, self.control_any_future(labels))
+            .await
+            .map_err(|_| Error::Timeout(format!("Control not granted within {timeout:?}")))?
+    }
+
+    /// Start observing events from the server.
+    pub async fn observe_events(&mut self) -> Result<EventReader, Error> {
+        self.tx.observe_events().await?;
+        self.event_response().await
+    }
+
+    /// Start observing the events from the server which relate to users,
+    /// such as them connecting or gaining control.
+    pub async fn observe_user_events(&mut self) -> Result<UserEventReader, Error> {
+        self.observe_events().await.map(UserEventReader::new)
+    }
+
+    /// Start observing the events of the given endpoint, such as it going idle.
+    pub async fn observe_endpoint_events(
+        &mut self,
+        id: &EndpointId,
+    ) -> Result<EndpointEventReader, Error> {
+        self.tx.observe_endpoint_events(id).await?;
+
+        match self.rx.next_response().await {
+            Ok(ClientResponse::ObservingEndpointEvents(reader)) => Ok(reader),
+            Ok(_) => unreachable!(),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Start observing every message put on or received from wire by the given endpoints,
+    /// whoever controls them.
+    pub async fn observe_messages(&mut self, ids: &[EndpointId]) -> Result<MessageReader, Error> {
+        self.tx.observe_messages(ids).await?;
+
+        match self.rx.next_response().await {
+            Ok(ClientResponse::ObservingMessages(reader)) => Ok(reader),
+            Ok(_) => unreachable!(),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Change the baud rate and/or flow control of a controlled TTY endpoint.
+    /// Settings which are `None` are left as they are.
+    pub async fn reconfigure(
+        &mut self,
+        id: &EndpointId,
+        baud: Option<u32>,
+        flow_control: Option<serialport::FlowControl>,
+    ) -> Result<(), Error> {
+        self.tx.reconfigure(id, baud, flow_control).await?;
+
+        match self.rx.next_response().await {
+            Ok(ClientResponse::Reconfigured(_)) => Ok(()),
+            Ok(_) => unreachable!(),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Discard what a controlled endpoint has buffered for the wire (`tx`)
+    /// and/or from the wire (`rx`).
+    pub async fn flush(&mut self, id: &EndpointId, tx: bool, rx: bool) -> Result<(), Error> {
+        self.tx.flush(id, tx, rx).await?;
+
+        match self.rx.next_response().await {
+            Ok(ClientResponse::Flushed(_)) => Ok(()),
+            Ok(_) => unreachable!(),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Opt in or out of macros the server runs when control is granted.
+    pub async fn skip_on_control_macros(&mut self, skip: bool) -> Result<(), Error> {
+        self.tx.skip_on_control_macros(skip).await?;
+
+        match self.rx.next_response().await {
+            Ok(ClientResponse::SkippingOnControlMacros(_)) => Ok(()),
+            Ok(_) => unreachable!(),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Opt in or out of the server referring to endpoints observed from now on by short handles.
+    /// This makes message frames smaller, and is transparent to readers.
+    pub async fn compact_endpoint_ids(&mut self, compact: bool) -> Result<(), Error> {
+        self.tx.compact_endpoint_ids(compact).await?;
+
+        match self.rx.next_response().await {
+            Ok(ClientResponse::CompactingEndpointIds(_)) => Ok(()),
+            Ok(_) => unreachable!(),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Hand control of the given endpoint to another connected user.
+    /// The user receiving control gets it via [`ClientHandle::receive_transferred_control`].
+    pub async fn transfer_control(&mut self, id: &EndpointId, to_user: &User) -> Result<(), Error> {
+        self.tx.transfer_control(id, &to_user.name).await?;
+
+        match self.rx.next_response().await {
+            Ok(ClientResponse::ControlTransferred(_)) => {
+                self.controlled().retain(|controlled| &controlled.id != id);
+                Ok(())
+            }
+            Ok(_) => unreachable!(),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Stop controlling the writer's endpoint, letting the next user in queue have it.
+    pub async fn release(&mut self, writer: EndpointWriter) -> Result<(), Error> {
+        self.tx.release(&writer.endpoint_id().id).await?;
+
+        loop {
+            match self.rx.next_response().await {
+                Ok(ClientResponse::Released(ids)) => {
+                    self.controlled()
+                        .retain(|controlled| !ids.contains(controlled));
+                    return Ok(());
+                }
+                // Writes are not awaited, so their confirmations may still be pending.
+                Ok(ClientResponse::WriteOk(_)) => continue,
+                Ok(_) => unreachable!(),
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    /// Write to the writer's endpoint, waiting for the server to confirm it.
+    async fn write_confirmed<M>(
+        &mut self,
+        writer: &mut EndpointWriter,
+        message: M,
+    ) -> Result<(), Error>
+    where
+        M: AsRef<[u8]>,
+    {
+        writer.write(message).await?;
+
+        match self.rx.next_response().await {
+            Ok(ClientResponse::WriteOk(_)) => Ok(()),
+            Ok(_) => unreachable!(),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Write to the one controlled endpoint bearing the given label, waiting for the server to confirm it.
+    /// Saves holding on to the writer after e.g. [`ClientHandle::control_any`] granted control of it.
+    ///
+    /// It is an error if no endpoint, or several endpoints, controlled by this client bear the label.
+    pub async fn write_to_label<M>(&mut self, label: &str, message: M) -> Result<(), Error>
+    where
+        M: AsRef<[u8]>,
+    {
+        let label = Label::new(label);
+        let matching = self
+            .controlled()
+            .iter()
+            .filter(|controlled| controlled.labels.iter().any(|l| l == &label))
+            .cloned()
+            .collect::<Vec<_>>();
+
+        let id = match matching.as_slice() {
+            [id] => id.clone(),
+            [] => {
+                return Err(Error::BadUsage(format!(
+                    "No controlled endpoint is labelled `{label}`"
+                )))
+            }
+            ambiguous => {
+                return Err(Error::BadUsage(format!(
+                    "The label `{label}` is ambiguous, it matches {} controlled endpoints",
+                    ambiguous.len()
+                )))
+            }
+        };
+
+        let mut writer = EndpointWriter::new(id, self.tx.0.clone());
+        self.write_confirmed(&mut writer, message).await
+    }
+
+    /// Control the given endpoint, write a message to it, then release it right away.
+    ///
+    /// The endpoint is released even if the write fails.
+    pub async fn control_write_release<M>(
+        &mut self,
+        id: &EndpointId,
+        message: M,
+    ) -> Result<(), Error>
+    where
+        M: AsRef<[u8]>,
+    {
+        let mut writer = self.control(id).await?;
+
+        let written = self.write_confirmed(&mut writer, message).await;
+        let released = self.release(writer).await;
+
+        written.and(released)
+    }
+
+    /// Like [`ClientHandle::control_write_release`], but for any endpoint matching all the given labels.
+    ///
+    /// If control is granted of a group, the message is written to the endpoint matching the labels,
+    /// and the whole group is released.
+    pub async fn control_any_write_release<S, M>(
+        &mut self,
+        labels: &[S],
+        message: M,
+    ) -> Result<(), Error>
+    where
+        S: AsRef<str>,
+        M: AsRef<[u8]>,
+    {
+        let mut writers = self.control_any(labels).await?;
+
+        let written = match writers.remove_writer_with_labels(&labels.iter().collect()) {
+            Some(mut writer) => {
+                let written = self.write_confirmed(&mut writer, message).await;
+                writers.0.push(writer);
+                written
+            }
+            None => Err(Error::InternalIssue(
+                "Control was granted without an endpoint matching the labels".into(),
+            )),
+        };
+
+        // The writers share one semaphore, so releasing one of them releases the whole group.
+        let released = match writers.0.into_iter().next() {
+            Some(writer) => self.release(writer).await,
+            None => Ok(()),
+        };
+
+        written.and(released)
+    }
+
+    /// Check whether writing to the given endpoint would be allowed, without writing anything.
+    /// Writing is allowed if the endpoint is controlled by this client.
+    pub async fn can_write(&mut self, id: &EndpointId) -> Result<(), Error> {
+        self.tx.can_write(id).await?;
+
+        loop {
+            match self.rx.next_response().await {
+                Ok(ClientResponse::WriteAllowed(_)) => return Ok(()),
+                // Writes are not awaited, so their confirmations may still be pending.
+                Ok(ClientResponse::WriteOk(_)) => continue,
+                Ok(_) => unreachable!(),
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    /// Wait for another user to hand control over to us.
+    pub async fn receive_transferred_control(&mut self) -> Result<Vec<EndpointWriter>, Error> {
+        self.wait_for_control().await
+    }
+
+    /// List the endpoints the server knows of, along with their descriptions.
+    pub async fn list_endpoints(&mut self) -> Result<Vec<ListedEndpoint>, Error> {
+        self.tx.list_endpoints().await?;
+
+        match self.rx.next_response().await {
+            Ok(ClientResponse::Endpoints(endpoints)) => Ok(endpoints),
+            Ok(_) => unreachable!(),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// List the distinct labels of the endpoints the server knows of,
+    /// along with how many endpoints carry each.
+    pub async fn list_labels(&mut self) -> Result<Vec<ListedLabel>, Error> {
+        self.tx.list_labels().await?;
+
+        match self.rx.next_response().await {
+            Ok(ClientResponse::Labels(labels)) => Ok(labels),
+            Ok(_) => unreachable!(),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Get up to `count` of the most recent messages the endpoint received, oldest first.
+    /// Neither control nor observing the endpoint is needed.
+    pub async fn recent_messages(
+        &mut self,
+        id: &EndpointId,
+        count: usize,
+    ) -> Result<Vec<SerialMessage>, Error> {
+        self.tx.recent_messages(id, count).await?;
+
+        match self.rx.next_response().await {
+            Ok(ClientResponse::RecentMessages((_, messages))) => Ok(messages),
+            Ok(_) => unreachable!(),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Get the server's current time.
+    /// Comparing it to the local time gives how far off the clocks are,
+    /// give or take the round trip.
+    pub async fn server_time(&mut self) -> Result<DateTime<Utc>, Error> {
+        self.tx.server_time().await?;
+
+        match self.rx.next_response().await {
+            Ok(ClientResponse::ServerTime(now)) => Ok(now),
+            Ok(_) => unreachable!(),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Get the server's view of this connection,
+    /// e.g. to see which address the server sees behind a proxy,
+    /// or which of several sessions of the same user this is.
+    pub async fn who_am_i(&mut self) -> Result<ConnectionInfo, Error> {
+        self.tx.who_am_i().await?;
+
+        match self.rx.next_response().await {
+            Ok(ClientResponse::Connection(info)) => Ok(info),
+            Ok(_) => unreachable!(),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Send a notice to the observers of the given endpoint, returning how many were told.
+    /// Only admins may send notices, see [`crate::config::Config::admins`].
+    pub async fn notice(&mut self, id: &EndpointId, text: &str) -> Result<usize, Error> {
+        self.tx.notice(id, text).await?;
+
+        match self.rx.next_response().await {
+            Ok(ClientResponse::NoticeSent((_, observers))) => Ok(observers),
+            Ok(_) => unreachable!(),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Take the given endpoint out of rotation, or put it back.
+    /// When disabling, `release_holders` makes those controlling it lose control.
+    /// Only admins may do this, see [`crate::config::Config::admins`].
+    pub async fn set_endpoint_enabled(
+        &mut self,
+        id: &EndpointId,
+        enabled: bool,
+        release_holders: bool,
+    ) -> Result<(), Error> {
+        self.tx
+            .set_endpoint_enabled(id, enabled, release_holders)
+            .await?;
+
+        match self.rx.next_response().await {
+            Ok(ClientResponse::EndpointEnabled(_)) => Ok(()),
+            Ok(_) => unreachable!(),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Check the device of a controlled endpoint is responsive, by having the server
+    /// put the probe on wire and wait for the expected message from wire.
+    /// Returns how long the answer took, or [`Error::Timeout`] if it did not arrive in time.
+    pub async fn ping_endpoint(
+        &mut self,
+        id: &EndpointId,
+        probe: &[u8],
+        expect: &[u8],
+        timeout: Duration,
+    ) -> Result<Duration, Error> {
+        self.tx.ping_endpoint(id, probe, expect, timeout).await?;
+
+        match self.rx.next_response().await {
+            Ok(ClientResponse::Pong((_, round_trip))) => Ok(round_trip),
+            Ok(_) => unreachable!(),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Mutable borrow of the tx.
+    pub fn tx_mut(&mut self) -> &mut ClientHandleTx {
+        &mut self.tx
+    }
+
+    /// Mutable borrow of the rx.
+    pub fn rx_mut(&mut self) -> &mut ClientHandleRx {
+        &mut self.rx
+    }
+}
+
+/// A blocking façade over [`ClientHandle`] for scripts which would rather not deal with async.
+///
+/// It owns a runtime which drives the client in the background,
+/// so it must not be created or used from within an async context.
+///
+/// ```no_run
+/// use serial_keel::client::BlockingClient;
+///
+/// let mut client = BlockingClient::new("localhost", 3123)?;
+///
+/// let mut writer = client.control_mock("my-mock")?;
+/// let mut reader = client.observe_mock("my-mock")?;
+///
+/// client.write(&mut writer, "Hello\n")?;
+/// assert_eq!(client.next_message(&mut reader)?.as_str(), "Hello");
+/// # Ok::<(), serial_keel::error::Error>(())
+/// ```
+#[derive(Debug)]
+pub struct BlockingClient {
+    // Declared first so the client is dropped before the runtime driving it.
+    handle: ClientHandle,
+    runtime: tokio::runtime::Runtime,
+}
+
+impl BlockingClient {
+    /// Create a new [`BlockingClient`] connected to the given address and port.
+    pub fn new(address: &str, port: u16) -> Result<Self, Error> {
+        let runtime = tokio::runtime::Runtime::new()
+            .map_err(|e| Error::InternalIssue(format!("Could not start runtime: {e}")))?;
+
+        let handle = runtime.block_on(ClientHandle::new(address, port))?;
+
+        Ok(Self { handle, runtime })
+    }
+
+    /// Start observing the TTY with the given path.
+    pub fn observe_tty(&mut self, path: &str) -> Result<EndpointReader, Error> {
+        self.runtime.block_on(self.handle.observe_tty(path))
+    }
+
+    /// Start observing the mock with the given name.
+    pub fn observe_mock(&mut self, name: &str) -> Result<EndpointReader, Error> {
+        self.runtime.block_on(self.handle.observe_mock(name))
+    }
+
+    /// Control the TTY with the given path.
+    /// Blocks until control is granted.
+    pub fn control_tty(&mut self, path: &str) -> Result<EndpointWriter, Error> {
+        self.runtime.block_on(self.handle.control_tty(path))
+    }
+
+    /// Control the mock with the given name.
+    /// Blocks until control is granted.
+    pub fn control_mock(&mut self, name: &str) -> Result<EndpointWriter, Error> {
+        self.runtime.block_on(self.handle.control_mock(name))
+    }
+
+    /// Control any endpoint matching all the given labels.
+    /// Blocks until control is granted.
+    pub fn control_any<S: AsRef<str>>(&mut self, labels: &[S]) -> Result<EndpointWriters, Error> {
+        self.runtime.block_on(self.handle.control_any(labels))
+    }
+
+    /// Start observing events from the server.
+    pub fn observe_events(&mut self) -> Result<EventReader, Error> {
+        self.runtime.block_on(self.handle.observe_events())
+    }
+
+    /// Write a message using the given writer.
+    pub fn write<M>(&self, writer: &mut EndpointWriter, message: M) -> Result<(), Error>
+    where
+        M: AsRef<[u8]>,
+    {
+        self.runtime.block_on(writer.write(message))
+    }
+
+    /// Block until the next message arrives on the given reader.
+    pub fn next_message(&self, reader: &mut EndpointReader) -> Result<SerialMessage, Error> {
+        self.runtime.block_on(reader.next_message())
+    }
+
+    /// Block until the next event arrives on the given reader.
+    pub fn next_event(&self, reader: &mut EventReader) -> events::TimestampedEvent {
+        self.runtime.block_on(reader.next_event())
+    }
+
+    /// Borrow the underlying async handle.
+    pub fn handle_mut(&mut self) -> &mut ClientHandle {
+        &mut self.handle
     }
 }
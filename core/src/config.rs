@@ -1,11 +1,20 @@
-use std::path::Path;
+use std::{
+    collections::{BTreeMap, HashSet},
+    path::Path,
+    time::Duration,
+};
 
 use itertools::Itertools;
 use serde::{Deserialize, Serialize};
+use tracing::warn;
 
 use crate::{
     endpoint::{EndpointId, Label, Labels},
     error::Error,
+    logging::LogLevel,
+    serial::{MockEcho, Transform},
+    server,
+    user::User,
 };
 
 /// A group of endpoints, identified by their ids.
@@ -25,6 +34,26 @@ pub struct Group {
     /// The endpoints which are members of this group.
     /// Implies shared control.
     pub endpoints: Vec<ConfigEndpoint>,
+
+    /// A human readable description of the group, e.g. "Lab bench 3, nRF5340 + power monitor".
+    /// Used for each member endpoint which has no description of its own.
+    #[serde(default)]
+    pub description: Option<String>,
+
+    /// A name for the group, e.g. "bench-3".
+    /// Mock members are owned by this name, which is what shows up in logs.
+    /// If not given, the group's labels are used, and failing that its position in the config.
+    #[serde(default)]
+    pub name: Option<String>,
+
+    /// If given, only these users may control the group's endpoints.
+    /// Applies on top of what each member endpoint allows.
+    #[serde(default)]
+    pub allow_users: Option<Vec<String>>,
+
+    /// These users may not control the group's endpoints.
+    #[serde(default)]
+    pub deny_users: Vec<String>,
 }
 
 impl From<EndpointId> for ConfigEndpoint {
@@ -33,6 +62,19 @@ impl From<EndpointId> for ConfigEndpoint {
             id: endpoint_id,
             labels: Labels::default(),
             flow_control: None,
+            baud: None,
+            idle_message_timeout: None,
+            write_terminator: None,
+            transforms: vec![],
+            write_delay: None,
+            echo: MockEcho::Echo,
+            shared_control: false,
+            allow_users: None,
+            deny_users: vec![],
+            description: None,
+            read_buffer_size: None,
+            charset: None,
+            prompt: None,
         }
     }
 }
@@ -42,8 +84,25 @@ impl Group {
         Self {
             labels: Labels::default(),
             endpoints: endpoints.into_iter().map(Into::into).collect(),
+            description: None,
+            name: None,
+            allow_users: None,
+            deny_users: vec![],
+        }
+    }
+
+    /// The owner of the group's mocks, at the given position in the config.
+    /// Does not depend on the position unless the group has neither a name nor labels.
+    pub(crate) fn mock_owner(&self, index: usize) -> String {
+        if let Some(name) = &self.name {
+            name.clone()
+        } else if !self.labels.is_empty() {
+            self.labels.iter().join("+")
+        } else {
+            format!("MockGroup{index}")
         }
     }
+
     pub(crate) fn is_mock_group(&self) -> bool {
         let member = self.endpoints.iter().last().expect("Groups are non-empty");
 
@@ -55,8 +114,16 @@ impl Group {
         Self {
             labels: Labels::from_iter(labels),
             endpoints: endpoints.into_iter().map(Into::into).collect(),
+            description: None,
+            name: None,
+            allow_users: None,
+            deny_users: vec![],
         }
     }
+
+    pub(crate) fn access_list(&self) -> AccessList {
+        AccessList::new(&self.allow_users, &self.deny_users)
+    }
 }
 
 impl From<Vec<EndpointId>> for Group {
@@ -65,6 +132,59 @@ impl From<Vec<EndpointId>> for Group {
     }
 }
 
+/// Several endpoints acting as one logical endpoint, e.g. the bootloader and application TTYs of a device.
+/// Known to users as [`EndpointId::Composite`].
+///
+/// The ports are controlled together, like the members of a [`Group`].
+/// Observing the composite observes each port, with every message tagged by the port it came from.
+/// Writes must go to a specific port.
+///
+/// Composites must uphold the same invariants as groups (validated at runtime),
+/// and their names must be unique.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Composite {
+    /// The name of the composite, e.g. "nrf5340-dk".
+    /// Mock ports are owned by this name.
+    pub name: String,
+
+    /// The label(s) for the composite.
+    /// Will propagate to each port.
+    #[serde(default)]
+    pub labels: Labels,
+
+    /// The endpoints making up the composite.
+    pub ports: Vec<ConfigEndpoint>,
+
+    /// A human readable description of the composite.
+    /// Used for each port which has no description of its own.
+    #[serde(default)]
+    pub description: Option<String>,
+}
+
+impl Composite {
+    /// A composite with the given name, made up of the given ports.
+    pub fn new(name: &str, ports: Vec<EndpointId>) -> Self {
+        Self {
+            name: name.into(),
+            labels: Labels::default(),
+            ports: ports.into_iter().map(Into::into).collect(),
+            description: None,
+        }
+    }
+
+    /// The ports as a group, since they are controlled together.
+    pub(crate) fn group(&self) -> Group {
+        Group {
+            labels: self.labels.clone(),
+            endpoints: self.ports.clone(),
+            description: self.description.clone(),
+            name: Some(self.name.clone()),
+            allow_users: None,
+            deny_users: vec![],
+        }
+    }
+}
+
 /// An endpoint as described by a configuration file.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ConfigEndpoint {
@@ -80,6 +200,86 @@ pub struct ConfigEndpoint {
     /// Only used by non-mocked endpoints.
     /// Defaults to none if not given.
     pub flow_control: Option<serialport::FlowControl>,
+
+    /// Which baud rate this endpoint should use.
+    /// Only used by non-mocked endpoints.
+    /// Defaults to 115200 if not given.
+    #[serde(default)]
+    pub baud: Option<u32>,
+
+    /// If no message arrives from wire within this long, the endpoint is considered idle.
+    /// See [`crate::events::general::Event::EndpointIdle`].
+    /// The endpoint never goes idle if not given.
+    #[serde(default)]
+    pub idle_message_timeout: Option<Duration>,
+
+    /// Appended to each message written to this endpoint, unless the message already ends with it.
+    /// For example `"\r\n"` for devices expecting that.
+    /// Messages are written unmodified if not given.
+    #[serde(default)]
+    pub write_terminator: Option<String>,
+
+    /// Applied in order to each message received from wire, before any user observes it.
+    /// For example to strip framing noise some devices emit.
+    #[serde(default)]
+    pub transforms: Vec<Transform>,
+
+    /// Only used by mocked endpoints.
+    /// Each write waits this long before being looped back, to simulate a slow device.
+    #[serde(default)]
+    pub write_delay: Option<Duration>,
+
+    /// Only used by mocked endpoints.
+    /// What the mock sends back from wire for each line written to it.
+    /// Lines are echoed as they are if not given.
+    #[serde(default)]
+    pub echo: MockEcho,
+
+    /// Let any number of users control this endpoint at the same time,
+    /// instead of one at a time.
+    /// Useful for e.g. a coordinator and a monitor both writing to a mock.
+    /// Not allowed for endpoints in groups, which are always controlled exclusively.
+    #[serde(default)]
+    pub shared_control: bool,
+
+    /// If given, only users with these names may control this endpoint.
+    /// Anyone may if not given.
+    /// Observing is not restricted.
+    #[serde(default)]
+    pub allow_users: Option<Vec<String>>,
+
+    /// Users with these names may not control this endpoint.
+    #[serde(default)]
+    pub deny_users: Vec<String>,
+
+    /// A human readable description of the endpoint, shown to clients listing endpoints.
+    /// Metadata only.
+    #[serde(default)]
+    pub description: Option<String>,
+
+    /// How many bytes are read from the port at once, which sizes the buffer lines are split in.
+    /// Larger buffers mean fewer reads for ports receiving a lot, and do not affect
+    /// how messages are split into lines.
+    /// Only used by non-mocked endpoints.
+    /// Defaults to 8 KiB if not given.
+    #[serde(default)]
+    pub read_buffer_size: Option<usize>,
+
+    /// The character encoding the endpoint's text is in, such as `latin1` or `shift_jis`.
+    /// Any label of the [Encoding Standard](https://encoding.spec.whatwg.org/#names-and-labels) works.
+    /// Messages from wire are decoded from it in events and recent messages,
+    /// observed messages are passed on as the bytes seen on wire.
+    /// Lossy UTF-8 if not given.
+    #[serde(default)]
+    pub charset: Option<String>,
+
+    /// What the device ends its responses with while waiting for the next command, e.g. `$ `.
+    /// Prompts are rarely followed by a newline, so a read ending in the prompt
+    /// is passed on as a message right away rather than waiting for one.
+    /// Listed to clients, such that they can read whole responses,
+    /// see [`crate::client::ControlledEndpoint::request_response_until_prompt`].
+    #[serde(default)]
+    pub prompt: Option<String>,
 }
 
 /// The configuration used for running the server.
@@ -94,12 +294,344 @@ pub struct Config {
     // TODO: Use Option<..> to allow omitting in config
     pub groups: Vec<Group>,
 
+    /// Endpoints made up of several ports.
+    /// See [`Composite`].
+    #[serde(default)]
+    pub composites: Vec<Composite>,
+
     /// When starting the server, if any listed endpoint cannot be opened,
     /// continue with a warning instead of quitting
     pub ignore_unavailable_endpoints: bool,
+
+    /// Limits on the memory used for events.
+    /// See [`EventsConfig`].
+    #[serde(default)]
+    pub events: EventsConfig,
+
+    /// Macros the server can run on endpoints on behalf of users.
+    /// See [`Macro`].
+    #[serde(default)]
+    pub macros: Vec<Macro>,
+
+    /// Macros the server runs by itself once it has started.
+    /// See [`StartupMacro`].
+    #[serde(default)]
+    pub startup_macros: Vec<StartupMacro>,
+
+    /// Named sets of labels.
+    /// Endpoint and group labels may reference a set by name with an `@` prefix,
+    /// e.g. `labels: ["@fixture-base", "extra"]`, which is expanded when the configuration is loaded.
+    #[serde(default)]
+    pub label_sets: BTreeMap<String, Labels>,
+
+    /// Settings for every endpoint bearing a label, by label name.
+    /// Settings an endpoint gives itself take precedence.
+    /// If several of its labels give the same setting, the alphabetically first label wins.
+    /// See [`LabelDefaults`].
+    #[serde(default)]
+    pub label_defaults: BTreeMap<String, LabelDefaults>,
+
+    /// Labels every endpoint carries, e.g. `["lab=oslo", "env=ci"]`.
+    /// An endpoint's own labels add to these.
+    #[serde(default)]
+    pub default_labels: Labels,
+
+    /// The largest message (in bytes) a user may write in one go.
+    /// Larger writes are rejected with [`crate::error::Error::MessageTooLarge`].
+    /// No limit if not given.
+    #[serde(default)]
+    pub max_message_bytes: Option<usize>,
+
+    /// If a user controls an endpoint for longer than this,
+    /// [`crate::events::general::Event::LongControl`] is sent once to event subscribers.
+    /// The user keeps control.
+    /// No such warning if not given.
+    #[serde(default)]
+    pub control_warn_after: Option<Duration>,
+
+    /// The path clients connect to the websocket at, e.g. `/serial-keel/client` behind a reverse proxy.
+    /// [`crate::actions::DEFAULT_WEBSOCKET_PATH`] if not given.
+    #[serde(default)]
+    pub websocket_path: Option<String>,
+
+    /// A message of the day sent to each client right after the handshake,
+    /// e.g. announcing maintenance.
+    /// Nothing is sent if empty.
+    #[serde(default)]
+    pub banner: String,
+
+    /// Users which may perform administrative actions,
+    /// such as sending notices to the observers of an endpoint.
+    #[serde(default)]
+    pub admins: Vec<String>,
+
+    /// Only allow observing, e.g. for a monitoring instance which must never touch a device.
+    /// Actions controlling or writing to endpoints are rejected.
+    #[serde(default)]
+    pub read_only: bool,
+}
+
+impl ConfigEndpoint {
+    pub(crate) fn access_list(&self) -> AccessList {
+        AccessList::new(&self.allow_users, &self.deny_users)
+    }
+
+    /// The encoding of [`Self::charset`], if given and known.
+    pub(crate) fn encoding(&self) -> Option<&'static encoding_rs::Encoding> {
+        self.charset
+            .as_ref()
+            .and_then(|charset| encoding_rs::Encoding::for_label(charset.as_bytes()))
+    }
+}
+
+/// Which users may control an endpoint, see [`ConfigEndpoint::allow_users`]
+/// and [`ConfigEndpoint::deny_users`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub(crate) struct AccessList {
+    allow: Option<HashSet<String>>,
+    deny: HashSet<String>,
+}
+
+impl AccessList {
+    pub(crate) fn new(allow_users: &Option<Vec<String>>, deny_users: &[String]) -> Self {
+        Self {
+            allow: allow_users
+                .as_ref()
+                .map(|users| users.iter().cloned().collect()),
+            deny: deny_users.iter().cloned().collect(),
+        }
+    }
+
+    /// Only let users through which both lists let through.
+    pub(crate) fn restricted_by(self, other: AccessList) -> Self {
+        let allow = match (self.allow, other.allow) {
+            (Some(ours), Some(theirs)) => Some(ours.intersection(&theirs).cloned().collect()),
+            (ours, theirs) => ours.or(theirs),
+        };
+
+        Self {
+            allow,
+            deny: self.deny.union(&other.deny).cloned().collect(),
+        }
+    }
+
+    /// Whether the user may control the endpoint.
+    pub(crate) fn permits(&self, user: &User) -> bool {
+        let name = user.name.as_str();
+
+        !self.deny.contains(name)
+            && self
+                .allow
+                .as_ref()
+                .map_or(true, |allowed| allowed.contains(name))
+    }
+}
+
+/// Settings applied to endpoints bearing some label, unless the endpoint sets them itself.
+/// See [`Config::label_defaults`].
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(default)]
+pub struct LabelDefaults {
+    /// See [`ConfigEndpoint::baud`].
+    pub baud: Option<u32>,
+
+    /// See [`ConfigEndpoint::flow_control`].
+    pub flow_control: Option<serialport::FlowControl>,
+
+    /// See [`ConfigEndpoint::write_terminator`].
+    pub write_terminator: Option<String>,
+}
+
+/// Labels starting with this refer to a label set.
+/// See [`Config::label_sets`].
+const LABEL_SET_PREFIX: char = '@';
+
+/// One step of a [`Macro`].
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub enum MacroStep {
+    /// Write this line, a newline is appended.
+    Line(String),
+
+    /// Write these bytes as they are.
+    Bytes(Vec<u8>),
+
+    /// Wait this many milliseconds before the next step.
+    DelayMs(u64),
+}
+
+/// Which endpoints a [`Macro`] may be run on.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub enum MacroTarget {
+    /// Just this endpoint.
+    Endpoint(EndpointId),
+
+    /// Any endpoint having all of these labels.
+    Labels(Labels),
+}
+
+/// A named sequence of steps the server runs on an endpoint when asked.
+/// This allows sharing e.g. device init sequences instead of each user sending them.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct Macro {
+    /// The name users refer to the macro by.
+    pub name: String,
+
+    /// Which endpoints the macro may be run on.
+    /// If both an endpoint specific and a labelled macro have the same name,
+    /// the endpoint specific one is used.
+    pub target: MacroTarget,
+
+    /// What the macro does, in order.
+    pub steps: Vec<MacroStep>,
+
+    /// Run the macro automatically whenever a user is granted control of a targeted endpoint,
+    /// unless the user opted out.
+    /// See [`crate::actions::Action::SkipOnControlMacros`].
+    #[serde(default)]
+    pub on_control: bool,
+}
+
+/// A macro the server runs on its own when starting, before any user may control the endpoints.
+/// This allows e.g. putting devices into a known state after the server restarts.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct StartupMacro {
+    /// The name of the macro to run, see [`Macro::name`].
+    pub name: String,
+
+    /// Which endpoints to run the macro on.
+    /// The macro must be allowed to run on each of them.
+    pub target: MacroTarget,
+}
+
+/// Limits on the memory used for events.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(default)]
+pub struct EventsConfig {
+    /// How many recent events the server keeps in its log.
+    pub log_size: usize,
+
+    /// How many events may be in flight to each subscriber before
+    /// a slow subscriber starts missing events.
+    /// Must be non-zero.
+    pub channel_depth: usize,
+
+    /// How often to send [`crate::events::general::Event::Stats`] to event subscribers.
+    /// Zero (the default) disables them.
+    pub stats_interval: Duration,
+
+    /// Send server log records at least this severe to event subscribers,
+    /// see [`crate::events::general::Event::ServerLog`].
+    /// Not sent if not given (the default).
+    pub server_logs: Option<LogLevel>,
+}
+
+impl Default for EventsConfig {
+    fn default() -> Self {
+        Self {
+            log_size: 10_000,
+            channel_depth: 100,
+            stats_interval: Duration::ZERO,
+            server_logs: None,
+        }
+    }
+}
+
+/// Builder for a [`Config`], such that setting it up in code
+/// does not have to spell out every field.
+///
+/// ```
+/// use serial_keel::{config::ConfigBuilder, endpoint::EndpointId};
+///
+/// let config = ConfigBuilder::new()
+///     .add_endpoint(EndpointId::mock("my-mock"))
+///     .set_ignore_unavailable_endpoints(true)
+///     .build();
+/// assert_eq!(config.endpoints.len(), 1);
+/// ```
+#[derive(Debug, Default)]
+pub struct ConfigBuilder {
+    config: Config,
+}
+
+impl ConfigBuilder {
+    /// Start from the default configuration.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add an endpoint for the server to set up.
+    pub fn add_endpoint<E: Into<ConfigEndpoint>>(mut self, endpoint: E) -> Self {
+        self.config.endpoints.push(endpoint.into());
+        self
+    }
+
+    /// Add a [`Group`].
+    pub fn add_group(mut self, group: Group) -> Self {
+        self.config.groups.push(group);
+        self
+    }
+
+    /// Add a [`Composite`].
+    pub fn add_composite(mut self, composite: Composite) -> Self {
+        self.config.composites.push(composite);
+        self
+    }
+
+    /// See [`Config::ignore_unavailable_endpoints`].
+    pub fn set_ignore_unavailable_endpoints(mut self, ignore: bool) -> Self {
+        self.config.ignore_unavailable_endpoints = ignore;
+        self
+    }
+
+    /// Set the [`EventsConfig`].
+    pub fn set_events(mut self, events: EventsConfig) -> Self {
+        self.config.events = events;
+        self
+    }
+
+    /// Add a [`Macro`].
+    pub fn add_macro(mut self, r#macro: Macro) -> Self {
+        self.config.macros.push(r#macro);
+        self
+    }
+
+    /// Add a [`StartupMacro`].
+    pub fn add_startup_macro(mut self, startup: StartupMacro) -> Self {
+        self.config.startup_macros.push(startup);
+        self
+    }
+
+    /// See [`Config::max_message_bytes`].
+    pub fn set_max_message_bytes(mut self, max: usize) -> Self {
+        self.config.max_message_bytes = Some(max);
+        self
+    }
+
+    /// See [`Config::banner`].
+    pub fn set_banner<S: Into<String>>(mut self, banner: S) -> Self {
+        self.config.banner = banner.into();
+        self
+    }
+
+    /// Let the given user perform administrative actions, see [`Config::admins`].
+    pub fn add_admin<S: Into<String>>(mut self, user: S) -> Self {
+        self.config.admins.push(user.into());
+        self
+    }
+
+    /// The configuration built.
+    pub fn build(self) -> Config {
+        self.config
+    }
 }
 
 impl Config {
+    /// Start building a configuration in code.
+    /// See [`ConfigBuilder`].
+    pub fn builder() -> ConfigBuilder {
+        ConfigBuilder::new()
+    }
+
     fn ron() -> ron::Options {
         ron::Options::default()
             .with_default_extension(ron::extensions::Extensions::IMPLICIT_SOME)
@@ -109,7 +641,164 @@ impl Config {
     /// Deserialize a .ron file's contents.
     /// Panics if the input is not valid .ron.
     pub fn deserialize(input: &str) -> Self {
-        Self::ron().from_str::<Config>(input).unwrap()
+        Self::try_deserialize(input).unwrap()
+    }
+
+    /// Deserialize a .ron file's contents, expanding label set references.
+    pub fn try_deserialize(input: &str) -> Result<Self, Error> {
+        let mut config = Self::ron()
+            .from_str::<Config>(input)
+            .map_err(|e| Error::BadConfig(e.to_string()))?;
+
+        config.expand_label_sets()?;
+
+        Ok(config)
+    }
+
+    /// Replace references to label sets by the labels in them.
+    fn expand_label_sets(&mut self) -> Result<(), Error> {
+        let label_sets = &self.label_sets;
+
+        let expand = |labels: &Labels| -> Result<Labels, Error> {
+            let mut expanded = Labels::default();
+
+            for label in labels.iter() {
+                match label.0.strip_prefix(LABEL_SET_PREFIX) {
+                    Some(name) => {
+                        let set = label_sets.get(name).ok_or_else(|| {
+                            Error::BadConfig(format!(
+                                "The label set `{name}` is not defined. Defined label sets: {:?}",
+                                label_sets.keys().collect::<Vec<_>>()
+                            ))
+                        })?;
+                        set.iter().for_each(|label| expanded.push(label));
+                    }
+                    None => expanded.push(label),
+                }
+            }
+
+            Ok(expanded)
+        };
+
+        self.default_labels = expand(&self.default_labels)?;
+
+        for endpoint in &mut self.endpoints {
+            endpoint.labels = expand(&endpoint.labels)?;
+        }
+
+        for group in &mut self.groups {
+            group.labels = expand(&group.labels)?;
+
+            for endpoint in &mut group.endpoints {
+                endpoint.labels = expand(&endpoint.labels)?;
+            }
+        }
+
+        for composite in &mut self.composites {
+            composite.labels = expand(&composite.labels)?;
+
+            for endpoint in &mut composite.ports {
+                endpoint.labels = expand(&endpoint.labels)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// The path clients connect to the websocket at.
+    /// See [`Self::websocket_path`].
+    pub fn websocket_path(&self) -> &str {
+        self.websocket_path
+            .as_deref()
+            .unwrap_or(crate::actions::DEFAULT_WEBSOCKET_PATH)
+    }
+
+    /// Leave out every serial port, keeping only mock endpoints and groups of mocks.
+    /// Useful for running a server where the configured ports do not exist.
+    pub fn remove_serial_ports(&mut self) {
+        let is_mock = |endpoint: &ConfigEndpoint| matches!(endpoint.id, EndpointId::Mock(_));
+
+        self.endpoints.retain(is_mock);
+        self.groups
+            .retain(|group| group.endpoints.iter().all(is_mock));
+        self.composites
+            .retain(|composite| composite.ports.iter().all(is_mock));
+    }
+
+    /// Give every endpoint the [`Self::default_labels`] besides its own labels.
+    /// Labels are kept sorted, so which labels are defaults does not show in their order.
+    pub(crate) fn apply_default_labels(&mut self) {
+        let default_labels = &self.default_labels;
+
+        let apply = |labels: &mut Labels| {
+            for label in default_labels.iter() {
+                if !labels.iter().any(|own| own == label) {
+                    labels.push(label);
+                }
+            }
+        };
+
+        for endpoint in &mut self.endpoints {
+            apply(&mut endpoint.labels);
+        }
+
+        for group in &mut self.groups {
+            for endpoint in &mut group.endpoints {
+                apply(&mut endpoint.labels);
+            }
+        }
+
+        for composite in &mut self.composites {
+            apply(&mut composite.labels);
+
+            for endpoint in &mut composite.ports {
+                apply(&mut endpoint.labels);
+            }
+        }
+    }
+
+    /// Fill in the settings endpoints do not give themselves from the defaults of their labels.
+    /// A group's labels count as labels of each member.
+    /// If several labels give the same setting, an endpoint's own labels win over those of its group.
+    /// Among either, labels are kept sorted, so the alphabetically first label wins.
+    pub(crate) fn apply_label_defaults(&mut self) {
+        let label_defaults = &self.label_defaults;
+
+        let apply = |endpoint: &mut ConfigEndpoint, group_labels: &Labels| {
+            let labels = endpoint
+                .labels
+                .iter()
+                .chain(group_labels.iter())
+                .cloned()
+                .collect_vec();
+
+            for defaults in labels
+                .iter()
+                .filter_map(|label| label_defaults.get(&label.0))
+            {
+                endpoint.baud = endpoint.baud.or(defaults.baud);
+                endpoint.flow_control = endpoint.flow_control.or(defaults.flow_control);
+                if endpoint.write_terminator.is_none() {
+                    endpoint.write_terminator = defaults.write_terminator.clone();
+                }
+            }
+        };
+
+        for endpoint in &mut self.endpoints {
+            apply(endpoint, &Labels::default());
+        }
+
+        for group in &mut self.groups {
+            for endpoint in &mut group.endpoints {
+                apply(endpoint, &group.labels);
+            }
+        }
+
+        for composite in &mut self.composites {
+            for endpoint in &mut composite.ports {
+                apply(endpoint, &composite.labels);
+            }
+        }
     }
 
     /// An example configuration with some fields filled in.
@@ -128,19 +817,85 @@ impl Config {
 
         Self {
             groups: vec![Group::new(g1), Group::new_with_labels(&["mocks"], g2)],
+            composites: vec![Composite {
+                description: Some("nRF5340 DK, bootloader and application UARTs".into()),
+                ..Composite::new(
+                    "nrf5340-dk",
+                    vec![
+                        EndpointId::Tty("/dev/ttyACM0".into()),
+                        EndpointId::Tty("/dev/ttyACM1".into()),
+                    ],
+                )
+            }],
             endpoints: vec![
                 ConfigEndpoint {
-                    id: EndpointId::Tty("COM1".into()),
+                    id: EndpointId::Tty("COM3".into()),
                     labels: Labels::from_iter([Label::new("device-type-1")]),
                     flow_control: Some(serialport::FlowControl::Hardware),
+                    baud: None,
+                    idle_message_timeout: Some(Duration::from_secs(30)),
+                    write_terminator: Some("\r\n".into()),
+                    transforms: vec![Transform::StripAnsi, Transform::Trim],
+                    write_delay: None,
+                    echo: MockEcho::Echo,
+                    shared_control: false,
+                    allow_users: Some(vec!["ci-runner".into(), "alice".into()]),
+                    deny_users: vec![],
+                    description: Some("Lab bench 1, nRF52840 DK".into()),
+                    read_buffer_size: Some(64 * 1024),
+                    charset: None,
+                    prompt: Some("uart:~$ ".into()),
                 },
                 ConfigEndpoint {
                     id: EndpointId::Mock("Mock1".into()),
                     labels: Labels::default(),
                     flow_control: None,
+                    baud: None,
+                    idle_message_timeout: None,
+                    write_terminator: None,
+                    transforms: vec![],
+                    write_delay: None,
+                    echo: MockEcho::Echo,
+                    shared_control: false,
+                    allow_users: None,
+                    deny_users: vec![],
+                    description: None,
+                    read_buffer_size: None,
+                    charset: None,
+                    prompt: None,
                 },
             ],
             ignore_unavailable_endpoints: false,
+            events: EventsConfig::default(),
+            macros: vec![Macro {
+                name: "init".into(),
+                target: MacroTarget::Labels(Labels::from_iter([Label::new("device-type-1")])),
+                steps: vec![
+                    MacroStep::Line("reset".into()),
+                    MacroStep::DelayMs(500),
+                    MacroStep::Bytes(vec![0xDE, 0xAD]),
+                ],
+                on_control: true,
+            }],
+            startup_macros: vec![StartupMacro {
+                name: "init".into(),
+                target: MacroTarget::Labels(Labels::from_iter([Label::new("device-type-1")])),
+            }],
+            label_sets: BTreeMap::default(),
+            label_defaults: BTreeMap::from([(
+                "device-type-1".into(),
+                LabelDefaults {
+                    baud: Some(921_600),
+                    ..Default::default()
+                },
+            )]),
+            default_labels: Labels::from_iter(["env=ci"]),
+            max_message_bytes: Some(64 * 1024),
+            control_warn_after: Some(Duration::from_secs(8 * 60 * 60)),
+            websocket_path: None,
+            banner: "Maintenance at 18:00".into(),
+            admins: vec!["operator".into()],
+            read_only: false,
         }
     }
 
@@ -158,11 +913,28 @@ impl Config {
         Self::deserialize(&s)
     }
 
+    // Every endpoint defined in the configuration, whether on its own, in a group or in a composite.
+    fn all_config_endpoints(&self) -> impl Iterator<Item = &ConfigEndpoint> {
+        self.endpoints
+            .iter()
+            .chain(self.groups.iter().flat_map(|group| &group.endpoints))
+            .chain(
+                self.composites
+                    .iter()
+                    .flat_map(|composite| &composite.ports),
+            )
+    }
+
     fn check_duplicates_across_groups(&self) -> Result<(), Error> {
         let duplicates = self
             .groups
             .iter()
             .flat_map(|group| &group.endpoints)
+            .chain(
+                self.composites
+                    .iter()
+                    .flat_map(|composite| &composite.ports),
+            )
             .map(|ce| &ce.id)
             .duplicates()
             .collect::<Vec<_>>();
@@ -174,6 +946,39 @@ impl Config {
         }
     }
 
+    fn check_duplicate_endpoints(&self) -> Result<(), Error> {
+        if let Some(duplicate) = self.endpoints.iter().map(|ce| &ce.id).duplicates().next() {
+            return Err(Error::BadConfig(format!(
+                "The endpoint `{duplicate}` is defined several times in `endpoints`. Each endpoint may only be defined once."
+            )));
+        }
+
+        let in_groups = self
+            .groups
+            .iter()
+            .flat_map(|group| &group.endpoints)
+            .chain(
+                self.composites
+                    .iter()
+                    .flat_map(|composite| &composite.ports),
+            )
+            .map(|ce| &ce.id)
+            .collect::<HashSet<_>>();
+
+        if let Some(duplicate) = self
+            .endpoints
+            .iter()
+            .map(|ce| &ce.id)
+            .find(|id| in_groups.contains(id))
+        {
+            return Err(Error::BadConfig(format!(
+                "The endpoint `{duplicate}` is defined both in `endpoints` and in a group or composite. Each endpoint may only be defined once, put its labels where it is defined."
+            )));
+        }
+
+        Ok(())
+    }
+
     fn check_empty_within_group(&self) -> Result<(), Error> {
         // Not defining any groups is ok.
         // The problem is defining a group and then not
@@ -211,15 +1016,244 @@ impl Config {
         Ok(())
     }
 
+    fn check_no_shared_control_within_group(&self) -> Result<(), Error> {
+        for (index, group) in self.groups.iter().enumerate() {
+            if let Some(shared) = group.endpoints.iter().find(|ce| ce.shared_control) {
+                return Err(Error::BadConfig(format!("The endpoint `{}` in the group with index {index} (zero indexed) has shared control, but groups are always controlled exclusively.", shared.id)));
+            }
+        }
+
+        Ok(())
+    }
+
+    fn check_composites(&self) -> Result<(), Error> {
+        if let Some(name) = self
+            .composites
+            .iter()
+            .map(|composite| &composite.name)
+            .duplicates()
+            .next()
+        {
+            return Err(Error::BadConfig(format!(
+                "There are several composites named `{name}`."
+            )));
+        }
+
+        let nested = self
+            .all_config_endpoints()
+            .find(|ce| ce.id.as_composite().is_some());
+        if let Some(nested) = nested {
+            return Err(Error::BadConfig(format!("The endpoint `{}` is a composite, which can only be defined as one of the composites.", nested.id)));
+        }
+
+        for composite in &self.composites {
+            let name = &composite.name;
+
+            if composite.ports.is_empty() {
+                return Err(Error::BadConfig(format!(
+                    "The composite `{name}` has no ports."
+                )));
+            }
+
+            let mocks = composite
+                .ports
+                .iter()
+                .filter(|ce| matches!(&ce.id, EndpointId::Mock(_)))
+                .count();
+            if mocks != 0 && mocks != composite.ports.len() {
+                return Err(Error::BadConfig(format!("The composite `{name}` has ports of different variants, please only put the same endpoint variant type within the same composite.")));
+            }
+
+            if let Some(shared) = composite.ports.iter().find(|ce| ce.shared_control) {
+                return Err(Error::BadConfig(format!("The port `{}` of the composite `{name}` has shared control, but composites are always controlled exclusively.", shared.id)));
+            }
+        }
+
+        Ok(())
+    }
+
+    fn check_labels(&self) -> Result<(), Error> {
+        let check = |labels: &Labels, owner: String| {
+            if labels.iter().any(Label::is_empty) {
+                Err(Error::BadConfig(format!(
+                    "{owner} has an empty label. Labels must not be empty or only whitespace."
+                )))
+            } else {
+                Ok(())
+            }
+        };
+
+        for endpoint in &self.endpoints {
+            check(&endpoint.labels, format!("The endpoint `{}`", endpoint.id))?;
+        }
+
+        for (index, group) in self.groups.iter().enumerate() {
+            check(
+                &group.labels,
+                format!("The group with index {index} (zero indexed)"),
+            )?;
+
+            for endpoint in &group.endpoints {
+                check(&endpoint.labels, format!("The endpoint `{}`", endpoint.id))?;
+            }
+        }
+
+        for composite in &self.composites {
+            check(
+                &composite.labels,
+                format!("The composite `{}`", composite.name),
+            )?;
+
+            for endpoint in &composite.ports {
+                check(&endpoint.labels, format!("The endpoint `{}`", endpoint.id))?;
+            }
+        }
+
+        for (name, labels) in &self.label_sets {
+            check(labels, format!("The label set `{name}`"))?;
+        }
+
+        Ok(())
+    }
+
+    fn check_read_buffer_sizes(&self) -> Result<(), Error> {
+        let empty = self
+            .all_config_endpoints()
+            .find(|ce| ce.read_buffer_size == Some(0));
+
+        match empty {
+            Some(ce) => Err(Error::BadConfig(format!(
+                "The endpoint `{}` has a read buffer size of zero, it must be able to read something.",
+                ce.id
+            ))),
+            None => Ok(()),
+        }
+    }
+
+    fn check_prompts(&self) -> Result<(), Error> {
+        let empty = self
+            .all_config_endpoints()
+            .find(|ce| ce.prompt.as_deref() == Some(""));
+
+        match empty {
+            Some(ce) => Err(Error::BadConfig(format!(
+                "The endpoint `{}` has an empty prompt, which every read would end with.",
+                ce.id
+            ))),
+            None => Ok(()),
+        }
+    }
+
+    fn check_charsets(&self) -> Result<(), Error> {
+        let unknown = self
+            .all_config_endpoints()
+            .find(|ce| ce.charset.is_some() && ce.encoding().is_none());
+
+        match unknown {
+            Some(ce) => Err(Error::BadConfig(format!(
+                "The endpoint `{}` has the unknown charset `{}`.",
+                ce.id,
+                ce.charset.as_deref().unwrap_or_default()
+            ))),
+            None => Ok(()),
+        }
+    }
+
+    fn check_mock_names(&self) -> Result<(), Error> {
+        let ids = self.all_config_endpoints().map(|ce| &ce.id).collect_vec();
+
+        let paths = ids
+            .iter()
+            .filter_map(|id| id.as_tty().or_else(|| id.as_pty()))
+            .collect::<HashSet<_>>();
+
+        for mock in ids.iter().filter_map(|id| id.as_mock()) {
+            if paths.contains(mock) {
+                return Err(Error::BadConfig(format!(
+                    "The mock `{mock}` is named like a configured serial port. Give it another name to tell them apart."
+                )));
+            }
+
+            if looks_like_tty_path(mock) {
+                warn!(%mock, "Mock is named like a serial port, which may be confused with a real one");
+            }
+        }
+
+        Ok(())
+    }
+
+    fn check_websocket_path(&self) -> Result<(), Error> {
+        let path = self.websocket_path();
+
+        if !path.starts_with('/') {
+            return Err(Error::BadConfig(format!(
+                "The websocket path `{path}` should start with `/`."
+            )));
+        }
+
+        if server::OTHER_ROUTES.contains(&path) {
+            return Err(Error::BadConfig(format!(
+                "The websocket path `{path}` is taken by the server. Taken paths: {:?}",
+                server::OTHER_ROUTES
+            )));
+        }
+
+        Ok(())
+    }
+
+    fn check_startup_macros(&self) -> Result<(), Error> {
+        for startup in &self.startup_macros {
+            if !self.macros.iter().any(|m| m.name == startup.name) {
+                return Err(Error::BadConfig(format!(
+                    "The startup macro `{}` does not refer to any macro.",
+                    startup.name
+                )));
+            }
+        }
+
+        Ok(())
+    }
+
+    fn check_events(&self) -> Result<(), Error> {
+        if self.events.channel_depth == 0 {
+            return Err(Error::BadConfig(
+                "The events channel depth must be non-zero.".into(),
+            ));
+        }
+
+        Ok(())
+    }
+
     pub(crate) fn validate(&self) -> Result<(), Error> {
         self.check_empty_within_group()?;
         self.check_group_variant_homogeneity()?;
         self.check_duplicates_across_groups()?;
+        self.check_duplicate_endpoints()?;
+        self.check_no_shared_control_within_group()?;
+        self.check_composites()?;
+        self.check_events()?;
+        self.check_startup_macros()?;
+        self.check_labels()?;
+        self.check_read_buffer_sizes()?;
+        self.check_charsets()?;
+        self.check_prompts()?;
+        self.check_mock_names()?;
+        self.check_websocket_path()?;
 
         Ok(())
     }
 }
 
+/// Whether the name is shaped like the path of a serial port,
+/// e.g. `/dev/ttyUSB0` or `COM3`.
+fn looks_like_tty_path(name: &str) -> bool {
+    let com_port = name
+        .strip_prefix("COM")
+        .is_some_and(|number| !number.is_empty() && number.chars().all(|c| c.is_ascii_digit()));
+
+    name.starts_with("/dev/") || com_port
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -326,6 +1360,103 @@ mod tests {
         assert!(err.contains("COM5"));
     }
 
+    #[test]
+    fn bad_config_duplicate_tty() {
+        let c = Config {
+            endpoints: vec![
+                EndpointId::tty("COM0").into(),
+                EndpointId::tty("COM1").into(),
+                EndpointId::tty("COM0").into(),
+            ],
+            ..Default::default()
+        };
+
+        let err = c.validate().unwrap_err().try_into_bad_config().unwrap();
+        assert!(err.contains("COM0"));
+        assert!(!err.contains("COM1"));
+
+        let c = Config {
+            endpoints: vec![ConfigEndpoint {
+                labels: Labels::from_iter(["standalone"]),
+                ..EndpointId::tty("COM2").into()
+            }],
+            groups: vec![Group::new_with_labels(
+                &["grouped"],
+                vec![EndpointId::tty("COM2"), EndpointId::tty("COM3")],
+            )],
+            ..Default::default()
+        };
+
+        let err = c.validate().unwrap_err().try_into_bad_config().unwrap();
+        assert!(err.contains("COM2"));
+        assert!(err.contains("group"));
+    }
+
+    #[test]
+    fn bad_config_zero_read_buffer() {
+        let c = Config {
+            endpoints: vec![ConfigEndpoint {
+                read_buffer_size: Some(0),
+                ..EndpointId::tty("COM0").into()
+            }],
+            ..Default::default()
+        };
+
+        let err = c.validate().unwrap_err().try_into_bad_config().unwrap();
+        assert!(err.contains("COM0"));
+        assert!(err.contains("read buffer"));
+    }
+
+    #[test]
+    fn bad_config_unknown_charset() {
+        let c = Config {
+            endpoints: vec![ConfigEndpoint {
+                charset: Some("klingon".into()),
+                ..EndpointId::tty("COM0").into()
+            }],
+            ..Default::default()
+        };
+
+        let err = c.validate().unwrap_err().try_into_bad_config().unwrap();
+        assert!(err.contains("COM0"));
+        assert!(err.contains("klingon"));
+    }
+
+    #[test]
+    fn bad_config_empty_prompt() {
+        let c = Config {
+            endpoints: vec![ConfigEndpoint {
+                prompt: Some("".into()),
+                ..EndpointId::tty("COM0").into()
+            }],
+            ..Default::default()
+        };
+
+        let err = c.validate().unwrap_err().try_into_bad_config().unwrap();
+        assert!(err.contains("COM0"));
+        assert!(err.contains("empty prompt"));
+    }
+
+    #[test]
+    fn bad_config_websocket_path() {
+        for (path, problem) in [("client", "start with"), ("/health", "taken")] {
+            let c = Config {
+                websocket_path: Some(path.into()),
+                ..Default::default()
+            };
+
+            let err = c.validate().unwrap_err().try_into_bad_config().unwrap();
+            assert!(err.contains(path));
+            assert!(err.contains(problem), "{err}");
+        }
+
+        let c = Config {
+            websocket_path: Some("/serial-keel/client".into()),
+            ..Default::default()
+        };
+        assert!(c.validate().is_ok());
+    }
+
     #[test]
     fn bad_config_empty() {
         let g1 = vec![EndpointId::Tty("COM0".into())];
@@ -367,4 +1498,318 @@ mod tests {
         assert!(err.contains("Mock1"));
         assert!(err.contains("COM1"));
     }
+
+    #[test]
+    fn bad_config_events_channel_depth() {
+        let c = Config {
+            events: EventsConfig {
+                channel_depth: 0,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+
+        let err = c.validate().unwrap_err().try_into_bad_config().unwrap();
+        assert!(err.contains("channel depth"));
+    }
+
+    #[test]
+    fn bad_config_unknown_startup_macro() {
+        let c = Config {
+            startup_macros: vec![StartupMacro {
+                name: "missing".into(),
+                target: MacroTarget::Endpoint(EndpointId::mock("Mock1")),
+            }],
+            ..Default::default()
+        };
+
+        let err = c.validate().unwrap_err().try_into_bad_config().unwrap();
+        assert!(err.contains("missing"));
+    }
+
+    #[test]
+    fn events_config_may_be_omitted() {
+        let config =
+            Config::deserialize("(endpoints: [], groups: [], ignore_unavailable_endpoints: false)");
+        assert_eq!(config.events, EventsConfig::default());
+
+        let config = Config::deserialize(
+            "(endpoints: [], groups: [], ignore_unavailable_endpoints: false, events: (log_size: 5))",
+        );
+        assert_eq!(config.events.log_size, 5);
+        assert_eq!(
+            config.events.channel_depth,
+            EventsConfig::default().channel_depth
+        );
+    }
+
+    #[test]
+    fn builder_with_one_mock() {
+        let config = Config::builder()
+            .add_endpoint(EndpointId::mock("Mock1"))
+            .build();
+
+        assert_eq!(config.endpoints.len(), 1);
+        assert_eq!(config.endpoints[0].id, EndpointId::mock("Mock1"));
+        assert!(config.groups.is_empty());
+        assert!(!config.ignore_unavailable_endpoints);
+        assert_eq!(config.events, EventsConfig::default());
+        config.validate().unwrap();
+    }
+
+    #[test]
+    fn labels_are_trimmed() {
+        let config = Config::try_deserialize(
+            r#"(endpoints: [(id: Mock("Mock1"), labels: [" foo "])], groups: [], ignore_unavailable_endpoints: false)"#,
+        )
+        .unwrap();
+
+        assert_eq!(config.endpoints[0].labels, Labels::from_iter(["foo"]));
+        assert_eq!(Label::new(" foo "), Label::new("foo"));
+    }
+
+    #[test]
+    fn bad_config_empty_labels() {
+        for label in ["", "  "] {
+            let c = Config {
+                endpoints: vec![ConfigEndpoint {
+                    labels: Labels::from_iter([label]),
+                    ..EndpointId::mock("Mock1").into()
+                }],
+                ..Default::default()
+            };
+
+            let err = c.validate().unwrap_err().try_into_bad_config().unwrap();
+            assert!(err.contains("empty label"), "`{label}` gave: {err}");
+
+            assert!(matches!(Label::try_new(label), Err(Error::BadUsage(_))));
+        }
+
+        let c = Config {
+            groups: vec![Group::new_with_labels(
+                &[" "],
+                vec![EndpointId::mock("Mock2")],
+            )],
+            ..Default::default()
+        };
+        assert!(c.validate().is_err());
+    }
+
+    #[test]
+    fn label_set_expands() {
+        let input = r#"
+(
+    endpoints: [
+        (
+            id: Mock("Mock1"),
+            labels: ["@fixture-base", "extra"],
+        ),
+    ],
+    groups: [
+        (
+            labels: ["@fixture-base"],
+            endpoints: [
+                (
+                    id: Mock("Mock2"),
+                    labels: [],
+                ),
+            ],
+        ),
+    ],
+    ignore_unavailable_endpoints: false,
+    label_sets: {
+        "fixture-base": ["nrf52", "fixture"],
+    },
+)
+"#;
+        let config = Config::try_deserialize(input).unwrap();
+
+        assert_eq!(
+            config.endpoints[0].labels,
+            Labels::from_iter(["nrf52", "fixture", "extra"])
+        );
+        assert_eq!(
+            config.groups[0].labels,
+            Labels::from_iter(["nrf52", "fixture"])
+        );
+    }
+
+    #[test]
+    fn label_defaults_apply_unless_endpoint_overrides() {
+        let mut config = Config {
+            endpoints: vec![
+                ConfigEndpoint {
+                    labels: Labels::from_iter(["nrf52"]),
+                    ..EndpointId::tty("/dev/ttyACM0").into()
+                },
+                ConfigEndpoint {
+                    labels: Labels::from_iter(["nrf52"]),
+                    baud: Some(9600),
+                    ..EndpointId::tty("/dev/ttyACM1").into()
+                },
+                EndpointId::tty("/dev/ttyACM2").into(),
+            ],
+            groups: vec![Group::new_with_labels(
+                &["nrf52"],
+                vec![EndpointId::tty("/dev/ttyACM3")],
+            )],
+            label_defaults: BTreeMap::from([(
+                "nrf52".into(),
+                LabelDefaults {
+                    baud: Some(1_000_000),
+                    write_terminator: Some("\r\n".into()),
+                    ..Default::default()
+                },
+            )]),
+            ..Default::default()
+        };
+
+        config.apply_label_defaults();
+
+        let bauds = config.endpoints.iter().map(|ce| ce.baud).collect_vec();
+        assert_eq!(bauds, vec![Some(1_000_000), Some(9600), None]);
+        assert_eq!(
+            config.endpoints[1].write_terminator.as_deref(),
+            Some("\r\n")
+        );
+
+        // Group labels propagate to members
+        assert_eq!(config.groups[0].endpoints[0].baud, Some(1_000_000));
+    }
+
+    #[test]
+    fn label_defaults_of_the_alphabetically_first_label_win() {
+        let baud = |baud| LabelDefaults {
+            baud: Some(baud),
+            ..Default::default()
+        };
+
+        let mut config = Config {
+            endpoints: vec![ConfigEndpoint {
+                // Labels are sorted, so the order given here does not matter
+                labels: Labels::from_iter(["zephyr", "arduino"]),
+                ..EndpointId::tty("/dev/ttyACM0").into()
+            }],
+            groups: vec![Group {
+                endpoints: vec![ConfigEndpoint {
+                    labels: Labels::from_iter(["zephyr"]),
+                    ..EndpointId::tty("/dev/ttyACM1").into()
+                }],
+                ..Group::new_with_labels(&["arduino"], vec![])
+            }],
+            label_defaults: BTreeMap::from([
+                ("arduino".into(), baud(9600)),
+                ("zephyr".into(), baud(115_200)),
+            ]),
+            ..Default::default()
+        };
+
+        config.apply_label_defaults();
+
+        assert_eq!(config.endpoints[0].baud, Some(9600));
+
+        // The endpoint's own label wins over the group's, whatever their order
+        assert_eq!(config.groups[0].endpoints[0].baud, Some(115_200));
+    }
+
+    #[test]
+    fn unknown_label_set_is_bad() {
+        let input = r#"
+(
+    endpoints: [
+        (
+            id: Mock("Mock1"),
+            labels: ["@nope"],
+        ),
+    ],
+    groups: [],
+    ignore_unavailable_endpoints: false,
+)
+"#;
+        let err = Config::try_deserialize(input)
+            .unwrap_err()
+            .try_into_bad_config()
+            .unwrap();
+
+        assert!(err.contains("nope"));
+    }
+
+    #[test]
+    fn bad_config_composites() {
+        let c = Config {
+            composites: vec![Composite::new("empty", vec![])],
+            ..Default::default()
+        };
+        let err = c.validate().unwrap_err().try_into_bad_config().unwrap();
+        assert!(err.contains("empty"));
+
+        let c = Config {
+            composites: vec![
+                Composite::new("twice", vec![EndpointId::mock("first")]),
+                Composite::new("twice", vec![EndpointId::mock("second")]),
+            ],
+            ..Default::default()
+        };
+        let err = c.validate().unwrap_err().try_into_bad_config().unwrap();
+        assert!(err.contains("twice"));
+
+        let c = Config {
+            composites: vec![Composite::new(
+                "mixed",
+                vec![EndpointId::mock("mock"), EndpointId::tty("COM0")],
+            )],
+            ..Default::default()
+        };
+        let err = c.validate().unwrap_err().try_into_bad_config().unwrap();
+        assert!(err.contains("mixed"));
+
+        let c = Config {
+            groups: vec![Group::new(vec![EndpointId::mock("shared-port")])],
+            composites: vec![Composite::new(
+                "overlapping",
+                vec![EndpointId::mock("shared-port")],
+            )],
+            ..Default::default()
+        };
+        let err = c.validate().unwrap_err().try_into_bad_config().unwrap();
+        assert!(err.contains("shared-port"));
+
+        let c = Config {
+            endpoints: vec![EndpointId::composite("nested").into()],
+            ..Default::default()
+        };
+        let err = c.validate().unwrap_err().try_into_bad_config().unwrap();
+        assert!(err.contains("nested"));
+    }
+
+    #[test]
+    fn mock_named_like_a_configured_port_is_bad() {
+        let c = Config {
+            endpoints: vec![EndpointId::tty("/dev/ttyUSB0").into()],
+            groups: vec![Group::new(vec![EndpointId::mock("/dev/ttyUSB0")])],
+            ..Default::default()
+        };
+        let err = c.validate().unwrap_err().try_into_bad_config().unwrap();
+        assert!(err.contains("/dev/ttyUSB0"), "{err}");
+
+        let c = Config {
+            endpoints: vec![
+                EndpointId::tty("/dev/ttyUSB0").into(),
+                EndpointId::mock("/dev/ttyUSB1").into(),
+            ],
+            ..Default::default()
+        };
+        assert!(c.validate().is_ok());
+    }
+
+    #[test]
+    fn tty_like_names_are_recognized() {
+        for name in ["/dev/ttyUSB0", "/dev/ttyACM12", "COM3", "COM10"] {
+            assert!(looks_like_tty_path(name), "{name}");
+        }
+
+        for name in ["some-mock", "COM", "COMPASS", "dev/ttyUSB0"] {
+            assert!(!looks_like_tty_path(name), "{name}");
+        }
+    }
 }
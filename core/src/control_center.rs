@@ -5,38 +5,73 @@
 
 use std::{
     borrow::Borrow,
-    collections::{HashMap, HashSet},
+    collections::{HashMap, HashSet, VecDeque},
     fmt::{Debug, Display},
+    time::Duration,
 };
 
 use futures::{channel::mpsc, SinkExt, StreamExt};
 use itertools::{Either, Itertools};
-use tokio::sync::{broadcast, oneshot, OwnedSemaphorePermit, TryAcquireError};
+use tokio::{
+    sync::{broadcast, oneshot, OwnedSemaphorePermit, TryAcquireError},
+    time::Instant,
+};
 use tracing::{debug, debug_span, error, info, info_span, warn, Instrument};
 
 use crate::{
-    config::{Config, ConfigEndpoint},
+    actions::{CloseReason, ListedEndpoint, ListedLabel, NotQueuedOn, QueuedOn},
+    config::{AccessList, Config, ConfigEndpoint, Macro, MacroStep, MacroTarget, StartupMacro},
     endpoint::{
-        self, Endpoint, EndpointExt, EndpointId, EndpointSemaphore, EndpointSemaphoreId,
-        InternalEndpointId, InternalEndpointInfo, LabelledEndpointId, Labels,
+        self, composite::CompositeEndpoint, Endpoint, EndpointExt, EndpointId, EndpointSemaphore,
+        EndpointSemaphoreId, InternalEndpointId, InternalEndpointInfo, LabelledEndpointId, Labels,
     },
     error::Error,
-    events::{self, general},
+    events::{self, TimestampedEvent},
+    logging::{self, LogLevel, ServerLog},
+    metrics::{ByteCounter, ByteCounts, EndpointMetrics},
     mock::{MockBuilder, MockId},
-    serial::{serial_port::SerialPortBuilder, SerialMessage, SerialMessageBytes},
+    peer::{PeerAction, PeerRequest},
+    serial::{
+        serial_port::SerialPortBuilder, Direction, SerialMessage, SerialMessageBytes, TaggedMessage,
+    },
     user::User,
 };
 
+type PeerRequestSender = tokio::sync::mpsc::UnboundedSender<PeerRequest>;
+
+/// How many of the most recent messages of each endpoint are kept,
+/// for observers asking for a replay.
+const RECENT_MESSAGES: usize = 32;
+
+/// A message an endpoint recently saw, and when it saw it.
+pub(crate) type RecentMessage = (chrono::DateTime<chrono::Utc>, endpoint::EndpointEvent);
+
 #[derive(Debug)]
 pub(crate) struct EndpointController {
     _permit: OwnedSemaphorePermit,
     pub(crate) endpoints: HashMap<InternalEndpointInfo, mpsc::UnboundedSender<SerialMessageBytes>>,
 }
 
+impl Drop for EndpointController {
+    fn drop(&mut self) {
+        // The permit is released along with the controller.
+        // Log it, such that endpoints staying locked can be traced to whoever never let go.
+        let released = self
+            .endpoints
+            .keys()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>()
+            .join(", ");
+        debug!(%released, "Controller dropped, endpoints released");
+    }
+}
+
 #[derive(Debug)]
 pub(crate) struct EndpointControllerQueue {
     pub(crate) inner: oneshot::Receiver<EndpointController>,
     pub(crate) endpoints: Vec<InternalEndpointInfo>,
+    pub(crate) queued_on: Vec<QueuedOn>,
+    pub(crate) not_queued_on: Vec<NotQueuedOn>,
 }
 
 impl EndpointControllerQueue {
@@ -111,13 +146,41 @@ impl MaybeEndpointController {
 #[derive(Debug, Default)]
 struct UserState {
     observing_endpoints: HashSet<InternalEndpointInfo>,
+    weakly_observing_endpoints: HashSet<InternalEndpointInfo>,
     observing_user_events: bool,
+    observing_endpoint_events: HashSet<InternalEndpointInfo>,
+    observing_messages_of: HashSet<InternalEndpointInfo>,
+    /// Endpoints to observe as soon as they appear.
+    deferred_observations: HashSet<InternalEndpointId>,
+    /// Labels to control any matching endpoint of as soon as one appears.
+    deferred_controls: HashSet<Labels>,
     in_queue_of: HashSet<InternalEndpointInfo>,
-    in_control_of: HashSet<EndpointSemaphoreId>,
+    in_control_of: HashMap<EndpointSemaphoreId, Control>,
+    peer_requests: Option<PeerRequestSender>,
+}
+
+/// A user's control of a group of endpoints.
+#[derive(Debug)]
+struct Control {
+    since: Instant,
+
+    /// Whether the control was reported as long, which is done once.
+    warned: bool,
+}
+
+impl Control {
+    fn new() -> Self {
+        Self {
+            since: Instant::now(),
+            warned: false,
+        }
+    }
 }
 
 pub(crate) struct Endpoints {
     inner: HashMap<InternalEndpointInfo, Box<dyn Endpoint + Send + Sync>>,
+    /// Counted by each endpoint's task, anew whenever the endpoint is opened.
+    byte_counters: HashMap<InternalEndpointId, ByteCounter>,
     control_center_handle: ControlCenterHandle,
 }
 
@@ -125,25 +188,89 @@ impl Endpoints {
     pub(crate) fn new(control_center_handle: ControlCenterHandle) -> Self {
         Self {
             inner: Default::default(),
+            byte_counters: Default::default(),
             control_center_handle,
         }
     }
 
-    fn insert(&mut self, id: InternalEndpointId, endpoint: impl Endpoint + Send + Sync + 'static) {
+    /// Add an endpoint.
+    /// If an idle timeout is given, the endpoint is reported idle whenever
+    /// no message arrives from wire within that time.
+    fn insert(
+        &mut self,
+        id: InternalEndpointId,
+        endpoint: impl Endpoint + Send + Sync + 'static,
+        idle_timeout: Option<Duration>,
+    ) {
         let labels = endpoint.labels();
         debug!(?labels, %id, "Adding endpoint");
 
         let endpoint = Box::new(endpoint);
         let mut events = endpoint.events();
 
-        let task_id = id.clone();
+        let task_info = InternalEndpointInfo::new(id.clone(), labels.clone());
         let task_cc_handle = self.control_center_handle.clone();
 
+        let byte_counter = ByteCounter::default();
+        let task_byte_counter = byte_counter.clone();
+        self.byte_counters.insert(id.clone(), byte_counter);
+
         tokio::spawn(
             async move {
-                while let Ok(event) = events.recv().await {
+                let mut idle_deadline = idle_timeout.map(|timeout| Instant::now() + timeout);
+                let mut is_idle = false;
+
+                // This task lives as long as the endpoint is open,
+                // so an endpoint opened again starts out not having heard anything.
+                let mut heard_from = false;
+
+                loop {
+                    let idle = async {
+                        match idle_deadline {
+                            Some(deadline) => tokio::time::sleep_until(deadline).await,
+                            None => futures::future::pending().await,
+                        }
+                    };
+
+                    let event = tokio::select! {
+                        event = events.recv() => event,
+                        _ = idle => {
+                            debug!("Endpoint idle");
+                            is_idle = true;
+                            idle_deadline = None;
+                            task_cc_handle.inform(Inform::EndpointIdle(task_info.clone()));
+                            continue;
+                        }
+                    };
+
+                    let Ok(event) = event else {
+                        break;
+                    };
                     debug!(?event, "Endpoint event");
-                    task_cc_handle.inform(Inform::EndpointEvent((task_id.clone(), event)))
+
+                    if let (Some(timeout), endpoint::EndpointEvent::FromWire(_)) =
+                        (idle_timeout, &event)
+                    {
+                        idle_deadline = Some(Instant::now() + timeout);
+
+                        if is_idle {
+                            debug!("Endpoint active");
+                            is_idle = false;
+                            task_cc_handle.inform(Inform::EndpointActive(task_info.clone()));
+                        }
+                    }
+
+                    let message = TaggedMessage::from_event(task_info.clone(), event);
+                    let from_wire = message.direction == Direction::FromWire;
+
+                    task_byte_counter.count(message.direction, message.bytes.len());
+                    task_cc_handle.inform(Inform::EndpointMessage(message));
+
+                    if from_wire && !heard_from {
+                        debug!("First message from wire");
+                        heard_from = true;
+                        task_cc_handle.inform(Inform::EndpointFirstMessage(task_info.clone()));
+                    }
                 }
                 warn!("Endpoint event stream closed");
             }
@@ -163,7 +290,7 @@ impl Endpoints {
         let id = InternalEndpointId::Mock(mock_id.clone());
 
         if self.inner.get(&id).is_none() {
-            self.insert(id.clone(), self.create_mock(mock_id));
+            self.insert(id.clone(), self.create_mock(mock_id), None);
         }
 
         // Borrow of a box
@@ -195,6 +322,58 @@ impl Endpoints {
     fn remove(&mut self, id: &InternalEndpointInfo) {
         // TODO: Stop the task related to this endpoint
         assert!(self.inner.remove(id).is_some());
+        self.byte_counters.remove(&id.id);
+    }
+
+    fn byte_counts(&self, id: &InternalEndpointId) -> ByteCounts {
+        self.byte_counters
+            .get(id)
+            .map(ByteCounter::summary)
+            .unwrap_or_default()
+    }
+
+    fn list(&self, disabled: &HashSet<InternalEndpointId>) -> Vec<ListedEndpoint> {
+        self.inner
+            .iter()
+            .map(|(info, endpoint)| ListedEndpoint {
+                id: info.clone().into(),
+                description: endpoint.description(),
+                prompt: endpoint.prompt(),
+                settings: endpoint.port_settings(),
+                device: endpoint.device(),
+                opened_at: endpoint.opened_at(),
+                disabled: disabled.contains(&info.id),
+                bytes: self.byte_counts(&info.id),
+            })
+            .sorted_by_key(|listed| listed.id.id.to_string())
+            .collect()
+    }
+
+    fn labels(&self) -> Vec<ListedLabel> {
+        self.inner
+            .keys()
+            .flat_map(|info| info.labels.iter())
+            .counts()
+            .into_iter()
+            .map(|(label, endpoints)| ListedLabel {
+                label: label.clone(),
+                endpoints,
+            })
+            .sorted_by(|a, b| a.label.0.cmp(&b.label.0))
+            .collect()
+    }
+
+    fn metrics(&self) -> Vec<EndpointMetrics> {
+        self.inner
+            .iter()
+            .map(|(info, endpoint)| EndpointMetrics {
+                endpoint: info.clone().into(),
+                write_latency: endpoint.write_latency(),
+                framing: endpoint.framing(),
+                bytes: self.byte_counts(&info.id),
+            })
+            .sorted_by_key(|metrics| metrics.endpoint.id.to_string())
+            .collect()
     }
 
     fn without_labels(&self) -> HashSet<InternalEndpointInfo> {
@@ -259,6 +438,19 @@ impl Endpoints {
             .cloned()
             .collect()
     }
+
+    // Get every endpoint matching the given label, ordered by id.
+    // Composites are left out, their ports are endpoints of their own.
+    fn labels_to_all_endpoint_ids(&self, labels: &Labels) -> Vec<InternalEndpointInfo> {
+        self.inner
+            .iter()
+            .filter(|(info, _)| !matches!(info.id, InternalEndpointId::Composite(_)))
+            .filter(|(_, endpoint)| endpoint.labels().is_superset(labels))
+            .map(|(info, _)| info)
+            .cloned()
+            .sorted_by_key(|info| info.id.to_string())
+            .collect()
+    }
 }
 
 pub(crate) struct ControlCenter {
@@ -273,26 +465,169 @@ pub(crate) struct ControlCenter {
 
     /// The state of each live user.
     user_state: HashMap<User, UserState>,
+
+    /// Macros users may run, from the configuration.
+    macros: Vec<Macro>,
+
+    /// Endpoints nobody but the given user may observe.
+    exclusive_observers: HashMap<InternalEndpointId, User>,
+
+    /// Endpoints an admin took out of rotation.
+    disabled_endpoints: HashSet<InternalEndpointId>,
+
+    /// The users waiting for control of each endpoint, in the order they queued.
+    control_queues: HashMap<InternalEndpointInfo, Vec<User>>,
+
+    /// The most recent messages of each endpoint and when they were seen, newest last.
+    recent_messages: HashMap<InternalEndpointId, VecDeque<RecentMessage>>,
+
+    /// Who may control configured endpoints.
+    /// Endpoints not in here may be controlled by anyone.
+    access_lists: HashMap<InternalEndpointId, AccessList>,
+
+    /// The encoding of configured endpoints whose text is not UTF-8.
+    charsets: HashMap<InternalEndpointId, &'static encoding_rs::Encoding>,
+
+    /// How often to send stats, if at all.
+    stats_interval: Option<Duration>,
+
+    /// Messages seen by endpoints since stats were last sent.
+    messages_since_stats: u64,
+
+    /// When stats were last sent.
+    stats_since: Instant,
+
+    /// Server log records to send as events, and how severe they must be.
+    server_logs: Option<(LogLevel, broadcast::Receiver<ServerLog>)>,
+
+    /// How long a user may control endpoints before it is reported, if at all.
+    control_warn_after: Option<Duration>,
+
+    /// Users which may perform administrative actions.
+    admins: HashSet<String>,
 }
 
 /// Actions available to ask of the control center.
 #[derive(Debug)]
 pub(crate) enum Action {
     Observe(InternalEndpointId),
+    ObserveIdempotent(InternalEndpointId),
+    ObserveWeak(InternalEndpointId),
+    ObserveExclusive(InternalEndpointId),
+    ObserveAny(Labels),
+    Unobserve(InternalEndpointId),
+    ObserveDeferred {
+        id: InternalEndpointId,
+        idempotent: bool,
+    },
     Control(InternalEndpointId),
     ControlAny(Labels),
+    ControlAnyDeferred(Labels),
     SubscribeToEvents,
+    SubscribeToEndpointEvents(InternalEndpointId),
+    SubscribeToMessages(Vec<InternalEndpointId>),
+    Reconfigure {
+        id: InternalEndpointId,
+        baud: Option<u32>,
+        flow_control: Option<serialport::FlowControl>,
+    },
+    Flush {
+        id: InternalEndpointId,
+        tx: bool,
+        rx: bool,
+    },
+    GetMacro {
+        id: InternalEndpointId,
+        name: String,
+    },
+    GetOnControlMacros(InternalEndpointId),
+    ListEndpoints,
+    ListLabels,
+    Metrics,
+    TransferControl {
+        id: InternalEndpointId,
+        to_user: String,
+    },
+    Release(InternalEndpointId),
+    CancelQueue,
+    RecentMessages {
+        id: InternalEndpointId,
+        count: usize,
+    },
+    Notice {
+        id: InternalEndpointId,
+        text: String,
+    },
+    SetEnabled {
+        id: InternalEndpointId,
+        enabled: bool,
+        release_holders: bool,
+    },
+    Listen(InternalEndpointId),
 }
 
 impl Display for Action {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             Action::Observe(id) => write!(f, "observe: {id}"),
+            Action::ObserveIdempotent(id) => write!(f, "observe idempotent: {id}"),
+            Action::ObserveWeak(id) => write!(f, "observe weak: {id}"),
+            Action::ObserveExclusive(id) => write!(f, "observe exclusive: {id}"),
+            Action::ObserveAny(labels) => write!(f, "observe any: {labels}"),
+            Action::Unobserve(id) => write!(f, "unobserve: {id}"),
+            Action::ObserveDeferred { id, idempotent } => {
+                write!(f, "observe deferred: {id}, idempotent: {idempotent}")
+            }
             Action::Control(id) => write!(f, "control: {id}"),
             Action::ControlAny(labels) => {
                 write!(f, "control any: {labels}")
             }
+            Action::ControlAnyDeferred(labels) => {
+                write!(f, "control any deferred: {labels}")
+            }
             Action::SubscribeToEvents => write!(f, "subscribe to events"),
+            Action::SubscribeToEndpointEvents(id) => {
+                write!(f, "subscribe to endpoint events: {id}")
+            }
+            Action::SubscribeToMessages(ids) => {
+                write!(f, "subscribe to messages:")?;
+                for id in ids {
+                    write!(f, " {id}")?;
+                }
+                Ok(())
+            }
+            Action::Reconfigure {
+                id,
+                baud,
+                flow_control,
+            } => write!(
+                f,
+                "reconfigure: {id}, baud: {baud:?}, flow control: {flow_control:?}"
+            ),
+            Action::Flush { id, tx, rx } => write!(f, "flush: {id}, tx: {tx}, rx: {rx}"),
+            Action::GetMacro { id, name } => write!(f, "get macro: {name} for {id}"),
+            Action::GetOnControlMacros(id) => write!(f, "get on control macros for {id}"),
+            Action::ListEndpoints => write!(f, "list endpoints"),
+            Action::ListLabels => write!(f, "list labels"),
+            Action::Metrics => write!(f, "metrics"),
+            Action::TransferControl { id, to_user } => {
+                write!(f, "transfer control: {id} to {to_user}")
+            }
+            Action::Release(id) => write!(f, "release: {id}"),
+            Action::CancelQueue => write!(f, "cancel queue"),
+            Action::RecentMessages { id, count } => {
+                write!(f, "recent messages: {id}, count: {count}")
+            }
+            Action::Notice { id, text } => write!(f, "notice: {text} on {id}"),
+            Action::SetEnabled {
+                id,
+                enabled,
+                release_holders,
+            } => write!(
+                f,
+                "set enabled: {id}, enabled: {enabled}, release holders: {release_holders}"
+            ),
+            Action::Listen(id) => write!(f, "listen: {id}"),
         }
     }
 }
@@ -303,37 +638,73 @@ pub(crate) enum Inform {
     /// A user arrived.
     UserArrived(User),
 
+    /// Where requests to a user's peer can be sent.
+    /// Needed for handing control from one user to another.
+    UserReachable {
+        user: User,
+        requests: PeerRequestSender,
+    },
+
     /// A user left.
     /// This is important to know because we might need to clean up state after them.
     UserLeft(User),
 
-    UserReceivedMessage((User, InternalEndpointInfo, SerialMessage)),
-    UserSentMessage((User, InternalEndpointId, SerialMessage)),
+    /// A user sent or received a message.
+    UserMessage((User, TaggedMessage)),
+
+    /// A user's write could not be put on wire, since the endpoint is closed.
+    WriteDropped((User, TaggedMessage)),
 
     NowControlling {
         user: User,
         context: ControlContext,
     },
 
-    EndpointEvent((InternalEndpointId, endpoint::EndpointEvent)),
+    /// An endpoint saw a message, regardless of which user (if any) was involved.
+    EndpointMessage(TaggedMessage),
+
+    /// An endpoint has not received anything from wire within its idle timeout.
+    EndpointIdle(InternalEndpointInfo),
+
+    /// An idle endpoint received something from wire again.
+    EndpointActive(InternalEndpointInfo),
+
+    /// An endpoint received something from wire for the first time since it was opened.
+    EndpointFirstMessage(InternalEndpointInfo),
+
+    /// A user's observation of an endpoint delivered as many messages as it was limited to.
+    ObserveLimitReached((User, InternalEndpointInfo)),
+
+    /// A user stopped waiting for an endpoint matching the labels to control.
+    DeferredControlGaveUp((User, Labels)),
 }
 
 impl Display for Inform {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             Inform::UserArrived(user) => write!(f, "user arrived: {user}"),
+            Inform::UserReachable { user, .. } => write!(f, "user reachable: {user}"),
             Inform::UserLeft(user) => write!(f, "user left: {user}"),
             Inform::NowControlling { user, context } => {
                 write!(f, "{user} now controlling, ctx: {context}")
             }
-            Inform::UserReceivedMessage((user, info, msg)) => {
-                write!(f, "user {user} got message {msg} via {info}")
+            Inform::UserMessage((user, message)) => {
+                write!(f, "user {user} message {message}")
+            }
+            Inform::WriteDropped((user, message)) => {
+                write!(f, "user {user} write dropped {message}")
             }
-            Inform::UserSentMessage((user, info, msg)) => {
-                write!(f, "user {user} sent message {msg} via {info}")
+            Inform::EndpointMessage(message) => {
+                write!(f, "endpoint message {message}")
             }
-            Inform::EndpointEvent((id, event)) => {
-                write!(f, "endpoint {id} event {event}")
+            Inform::EndpointIdle(info) => write!(f, "endpoint idle {info}"),
+            Inform::EndpointActive(info) => write!(f, "endpoint active {info}"),
+            Inform::EndpointFirstMessage(info) => write!(f, "endpoint first message {info}"),
+            Inform::ObserveLimitReached((user, info)) => {
+                write!(f, "user {user} observe limit reached for {info}")
+            }
+            Inform::DeferredControlGaveUp((user, labels)) => {
+                write!(f, "user {user} gave up deferred control of {labels}")
             }
         }
     }
@@ -369,13 +740,117 @@ impl Debug for ControlCenterMessage {
 #[derive(Debug)]
 pub(crate) enum ControlCenterResponse {
     ControlThis(MaybeEndpointController),
+    /// The endpoint to observe, along with its recent messages (oldest first) for replaying.
     EndpointObserver(
         (
             InternalEndpointInfo,
             broadcast::Receiver<endpoint::EndpointEvent>,
+            Vec<RecentMessage>,
         ),
     ),
     EventObserver(broadcast::Receiver<events::TimestampedEvent>),
+
+    /// The composite to observe, along with an observer of each of its ports.
+    CompositeObserver(
+        (
+            InternalEndpointInfo,
+            Vec<(
+                InternalEndpointInfo,
+                broadcast::Receiver<endpoint::EndpointEvent>,
+                Vec<RecentMessage>,
+            )>,
+        ),
+    ),
+
+    /// The labels observed, along with an observer of each matching endpoint.
+    AnyObserver(
+        (
+            Labels,
+            Vec<(
+                InternalEndpointInfo,
+                broadcast::Receiver<endpoint::EndpointEvent>,
+                Vec<RecentMessage>,
+            )>,
+        ),
+    ),
+
+    /// The user was already observing the endpoint, and asked for that to be fine.
+    AlreadyObserving(InternalEndpointInfo),
+
+    /// The user no longer observes the endpoint, if they did.
+    Unobserved(Option<InternalEndpointInfo>),
+
+    /// The endpoint does not exist yet.
+    /// The user's peer is sent [`PeerAction::DeferredObserverReady`] once it does.
+    ObservationDeferred(InternalEndpointId),
+
+    /// No endpoint matches the labels yet.
+    /// The user's peer is sent [`PeerAction::DeferredControlReady`] once one does.
+    ControlDeferred(Labels),
+
+    /// All events, which should be narrowed down to the given endpoint's.
+    EndpointEventObserver(
+        (
+            InternalEndpointInfo,
+            broadcast::Receiver<events::TimestampedEvent>,
+        ),
+    ),
+
+    /// All events, which should be narrowed down to the messages of the given endpoints.
+    MessageObserver(
+        (
+            Vec<InternalEndpointInfo>,
+            broadcast::Receiver<events::TimestampedEvent>,
+        ),
+    ),
+
+    /// Resolves when the endpoint has applied new settings.
+    Reconfiguring(oneshot::Receiver<Result<(), Error>>),
+
+    /// Resolves when the endpoint has discarded its buffers.
+    Flushing(oneshot::Receiver<Result<(), Error>>),
+
+    /// The most recent messages the endpoint received from wire, oldest first.
+    RecentMessages((InternalEndpointInfo, Vec<SerialMessageBytes>)),
+
+    /// A notice was sent to this many observers of the endpoint.
+    NoticeSent((InternalEndpointInfo, usize)),
+
+    /// The endpoint was enabled or disabled.
+    EnabledSet((InternalEndpointInfo, bool)),
+
+    /// What happens on the endpoint from now on, without observing it.
+    Listener(broadcast::Receiver<endpoint::EndpointEvent>),
+
+    /// The macro which should be run.
+    Macro(Macro),
+
+    /// The macros which should be run, in order.
+    Macros(Vec<Macro>),
+
+    /// The endpoints known, sorted by id.
+    Endpoints(Vec<ListedEndpoint>),
+
+    /// The distinct labels of the endpoints known, sorted by label.
+    Labels(Vec<ListedLabel>),
+
+    /// The metrics of each endpoint known, sorted by id.
+    Metrics(Vec<EndpointMetrics>),
+
+    /// The user no longer controls the endpoint(s), and the controller
+    /// should be handed to the given peer.
+    TransferTo {
+        context: ControlContext,
+        requests: PeerRequestSender,
+    },
+
+    /// The user no longer controls the endpoint(s),
+    /// and the controller should be dropped.
+    Released,
+
+    /// The user is no longer queued for these endpoints,
+    /// and should stop waiting for a controller.
+    QueueCancelled(Vec<InternalEndpointInfo>),
 }
 
 impl ControlCenterResponse {
@@ -445,31 +920,82 @@ impl ControlCenterHandle {
 
 impl ControlCenter {
     pub(crate) fn new(
-        config: Config,
+        mut config: Config,
         requests: mpsc::UnboundedReceiver<ControlCenterMessage>,
         mut endpoints: Endpoints,
     ) -> Self {
         let _span = info_span!("ControlCenter init").entered();
 
-        for ConfigEndpoint {
-            id: endpoint_id,
-            labels,
-            flow_control,
-        } in config.endpoints
-        {
+        config.apply_default_labels();
+        config.apply_label_defaults();
+
+        let mut access_lists = HashMap::new();
+        let mut charsets = HashMap::new();
+
+        for config_endpoint in config.endpoints {
+            let encoding = config_endpoint.encoding();
+            let ConfigEndpoint {
+                id: endpoint_id,
+                labels,
+                flow_control,
+                baud,
+                idle_message_timeout,
+                write_terminator,
+                transforms,
+                write_delay,
+                echo,
+                shared_control,
+                allow_users,
+                deny_users,
+                description,
+                read_buffer_size,
+                charset: _,
+                prompt,
+            } = config_endpoint;
+
+            let access_list = AccessList::new(&allow_users, &deny_users);
+
             match endpoint_id {
-                EndpointId::Tty(tty) => {
-                    let mut builder = SerialPortBuilder::new(&tty);
+                EndpointId::Tty(ref path) | EndpointId::Pty(ref path) => {
+                    let mut builder = if endpoint_id.as_pty().is_some() {
+                        SerialPortBuilder::new_pty(path)
+                    } else {
+                        SerialPortBuilder::new(path)
+                    };
+
+                    if shared_control {
+                        builder = builder.set_semaphore(EndpointSemaphore::shared());
+                    }
 
                     let flow_control = flow_control.unwrap_or(serialport::FlowControl::None);
                     builder.set_flow_control(flow_control);
 
+                    if let Some(baud) = baud {
+                        builder.set_baud(baud as usize);
+                    }
+
+                    if let Some(size) = read_buffer_size {
+                        builder = builder.set_read_buffer_size(size);
+                    }
+
+                    if let Some(terminator) = write_terminator {
+                        builder = builder.set_write_terminator(terminator.into_bytes());
+                    }
+
+                    builder = builder.set_transforms(transforms);
+
+                    if let Some(description) = description {
+                        builder = builder.set_description(description);
+                    }
+
+                    if let Some(prompt) = prompt {
+                        builder = builder.set_prompt(prompt);
+                    }
+
                     for label in labels.into_iter() {
                         builder = builder.add_label(label);
                     }
 
-                    let id = InternalEndpointId::Tty(tty);
-
                     let endpoint = match builder.build() {
                         Ok(e) => e,
                         Err(e) if config.ignore_unavailable_endpoints => {
@@ -481,29 +1007,81 @@ impl ControlCenter {
                         }
                     };
 
-                    endpoints.insert(id, endpoint);
+                    access_lists.insert(endpoint.internal_endpoint_id(), access_list);
+                    if let Some(encoding) = encoding {
+                        charsets.insert(endpoint.internal_endpoint_id(), encoding);
+                    }
+                    endpoints.insert(
+                        endpoint.internal_endpoint_id(),
+                        endpoint,
+                        idle_message_timeout,
+                    );
+                }
+                EndpointId::Composite(_) => {
+                    unreachable!("Composites are rejected as endpoints when validating the config")
                 }
                 EndpointId::Mock(mock) => {
                     let mock_id = MockId::new("MockFromConfig", &mock);
                     let id = InternalEndpointId::Mock(mock_id.clone());
 
                     let mut builder = MockBuilder::new(mock_id);
+
+                    if shared_control {
+                        builder = builder.set_semaphore(EndpointSemaphore::shared());
+                    }
+
+                    if let Some(delay) = write_delay {
+                        builder = builder.set_write_delay(delay);
+                    }
+                    builder = builder.set_echo(echo);
                     for label in labels.into_iter() {
                         builder = builder.add_label(label);
                     }
 
-                    endpoints.insert(id, builder.build());
+                    if let Some(terminator) = write_terminator {
+                        builder = builder.set_write_terminator(terminator.into_bytes());
+                    }
+
+                    builder = builder.set_transforms(transforms);
+
+                    if let Some(description) = description {
+                        builder = builder.set_description(description);
+                    }
+
+                    if let Some(prompt) = prompt {
+                        builder = builder.set_prompt(prompt);
+                    }
+
+                    access_lists.insert(id.clone(), access_list);
+                    if let Some(encoding) = encoding {
+                        charsets.insert(id.clone(), encoding);
+                    }
+                    endpoints.insert(id, builder.build(), idle_message_timeout);
                 }
             }
         }
 
-        for (index, group) in config.groups.iter().enumerate() {
+        // The ports of a composite are controlled together, just like a group.
+        let composites = config
+            .composites
+            .iter()
+            .map(|composite| (composite.group(), Some(composite)));
+
+        for (index, (group, composite)) in config
+            .groups
+            .iter()
+            .cloned()
+            .map(|group| (group, None))
+            .chain(composites)
+            .enumerate()
+        {
             let shared_semaphore = EndpointSemaphore::default();
+            let mut members = vec![];
 
             let group_label = &group.labels;
 
             if group.is_mock_group() {
-                let group_name = format!("MockGroup{index}");
+                let group_name = group.mock_owner(index);
 
                 for config_endpoint in &group.endpoints {
                     let endpoint_name = config_endpoint.id.as_mock().unwrap();
@@ -514,6 +1092,11 @@ impl ControlCenter {
                     let mut builder =
                         MockBuilder::new(mock_id).set_semaphore(shared_semaphore.clone());
 
+                    if let Some(delay) = config_endpoint.write_delay {
+                        builder = builder.set_write_delay(delay);
+                    }
+                    builder = builder.set_echo(config_endpoint.echo);
+
                     for label in group_label.iter() {
                         builder = builder.add_label(label.clone());
                     }
@@ -522,13 +1105,58 @@ impl ControlCenter {
                         builder = builder.add_label(label.clone());
                     }
 
-                    endpoints.insert(id, builder.build());
+                    if let Some(terminator) = &config_endpoint.write_terminator {
+                        builder = builder.set_write_terminator(terminator.clone().into_bytes());
+                    }
+
+                    builder = builder.set_transforms(config_endpoint.transforms.clone());
+
+                    // The endpoint's own description is more specific than the group's.
+                    if let Some(description) = config_endpoint
+                        .description
+                        .as_ref()
+                        .or(group.description.as_ref())
+                    {
+                        builder = builder.set_description(description.clone());
+                    }
+
+                    if let Some(prompt) = &config_endpoint.prompt {
+                        builder = builder.set_prompt(prompt.clone());
+                    }
+
+                    let endpoint = builder.build();
+                    members.push(InternalEndpointInfo::new(id.clone(), endpoint.labels()));
+
+                    access_lists.insert(
+                        id.clone(),
+                        config_endpoint
+                            .access_list()
+                            .restricted_by(group.access_list()),
+                    );
+                    if let Some(encoding) = config_endpoint.encoding() {
+                        charsets.insert(id.clone(), encoding);
+                    }
+                    endpoints.insert(id, endpoint, config_endpoint.idle_message_timeout);
                 }
             } else {
                 for config_endpoint in &group.endpoints {
-                    let tty_path = config_endpoint.id.as_tty().unwrap();
-                    let mut builder =
-                        SerialPortBuilder::new(tty_path).set_semaphore(shared_semaphore.clone());
+                    let mut builder = match &config_endpoint.id {
+                        EndpointId::Pty(name) => SerialPortBuilder::new_pty(name),
+                        id => SerialPortBuilder::new(id.as_tty().unwrap()),
+                    }
+                    .set_semaphore(shared_semaphore.clone());
+
+                    if let Some(flow_control) = config_endpoint.flow_control {
+                        builder.set_flow_control(flow_control);
+                    }
+
+                    if let Some(baud) = config_endpoint.baud {
+                        builder.set_baud(baud as usize);
+                    }
+
+                    if let Some(size) = config_endpoint.read_buffer_size {
+                        builder = builder.set_read_buffer_size(size);
+                    }
 
                     for label in group_label.iter() {
                         builder = builder.add_label(label.clone());
@@ -538,7 +1166,24 @@ impl ControlCenter {
                         builder = builder.add_label(label.clone());
                     }
 
-                    let id = InternalEndpointId::Tty(tty_path.into());
+                    if let Some(terminator) = &config_endpoint.write_terminator {
+                        builder = builder.set_write_terminator(terminator.clone().into_bytes());
+                    }
+
+                    builder = builder.set_transforms(config_endpoint.transforms.clone());
+
+                    // The endpoint's own description is more specific than the group's.
+                    if let Some(description) = config_endpoint
+                        .description
+                        .as_ref()
+                        .or(group.description.as_ref())
+                    {
+                        builder = builder.set_description(description.clone());
+                    }
+
+                    if let Some(prompt) = &config_endpoint.prompt {
+                        builder = builder.set_prompt(prompt.clone());
+                    }
 
                     let endpoint = match builder.build() {
                         Ok(e) => e,
@@ -551,46 +1196,208 @@ impl ControlCenter {
                         }
                     };
 
-                    endpoints.insert(id, endpoint);
+                    members.push(InternalEndpointInfo::new(
+                        endpoint.internal_endpoint_id(),
+                        endpoint.labels(),
+                    ));
+
+                    access_lists.insert(
+                        endpoint.internal_endpoint_id(),
+                        config_endpoint
+                            .access_list()
+                            .restricted_by(group.access_list()),
+                    );
+                    if let Some(encoding) = config_endpoint.encoding() {
+                        charsets.insert(endpoint.internal_endpoint_id(), encoding);
+                    }
+                    endpoints.insert(
+                        endpoint.internal_endpoint_id(),
+                        endpoint,
+                        config_endpoint.idle_message_timeout,
+                    );
                 }
             }
+
+            if let Some(composite) = composite {
+                // Controlling the composite controls every port, so all their lists apply.
+                let access_list = members
+                    .iter()
+                    .filter_map(|member| access_lists.get(&member.id).cloned())
+                    .fold(AccessList::default(), AccessList::restricted_by);
+
+                let endpoint = CompositeEndpoint::new(composite, shared_semaphore, members);
+                access_lists.insert(endpoint.internal_endpoint_id(), access_list);
+                endpoints.insert(endpoint.internal_endpoint_id(), endpoint, None);
+            }
         }
 
-        Self {
+        let control_center = Self {
             messages: requests,
             endpoints,
-            events: events::Events::new(10_000),
+            events: events::Events::new(config.events.log_size, config.events.channel_depth),
             user_state: HashMap::new(),
-        }
-    }
+            macros: config.macros,
+            exclusive_observers: HashMap::new(),
+            disabled_endpoints: HashSet::new(),
+            control_queues: HashMap::new(),
+            access_lists,
+            charsets,
+            recent_messages: HashMap::new(),
+            stats_interval: Some(config.events.stats_interval).filter(|i| !i.is_zero()),
+            messages_since_stats: 0,
+            stats_since: Instant::now(),
+            server_logs: config
+                .events
+                .server_logs
+                .map(|level| (level, logging::subscribe_server_logs())),
+            control_warn_after: config.control_warn_after,
+            admins: config.admins.iter().cloned().collect(),
+        };
 
-    fn is_observing_endpoint(&self, user: &User, id: &InternalEndpointInfo) -> bool {
-        self.user_state
-            .get(user)
-            .expect("We should know about live users")
-            .observing_endpoints
-            .contains(id)
-    }
+        control_center.run_startup_macros(&config.startup_macros);
 
-    fn is_observing_events(&self, user: &User) -> bool {
-        self.user_state
-            .get(user)
-            .expect("We should know about live users")
-            .observing_user_events
+        control_center
     }
 
-    fn set_observing_events(&mut self, user: &User) {
-        self.user_state
-            .get_mut(user)
-            .expect("We should know about live users")
-            .observing_user_events = true;
-    }
+    /// Run the startup macros on each endpoint they target.
+    ///
+    /// Each endpoint gets its own task, which holds the endpoint's permit
+    /// until done, such that users wanting control queue up behind it.
+    fn run_startup_macros(&self, startup_macros: &[StartupMacro]) {
+        for (info, endpoint) in &self.endpoints.inner {
+            // A composite has no wire of its own, its ports run their own macros.
+            if let InternalEndpointId::Composite(_) = info.id {
+                continue;
+            }
 
-    fn set_observing_endpoint(&mut self, user: &User, id: InternalEndpointInfo) {
-        // Assert: Just making sure we don't double insert,
-        // which would be a bug on our part.
-        assert!(self
-            .user_state_mut(user)
+            let endpoint_id = EndpointId::from(info.id.clone());
+
+            let macros = startup_macros
+                .iter()
+                .filter(|startup| match &startup.target {
+                    MacroTarget::Endpoint(target) => target == &endpoint_id,
+                    MacroTarget::Labels(labels) => info.labels.is_superset(labels),
+                })
+                .filter_map(
+                    |startup| match self.get_macro(info.id.clone(), &startup.name) {
+                        Ok(m) => Some(m),
+                        Err(e) => {
+                            error!(%info, ?e, "Cannot run startup macro");
+                            None
+                        }
+                    },
+                )
+                .collect_vec();
+
+            if macros.is_empty() {
+                continue;
+            }
+
+            let semaphore = endpoint.semaphore();
+            let mut sender = endpoint.message_sender();
+
+            tokio::spawn(
+                async move {
+                    let _permit = semaphore
+                        .inner
+                        .acquire_owned()
+                        .await
+                        .expect("Endpoint semaphores are never closed");
+
+                    for startup in macros {
+                        info!(name = %startup.name, "Running startup macro");
+
+                        for step in startup.steps {
+                            info!(?step, "Startup macro step");
+
+                            let message = match step {
+                                MacroStep::Line(line) => format!("{line}\n").into_bytes(),
+                                MacroStep::Bytes(bytes) => bytes,
+                                MacroStep::DelayMs(ms) => {
+                                    tokio::time::sleep(Duration::from_millis(ms)).await;
+                                    continue;
+                                }
+                            };
+
+                            if let Err(e) = sender.send(message).await {
+                                warn!(?e, "Endpoint closed during startup macro");
+                                return;
+                            }
+                        }
+                    }
+
+                    info!("Startup macros done");
+                }
+                .instrument(info_span!("startup_macros", %info)),
+            );
+        }
+    }
+
+    /// The macros which may be run on the given endpoint.
+    /// If names clash, endpoint specific macros take precedence over labelled ones.
+    fn macros_for(&self, id: InternalEndpointId) -> Result<Vec<Macro>, Error> {
+        let info = self.endpoints.id_to_info(id)?;
+        let endpoint_id = EndpointId::from(info.id.clone());
+
+        let (specific, labelled): (Vec<_>, Vec<_>) = self
+            .macros
+            .iter()
+            .filter(|m| match &m.target {
+                MacroTarget::Endpoint(target) => target == &endpoint_id,
+                MacroTarget::Labels(labels) => info.labels.is_superset(labels),
+            })
+            .partition(|m| matches!(m.target, MacroTarget::Endpoint(_)));
+
+        let labelled = labelled
+            .into_iter()
+            .filter(|l| !specific.iter().any(|s| s.name == l.name))
+            .collect_vec();
+
+        Ok(specific.into_iter().chain(labelled).cloned().collect())
+    }
+
+    /// Find the macro with the given name which may be run on the given endpoint.
+    fn get_macro(&self, id: InternalEndpointId, name: &str) -> Result<Macro, Error> {
+        let endpoint_id = EndpointId::from(id.clone());
+
+        self.macros_for(id)?
+            .into_iter()
+            .find(|m| m.name == name)
+            .ok_or_else(|| {
+                Error::BadUsage(format!(
+                    "There is no macro named `{name}` for the endpoint {endpoint_id}"
+                ))
+            })
+    }
+
+    fn is_observing_endpoint(&self, user: &User, id: &InternalEndpointInfo) -> bool {
+        let state = self
+            .user_state
+            .get(user)
+            .expect("We should know about live users");
+
+        state.observing_endpoints.contains(id) || state.weakly_observing_endpoints.contains(id)
+    }
+
+    fn is_observing_events(&self, user: &User) -> bool {
+        self.user_state
+            .get(user)
+            .expect("We should know about live users")
+            .observing_user_events
+    }
+
+    fn set_observing_events(&mut self, user: &User) {
+        self.user_state
+            .get_mut(user)
+            .expect("We should know about live users")
+            .observing_user_events = true;
+    }
+
+    fn set_observing_endpoint(&mut self, user: &User, id: InternalEndpointInfo) {
+        // Assert: Just making sure we don't double insert,
+        // which would be a bug on our part.
+        assert!(self
+            .user_state_mut(user)
             .observing_endpoints
             .insert(id.clone()));
 
@@ -598,6 +1405,16 @@ impl ControlCenter {
             .send_user_event(user, events::user::Event::Observing(vec![id]));
     }
 
+    fn set_weakly_observing_endpoint(&mut self, user: &User, id: InternalEndpointInfo) {
+        assert!(self
+            .user_state_mut(user)
+            .weakly_observing_endpoints
+            .insert(id.clone()));
+
+        self.events
+            .send_user_event(user, events::user::Event::Observing(vec![id]));
+    }
+
     fn user_state_mut(&mut self, user: &User) -> &mut UserState {
         self.user_state.get_mut(user).expect("User should be alive")
     }
@@ -606,29 +1423,44 @@ impl ControlCenter {
         self.user_state.get(user).expect("User should be alive")
     }
 
-    fn set_controls(&mut self, user: &User, endpoints_infos: Vec<InternalEndpointInfo>) {
+    fn set_controls(
+        &mut self,
+        user: &User,
+        endpoints_infos: Vec<InternalEndpointInfo>,
+    ) -> Result<(), Error> {
         info_span!("Now controls", %user);
 
-        self.events.send_user_event(
-            user,
-            events::user::Event::InControlOf(endpoints_infos.clone()),
-        );
-
+        // The endpoints may be gone by now, e.g. if control was transferred
+        // from a user of a mock which left before the new user got here.
         let mut semaphore_ids = endpoints_infos
-            .into_iter()
-            .map(|id| self.endpoints.endpoint_semaphore_id(&id).expect("Exists"))
-            .collect::<Vec<_>>();
+            .iter()
+            .map(|info| {
+                self.endpoints
+                    .endpoint_semaphore_id(info)
+                    .ok_or_else(|| Error::NoSuchEndpoint(info.to_string()))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
         semaphore_ids.dedup();
         debug!("Semaphore ids: {semaphore_ids:?}");
 
-        assert!(semaphore_ids.len() == 1);
+        let [semaphore_id] = semaphore_ids.as_slice() else {
+            return Err(Error::InternalIssue(format!(
+                "Control should be over exactly one group of endpoints, got {semaphore_ids:?}"
+            )));
+        };
 
-        // Assert: Just making sure we don't double insert,
-        // which would be a bug on our part.
-        assert!(self
-            .user_state_mut(user)
-            .in_control_of
-            .insert(semaphore_ids[0].clone()));
+        let in_control_of = &mut self.user_state_mut(user).in_control_of;
+        if in_control_of.contains_key(semaphore_id) {
+            return Err(Error::SuperfluousRequest(format!(
+                "`{user}` is already in control of {semaphore_id:?}"
+            )));
+        }
+        in_control_of.insert(semaphore_id.clone(), Control::new());
+
+        self.events
+            .send_user_event(user, events::user::Event::InControlOf(endpoints_infos));
+
+        Ok(())
     }
 
     fn set_in_control_queue(&mut self, user: &User, controller_queue: &EndpointControllerQueue) {
@@ -638,15 +1470,54 @@ impl ControlCenter {
             .send_user_event(user, events::user::Event::InQueueFor(endpoint_ids.clone()));
 
         for id in endpoint_ids {
-            assert!(self.user_state_mut(user).in_queue_of.insert(id));
+            if !self.user_state_mut(user).in_queue_of.insert(id.clone()) {
+                warn!(%user, %id, "Already queued");
+                continue;
+            }
+
+            self.control_queues
+                .entry(id.clone())
+                .or_default()
+                .push(user.clone());
+            self.send_queue_updated(&id);
         }
     }
 
+    fn leave_control_queue(&mut self, user: &User, info: &InternalEndpointInfo) {
+        if let Some(queue) = self.control_queues.get_mut(info) {
+            queue.retain(|queued| queued != user);
+        }
+        self.send_queue_updated(info);
+
+        if self
+            .control_queues
+            .get(info)
+            .map_or(false, |queue| queue.is_empty())
+        {
+            self.control_queues.remove(info);
+        }
+    }
+
+    fn send_queue_updated(&mut self, info: &InternalEndpointInfo) {
+        let ordered_users = self.control_queues.get(info).cloned().unwrap_or_default();
+
+        self.events
+            .send_general_event(events::general::Event::QueueUpdated {
+                endpoint: info.clone().into(),
+                ordered_users,
+            });
+    }
+
     fn observe(
         &mut self,
         user: User,
         id: InternalEndpointId,
+        idempotent: bool,
     ) -> Result<ControlCenterResponse, Error> {
+        if let InternalEndpointId::Composite(_) = &id {
+            return self.observe_composite(user, id);
+        }
+
         let to_observe = if let InternalEndpointId::Mock(mock_id) = &id {
             self.endpoints.get_or_create_mock(mock_id)
         } else {
@@ -656,6 +1527,129 @@ impl ControlCenter {
 
         let info = self.endpoints.id_to_info(id.clone())?;
 
+        if self.is_observing_endpoint(&user, &info) {
+            if idempotent {
+                return Ok(ControlCenterResponse::AlreadyObserving(info));
+            }
+
+            return Err(Error::SuperfluousRequest(format!(
+                "`{user}` is already observing endpoint `{}`",
+                LabelledEndpointId::from(info)
+            )));
+        }
+
+        self.check_not_exclusively_observed(&user, &info)?;
+        self.check_enabled(&info)?;
+        self.set_observing_endpoint(&user, info.clone());
+
+        let recent = self.recent_messages(&info);
+
+        Ok(ControlCenterResponse::EndpointObserver((
+            info, to_observe, recent,
+        )))
+    }
+
+    /// Observe each port of a composite.
+    /// Either all ports are observed, or none are.
+    fn observe_composite(
+        &mut self,
+        user: User,
+        id: InternalEndpointId,
+    ) -> Result<ControlCenterResponse, Error> {
+        let composite = self.endpoints.id_to_info(id.clone())?;
+        let ports = self.endpoints.get(id)?.ports();
+
+        for port in &ports {
+            if self.is_observing_endpoint(&user, port) {
+                return Err(Error::SuperfluousRequest(format!(
+                    "`{user}` is already observing the port `{}` of `{}`",
+                    LabelledEndpointId::from(port.clone()),
+                    LabelledEndpointId::from(composite)
+                )));
+            }
+
+            self.check_not_exclusively_observed(&user, port)?;
+            self.check_enabled(port)?;
+        }
+
+        let mut observers = vec![];
+        for port in ports {
+            match self.observe(user.clone(), port.id, false)? {
+                ControlCenterResponse::EndpointObserver(observer) => observers.push(observer),
+                _ => unreachable!(),
+            }
+        }
+
+        Ok(ControlCenterResponse::CompositeObserver((
+            composite, observers,
+        )))
+    }
+
+    /// Observe every endpoint matching the labels.
+    /// Either all matching endpoints are observed, or none are.
+    fn observe_any(&mut self, user: User, labels: Labels) -> Result<ControlCenterResponse, Error> {
+        if labels.is_empty() {
+            return Err(Error::BadUsage(
+                "At least one label must be provided".to_string(),
+            ));
+        }
+
+        let infos = self.endpoints.labels_to_all_endpoint_ids(&labels);
+        if infos.is_empty() {
+            return Err(Error::NoMatchingEndpoints(labels));
+        }
+
+        for info in &infos {
+            if self.is_observing_endpoint(&user, info) {
+                return Err(Error::SuperfluousRequest(format!(
+                    "`{user}` is already observing endpoint `{}` matching `{labels}`",
+                    LabelledEndpointId::from(info.clone())
+                )));
+            }
+
+            self.check_not_exclusively_observed(&user, info)?;
+            self.check_enabled(info)?;
+        }
+
+        let mut observers = vec![];
+        for info in infos {
+            match self.observe(user.clone(), info.id, false)? {
+                ControlCenterResponse::EndpointObserver(observer) => observers.push(observer),
+                _ => unreachable!(),
+            }
+        }
+
+        Ok(ControlCenterResponse::AnyObserver((labels, observers)))
+    }
+
+    fn check_not_exclusively_observed(
+        &self,
+        user: &User,
+        info: &InternalEndpointInfo,
+    ) -> Result<(), Error> {
+        match self.exclusive_observers.get(&info.id) {
+            Some(holder) if holder != user => Err(Error::EndpointBusy(format!(
+                "`{}` is exclusively observed by `{holder}`",
+                LabelledEndpointId::from(info.clone())
+            ))),
+            _ => Ok(()),
+        }
+    }
+
+    fn observe_exclusive(
+        &mut self,
+        user: User,
+        id: InternalEndpointId,
+    ) -> Result<ControlCenterResponse, Error> {
+        let to_observe = if let InternalEndpointId::Mock(mock_id) = &id {
+            self.endpoints.get_or_create_mock(mock_id)
+        } else {
+            self.endpoints.get(id.borrow())?
+        }
+        .events();
+
+        let info = self.endpoints.id_to_info(id)?;
+
         if self.is_observing_endpoint(&user, &info) {
             return Err(Error::SuperfluousRequest(format!(
                 "`{user}` is already observing endpoint `{}`",
@@ -663,9 +1657,301 @@ impl ControlCenter {
             )));
         }
 
+        self.check_enabled(&info)?;
+
+        let others = self
+            .user_state
+            .iter()
+            .filter(|(other, state)| {
+                *other != &user
+                    && (state.observing_endpoints.contains(&info)
+                        || state.weakly_observing_endpoints.contains(&info))
+            })
+            .map(|(other, _)| format!("`{other}`"))
+            .sorted()
+            .join(", ");
+        if !others.is_empty() {
+            return Err(Error::EndpointBusy(format!(
+                "`{}` is observed by {others}",
+                LabelledEndpointId::from(info)
+            )));
+        }
+
+        debug!(%user, %info, "Observing exclusively");
+        self.exclusive_observers
+            .insert(info.id.clone(), user.clone());
         self.set_observing_endpoint(&user, info.clone());
 
-        Ok(ControlCenterResponse::EndpointObserver((info, to_observe)))
+        let recent = self.recent_messages(&info);
+
+        Ok(ControlCenterResponse::EndpointObserver((
+            info, to_observe, recent,
+        )))
+    }
+
+    fn unobserve(&mut self, user: &User, id: &InternalEndpointId) -> ControlCenterResponse {
+        let state = self.user_state_mut(user);
+        state.deferred_observations.remove(id);
+
+        let Some(info) = state
+            .observing_endpoints
+            .iter()
+            .chain(&state.weakly_observing_endpoints)
+            .find(|info| &info.id == id)
+            .cloned()
+        else {
+            debug!(%user, %id, "Not observing, nothing to stop");
+            return ControlCenterResponse::Unobserved(None);
+        };
+
+        state.observing_endpoints.remove(&info);
+        state.weakly_observing_endpoints.remove(&info);
+
+        if self.exclusive_observers.get(id) == Some(user) {
+            self.exclusive_observers.remove(id);
+        }
+
+        self.events.send_user_event(
+            user,
+            events::user::Event::NoLongerObserving(vec![info.clone()]),
+        );
+        self.remove_dangling_mock_endpoints();
+
+        ControlCenterResponse::Unobserved(Some(info))
+    }
+
+    fn remember_message(&mut self, message: &TaggedMessage) {
+        let event = match message.direction {
+            Direction::FromWire => endpoint::EndpointEvent::FromWire(message.bytes.clone()),
+            Direction::ToWire => endpoint::EndpointEvent::ToWire(message.bytes.clone()),
+        };
+
+        let recent = self
+            .recent_messages
+            .entry(message.endpoint.id.clone())
+            .or_default();
+        recent.push_back((message.timestamp, event));
+        if recent.len() > RECENT_MESSAGES {
+            recent.pop_front();
+        }
+    }
+
+    fn recent_messages(&self, info: &InternalEndpointInfo) -> Vec<RecentMessage> {
+        self.recent_messages
+            .get(&info.id)
+            .map(|recent| recent.iter().cloned().collect())
+            .unwrap_or_default()
+    }
+
+    /// Decode a message from wire of an endpoint with another charset into UTF-8.
+    fn as_utf8(&self, id: &InternalEndpointId, bytes: SerialMessageBytes) -> SerialMessageBytes {
+        match self.charsets.get(id) {
+            Some(encoding) => SerialMessage::decode(bytes, encoding).into_bytes(),
+            None => bytes,
+        }
+    }
+
+    fn recent_messages_from_wire(
+        &self,
+        user: &User,
+        id: InternalEndpointId,
+        count: usize,
+    ) -> Result<ControlCenterResponse, Error> {
+        let info = self.endpoints.id_to_info(id)?;
+        self.check_not_exclusively_observed(user, &info)?;
+
+        let mut messages = self
+            .recent_messages(&info)
+            .into_iter()
+            .filter_map(|(_, event)| match event {
+                endpoint::EndpointEvent::FromWire(message) => Some(self.as_utf8(&info.id, message)),
+                endpoint::EndpointEvent::ToWire(_) => None,
+            })
+            .collect::<Vec<_>>();
+        messages.drain(..messages.len().saturating_sub(count));
+
+        Ok(ControlCenterResponse::RecentMessages((info, messages)))
+    }
+
+    fn observe_deferred(
+        &mut self,
+        user: User,
+        id: InternalEndpointId,
+        idempotent: bool,
+    ) -> Result<ControlCenterResponse, Error> {
+        if self.endpoints.get(&id).is_ok() {
+            return self.observe(user, id, idempotent);
+        }
+
+        if !self
+            .user_state_mut(&user)
+            .deferred_observations
+            .insert(id.clone())
+            && !idempotent
+        {
+            return Err(Error::SuperfluousRequest(format!(
+                "`{user}` is already waiting to observe endpoint `{id}`"
+            )));
+        }
+
+        Ok(ControlCenterResponse::ObservationDeferred(id))
+    }
+
+    /// Tell the observers of the endpoint something on behalf of an admin.
+    fn notice(
+        &self,
+        user: &User,
+        id: InternalEndpointId,
+        text: String,
+    ) -> Result<ControlCenterResponse, Error> {
+        if !self.admins.contains(user.name.as_str()) {
+            return Err(Error::AccessDenied(format!(
+                "User {user} is not an admin, so may not send notices"
+            )));
+        }
+
+        let info = self.endpoints.id_to_info(id)?;
+
+        let observers = self
+            .user_state
+            .values()
+            .filter(|state| {
+                state.observing_endpoints.contains(&info)
+                    || state.weakly_observing_endpoints.contains(&info)
+            })
+            .filter_map(|state| state.peer_requests.as_ref())
+            .filter(|requests| {
+                // Observers may leave at any time.
+                requests
+                    .send(PeerRequest::InternalAction(PeerAction::Notice((
+                        info.clone(),
+                        text.clone(),
+                    ))))
+                    .is_ok()
+            })
+            .count();
+
+        info!(%user, %info, %observers, "Notice sent");
+
+        Ok(ControlCenterResponse::NoticeSent((info, observers)))
+    }
+
+    /// Take an endpoint out of rotation or put it back, on behalf of an admin.
+    fn set_enabled(
+        &mut self,
+        user: &User,
+        id: InternalEndpointId,
+        enabled: bool,
+        release_holders: bool,
+    ) -> Result<ControlCenterResponse, Error> {
+        if !self.admins.contains(user.name.as_str()) {
+            return Err(Error::AccessDenied(format!(
+                "User {user} is not an admin, so may not enable or disable endpoints"
+            )));
+        }
+
+        let info = self.endpoints.id_to_info(id)?;
+
+        if enabled {
+            self.disabled_endpoints.remove(&info.id);
+            info!(%user, %info, "Endpoint enabled");
+
+            return Ok(ControlCenterResponse::EnabledSet((info, enabled)));
+        }
+
+        self.disabled_endpoints.insert(info.id.clone());
+        info!(%user, %info, "Endpoint disabled");
+
+        if release_holders {
+            let semaphore_id = self
+                .endpoints
+                .endpoint_semaphore_id(&info)
+                .expect("Endpoint exists");
+
+            for (holder, state) in &self.user_state {
+                if !state.in_control_of.contains_key(&semaphore_id) {
+                    continue;
+                }
+
+                // Holders may leave at any time, releasing control anyway.
+                if let Some(requests) = &state.peer_requests {
+                    if requests
+                        .send(PeerRequest::InternalAction(PeerAction::ControlRevoked(
+                            info.clone(),
+                        )))
+                        .is_err()
+                    {
+                        debug!(%holder, "Holder left before control was revoked");
+                    }
+                }
+            }
+        }
+
+        Ok(ControlCenterResponse::EnabledSet((info, enabled)))
+    }
+
+    fn check_enabled(&self, info: &InternalEndpointInfo) -> Result<(), Error> {
+        if self.disabled_endpoints.contains(&info.id) {
+            return Err(Error::EndpointDisabled(
+                LabelledEndpointId::from(info.clone()).to_string(),
+            ));
+        }
+
+        Ok(())
+    }
+
+    fn control_any_deferred(
+        &mut self,
+        user: User,
+        labels: Labels,
+    ) -> Result<ControlCenterResponse, Error> {
+        // Empty labels are refused as usual.
+        if labels.is_empty() || !self.endpoints.labels_to_endpoint_ids(&labels).is_empty() {
+            return self
+                .control_any(user, labels)
+                .map(ControlCenterResponse::ControlThis);
+        }
+
+        if !self
+            .user_state_mut(&user)
+            .deferred_controls
+            .insert(labels.clone())
+        {
+            return Err(Error::SuperfluousRequest(format!(
+                "`{user}` is already waiting to control any endpoint matching {labels}"
+            )));
+        }
+
+        Ok(ControlCenterResponse::ControlDeferred(labels))
+    }
+
+    fn observe_weak(
+        &mut self,
+        user: User,
+        id: InternalEndpointId,
+    ) -> Result<ControlCenterResponse, Error> {
+        // Unlike a regular observe, mocks are not created here:
+        // they would be removed again right away.
+        let to_observe = self.endpoints.get(id.borrow())?.events();
+
+        let info = self.endpoints.id_to_info(id)?;
+
+        if self.is_observing_endpoint(&user, &info) {
+            return Err(Error::SuperfluousRequest(format!(
+                "`{user}` is already observing endpoint `{}`",
+                LabelledEndpointId::from(info)
+            )));
+        }
+
+        self.check_not_exclusively_observed(&user, &info)?;
+        self.check_enabled(&info)?;
+        self.set_weakly_observing_endpoint(&user, info.clone());
+
+        let recent = self.recent_messages(&info);
+
+        Ok(ControlCenterResponse::EndpointObserver((
+            info, to_observe, recent,
+        )))
     }
 
     fn control_impl(
@@ -701,12 +1987,21 @@ impl ControlCenter {
                 },
             ),
             Err(TryAcquireError::NoPermits) => {
-                let (permit_tx, permit_rx) = oneshot::channel();
+                let (mut permit_tx, permit_rx) = oneshot::channel();
                 let permit_fut = semaphore.inner.acquire_owned();
                 let task_endpoints = endpoints_with_semaphore.clone();
 
                 tokio::spawn(async move {
-                    if let Ok(permit) = permit_fut.await {
+                    let permit = tokio::select! {
+                        permit = permit_fut => permit,
+                        // Leave the semaphore queue instead of holding up whoever is behind.
+                        _ = permit_tx.closed() => {
+                            debug!("No user waits for the permit anymore");
+                            return;
+                        }
+                    };
+
+                    if let Ok(permit) = permit {
                         if permit_tx
                             .send(EndpointController {
                                 _permit: permit,
@@ -721,11 +2016,16 @@ impl ControlCenter {
                     }
                 });
 
+                let endpoints = endpoints_with_semaphore.keys().cloned().collect::<Vec<_>>();
+                let queued_on = self.queued_on(&endpoints);
+
                 MaybeEndpointController::busy(
                     control_context,
                     EndpointControllerQueue {
                         inner: permit_rx,
-                        endpoints: endpoints_with_semaphore.keys().cloned().collect(),
+                        endpoints,
+                        queued_on,
+                        not_queued_on: vec![],
                     },
                 )
             }
@@ -735,12 +2035,61 @@ impl ControlCenter {
         Ok(maybe_control)
     }
 
+    // Who controls each endpoint, and how many are queued for it (not counting a user about to queue).
+    fn queued_on(&self, infos: &[InternalEndpointInfo]) -> Vec<QueuedOn> {
+        infos
+            .iter()
+            .map(|info| {
+                let semaphore_id = self.endpoints.endpoint_semaphore_id(info);
+                let controller = self
+                    .user_state
+                    .iter()
+                    .find(|(_, state)| {
+                        semaphore_id
+                            .as_ref()
+                            .map_or(false, |id| state.in_control_of.contains_key(id))
+                    })
+                    .map(|(user, _)| user.name.to_string());
+                let position = self
+                    .user_state
+                    .values()
+                    .filter(|state| state.in_queue_of.contains(info))
+                    .count();
+
+                QueuedOn {
+                    endpoint: info.clone().into(),
+                    controller,
+                    position,
+                }
+            })
+            .collect()
+    }
+
+    /// Whether the user may control the endpoint.
+    /// Control of an endpoint is control of every endpoint sharing its semaphore,
+    /// e.g. the rest of its group, so the user must be permitted on all of them.
+    fn may_control(&self, user: &User, info: &InternalEndpointInfo) -> bool {
+        let permits = |id: &InternalEndpointId| {
+            self.access_lists
+                .get(id)
+                .map_or(true, |access_list| access_list.permits(user))
+        };
+
+        let sharing = self
+            .endpoints
+            .endpoint_semaphore_id(info)
+            .map(|semaphore_id| self.endpoints.semaphore_id_to_endpoints(&semaphore_id))
+            .unwrap_or_default();
+
+        permits(&info.id) && sharing.iter().all(|other| permits(&other.id))
+    }
+
     // Check if the semaphore id matching the id is already granted or requested by the user
     fn control_requested_or_given(&self, user: &User, info: &InternalEndpointInfo) -> bool {
         if let Some(semaphore_id) = self.endpoints.endpoint_semaphore_id(info) {
             let us = self.user_state(user);
 
-            us.in_queue_of.contains(info) || us.in_control_of.contains(&semaphore_id)
+            us.in_queue_of.contains(info) || us.in_control_of.contains_key(&semaphore_id)
         } else {
             false
         }
@@ -757,6 +2106,14 @@ impl ControlCenter {
 
         let info = self.endpoints.id_to_info(id)?;
 
+        if !self.may_control(user, &info) {
+            return Err(Error::AccessDenied(format!(
+                "User {user} may not control {info}"
+            )));
+        }
+
+        self.check_enabled(&info)?;
+
         if self.control_requested_or_given(user, &info) {
             let error_message =
                 format!("User {user} is already queued or already has control over {info}.");
@@ -787,7 +2144,8 @@ impl ControlCenter {
         //  3.  Attempt controlling all matching ones
         //  4.  If only errors, quit
         //  5.  If at least one is available without a queue, use the first one, quit
-        //  6.  Else: Make a queue which yields the first one.
+        //  6.  Else: Make a queue which yields the first one,
+        //      telling about the ones which errored.
 
         if labels.is_empty() {
             return Err(Error::BadUsage(
@@ -805,10 +2163,24 @@ impl ControlCenter {
             return Err(Error::NoMatchingEndpoints(labels));
         }
 
+        // Endpoints the user may not control are not candidates.
+        let infos = infos
+            .into_iter()
+            .filter(|info| self.may_control(&user, info))
+            .collect::<HashSet<_>>();
+        if infos.is_empty() {
+            return Err(Error::AccessDenied(format!(
+                "User {user} may not control any endpoint matching {labels}"
+            )));
+        }
+
         let (oks, errs): (Vec<_>, Vec<_>) = infos
             .clone()
             .into_iter()
-            .map(|info| self.control(&user, info.id))
+            .map(|info| {
+                self.control(&user, info.id.clone())
+                    .map_err(|error| (info, error))
+            })
             .partition_result();
 
         if oks.is_empty() {
@@ -817,7 +2189,7 @@ impl ControlCenter {
                 error_message += &format!(" {id}");
             }
             error_message += " ] resulted in errors: [";
-            for e in errs {
+            for (_, e) in errs {
                 error_message += &format!(" <{e}>");
             }
             error_message += " ]";
@@ -845,6 +2217,11 @@ impl ControlCenter {
                 .flat_map(|queue| queue.endpoints.clone())
                 .collect::<Vec<_>>();
 
+            let queued_on = busy
+                .iter()
+                .flat_map(|queue| queue.queued_on.clone())
+                .collect::<Vec<_>>();
+
             let queues = busy
                 .into_iter()
                 .map(|queue| queue.inner)
@@ -852,26 +2229,155 @@ impl ControlCenter {
 
             let queue_fut = futures::future::select_ok(queues);
 
-            let (controller_tx, controller_rx) = oneshot::channel();
+            let (mut controller_tx, controller_rx) = oneshot::channel();
 
             tokio::spawn(async move {
-                let (controller, _other_futs) = queue_fut
-                    .await
-                    .expect("We currently cannot handle a multi-queue failing");
+                let queued = tokio::select! {
+                    queued = queue_fut => queued,
+                    // Dropping the queues lets each of them give up its place.
+                    _ = controller_tx.closed() => {
+                        debug!("No user waits for a label queue anymore");
+                        return;
+                    }
+                };
+                let (controller, _other_futs) =
+                    queued.expect("We currently cannot handle a multi-queue failing");
+
+                if let Err(e) = controller_tx.send(controller) {
+                    warn!(?e, "User left while in a label queue");
+                }
+            });
+
+            Ok(MaybeEndpointController::busy(
+                control_context,
+                EndpointControllerQueue {
+                    inner: controller_rx,
+                    endpoints: queued_endpoints,
+                    queued_on,
+                    not_queued_on: errs
+                        .into_iter()
+                        .map(|(info, error)| NotQueuedOn {
+                            endpoint: info.into(),
+                            error,
+                        })
+                        .collect(),
+                },
+            ))
+        }
+    }
+
+    fn transfer_control(
+        &mut self,
+        user: &User,
+        id: InternalEndpointId,
+        to_user: &str,
+    ) -> Result<ControlCenterResponse, Error> {
+        let info = self.endpoints.id_to_info(id)?;
+        let semaphore_id = self
+            .endpoints
+            .endpoint_semaphore_id(&info)
+            .expect("Endpoint exists");
+
+        if !self
+            .user_state(user)
+            .in_control_of
+            .contains_key(&semaphore_id)
+        {
+            return Err(Error::NoPermit(format!("transfer control of {info}")));
+        }
+
+        let (to, to_state) = self
+            .user_state
+            .iter()
+            .find(|(other, _)| other.name.as_str() == to_user)
+            .ok_or_else(|| Error::NoSuchUser(to_user.into()))?;
+
+        if to_state.in_control_of.contains_key(&semaphore_id) {
+            return Err(Error::SuperfluousRequest(format!(
+                "`{to}` is already in control of {info}"
+            )));
+        }
+
+        if !self.may_control(to, &info) {
+            return Err(Error::AccessDenied(format!(
+                "User {to} may not control {info}"
+            )));
+        }
+
+        let requests = to_state
+            .peer_requests
+            .clone()
+            .ok_or_else(|| Error::NoSuchUser(to_user.into()))?;
+
+        assert!(self
+            .user_state_mut(user)
+            .in_control_of
+            .remove(&semaphore_id)
+            .is_some());
+        self.events.send_user_event(
+            user,
+            events::user::Event::NoLongerInControlOf(
+                self.endpoints.semaphore_id_to_endpoints(&semaphore_id),
+            ),
+        );
+
+        Ok(ControlCenterResponse::TransferTo {
+            context: ControlContext {
+                user_request: UserRequest::EndpointId(EndpointId::from(info.id)),
+                got_control: None,
+            },
+            requests,
+        })
+    }
+
+    fn release(
+        &mut self,
+        user: &User,
+        id: InternalEndpointId,
+    ) -> Result<ControlCenterResponse, Error> {
+        let info = self.endpoints.id_to_info(id)?;
+        let semaphore_id = self
+            .endpoints
+            .endpoint_semaphore_id(&info)
+            .expect("Endpoint exists");
+
+        if self
+            .user_state_mut(user)
+            .in_control_of
+            .remove(&semaphore_id)
+            .is_none()
+        {
+            return Err(Error::NoPermit(format!("release {info}")));
+        }
+
+        self.events.send_user_event(
+            user,
+            events::user::Event::NoLongerInControlOf(
+                self.endpoints.semaphore_id_to_endpoints(&semaphore_id),
+            ),
+        );
+
+        Ok(ControlCenterResponse::Released)
+    }
 
-                if let Err(e) = controller_tx.send(controller) {
-                    warn!(?e, "User left while in a label queue");
-                }
-            });
+    fn cancel_queue(&mut self, user: &User) -> ControlCenterResponse {
+        let in_queue_for = self
+            .user_state_mut(user)
+            .in_queue_of
+            .drain()
+            .collect::<Vec<_>>();
 
-            Ok(MaybeEndpointController::busy(
-                control_context,
-                EndpointControllerQueue {
-                    inner: controller_rx,
-                    endpoints: queued_endpoints,
-                },
-            ))
+        if !in_queue_for.is_empty() {
+            for info in &in_queue_for {
+                self.leave_control_queue(user, info);
+            }
+            self.events.send_user_event(
+                user,
+                events::user::Event::NoLongerInQueueOf(in_queue_for.clone()),
+            );
         }
+
+        ControlCenterResponse::QueueCancelled(in_queue_for)
     }
 
     fn subscribe_to_events(&mut self, user: &User) -> Result<ControlCenterResponse, Error> {
@@ -887,6 +2393,82 @@ impl ControlCenter {
         ))
     }
 
+    fn subscribe_to_endpoint_events(
+        &mut self,
+        user: &User,
+        id: InternalEndpointId,
+    ) -> Result<ControlCenterResponse, Error> {
+        let info = self.endpoints.id_to_info(id)?;
+        self.check_not_exclusively_observed(user, &info)?;
+        self.check_enabled(&info)?;
+
+        if !self
+            .user_state_mut(user)
+            .observing_endpoint_events
+            .insert(info.clone())
+        {
+            return Err(Error::SuperfluousRequest(format!(
+                "User is already subscribed to events of {info}"
+            )));
+        }
+
+        Ok(ControlCenterResponse::EndpointEventObserver((
+            info,
+            self.events.subscribe(),
+        )))
+    }
+
+    fn listen(&self, user: &User, id: InternalEndpointId) -> Result<ControlCenterResponse, Error> {
+        let info = self.endpoints.id_to_info(id)?;
+        self.check_not_exclusively_observed(user, &info)?;
+        self.check_enabled(&info)?;
+
+        self.endpoints
+            .get(&info.id)
+            .map(|endpoint| ControlCenterResponse::Listener(endpoint.events()))
+    }
+
+    fn subscribe_to_messages(
+        &mut self,
+        user: &User,
+        ids: Vec<InternalEndpointId>,
+    ) -> Result<ControlCenterResponse, Error> {
+        if ids.is_empty() {
+            return Err(Error::BadUsage(
+                "No endpoints to observe the messages of were given".to_string(),
+            ));
+        }
+
+        let mut infos = vec![];
+        for id in ids {
+            let info = self.endpoints.id_to_info(id)?;
+            self.check_not_exclusively_observed(user, &info)?;
+            self.check_enabled(&info)?;
+
+            if self
+                .user_state_mut(user)
+                .observing_messages_of
+                .contains(&info)
+            {
+                return Err(Error::SuperfluousRequest(format!(
+                    "User is already subscribed to messages of {info}"
+                )));
+            }
+            if !infos.contains(&info) {
+                infos.push(info);
+            }
+        }
+
+        self.user_state_mut(user)
+            .observing_messages_of
+            .extend(infos.iter().cloned());
+
+        Ok(ControlCenterResponse::MessageObserver((
+            infos,
+            self.events.subscribe(),
+        )))
+    }
+
     fn handle_request(
         &mut self,
         Request {
@@ -898,19 +2480,168 @@ impl ControlCenter {
         debug!("Got action request: `{action}` from user `{user}`");
 
         let reply = match action {
-            Action::Observe(id) => self.observe(user, id),
+            Action::Observe(id) => self.observe(user, id, false),
+            Action::ObserveIdempotent(id) => self.observe(user, id, true),
+            Action::ObserveWeak(id) => self.observe_weak(user, id),
+            Action::ObserveExclusive(id) => self.observe_exclusive(user, id),
+            Action::ObserveAny(labels) => self.observe_any(user, labels),
+            Action::Unobserve(id) => Ok(self.unobserve(&user, &id)),
+            Action::ObserveDeferred { id, idempotent } => {
+                self.observe_deferred(user, id, idempotent)
+            }
             Action::Control(id) => self
                 .control(&user, id)
                 .map(ControlCenterResponse::ControlThis),
             Action::ControlAny(labels) => self
                 .control_any(user, labels)
                 .map(ControlCenterResponse::ControlThis),
+            Action::ControlAnyDeferred(labels) => self.control_any_deferred(user, labels),
             Action::SubscribeToEvents => self.subscribe_to_events(&user),
+            Action::SubscribeToEndpointEvents(id) => self.subscribe_to_endpoint_events(&user, id),
+            Action::SubscribeToMessages(ids) => self.subscribe_to_messages(&user, ids),
+            Action::Reconfigure {
+                id,
+                baud,
+                flow_control,
+            } => self
+                .endpoints
+                .get(&id)
+                .and_then(|endpoint| endpoint.reconfigure(baud, flow_control))
+                .map(ControlCenterResponse::Reconfiguring),
+            Action::Flush { id, tx, rx } => self
+                .endpoints
+                .get(&id)
+                .and_then(|endpoint| endpoint.flush(tx, rx))
+                .map(ControlCenterResponse::Flushing),
+            Action::GetMacro { id, name } => {
+                self.get_macro(id, &name).map(ControlCenterResponse::Macro)
+            }
+            Action::GetOnControlMacros(id) => self
+                .macros_for(id)
+                .map(|macros| macros.into_iter().filter(|m| m.on_control).collect())
+                .map(ControlCenterResponse::Macros),
+            Action::ListEndpoints => Ok(ControlCenterResponse::Endpoints(
+                self.endpoints.list(&self.disabled_endpoints),
+            )),
+            Action::ListLabels => Ok(ControlCenterResponse::Labels(self.endpoints.labels())),
+            Action::Metrics => Ok(ControlCenterResponse::Metrics(self.endpoints.metrics())),
+            Action::TransferControl { id, to_user } => self.transfer_control(&user, id, &to_user),
+            Action::Release(id) => self.release(&user, id),
+            Action::CancelQueue => Ok(self.cancel_queue(&user)),
+            Action::RecentMessages { id, count } => {
+                self.recent_messages_from_wire(&user, id, count)
+            }
+            Action::Notice { id, text } => self.notice(&user, id, text),
+            Action::SetEnabled {
+                id,
+                enabled,
+                release_holders,
+            } => self.set_enabled(&user, id, enabled, release_holders),
+            Action::Listen(id) => self.listen(&user, id),
         };
 
-        response
-            .send(reply)
-            .expect("Response receiver should not drop");
+        // The user may have left while waiting for the reply.
+        if response.send(reply).is_err() {
+            debug!("Response receiver dropped");
+        }
+
+        // Handling the request may have created an endpoint someone waits for.
+        self.start_deferred_observations();
+        self.start_deferred_controls();
+    }
+
+    // Users waiting for endpoints which now exist start observing them.
+    fn start_deferred_observations(&mut self) {
+        let ready = self
+            .user_state
+            .iter()
+            .filter(|(_, state)| state.peer_requests.is_some())
+            .flat_map(|(user, state)| {
+                state
+                    .deferred_observations
+                    .iter()
+                    .filter(|id| self.endpoints.get(*id).is_ok())
+                    .filter(|id| match self.exclusive_observers.get(*id) {
+                        Some(holder) => holder == user,
+                        None => true,
+                    })
+                    .map(|id| (user.clone(), id.clone()))
+            })
+            .collect_vec();
+
+        for (user, id) in ready {
+            self.user_state_mut(&user).deferred_observations.remove(&id);
+
+            let info = self
+                .endpoints
+                .id_to_info(id.clone())
+                .expect("The endpoint was just found");
+
+            // The user may have started observing some other way in the meantime.
+            if self.is_observing_endpoint(&user, &info) {
+                continue;
+            }
+
+            let endpoint = self
+                .endpoints
+                .get(&id)
+                .expect("The endpoint was just found")
+                .events();
+
+            debug!(%user, %info, "Deferred observation starting");
+            self.set_observing_endpoint(&user, info.clone());
+            let recent = self.recent_messages(&info);
+
+            let requests = self
+                .user_state_mut(&user)
+                .peer_requests
+                .as_ref()
+                .expect("Only reachable users were picked");
+            if requests
+                .send(PeerRequest::InternalAction(
+                    PeerAction::DeferredObserverReady((info, endpoint, recent)),
+                ))
+                .is_err()
+            {
+                debug!(%user, "User left before deferred observation started");
+            }
+        }
+    }
+
+    // Users waiting for endpoints matching labels which now exist are told to control them.
+    fn start_deferred_controls(&mut self) {
+        let ready = self
+            .user_state
+            .iter()
+            .filter(|(_, state)| state.peer_requests.is_some())
+            .flat_map(|(user, state)| {
+                state
+                    .deferred_controls
+                    .iter()
+                    .filter(|labels| !self.endpoints.labels_to_endpoint_ids(labels).is_empty())
+                    .map(|labels| (user.clone(), labels.clone()))
+            })
+            .collect_vec();
+
+        for (user, labels) in ready {
+            let state = self.user_state_mut(&user);
+            state.deferred_controls.remove(&labels);
+
+            debug!(%user, %labels, "Deferred control ready");
+
+            let requests = state
+                .peer_requests
+                .as_ref()
+                .expect("Only reachable users were picked");
+            if requests
+                .send(PeerRequest::InternalAction(
+                    PeerAction::DeferredControlReady(labels),
+                ))
+                .is_err()
+            {
+                debug!(%user, "User left before deferred control started");
+            }
+        }
     }
 
     // Mock endpoints which were not a part of a config file
@@ -920,7 +2651,7 @@ impl ControlCenter {
             let all_controlled = self
                 .user_state
                 .values()
-                .flat_map(|state| &state.in_control_of)
+                .flat_map(|state| state.in_control_of.keys())
                 .flat_map(|e| self.endpoints.semaphore_id_to_endpoints(e))
                 .filter(|info| info.labels.is_empty())
                 .collect::<HashSet<_>>();
@@ -933,8 +2664,18 @@ impl ControlCenter {
                 .cloned()
                 .collect::<HashSet<_>>();
 
+            // Weak observers are deliberately left out here.
+            let all_observed = self
+                .user_state
+                .values()
+                .flat_map(|state| &state.observing_endpoints)
+                .filter(|info| info.labels.is_empty())
+                .cloned()
+                .collect::<HashSet<_>>();
+
             let all_active = all_controlled
                 .union(&all_queued)
+                .chain(&all_observed)
                 .cloned()
                 .collect::<HashSet<_>>();
 
@@ -949,6 +2690,34 @@ impl ControlCenter {
         for inactive in inactive {
             debug!(%inactive, "No more observers/controllers for mock (using or queued), removing");
             self.endpoints.remove(&inactive);
+            self.recent_messages.remove(&inactive.id);
+            self.close_weak_observers(&inactive);
+        }
+    }
+
+    // Let weak observers know the endpoint they observed is gone.
+    fn close_weak_observers(&mut self, info: &InternalEndpointInfo) {
+        for (user, state) in self.user_state.iter_mut() {
+            if !state.weakly_observing_endpoints.remove(info) {
+                continue;
+            }
+
+            self.events.send_user_event(
+                user,
+                events::user::Event::NoLongerObserving(vec![info.clone()]),
+            );
+
+            if let Some(requests) = &state.peer_requests {
+                if requests
+                    .send(PeerRequest::InternalAction(PeerAction::EndpointClosed((
+                        info.clone(),
+                        CloseReason::Unavailable,
+                    ))))
+                    .is_err()
+                {
+                    debug!(%user, "Weak observer left before endpoint closed");
+                }
+            }
         }
     }
 
@@ -959,9 +2728,18 @@ impl ControlCenter {
             Inform::UserLeft(user) => {
                 let _span = debug_span!("User leaving", %user).entered();
 
-                let mut state = self.user_state.remove(&user).expect("User was alive");
+                let Some(mut state) = self.user_state.remove(&user) else {
+                    error!(%user, "Unknown user left");
+                    return;
+                };
+
+                self.exclusive_observers.retain(|_, holder| holder != &user);
 
-                let observing = state.observing_endpoints.drain().collect::<Vec<_>>();
+                let observing = state
+                    .observing_endpoints
+                    .drain()
+                    .chain(state.weakly_observing_endpoints.drain())
+                    .collect::<Vec<_>>();
                 if !observing.is_empty() {
                     self.events
                         .send_user_event(&user, events::user::Event::NoLongerObserving(observing));
@@ -969,13 +2747,16 @@ impl ControlCenter {
 
                 let in_queue_for = state.in_queue_of.drain().collect::<Vec<_>>();
                 if !in_queue_for.is_empty() {
+                    for info in &in_queue_for {
+                        self.leave_control_queue(&user, info);
+                    }
                     self.events.send_user_event(
                         &user,
                         events::user::Event::NoLongerInQueueOf(in_queue_for),
                     );
                 }
 
-                let controlling = state.in_control_of.drain().collect::<Vec<_>>();
+                let controlling = state.in_control_of.drain().map(|(id, _)| id).collect_vec();
                 if !controlling.is_empty() {
                     self.events.send_user_event(
                         &user,
@@ -994,6 +2775,9 @@ impl ControlCenter {
                     .send_user_event(&user, events::user::Event::Disconnected);
 
                 self.remove_dangling_mock_endpoints();
+
+                // The user may have been holding an exclusive observation others wait for.
+                self.start_deferred_observations();
             }
             Inform::NowControlling { user, context } => {
                 let _span = info_span!("NowControlling", %user, %context).entered();
@@ -1024,6 +2808,10 @@ impl ControlCenter {
                     }
                 }
 
+                for info in &not_queued_anymore {
+                    self.leave_control_queue(&user, info);
+                }
+
                 if !not_queued_anymore.is_empty() {
                     self.events.send_user_event(
                         &user,
@@ -1031,56 +2819,208 @@ impl ControlCenter {
                     );
                 }
 
-                self.set_controls(&user, which);
+                if let Err(e) = self.set_controls(&user, which) {
+                    error!(%user, %e, "Could not set controls");
+                }
             }
             Inform::UserArrived(user) => {
                 info!(%user, "New user");
-                assert!(self
-                    .user_state
-                    .insert(user.clone(), UserState::default())
-                    .is_none());
+                if self.user_state.contains_key(&user) {
+                    error!(%user, "User arrived twice");
+                    return;
+                }
+                self.user_state.insert(user.clone(), UserState::default());
 
                 self.events
                     .send_user_event(&user, events::user::Event::Connected);
             }
-            Inform::UserReceivedMessage((user, info, msg)) => self
+            Inform::UserReachable { user, requests } => {
+                self.user_state_mut(&user).peer_requests = Some(requests);
+            }
+            Inform::UserMessage((user, message)) => self
                 .events
-                .send_user_event(&user, events::user::Event::MessageReceived((info, msg))),
-            Inform::UserSentMessage((user, id, msg)) => self.events.send_user_event(
+                .send_event(TimestampedEvent::new_user_message_event(&user, message)),
+            Inform::WriteDropped((user, message)) => self.events.send_user_event(
                 &user,
-                events::user::Event::MessageSent((
-                    self.endpoints
-                        .id_to_info(id)
-                        .expect("Message should be sent to a known endpoint"),
-                    msg,
-                )),
+                events::user::Event::WriteDropped((message.endpoint, message.bytes)),
             ),
-            Inform::EndpointEvent((id, endpoint_event)) => {
-                let endpoint_info = self
-                    .endpoints
-                    .id_to_info(id)
-                    .expect("Message should relate to a known endpoint");
-
-                let event = match endpoint_event {
-                    endpoint::EndpointEvent::ToWire(bytes) => general::Event::MessageSent((
-                        endpoint_info,
-                        SerialMessage::new_lossy(bytes),
-                    )),
-                    endpoint::EndpointEvent::FromWire(bytes) => general::Event::MessageReceived((
-                        endpoint_info,
-                        SerialMessage::new_lossy(bytes),
-                    )),
+            Inform::EndpointMessage(message) => {
+                self.messages_since_stats += 1;
+                self.remember_message(&message);
+
+                // Observers get the bytes as seen on wire, events carry text.
+                let message = match message.direction {
+                    Direction::FromWire => TaggedMessage {
+                        bytes: self.as_utf8(&message.endpoint.id, message.bytes),
+                        ..message
+                    },
+                    Direction::ToWire => message,
+                };
+
+                self.events
+                    .send_event(TimestampedEvent::new_general_message_event(message))
+            }
+            Inform::EndpointIdle(info) => self
+                .events
+                .send_general_event(events::general::Event::EndpointIdle(info.into())),
+            Inform::EndpointActive(info) => self
+                .events
+                .send_general_event(events::general::Event::EndpointActive(info.into())),
+            Inform::EndpointFirstMessage(info) => self
+                .events
+                .send_general_event(events::general::Event::EndpointFirstMessage(info.into())),
+            Inform::ObserveLimitReached((user, info)) => {
+                // The user may have left in the meantime.
+                let Some(state) = self.user_state.get_mut(&user) else {
+                    return;
                 };
-                self.events.send_general_event(event)
+
+                if state.observing_endpoints.remove(&info) {
+                    self.events
+                        .send_user_event(&user, events::user::Event::NoLongerObserving(vec![info]));
+                    self.remove_dangling_mock_endpoints();
+                }
+            }
+            Inform::DeferredControlGaveUp((user, labels)) => {
+                // The user may have left in the meantime.
+                if let Some(state) = self.user_state.get_mut(&user) {
+                    state.deferred_controls.remove(&labels);
+                }
+            }
+        }
+    }
+
+    fn send_stats(&mut self) {
+        let elapsed = self.stats_since.elapsed().as_secs_f64();
+        let messages_per_sec = if elapsed > 0.0 {
+            (self.messages_since_stats as f64 / elapsed) as u64
+        } else {
+            0
+        };
+
+        let active_controllers = self
+            .user_state
+            .values()
+            .filter(|state| !state.in_control_of.is_empty())
+            .count();
+
+        self.events
+            .send_general_event(events::general::Event::Stats {
+                messages_per_sec,
+                active_controllers,
+                connected_users: self.user_state.len(),
+            });
+
+        self.messages_since_stats = 0;
+        self.stats_since = Instant::now();
+    }
+
+    fn send_server_log(&mut self, log: ServerLog) {
+        self.events
+            .send_general_event(events::general::Event::ServerLog {
+                level: log.level,
+                target: log.target,
+                message: log.message,
+            });
+    }
+
+    /// When the next control not yet reported becomes long.
+    fn next_long_control(&self) -> Option<Instant> {
+        let warn_after = self.control_warn_after?;
+
+        self.user_state
+            .values()
+            .flat_map(|state| state.in_control_of.values())
+            .filter(|control| !control.warned)
+            .map(|control| control.since + warn_after)
+            .min()
+    }
+
+    fn send_long_controls(&mut self) {
+        let Some(warn_after) = self.control_warn_after else {
+            return;
+        };
+
+        let mut long_controls = vec![];
+        for (user, state) in &mut self.user_state {
+            for (semaphore_id, control) in &mut state.in_control_of {
+                let held_for = control.since.elapsed();
+
+                if !control.warned && held_for >= warn_after {
+                    control.warned = true;
+                    long_controls.push((user.clone(), semaphore_id.clone(), held_for));
+                }
+            }
+        }
+
+        for (user, semaphore_id, held_for) in long_controls {
+            for info in self.endpoints.semaphore_id_to_endpoints(&semaphore_id) {
+                warn!(%user, %info, ?held_for, "Long control");
+
+                self.events
+                    .send_general_event(events::general::Event::LongControl {
+                        user: user.clone(),
+                        endpoint: info.into(),
+                        held_for,
+                    });
             }
         }
     }
 
     pub(crate) async fn run(&mut self) {
-        while let Some(message) = self.messages.next().await {
-            match message {
-                ControlCenterMessage::Request(request) => self.handle_request(request),
-                ControlCenterMessage::Inform(information) => self.handle_information(information),
+        let mut stats = self
+            .stats_interval
+            .map(|period| tokio::time::interval_at(Instant::now() + period, period));
+        let mut server_logs = self.server_logs.take();
+
+        loop {
+            let stats_due = async {
+                match &mut stats {
+                    Some(interval) => interval.tick().await,
+                    None => futures::future::pending().await,
+                }
+            };
+
+            let server_log = async {
+                match &mut server_logs {
+                    Some((level, logs)) => loop {
+                        match logs.recv().await {
+                            Ok(log) if log.level >= *level => break log,
+                            // Records dropped for lagging were logged anyway.
+                            Ok(_) | Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                            Err(broadcast::error::RecvError::Closed) => {
+                                futures::future::pending().await
+                            }
+                        }
+                    },
+                    None => futures::future::pending().await,
+                }
+            };
+
+            let next_long_control = self.next_long_control();
+            let long_control_due = async {
+                match next_long_control {
+                    Some(deadline) => tokio::time::sleep_until(deadline).await,
+                    None => futures::future::pending().await,
+                }
+            };
+
+            tokio::select! {
+                message = self.messages.next() => {
+                    let Some(message) = message else {
+                        break;
+                    };
+
+                    match message {
+                        ControlCenterMessage::Request(request) => self.handle_request(request),
+                        ControlCenterMessage::Inform(information) => {
+                            self.handle_information(information)
+                        }
+                    }
+                }
+                _ = stats_due => self.send_stats(),
+                log = server_log => self.send_server_log(log),
+                _ = long_control_due => self.send_long_controls(),
             }
         }
     }
@@ -1088,7 +3028,10 @@ impl ControlCenter {
 
 #[cfg(test)]
 mod tests {
+    use futures::FutureExt;
+
     use super::*;
+    use crate::config::Group;
     use crate::mock::MockId;
     use crate::user::User;
 
@@ -1120,6 +3063,42 @@ mod tests {
         ));
     }
 
+    #[tokio::test]
+    async fn reordering_groups_keeps_mock_ids() {
+        let group = |name: &str| Group {
+            name: Some(name.into()),
+            ..Group::from(vec![EndpointId::mock(&format!("{name}-mock"))])
+        };
+
+        for groups in [
+            vec![group("bench-a"), group("bench-b")],
+            vec![group("bench-b"), group("bench-a")],
+        ] {
+            let mut cc = ControlCenterHandle::new(&Config {
+                groups,
+                ..Default::default()
+            });
+            let user = User::new("foo");
+            cc.inform(Inform::UserArrived(user.clone()));
+
+            for name in ["bench-a", "bench-b"] {
+                // Weakly observing does not create mocks, so this only works if the id exists.
+                let id = MockId::new(name, &format!("{name}-mock"));
+                let response = cc
+                    .perform_action(
+                        user.clone(),
+                        Action::ObserveWeak(InternalEndpointId::Mock(id)),
+                    )
+                    .await;
+
+                assert!(matches!(
+                    response,
+                    Ok(ControlCenterResponse::EndpointObserver(_))
+                ));
+            }
+        }
+    }
+
     #[tokio::test]
     async fn observe_non_existing_tty_id() {
         let mut cc = cc();
@@ -1136,6 +3115,22 @@ mod tests {
         assert!(matches!(response, Err(Error::NoSuchEndpoint(_))));
     }
 
+    #[tokio::test]
+    async fn control_non_existing_tty_id() {
+        let mut cc = cc();
+        let user = User::new("foo");
+        cc.inform(Inform::UserArrived(user.clone()));
+
+        let response = cc
+            .perform_action(
+                user,
+                Action::Control(InternalEndpointId::Tty("/dev/tty1234".into())),
+            )
+            .await;
+
+        assert!(matches!(response, Err(Error::NoSuchEndpoint(_))));
+    }
+
     #[tokio::test]
     async fn can_not_observe_mock_endpoint_several_times() {
         let mut cc = cc();
@@ -1166,4 +3161,206 @@ mod tests {
             }
         }
     }
+
+    #[tokio::test]
+    async fn dropped_request_does_not_bring_down_control_center() {
+        let mut cc = cc();
+        let user = User::new("impatient");
+        cc.inform(Inform::UserArrived(user.clone()));
+
+        // Sends the request, then stops waiting for the reply.
+        let pending = cc
+            .perform_action(
+                user.clone(),
+                Action::Observe(InternalEndpointId::Mock(MockId::new("impatient", "mock"))),
+            )
+            .now_or_never();
+        assert!(pending.is_none());
+
+        let response = cc.perform_action(user, Action::ListEndpoints).await;
+        assert!(matches!(response, Ok(ControlCenterResponse::Endpoints(_))));
+    }
+
+    #[tokio::test]
+    async fn controlling_a_removed_endpoint_does_not_bring_down_control_center() {
+        let mut cc = cc();
+        let user = User::new("late");
+        cc.inform(Inform::UserArrived(user.clone()));
+
+        let id = EndpointId::mock("gone");
+        cc.inform(Inform::NowControlling {
+            user: user.clone(),
+            context: ControlContext {
+                user_request: UserRequest::EndpointId(id.clone()),
+                got_control: Some(vec![InternalEndpointInfo::new(
+                    InternalEndpointId::Mock(MockId::new("late", "gone")),
+                    Labels::default(),
+                )]),
+            },
+        });
+
+        // Nothing was controlled, so there is nothing to release.
+        let response = cc
+            .perform_action(
+                user,
+                Action::Release(InternalEndpointId::Mock(MockId::new("late", "gone"))),
+            )
+            .await;
+        assert!(matches!(response, Err(Error::NoSuchEndpoint(_))));
+    }
+
+    #[tokio::test]
+    async fn user_arriving_or_leaving_twice_does_not_bring_down_control_center() {
+        let mut cc = cc();
+        let user = User::new("twice");
+
+        cc.inform(Inform::UserArrived(user.clone()));
+        cc.inform(Inform::UserArrived(user.clone()));
+        cc.inform(Inform::UserLeft(user.clone()));
+        cc.inform(Inform::UserLeft(user.clone()));
+
+        let response = cc.perform_action(user, Action::ListEndpoints).await;
+        assert!(matches!(response, Ok(ControlCenterResponse::Endpoints(_))));
+    }
+
+    #[tokio::test]
+    async fn messages_from_wire_are_decoded_by_charset() {
+        let mut cc = ControlCenterHandle::new(&Config {
+            endpoints: vec![ConfigEndpoint {
+                charset: Some("latin1".into()),
+                ..EndpointId::mock("latin").into()
+            }],
+            ..Default::default()
+        });
+        let user = User::new("reader");
+        cc.inform(Inform::UserArrived(user.clone()));
+
+        let Ok(ControlCenterResponse::EventObserver(mut events)) = cc
+            .perform_action(user.clone(), Action::SubscribeToEvents)
+            .await
+        else {
+            panic!("Should be able to subscribe to events");
+        };
+
+        let id = InternalEndpointId::Mock(MockId::new("MockFromConfig", "latin"));
+        let info = InternalEndpointInfo::new(id.clone(), Labels::default());
+
+        // "café" in Latin-1.
+        let latin1 = vec![b'c', b'a', b'f', 0xe9];
+        cc.inform(Inform::EndpointMessage(TaggedMessage::new(
+            info,
+            Direction::FromWire,
+            latin1.clone(),
+        )));
+
+        loop {
+            let event = events.recv().await.expect("Should see the message");
+            if let events::Event::General(events::general::Event::MessageReceived((_, message))) =
+                event.inner
+            {
+                assert_eq!(message, "café".as_bytes());
+                break;
+            }
+        }
+
+        // Replaying to observers is as seen on wire, but recent messages are text.
+        let response = cc
+            .perform_action(user, Action::RecentMessages { id, count: 1 })
+            .await;
+        let Ok(ControlCenterResponse::RecentMessages((_, messages))) = response else {
+            panic!("Should get the recent messages, got {response:?}");
+        };
+        assert_eq!(messages, vec!["café".as_bytes().to_vec()]);
+    }
+
+    #[tokio::test]
+    async fn deferred_control_is_ready_once_a_matching_mock_is_created() {
+        let (requests, requests_rx) = futures::channel::mpsc::unbounded();
+        let mut cc = ControlCenter::new(
+            Config::default(),
+            requests_rx,
+            Endpoints::new(ControlCenterHandle(requests)),
+        );
+
+        let user = User::new("patient");
+        let (peer_requests, mut peer_requests_rx) = tokio::sync::mpsc::unbounded_channel();
+        cc.handle_information(Inform::UserArrived(user.clone()));
+        cc.handle_information(Inform::UserReachable {
+            user: user.clone(),
+            requests: peer_requests,
+        });
+
+        let labels = Labels::from_iter(["hotplugged"]);
+        let response = cc.control_any_deferred(user.clone(), labels.clone());
+        assert!(matches!(
+            response,
+            Ok(ControlCenterResponse::ControlDeferred(_))
+        ));
+
+        // Nothing matches yet.
+        cc.start_deferred_controls();
+        assert!(peer_requests_rx.try_recv().is_err());
+
+        let mock_id = MockId::new("hotplug", "device");
+        cc.endpoints.insert(
+            InternalEndpointId::Mock(mock_id.clone()),
+            MockBuilder::new(mock_id)
+                .add_label(endpoint::Label::new("hotplugged"))
+                .build(),
+            None,
+        );
+
+        cc.start_deferred_controls();
+        assert!(matches!(
+            peer_requests_rx.try_recv(),
+            Ok(PeerRequest::InternalAction(PeerAction::DeferredControlReady(ready))) if ready == labels
+        ));
+
+        // Only told once.
+        cc.start_deferred_controls();
+        assert!(peer_requests_rx.try_recv().is_err());
+
+        let controller = cc
+            .control_any(user, labels)
+            .expect("The matching mock should be controllable");
+        assert!(matches!(
+            controller.inner,
+            AvailableOrBusyEndpointController::Available(_)
+        ));
+    }
+
+    #[tokio::test]
+    async fn dropped_controller_frees_endpoint_for_next_user() {
+        let (requests, requests_rx) = futures::channel::mpsc::unbounded();
+        let mut cc = ControlCenter::new(
+            Config::default(),
+            requests_rx,
+            Endpoints::new(ControlCenterHandle(requests)),
+        );
+
+        let mock_id = MockId::new("leaky", "device");
+        let id = InternalEndpointId::Mock(mock_id.clone());
+        cc.endpoints
+            .insert(id.clone(), MockBuilder::new(mock_id).build(), None);
+        let info = cc.endpoints.id_to_info(id).unwrap();
+
+        let first = cc.control_impl(info.clone()).unwrap();
+        let AvailableOrBusyEndpointController::Available(controller) = first.inner else {
+            panic!("The endpoint should be available at first");
+        };
+
+        let second = cc.control_impl(info.clone()).unwrap();
+        let AvailableOrBusyEndpointController::Busy(mut queue) = second.inner else {
+            panic!("The endpoint should be busy while controlled");
+        };
+        assert!(queue.inner.try_recv().is_err());
+
+        drop(controller);
+
+        let next = tokio::time::timeout(std::time::Duration::from_secs(1), queue.inner)
+            .await
+            .expect("The next user should get control once the controller is dropped")
+            .unwrap();
+        assert!(next.endpoints.contains_key(&info));
+    }
 }
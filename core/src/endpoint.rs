@@ -3,20 +3,26 @@ use std::{borrow::Borrow, fmt::Display};
 use std::{collections::HashSet, hash::Hash};
 
 use futures::channel::mpsc;
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
-use tokio::sync::{broadcast, Mutex, Semaphore};
+use tokio::sync::{broadcast, oneshot, Mutex, Semaphore};
 use uuid::Uuid;
 
 use crate::{
+    error::Error,
+    metrics::{FramingSummary, LatencySummary},
     mock::MockId,
-    serial::{SerialMessage, SerialMessageBytes},
+    serial::{PortSettings, SerialMessage, SerialMessageBytes},
 };
 
+pub(crate) mod composite;
 pub(crate) mod mock;
 pub(crate) mod serial;
 
 /// An endpoint a client may ask to observe.
-#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Hash, Clone, PartialOrd, Ord)]
+#[derive(
+    Debug, Serialize, Deserialize, PartialEq, Eq, Hash, Clone, PartialOrd, Ord, JsonSchema,
+)]
 pub enum EndpointId {
     /// A tty/COM endpoint.
     Tty(String),
@@ -24,6 +30,17 @@ pub enum EndpointId {
     /// An endpoint consisting of in-memory data,
     /// like lines of serial output.
     Mock(String),
+
+    /// A pseudo-terminal with the given name, created by the server.
+    /// Whatever is written to the other end (see [`crate::actions::ListedEndpoint::device`])
+    /// is read through the same path as a real TTY.
+    /// Only available on unix.
+    Pty(String),
+
+    /// Several endpoints acting as one, see [`crate::config::Composite`].
+    /// Observing it observes each of its ports,
+    /// but writes must go to a specific port.
+    Composite(String),
 }
 
 impl From<InternalEndpointInfo> for EndpointId {
@@ -31,6 +48,8 @@ impl From<InternalEndpointInfo> for EndpointId {
         match iei.id {
             InternalEndpointId::Tty(tty) => Self::Tty(tty),
             InternalEndpointId::Mock(mock) => Self::Mock(mock.to_string()),
+            InternalEndpointId::Pty(name) => Self::Pty(name),
+            InternalEndpointId::Composite(name) => Self::Composite(name),
         }
     }
 }
@@ -46,12 +65,14 @@ impl Display for EndpointId {
         match self {
             EndpointId::Tty(tty) => write!(f, "tty: {tty}"),
             EndpointId::Mock(mock) => write!(f, "mock: {mock}"),
+            EndpointId::Pty(name) => write!(f, "pty: {name}"),
+            EndpointId::Composite(name) => write!(f, "composite: {name}"),
         }
     }
 }
 
 /// An enpoint and the labels associated with it, if any.
-#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Hash, Clone)]
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Hash, Clone, JsonSchema)]
 pub struct LabelledEndpointId {
     /// The [`EndpointId`].
     pub id: EndpointId,
@@ -97,7 +118,7 @@ impl Display for LabelledEndpointId {
 /// An endpoint as used internally.
 /// May have extra internal fields not relevant to users,
 /// which should look at [`EndpointId`] instead.
-#[derive(Debug, PartialEq, Eq, Hash, Clone, Serialize, Deserialize)]
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Serialize, Deserialize, JsonSchema)]
 pub enum InternalEndpointId {
     /// A tty/COM endpoint.
     Tty(String),
@@ -105,10 +126,16 @@ pub enum InternalEndpointId {
     /// An endpoint consisting of in-memory data,
     /// like lines of serial output.
     Mock(MockId),
+
+    /// A pseudo-terminal created by the server.
+    Pty(String),
+
+    /// Several endpoints acting as one.
+    Composite(String),
 }
 
 /// An endpoint as seen internally.
-#[derive(Debug, Clone, Eq, Serialize, Deserialize)]
+#[derive(Debug, Clone, Eq, Serialize, Deserialize, JsonSchema)]
 pub struct InternalEndpointInfo {
     /// The id of the endpoint.
     /// Unique per endpoint.
@@ -168,6 +195,8 @@ impl Display for InternalEndpointId {
             InternalEndpointId::Mock(mock_id) => {
                 write!(f, "{mock_id}")
             }
+            InternalEndpointId::Pty(name) => write!(f, "pty {name}"),
+            InternalEndpointId::Composite(name) => write!(f, "composite {name}"),
         }
     }
 }
@@ -177,6 +206,8 @@ impl From<InternalEndpointId> for EndpointId {
         match internal {
             InternalEndpointId::Tty(tty) => Self::Tty(tty),
             InternalEndpointId::Mock(mock_id) => Self::Mock(mock_id.name),
+            InternalEndpointId::Pty(name) => Self::Pty(name),
+            InternalEndpointId::Composite(name) => Self::Composite(name),
         }
     }
 }
@@ -192,6 +223,16 @@ impl EndpointId {
         Self::Mock(name.into())
     }
 
+    /// An id for a pseudo-terminal endpoint.
+    pub fn pty(name: &str) -> Self {
+        Self::Pty(name.into())
+    }
+
+    /// An id for a composite endpoint.
+    pub fn composite(name: &str) -> Self {
+        Self::Composite(name.into())
+    }
+
     /// Borrow endpoint id as the mock variant.
     pub fn as_mock(&self) -> Option<&String> {
         if let Self::Mock(v) = self {
@@ -209,6 +250,24 @@ impl EndpointId {
             None
         }
     }
+
+    /// Borrow endpoint id as the pseudo-terminal variant.
+    pub fn as_pty(&self) -> Option<&String> {
+        if let Self::Pty(v) = self {
+            Some(v)
+        } else {
+            None
+        }
+    }
+
+    /// Borrow endpoint id as the composite variant.
+    pub fn as_composite(&self) -> Option<&String> {
+        if let Self::Composite(v) = self {
+            Some(v)
+        } else {
+            None
+        }
+    }
 }
 
 /// A handle to an endpoint.
@@ -231,6 +290,16 @@ impl Display for EndpointSemaphoreId {
     }
 }
 
+impl EndpointSemaphore {
+    /// A semaphore with enough permits that nobody ever has to queue for it.
+    pub(crate) fn shared() -> Self {
+        Self {
+            inner: Arc::new(Semaphore::new(Semaphore::MAX_PERMITS)),
+            id: EndpointSemaphoreId(Uuid::new_v4()),
+        }
+    }
+}
+
 /// Endpoints which should be grouped in terms of being controlled
 /// (so controlling one means controlling all) should clone this
 /// endpoint semaphore.
@@ -261,9 +330,20 @@ impl Default for EndpointSemaphore {
 /// endpoints sharing the label.
 /// This allows control access over the first
 /// endpoint available with the matching label.
-#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Hash, Clone)]
+///
+/// Surrounding whitespace is not part of a label.
+#[derive(
+    Debug, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord, Hash, Clone, JsonSchema,
+)]
+#[serde(from = "String")]
 pub struct Label(pub String);
 
+impl From<String> for Label {
+    fn from(label: String) -> Self {
+        Self::new(label)
+    }
+}
+
 impl Display for Label {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(f, "{}", self.0)
@@ -283,15 +363,38 @@ impl Borrow<str> for &Label {
 }
 
 impl Label {
-    /// Create a new label.
+    /// Create a new label, trimming surrounding whitespace.
     pub fn new<S: AsRef<str>>(label: S) -> Self {
-        Self(label.as_ref().into())
+        Self(label.as_ref().trim().into())
+    }
+
+    /// Create a new label like [`Label::new`],
+    /// but reject it if nothing is left after trimming.
+    /// An empty label matches nothing anyone would ask for.
+    pub fn try_new<S: AsRef<str>>(label: S) -> Result<Self, Error> {
+        let label = Self::new(label);
+
+        if label.is_empty() {
+            Err(Error::BadUsage(
+                "Labels must not be empty or only whitespace".into(),
+            ))
+        } else {
+            Ok(label)
+        }
+    }
+
+    /// Whether the label is empty.
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
     }
 }
 
 /// A collection of [`Label`]s.
-#[derive(Debug, Default, Serialize, Deserialize, PartialEq, Eq, Hash, Clone)]
-pub struct Labels(Vec<Label>);
+///
+/// The labels are kept sorted, such that the same labels given in any order
+/// compare equal and serialize the same.
+#[derive(Debug, Default, Serialize, Deserialize, PartialEq, Eq, Hash, Clone, JsonSchema)]
+pub struct Labels(#[serde(deserialize_with = "deserialize_sorted")] Vec<Label>);
 
 impl Labels {
     /// Borrow the [`Label`]s as a [`HashSet`].
@@ -319,15 +422,41 @@ impl Labels {
         self.0.is_empty()
     }
 
-    /// Push a new label.
+    /// Push a new label, trimming surrounding whitespace.
     pub fn push<S>(&mut self, label: S)
     where
         S: AsRef<str>,
     {
-        self.0.push(Label::new(label))
+        self.insert(Label::new(label))
+    }
+
+    /// Push a new label, rejecting it if empty. See [`Label::try_new`].
+    pub fn try_push<S>(&mut self, label: S) -> Result<(), Error>
+    where
+        S: AsRef<str>,
+    {
+        self.insert(Label::try_new(label)?);
+        Ok(())
+    }
+
+    fn sorted(mut labels: Vec<Label>) -> Self {
+        labels.sort();
+        Self(labels)
+    }
+
+    fn insert(&mut self, label: Label) {
+        let at = self.0.partition_point(|existing| existing <= &label);
+        self.0.insert(at, label);
     }
 }
 
+fn deserialize_sorted<'de, D>(deserializer: D) -> Result<Vec<Label>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    Ok(Labels::sorted(Vec::deserialize(deserializer)?).0)
+}
+
 impl AsRef<[Label]> for Labels {
     fn as_ref(&self) -> &[Label] {
         self.0.as_slice()
@@ -348,7 +477,7 @@ where
     S: AsRef<str>,
 {
     fn from_iter<T: IntoIterator<Item = S>>(iter: T) -> Self {
-        Labels(iter.into_iter().map(Label::new).collect())
+        Labels::sorted(iter.into_iter().map(Label::new).collect())
     }
 }
 
@@ -451,6 +580,71 @@ pub(crate) trait Endpoint {
     fn labels(&self) -> Labels {
         Labels::default()
     }
+
+    /// A human readable description of this endpoint, if any.
+    fn description(&self) -> Option<String> {
+        None
+    }
+
+    /// What the other end prints when waiting for input, if configured.
+    fn prompt(&self) -> Option<String> {
+        None
+    }
+
+    /// How long the most recent writes took to reach the wire.
+    fn write_latency(&self) -> LatencySummary;
+
+    /// When the endpoint was opened, or created for mocks.
+    fn opened_at(&self) -> chrono::DateTime<chrono::Utc>;
+
+    /// Where the other end of the endpoint can be opened, for pseudo-terminals.
+    fn device(&self) -> Option<String> {
+        None
+    }
+
+    /// The endpoints this endpoint is made up of, for composites.
+    fn ports(&self) -> Vec<InternalEndpointInfo> {
+        vec![]
+    }
+
+    /// Problems splitting what was read into messages.
+    /// Only serial ports read frames off a wire.
+    fn framing(&self) -> FramingSummary {
+        FramingSummary::default()
+    }
+
+    /// The wire settings in use, as read back from the port.
+    /// Only serial ports have wire settings.
+    fn port_settings(&self) -> Option<PortSettings> {
+        None
+    }
+
+    /// Change the wire settings of the endpoint while it stays open.
+    /// Settings which are `None` are left as they are.
+    ///
+    /// The returned receiver resolves when the endpoint has applied the settings.
+    /// Only serial ports have wire settings, so by default this is an error.
+    fn reconfigure(
+        &self,
+        _baud: Option<u32>,
+        _flow_control: Option<serialport::FlowControl>,
+    ) -> Result<oneshot::Receiver<Result<(), Error>>, Error> {
+        Err(Error::BadUsage(format!(
+            "The endpoint {} has no settings to reconfigure",
+            self.internal_endpoint_id()
+        )))
+    }
+
+    /// Discard what is buffered for the wire (`tx`) and/or from the wire (`rx`).
+    ///
+    /// The returned receiver resolves when the endpoint has discarded it.
+    /// By default endpoints have no buffers to flush, so this is an error.
+    fn flush(&self, _tx: bool, _rx: bool) -> Result<oneshot::Receiver<Result<(), Error>>, Error> {
+        Err(Error::BadUsage(format!(
+            "The endpoint {} has no buffers to flush",
+            self.internal_endpoint_id()
+        )))
+    }
 }
 
 pub(crate) trait EndpointExt: Endpoint {
@@ -0,0 +1,98 @@
+//! A composite endpoint.
+//! Several endpoints, e.g. the bootloader and application TTYs of a device,
+//! acting as one logical endpoint.
+//!
+//! The composite has no wire of its own.
+//! Its ports are regular endpoints sharing its semaphore,
+//! so controlling the composite means controlling each port.
+
+use futures::channel::mpsc;
+use tokio::sync::broadcast;
+
+use super::{
+    Endpoint, EndpointEvent, EndpointSemaphore, InternalEndpointId, InternalEndpointInfo, Labels,
+};
+use crate::{
+    config::Composite,
+    metrics::{LatencySummary, WriteLatency},
+    serial::SerialMessageBytes,
+};
+
+#[derive(Debug)]
+pub(crate) struct CompositeEndpoint {
+    name: String,
+    labels: Labels,
+    description: Option<String>,
+    semaphore: EndpointSemaphore,
+    ports: Vec<InternalEndpointInfo>,
+
+    /// Nothing is sent here, what arrives is seen by observing the ports.
+    events: broadcast::Sender<EndpointEvent>,
+
+    /// Nobody receives these, writes must go to the ports.
+    messages: mpsc::UnboundedSender<SerialMessageBytes>,
+
+    created_at: chrono::DateTime<chrono::Utc>,
+}
+
+impl CompositeEndpoint {
+    /// The composite over the given ports, which must share the given semaphore.
+    pub(crate) fn new(
+        composite: &Composite,
+        semaphore: EndpointSemaphore,
+        ports: Vec<InternalEndpointInfo>,
+    ) -> Self {
+        let (events, _) = broadcast::channel(1);
+        let (messages, _) = mpsc::unbounded();
+
+        Self {
+            name: composite.name.clone(),
+            labels: composite.labels.clone(),
+            description: composite.description.clone(),
+            semaphore,
+            ports,
+            events,
+            messages,
+            created_at: chrono::Utc::now(),
+        }
+    }
+}
+
+impl Endpoint for CompositeEndpoint {
+    fn events(&self) -> broadcast::Receiver<EndpointEvent> {
+        self.events.subscribe()
+    }
+
+    fn semaphore(&self) -> EndpointSemaphore {
+        self.semaphore.clone()
+    }
+
+    fn message_sender(&self) -> mpsc::UnboundedSender<SerialMessageBytes> {
+        self.messages.clone()
+    }
+
+    fn internal_endpoint_id(&self) -> InternalEndpointId {
+        InternalEndpointId::Composite(self.name.clone())
+    }
+
+    fn labels(&self) -> Labels {
+        self.labels.clone()
+    }
+
+    fn description(&self) -> Option<String> {
+        self.description.clone()
+    }
+
+    fn write_latency(&self) -> LatencySummary {
+        // Nothing is ever written to the composite itself.
+        WriteLatency::default().summary()
+    }
+
+    fn opened_at(&self) -> chrono::DateTime<chrono::Utc> {
+        self.created_at
+    }
+
+    fn ports(&self) -> Vec<InternalEndpointInfo> {
+        self.ports.clone()
+    }
+}
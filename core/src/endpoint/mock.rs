@@ -7,10 +7,10 @@
 //! regular serial ports- but faster and more reliable.
 
 use futures::channel::mpsc;
-use tokio::sync::broadcast;
+use tokio::sync::{broadcast, oneshot};
 
 use super::{Endpoint, EndpointSemaphore};
-use crate::{mock::MockHandle, serial::SerialMessageBytes};
+use crate::{error::Error, metrics::LatencySummary, mock::MockHandle, serial::SerialMessageBytes};
 
 impl Endpoint for MockHandle {
     fn events(&self) -> broadcast::Receiver<super::EndpointEvent> {
@@ -32,4 +32,32 @@ impl Endpoint for MockHandle {
     fn labels(&self) -> super::Labels {
         self.labels.clone()
     }
+
+    fn description(&self) -> Option<String> {
+        self.description.clone()
+    }
+
+    fn prompt(&self) -> Option<String> {
+        self.prompt.clone()
+    }
+
+    fn write_latency(&self) -> LatencySummary {
+        self.write_latency.summary()
+    }
+
+    fn opened_at(&self) -> chrono::DateTime<chrono::Utc> {
+        self.created_at
+    }
+
+    /// A mock loops writes back, so whether `tx` or `rx` is flushed
+    /// the messages not yet looped back are dropped.
+    fn flush(&self, _tx: bool, _rx: bool) -> Result<oneshot::Receiver<Result<(), Error>>, Error> {
+        let (reply, done) = oneshot::channel();
+
+        self.flush_sender.unbounded_send(reply).map_err(|_| {
+            Error::InternalIssue(format!("The mock {} is gone but should be alive", self.id))
+        })?;
+
+        Ok(done)
+    }
 }
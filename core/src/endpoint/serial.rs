@@ -2,10 +2,17 @@
 //! TODO
 
 use futures::channel::mpsc;
-use tokio::sync::broadcast;
+use tokio::sync::{broadcast, oneshot};
 
 use super::{Endpoint, EndpointSemaphore};
-use crate::serial::{serial_port::SerialPortHandle, SerialMessageBytes};
+use crate::{
+    error::Error,
+    metrics::{FramingSummary, LatencySummary},
+    serial::{
+        serial_port::{SerialPortControl, SerialPortHandle},
+        PortSettings, SerialMessageBytes,
+    },
+};
 
 impl Endpoint for SerialPortHandle {
     fn events(&self) -> broadcast::Receiver<super::EndpointEvent> {
@@ -13,7 +20,7 @@ impl Endpoint for SerialPortHandle {
     }
 
     fn internal_endpoint_id(&self) -> super::InternalEndpointId {
-        super::InternalEndpointId::Tty(self.tty.clone())
+        self.id.clone()
     }
 
     fn semaphore(&self) -> EndpointSemaphore {
@@ -27,4 +34,75 @@ impl Endpoint for SerialPortHandle {
     fn labels(&self) -> super::Labels {
         self.labels.clone()
     }
+
+    fn description(&self) -> Option<String> {
+        self.description.clone()
+    }
+
+    fn prompt(&self) -> Option<String> {
+        self.prompt.clone()
+    }
+
+    fn device(&self) -> Option<String> {
+        self.device.clone()
+    }
+
+    fn write_latency(&self) -> LatencySummary {
+        self.write_latency.summary()
+    }
+
+    fn opened_at(&self) -> chrono::DateTime<chrono::Utc> {
+        self.opened_at
+    }
+
+    fn framing(&self) -> FramingSummary {
+        self.framing.summary()
+    }
+
+    fn port_settings(&self) -> Option<PortSettings> {
+        Some(
+            *self
+                .settings
+                .lock()
+                .expect("Settings lock should not be poisoned"),
+        )
+    }
+
+    fn reconfigure(
+        &self,
+        baud: Option<u32>,
+        flow_control: Option<serialport::FlowControl>,
+    ) -> Result<oneshot::Receiver<Result<(), Error>>, Error> {
+        let (reply, done) = oneshot::channel();
+
+        self.control_tx
+            .unbounded_send(SerialPortControl::Reconfigure {
+                baud,
+                flow_control,
+                reply,
+            })
+            .map_err(|_| {
+                Error::InternalIssue(format!(
+                    "The serial port {} is closed but should be alive",
+                    self.id
+                ))
+            })?;
+
+        Ok(done)
+    }
+
+    fn flush(&self, tx: bool, rx: bool) -> Result<oneshot::Receiver<Result<(), Error>>, Error> {
+        let (reply, done) = oneshot::channel();
+
+        self.control_tx
+            .unbounded_send(SerialPortControl::Flush { tx, rx, reply })
+            .map_err(|_| {
+                Error::InternalIssue(format!(
+                    "The serial port {} is closed but should be alive",
+                    self.id
+                ))
+            })?;
+
+        Ok(done)
+    }
 }
@@ -1,15 +1,27 @@
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 use thiserror::Error;
 
-use crate::endpoint::Labels;
+use crate::{
+    actions::CloseReason,
+    endpoint::{EndpointId, Labels},
+};
 
 /// Errors thay may occur in this library.
-#[derive(Debug, Error, Serialize, Deserialize, PartialEq, Eq)]
+#[derive(Debug, Error, Serialize, Deserialize, PartialEq, Eq, JsonSchema)]
 pub enum Error {
     /// Endpoint does not exist.
     #[error("The endpoint `{0}` does not exist")]
     NoSuchEndpoint(String),
 
+    /// User is not connected.
+    #[error("The user `{0}` is not connected")]
+    NoSuchUser(String),
+
+    /// The username a client chose is not accepted.
+    #[error("The username is not valid. Problem: `{0}`")]
+    InvalidUsername(String),
+
     /// Bad json.
     #[error("The request `{request}` could not be deserialized. Problem: {problem}")]
     BadJson {
@@ -20,11 +32,58 @@ pub enum Error {
         problem: String,
     },
 
+    /// Bad binary request or response.
+    #[error("The binary message could not be decoded. Problem: {0}")]
+    BadBinary(String),
+
     /// User tried to perform something which requires permission
     /// without having that permission first.
     #[error("No permit: {0}")]
     NoPermit(String),
 
+    /// The endpoint's configuration does not let this user control it.
+    #[error("Access denied: `{0}`")]
+    AccessDenied(String),
+
+    /// User tried writing to an endpoint they do not control.
+    /// Observing an endpoint is not enough to write to it.
+    #[error("Not in control of `{endpoint}`, control it before writing to it")]
+    NotControlling {
+        /// The endpoint written to.
+        endpoint: EndpointId,
+    },
+
+    /// The endpoint written to is no longer running, e.g. because its device went away.
+    #[error("The endpoint `{endpoint}` is closed, the write was dropped")]
+    EndpointClosed {
+        /// The endpoint written to.
+        endpoint: EndpointId,
+    },
+
+    /// The endpoint is in use in a way which does not allow what was asked,
+    /// e.g. observing it while another user observes it exclusively.
+    #[error("The endpoint is busy: `{0}`")]
+    EndpointBusy(String),
+
+    /// The user tried writing more than the server allows in one message.
+    #[error("The message of {size} bytes is larger than the maximum of {max} bytes")]
+    MessageTooLarge {
+        /// The size of the message.
+        size: usize,
+
+        /// The largest size allowed.
+        max: usize,
+    },
+
+    /// The server only allows observing, and the action would control or write to an endpoint.
+    #[error("The server is read-only, `{0}` is not allowed")]
+    ReadOnlyServer(String),
+
+    /// An admin took the endpoint out of rotation.
+    /// See [`crate::actions::Action::SetEndpointEnabled`].
+    #[error("The endpoint `{0}` is disabled")]
+    EndpointDisabled(String),
+
     /// The user asked for more than what was needed.
     /// For example, observe same endpoint twice.
     #[error("The request was superfluous. Problem: `{0}`")]
@@ -46,9 +105,37 @@ pub enum Error {
     #[error("There was a problem relating to the websocket connection: `{0}`")]
     WebsocketIssue(String),
 
+    /// The client and server do not speak the same wire protocol version.
+    #[error("Incompatible protocol: `{0}`")]
+    IncompatibleProtocol(String),
+
+    /// Something did not happen in time.
+    #[error("Timed out: `{0}`")]
+    Timeout(String),
+
     /// Internal issues.
     #[error("An internal issue occurred. `{0}`")]
     InternalIssue(String),
+
+    /// No more messages arrive from an observed endpoint.
+    #[error("Observing `{endpoint}` ended: {reason}")]
+    ObservationEnded {
+        /// The endpoint observed.
+        endpoint: EndpointId,
+
+        /// Why the observation ended.
+        reason: CloseReason,
+    },
+
+    /// An action given a correlation id failed.
+    #[error("The action `{correlation_id}` failed: {error}")]
+    Correlated {
+        /// The correlation id of the failed action.
+        correlation_id: String,
+
+        /// Why the action failed.
+        error: Box<Error>,
+    },
 }
 
 impl Error {
@@ -60,6 +147,39 @@ impl Error {
             Err(self)
         }
     }
+
+    /// Examples of errors a user may be sent, to show what they look like.
+    pub fn examples() -> Vec<Self> {
+        let not_controlling = || Self::NotControlling {
+            endpoint: EndpointId::tty("/dev/ttyACM0"),
+        };
+
+        vec![
+            Self::NoSuchEndpoint("/dev/ttyACM9".into()),
+            Self::NoMatchingEndpoints(Labels::from_iter(["blue-device"])),
+            Self::EndpointBusy("Observed exclusively by another user".into()),
+            Self::MessageTooLarge {
+                size: 2048,
+                max: 1024,
+            },
+            Self::Correlated {
+                correlation_id: "write-1".into(),
+                error: Box::new(not_controlling()),
+            },
+            not_controlling(),
+        ]
+    }
+
+    /// Tag this error with the correlation id of the action which failed, if it had one.
+    pub(crate) fn correlated(self, correlation_id: Option<&str>) -> Self {
+        match correlation_id {
+            Some(correlation_id) => Self::Correlated {
+                correlation_id: correlation_id.into(),
+                error: Box::new(self),
+            },
+            None => self,
+        }
+    }
 }
 
 impl From<tokio_tungstenite::tungstenite::Error> for Error {
@@ -1,30 +1,120 @@
-use crate::{endpoint::InternalEndpointInfo, serial::SerialMessage, user::User};
+use crate::{
+    endpoint::{InternalEndpointInfo, LabelledEndpointId},
+    logging::LogLevel,
+    serial::{Direction, SerialMessage, SerialMessageBytes, TaggedMessage},
+    user::User,
+};
 use itertools::Itertools;
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 use std::{self, collections::VecDeque, fmt::Display};
 use tokio::sync::broadcast;
-use tracing::info;
+use tracing::{info, warn};
 
 /// These events are not necessarily tied to any user.
 pub mod general {
     use super::*;
 
     /// General events.
-    #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+    #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, JsonSchema)]
     pub enum Event {
         /// A message was sent (i.e. put on wire).
-        MessageSent((InternalEndpointInfo, SerialMessage)),
+        MessageSent((InternalEndpointInfo, SerialMessageBytes)),
         /// A message was received (i.e. from wire).
-        MessageReceived((InternalEndpointInfo, SerialMessage)),
+        MessageReceived((InternalEndpointInfo, SerialMessageBytes)),
+        /// This observer could not keep up, and this many events were skipped.
+        Lagged(u64),
+        /// No message was received from wire within the endpoint's idle timeout.
+        EndpointIdle(LabelledEndpointId),
+        /// An idle endpoint received a message from wire again.
+        EndpointActive(LabelledEndpointId),
+        /// The endpoint received its first message from wire since it was opened.
+        /// Useful for telling whether a device started talking at all.
+        EndpointFirstMessage(LabelledEndpointId),
+        /// Server-wide numbers, sent periodically if configured.
+        /// See [`crate::config::EventsConfig::stats_interval`].
+        Stats {
+            /// Messages seen by all endpoints per second since the previous stats,
+            /// in either direction.
+            messages_per_sec: u64,
+            /// How many users control at least one endpoint.
+            active_controllers: usize,
+            /// How many users are connected.
+            connected_users: usize,
+        },
+        /// The users waiting for control of an endpoint changed.
+        QueueUpdated {
+            /// The endpoint queued for.
+            endpoint: LabelledEndpointId,
+            /// The waiting users, the one to get control next first.
+            ordered_users: Vec<User>,
+        },
+        /// A user has controlled an endpoint for longer than configured,
+        /// see [`crate::config::Config::control_warn_after`].
+        /// Sent once per control, the user keeps control.
+        LongControl {
+            /// The controlling user.
+            user: User,
+            /// The endpoint controlled.
+            endpoint: LabelledEndpointId,
+            /// How long the user has had control.
+            held_for: std::time::Duration,
+        },
+        /// The server logged something.
+        /// Only sent if configured, see [`crate::config::EventsConfig::server_logs`].
+        ServerLog {
+            /// How severe the record is.
+            level: LogLevel,
+            /// Where in the server the record is from.
+            target: String,
+            /// What was logged.
+            message: String,
+        },
     }
 
     impl Display for Event {
         fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
             match self {
-                Event::MessageSent((endpoint, msg)) => write!(f, "sent: {msg} to {endpoint}"),
+                Event::MessageSent((endpoint, msg)) => {
+                    write!(f, "sent: {} to {endpoint}", SerialMessage::new_lossy(msg))
+                }
                 Event::MessageReceived((endpoint, msg)) => {
-                    write!(f, "received: {msg} from {endpoint}")
+                    write!(
+                        f,
+                        "received: {} from {endpoint}",
+                        SerialMessage::new_lossy(msg)
+                    )
                 }
+                Event::Lagged(skipped) => write!(f, "lagged: skipped {skipped} events"),
+                Event::EndpointIdle(endpoint) => write!(f, "idle: {endpoint}"),
+                Event::EndpointActive(endpoint) => write!(f, "active: {endpoint}"),
+                Event::EndpointFirstMessage(endpoint) => write!(f, "first message: {endpoint}"),
+                Event::Stats {
+                    messages_per_sec,
+                    active_controllers,
+                    connected_users,
+                } => write!(
+                    f,
+                    "stats: {messages_per_sec} messages/s, {active_controllers} controller(s), {connected_users} user(s)"
+                ),
+                Event::QueueUpdated {
+                    endpoint,
+                    ordered_users,
+                } => write!(
+                    f,
+                    "queue of {endpoint}: [{}]",
+                    ordered_users.iter().join(", ")
+                ),
+                Event::LongControl {
+                    user,
+                    endpoint,
+                    held_for,
+                } => write!(f, "long control: {user} has held {endpoint} for {held_for:?}"),
+                Event::ServerLog {
+                    level,
+                    target,
+                    message,
+                } => write!(f, "log: {level} {target}: {message}"),
             }
         }
     }
@@ -35,7 +125,7 @@ pub mod user {
     use super::*;
 
     /// An event related to some user.
-    #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+    #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, JsonSchema)]
     pub struct UserEvent {
         /// The user related to this event.
         pub user: User,
@@ -50,8 +140,35 @@ pub mod user {
         }
     }
 
+    /// An event related to some user, and when it happened.
+    #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, JsonSchema)]
+    pub struct TimestampedUserEvent {
+        /// The user related to this event.
+        pub user: User,
+
+        /// The event.
+        pub event: Event,
+
+        /// When the event happened.
+        pub timestamp: chrono::DateTime<chrono::Utc>,
+    }
+
+    impl TimestampedUserEvent {
+        /// The user event an event is, if it is one.
+        pub(crate) fn from_event(event: TimestampedEvent) -> Option<Self> {
+            match event.inner {
+                super::Event::User(UserEvent { user, event: inner }) => Some(Self {
+                    user,
+                    event: inner,
+                    timestamp: event.timestamp,
+                }),
+                super::Event::General(_) => None,
+            }
+        }
+    }
+
     /// Events that can happen to a user.
-    #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+    #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, JsonSchema)]
     pub enum Event {
         /// A user has connected.
         Connected,
@@ -60,9 +177,11 @@ pub mod user {
         Disconnected,
 
         /// A user sent (i.e. put on wire) this message.
-        MessageSent((InternalEndpointInfo, SerialMessage)),
+        MessageSent((InternalEndpointInfo, SerialMessageBytes)),
         /// A user received (i.e. got from wire) this message.
-        MessageReceived((InternalEndpointInfo, SerialMessage)),
+        MessageReceived((InternalEndpointInfo, SerialMessageBytes)),
+        /// A user wrote this message, but it was dropped since the endpoint is closed.
+        WriteDropped((InternalEndpointInfo, SerialMessageBytes)),
 
         /// A user is now observing some endpoints.
         Observing(Vec<InternalEndpointInfo>),
@@ -108,15 +227,164 @@ pub mod user {
                 Event::NoLongerInControlOf(endpoints) => {
                     write_endpoints("no longer in control of", endpoints)
                 }
-                Event::MessageSent((info, msg)) => write!(f, "sent: {msg} to {info}"),
-                Event::MessageReceived((info, msg)) => write!(f, "received: {msg} to {info}"),
+                Event::MessageSent((info, msg)) => {
+                    write!(f, "sent: {} to {info}", SerialMessage::new_lossy(msg))
+                }
+                Event::MessageReceived((info, msg)) => {
+                    write!(f, "received: {} to {info}", SerialMessage::new_lossy(msg))
+                }
+                Event::WriteDropped((info, msg)) => {
+                    write!(f, "dropped: {} to {info}", SerialMessage::new_lossy(msg))
+                }
             }
         }
     }
 }
 
+/// These events relate to a single endpoint.
+/// See [`crate::actions::Action::ObserveEndpointEvents`].
+pub mod endpoint {
+    use super::*;
+
+    /// Something which happened to an endpoint.
+    #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, JsonSchema)]
+    pub enum Event {
+        /// No message was received from wire within the endpoint's idle timeout.
+        Idle,
+
+        /// An idle endpoint received a message from wire again.
+        Active,
+
+        /// The endpoint received its first message from wire since it was opened.
+        FirstMessage,
+
+        /// A message written to the endpoint was dropped since the endpoint is closed.
+        WriteDropped(SerialMessageBytes),
+
+        /// This observer could not keep up, and up to this many events were skipped.
+        Lagged(u64),
+    }
+
+    impl Display for Event {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            match self {
+                Event::Idle => write!(f, "idle"),
+                Event::Active => write!(f, "active"),
+                Event::FirstMessage => write!(f, "first message"),
+                Event::WriteDropped(msg) => {
+                    write!(f, "dropped: {}", SerialMessage::new_lossy(msg))
+                }
+                Event::Lagged(skipped) => write!(f, "lagged: skipped up to {skipped} events"),
+            }
+        }
+    }
+
+    /// An event of some endpoint, and when it happened.
+    #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, JsonSchema)]
+    pub struct TimestampedEndpointEvent {
+        /// The endpoint the event happened to.
+        pub endpoint: LabelledEndpointId,
+
+        /// The event.
+        pub event: Event,
+
+        /// When the event happened.
+        pub timestamp: chrono::DateTime<chrono::Utc>,
+    }
+
+    impl TimestampedEndpointEvent {
+        /// The endpoint event an event is about, if any.
+        pub(crate) fn from_event(event: &TimestampedEvent) -> Option<Self> {
+            let (endpoint, inner) = match &event.inner {
+                super::Event::General(general::Event::EndpointIdle(endpoint)) => {
+                    (endpoint.clone(), Event::Idle)
+                }
+                super::Event::General(general::Event::EndpointActive(endpoint)) => {
+                    (endpoint.clone(), Event::Active)
+                }
+                super::Event::General(general::Event::EndpointFirstMessage(endpoint)) => {
+                    (endpoint.clone(), Event::FirstMessage)
+                }
+                super::Event::User(user::UserEvent {
+                    event: user::Event::WriteDropped((info, msg)),
+                    ..
+                }) => (info.clone().into(), Event::WriteDropped(msg.clone())),
+                _ => return None,
+            };
+
+            Some(Self {
+                endpoint,
+                event: inner,
+                timestamp: event.timestamp,
+            })
+        }
+    }
+
+    impl Display for TimestampedEndpointEvent {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            write!(f, "{}: {}", self.endpoint, self.event)
+        }
+    }
+}
+
+/// Messages put on or received from wire, whoever did it.
+/// See [`crate::actions::Action::ObserveMessages`].
+pub mod message {
+    use super::*;
+
+    /// A message seen by some endpoint, and when it was seen.
+    #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, JsonSchema)]
+    pub struct TimestampedMessage {
+        /// The endpoint which saw the message.
+        pub endpoint: LabelledEndpointId,
+
+        /// Whether the message was put on or received from wire.
+        pub direction: Direction,
+
+        /// The message.
+        pub message: SerialMessageBytes,
+
+        /// When the message was seen.
+        pub timestamp: chrono::DateTime<chrono::Utc>,
+    }
+
+    impl TimestampedMessage {
+        /// The message an event is about, if any.
+        pub(crate) fn from_event(event: &TimestampedEvent) -> Option<Self> {
+            let (direction, (info, message)) = match &event.inner {
+                super::Event::General(general::Event::MessageSent(sent)) => {
+                    (Direction::ToWire, sent)
+                }
+                super::Event::General(general::Event::MessageReceived(received)) => {
+                    (Direction::FromWire, received)
+                }
+                _ => return None,
+            };
+
+            Some(Self {
+                endpoint: info.clone().into(),
+                direction,
+                message: message.clone(),
+                timestamp: event.timestamp,
+            })
+        }
+    }
+
+    impl Display for TimestampedMessage {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            write!(
+                f,
+                "{} {} {}",
+                SerialMessage::new_lossy(&self.message),
+                self.direction,
+                self.endpoint
+            )
+        }
+    }
+}
+
 /// Any event.
-#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, JsonSchema)]
 pub enum Event {
     /// An event tied to a user.
     User(user::UserEvent),
@@ -135,7 +403,7 @@ impl Display for Event {
 }
 
 /// An event related to some user.
-#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, JsonSchema)]
 pub struct TimestampedEvent {
     /// The event.
     pub inner: Event,
@@ -163,6 +431,36 @@ impl TimestampedEvent {
             timestamp: chrono::Utc::now(),
         }
     }
+
+    /// Create a user event for a message the user sent or received.
+    /// The timestamp is when the message was seen, not when the event was made.
+    pub(crate) fn new_user_message_event(user: &User, message: TaggedMessage) -> Self {
+        let event = match message.direction {
+            Direction::ToWire => user::Event::MessageSent((message.endpoint, message.bytes)),
+            Direction::FromWire => user::Event::MessageReceived((message.endpoint, message.bytes)),
+        };
+
+        Self {
+            timestamp: message.timestamp,
+            ..Self::new_user_event(user, event)
+        }
+    }
+
+    /// Create a general event for a message seen on some endpoint.
+    /// The timestamp is when the message was seen, not when the event was made.
+    pub(crate) fn new_general_message_event(message: TaggedMessage) -> Self {
+        let event = match message.direction {
+            Direction::ToWire => general::Event::MessageSent((message.endpoint, message.bytes)),
+            Direction::FromWire => {
+                general::Event::MessageReceived((message.endpoint, message.bytes))
+            }
+        };
+
+        Self {
+            timestamp: message.timestamp,
+            ..Self::new_general_event(event)
+        }
+    }
 }
 
 impl Display for TimestampedEvent {
@@ -171,12 +469,69 @@ impl Display for TimestampedEvent {
     }
 }
 
+/// Tells the time events happen at.
+///
+/// The real clock is [`SystemClock`].
+/// Tests may use [`FakeClock`] instead, which makes timestamps predictable.
+pub(crate) trait Clock: std::fmt::Debug + Send + std::marker::Sync {
+    /// The time right now.
+    fn now(&self) -> chrono::DateTime<chrono::Utc>;
+}
+
+/// The clock of the system.
+#[derive(Debug, Default, Clone, Copy)]
+pub(crate) struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> chrono::DateTime<chrono::Utc> {
+        chrono::Utc::now()
+    }
+}
+
+#[cfg(test)]
+pub(crate) use fake::FakeClock;
+
+#[cfg(test)]
+mod fake {
+    use std::sync::Mutex;
+
+    use super::Clock;
+
+    /// A clock starting at some time, advancing by a fixed step each time it is asked.
+    #[derive(Debug)]
+    pub(crate) struct FakeClock {
+        next: Mutex<chrono::DateTime<chrono::Utc>>,
+        step: chrono::Duration,
+    }
+
+    impl FakeClock {
+        pub(crate) fn new(start: chrono::DateTime<chrono::Utc>, step: chrono::Duration) -> Self {
+            Self {
+                next: Mutex::new(start),
+                step,
+            }
+        }
+    }
+
+    impl Clock for FakeClock {
+        fn now(&self) -> chrono::DateTime<chrono::Utc> {
+            let mut next = self.next.lock().expect("Clock lock should not be poisoned");
+            let now = *next;
+            *next = now + self.step;
+            now
+        }
+    }
+}
+
 /// An event logger and broadcaster.
 #[derive(Debug)]
 pub struct Events {
     log: VecDeque<TimestampedEvent>,
     log_size: usize,
 
+    /// Timestamps the events made here.
+    clock: Box<dyn Clock>,
+
     tx: broadcast::Sender<TimestampedEvent>,
     #[allow(dead_code)]
     rx: broadcast::Receiver<TimestampedEvent>,
@@ -185,14 +540,25 @@ pub struct Events {
 impl Events {
     /// Create a new events handler.
     /// It will keep a log of at most `log_size` events.
-    /// It may be subscribed to to receive any events it sees.
-    pub fn new(log_size: usize) -> Self {
-        let (tx, rx) = broadcast::channel(100);
+    /// It may be subscribed to to receive any events it sees,
+    /// subscribers lagging more than `channel_depth` events behind will miss some.
+    pub fn new(log_size: usize, channel_depth: usize) -> Self {
+        let (tx, rx) = broadcast::channel(channel_depth);
         Self {
             tx,
             rx,
             log: VecDeque::new(),
             log_size,
+            clock: Box::new(SystemClock),
+        }
+    }
+
+    /// Timestamp events by the given clock instead of the system's.
+    #[cfg(test)]
+    pub(crate) fn with_clock(self, clock: impl Clock + 'static) -> Self {
+        Self {
+            clock: Box::new(clock),
+            ..self
         }
     }
 
@@ -210,16 +576,78 @@ impl Events {
         // Truncate removes from the back, which means older events are split off first.
         self.log.truncate(self.log_size);
 
-        self.tx.send(event).expect("Broadcast should work");
+        // Sending only fails if there are no subscribers, so the event is simply not seen live.
+        if let Err(broadcast::error::SendError(event)) = self.tx.send(event) {
+            warn!(%event, "Event not broadcast, no subscribers");
+        }
     }
 
     /// Send a general event. See [`self.send_event`].
     pub fn send_general_event(&mut self, event: general::Event) {
-        self.send_event(TimestampedEvent::new_general_event(event))
+        self.send_event(TimestampedEvent {
+            timestamp: self.clock.now(),
+            ..TimestampedEvent::new_general_event(event)
+        })
     }
 
     /// Send a user event. See [`self.send_event`].
     pub fn send_user_event(&mut self, user: &User, event: user::Event) {
-        self.send_event(TimestampedEvent::new_user_event(user, event))
+        self.send_event(TimestampedEvent {
+            timestamp: self.clock.now(),
+            ..TimestampedEvent::new_user_event(user, event)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn log_never_exceeds_cap() {
+        let mut events = Events::new(10, 4);
+        let user = User::new("burst");
+
+        for _ in 0..1000 {
+            events.send_user_event(&user, user::Event::Connected);
+            assert!(events.log.len() <= 10);
+        }
+
+        assert_eq!(events.log.len(), 10);
+    }
+
+    #[test]
+    fn events_are_timestamped_by_the_clock() {
+        let start = chrono::DateTime::parse_from_rfc3339("2022-11-01T12:00:00Z")
+            .unwrap()
+            .with_timezone(&chrono::Utc);
+        let step = chrono::Duration::milliseconds(10);
+
+        let mut events = Events::new(10, 4).with_clock(FakeClock::new(start, step));
+        let mut rx = events.subscribe();
+        let user = User::new("punctual");
+
+        events.send_user_event(&user, user::Event::Connected);
+        events.send_general_event(general::Event::Lagged(1));
+        events.send_user_event(&user, user::Event::Disconnected);
+
+        for (index, expected) in [
+            Event::User(user::UserEvent {
+                user: user.clone(),
+                event: user::Event::Connected,
+            }),
+            Event::General(general::Event::Lagged(1)),
+            Event::User(user::UserEvent {
+                user: user.clone(),
+                event: user::Event::Disconnected,
+            }),
+        ]
+        .into_iter()
+        .enumerate()
+        {
+            let event = rx.try_recv().unwrap();
+            assert_eq!(event.inner, expected);
+            assert_eq!(event.timestamp, start + step * index as i32);
+        }
     }
 }
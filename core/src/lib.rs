@@ -50,3 +50,6 @@ pub mod user;
 
 /// Events the server emits.
 pub mod events;
+
+/// Measurements of how endpoints perform.
+pub mod metrics;
@@ -1,10 +1,114 @@
-use std::path::PathBuf;
+use std::{fmt::Display, path::PathBuf, sync::OnceLock};
 
-use tokio::sync::RwLock;
-use tracing::Level;
-use tracing::{debug, info, metadata::LevelFilter, trace};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use tokio::sync::{broadcast, RwLock};
+use tracing::{debug, field::Field, info, metadata::LevelFilter, trace, Level, Subscriber};
 use tracing_appender::rolling::{RollingFileAppender, Rotation};
-use tracing_subscriber::prelude::*;
+use tracing_subscriber::{layer::Context, prelude::*, Layer};
+
+/// How severe a forwarded server log record is.
+/// Records less severe than [`LogLevel::Info`] are never forwarded.
+#[derive(
+    Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord, JsonSchema,
+)]
+pub enum LogLevel {
+    /// Informational.
+    Info,
+    /// Something might be wrong.
+    Warn,
+    /// Something is wrong.
+    Error,
+}
+
+impl LogLevel {
+    fn from_level(level: &Level) -> Option<Self> {
+        match *level {
+            Level::INFO => Some(Self::Info),
+            Level::WARN => Some(Self::Warn),
+            Level::ERROR => Some(Self::Error),
+            _ => None,
+        }
+    }
+}
+
+impl Display for LogLevel {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LogLevel::Info => write!(f, "INFO"),
+            LogLevel::Warn => write!(f, "WARN"),
+            LogLevel::Error => write!(f, "ERROR"),
+        }
+    }
+}
+
+/// A log record of the server, see [`subscribe_server_logs`].
+#[derive(Debug, Clone)]
+pub(crate) struct ServerLog {
+    pub(crate) level: LogLevel,
+    pub(crate) target: String,
+    pub(crate) message: String,
+}
+
+/// Log records are forwarded here while anyone is subscribed.
+fn server_logs() -> &'static broadcast::Sender<ServerLog> {
+    static SERVER_LOGS: OnceLock<broadcast::Sender<ServerLog>> = OnceLock::new();
+    SERVER_LOGS.get_or_init(|| broadcast::channel(100).0)
+}
+
+/// Receive the log records of the server.
+/// Nothing is received unless logging was set up via [`init`].
+pub(crate) fn subscribe_server_logs() -> broadcast::Receiver<ServerLog> {
+    server_logs().subscribe()
+}
+
+/// Collects the message of a record, followed by any other fields.
+#[derive(Default)]
+struct MessageVisitor(String);
+
+impl tracing::field::Visit for MessageVisitor {
+    fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+        if field.name() == "message" {
+            self.0.insert_str(0, &format!("{value:?}"));
+        } else {
+            self.0 += &format!(" {}={value:?}", field.name());
+        }
+    }
+}
+
+/// Forwards log records to [`subscribe_server_logs`].
+struct ServerLogLayer;
+
+impl<S: Subscriber> Layer<S> for ServerLogLayer {
+    fn on_event(&self, event: &tracing::Event<'_>, _ctx: Context<'_, S>) {
+        let tx = server_logs();
+        if tx.receiver_count() == 0 {
+            return;
+        }
+
+        let metadata = event.metadata();
+
+        // Sending an event is itself logged, so forwarding those
+        // records would turn each event into another one.
+        if metadata.target().starts_with("serial_keel::events") {
+            return;
+        }
+
+        let Some(level) = LogLevel::from_level(metadata.level()) else {
+            return;
+        };
+
+        let mut visitor = MessageVisitor::default();
+        event.record(&mut visitor);
+
+        // Nobody might be subscribed anymore, which is fine.
+        let _ = tx.send(ServerLog {
+            level,
+            target: metadata.target().to_owned(),
+            message: visitor.0,
+        });
+    }
+}
 
 fn do_init(stdout_level: Level, file_level: Option<(Level, PathBuf)>) {
     let mut message = String::from("Logging with:");
@@ -58,7 +162,9 @@ fn do_init(stdout_level: Level, file_level: Option<(Level, PathBuf)>) {
     let stdout_layer =
         tracing_subscriber::fmt::layer().with_filter(LevelFilter::from(stdout_level));
 
-    let registry = tracing_subscriber::registry().with(stdout_layer);
+    let registry = tracing_subscriber::registry()
+        .with(stdout_layer)
+        .with(ServerLogLayer.with_filter(LevelFilter::INFO));
 
     let maybe_file_layer = if let Some((level, output_dir)) = file_level {
         message += &format!(", file (in dir {output_dir:?})");
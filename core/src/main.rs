@@ -24,13 +24,14 @@ async fn main() -> Result<()> {
 
     logging::init(cli.stdout_log, Some((cli.file_log, cli.file_dir))).await;
 
-    let config = if let Some(config_path) = cli.config {
+    let mut config = if let Some(config_path) = &cli.config {
         debug!(?config_path, "Config from path");
         Config::new_from_path(config_path)
     } else {
         debug!("Default config");
         Config::default()
     };
+    cli.apply_overrides(&mut config);
 
     #[cfg(unix)]
     {
@@ -0,0 +1,195 @@
+use std::{
+    collections::VecDeque,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use futures::{channel::mpsc, StreamExt};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use tokio::time::Instant;
+
+use crate::{
+    endpoint::LabelledEndpointId,
+    serial::{Direction, SerialMessageBytes},
+};
+
+/// How many of the most recent writes the latency histogram covers.
+const LATENCY_WINDOW: usize = 1024;
+
+/// Upper bounds of the latency histogram buckets.
+/// Anything slower ends up in an extra last bucket.
+const LATENCY_BUCKETS: [Duration; 4] = [
+    Duration::from_millis(1),
+    Duration::from_millis(10),
+    Duration::from_millis(100),
+    Duration::from_secs(1),
+];
+
+/// A rolling record of how long writes take from being enqueued
+/// until they have been put on wire.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct WriteLatency(Arc<Mutex<VecDeque<Duration>>>);
+
+impl WriteLatency {
+    pub(crate) fn record(&self, latency: Duration) {
+        let mut samples = self.0.lock().expect("Latency lock should not be poisoned");
+
+        if samples.len() == LATENCY_WINDOW {
+            samples.pop_front();
+        }
+        samples.push_back(latency);
+    }
+
+    pub(crate) fn summary(&self) -> LatencySummary {
+        let samples = self.0.lock().expect("Latency lock should not be poisoned");
+
+        let mut buckets = vec![0; LATENCY_BUCKETS.len() + 1];
+        for sample in samples.iter() {
+            let bucket = LATENCY_BUCKETS
+                .iter()
+                .position(|bound| sample < bound)
+                .unwrap_or(LATENCY_BUCKETS.len());
+            buckets[bucket] += 1;
+        }
+
+        let mean =
+            (!samples.is_empty()).then(|| samples.iter().sum::<Duration>() / samples.len() as u32);
+
+        LatencySummary {
+            count: samples.len(),
+            min: samples.iter().min().copied(),
+            max: samples.iter().max().copied(),
+            mean,
+            buckets,
+        }
+    }
+}
+
+/// Running counts of what went wrong when splitting what a port reads into frames.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct FramingStats(Arc<Mutex<FramingSummary>>);
+
+impl FramingStats {
+    fn update(&self, f: impl FnOnce(&mut FramingSummary)) {
+        f(&mut self.0.lock().expect("Framing lock should not be poisoned"));
+    }
+
+    pub(crate) fn decode_error(&self) {
+        self.update(|summary| summary.decode_errors += 1);
+    }
+
+    pub(crate) fn oversized_frame(&self) {
+        self.update(|summary| summary.oversized_frames += 1);
+    }
+
+    pub(crate) fn dropped(&self, bytes: usize) {
+        self.update(|summary| summary.dropped_bytes += bytes);
+    }
+
+    pub(crate) fn summary(&self) -> FramingSummary {
+        *self.0.lock().expect("Framing lock should not be poisoned")
+    }
+}
+
+/// Running totals of the bytes an endpoint put on and received from wire since it was opened.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct ByteCounter(Arc<Mutex<ByteCounts>>);
+
+impl ByteCounter {
+    pub(crate) fn count(&self, direction: Direction, bytes: usize) {
+        let mut counts = self
+            .0
+            .lock()
+            .expect("Byte count lock should not be poisoned");
+
+        match direction {
+            Direction::ToWire => counts.sent += bytes as u64,
+            Direction::FromWire => counts.received += bytes as u64,
+        }
+    }
+
+    pub(crate) fn summary(&self) -> ByteCounts {
+        *self
+            .0
+            .lock()
+            .expect("Byte count lock should not be poisoned")
+    }
+}
+
+/// Stamp each message with when it was enqueued,
+/// so that the endpoint writing it can tell how long it took.
+pub(crate) fn stamped(
+    mut messages: mpsc::UnboundedReceiver<SerialMessageBytes>,
+) -> mpsc::UnboundedReceiver<(Instant, SerialMessageBytes)> {
+    let (tx, rx) = mpsc::unbounded();
+
+    tokio::spawn(async move {
+        while let Some(message) = messages.next().await {
+            if tx.unbounded_send((Instant::now(), message)).is_err() {
+                break;
+            }
+        }
+    });
+
+    rx
+}
+
+/// A summary of the most recent write latencies of an endpoint.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LatencySummary {
+    /// How many writes the summary covers.
+    pub count: usize,
+
+    /// The fastest write.
+    pub min: Option<Duration>,
+
+    /// The slowest write.
+    pub max: Option<Duration>,
+
+    /// The average write.
+    pub mean: Option<Duration>,
+
+    /// How many writes took less than 1 ms, 10 ms, 100 ms, 1 s, and longer than that.
+    pub buckets: Vec<usize>,
+}
+
+/// How reading frames from an endpoint went so far.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FramingSummary {
+    /// Reads which failed to produce a frame.
+    pub decode_errors: usize,
+
+    /// Frames longer than allowed, which were dropped.
+    pub oversized_frames: usize,
+
+    /// Bytes read but never passed on,
+    /// either as part of oversized frames or flushed before making up a frame.
+    pub dropped_bytes: usize,
+}
+
+/// How many bytes an endpoint saw since it was opened.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub struct ByteCounts {
+    /// Bytes put on wire.
+    pub sent: u64,
+
+    /// Bytes received from wire.
+    pub received: u64,
+}
+
+/// The metrics kept for an endpoint.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EndpointMetrics {
+    /// The endpoint measured.
+    pub endpoint: LabelledEndpointId,
+
+    /// How long writes take from a user sending them until they are on wire.
+    pub write_latency: LatencySummary,
+
+    /// Problems splitting what the endpoint read into messages.
+    pub framing: FramingSummary,
+
+    /// How many bytes the endpoint saw since it was opened.
+    pub bytes: ByteCounts,
+}
@@ -2,20 +2,24 @@
 
 use std::fmt::Display;
 use std::hash::Hash;
+use std::time::Duration;
 
 use futures::{channel::mpsc, StreamExt};
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
-use tokio::sync::broadcast;
+use tokio::sync::{broadcast, oneshot};
 use tokio_stream::wrappers::BroadcastStream;
 use tracing::{debug, info, trace, warn};
 
 use crate::{
     endpoint::{self, EndpointEvent, EndpointSemaphore, Label, Labels},
-    serial::SerialMessageBytes,
+    error::Error,
+    metrics::{self, WriteLatency},
+    serial::{self, MockEcho, SerialMessageBytes, Transform},
     user::User,
 };
 
-#[derive(Debug, Clone, Eq, Serialize, Deserialize)]
+#[derive(Debug, Clone, Eq, Serialize, Deserialize, JsonSchema)]
 #[cfg_attr(not(feature = "mocks-share-endpoints"), derive(Hash, PartialEq))]
 pub struct MockId {
     pub(crate) user: User,
@@ -60,6 +64,12 @@ pub(crate) struct MockBuilder {
     mock_id: MockId,
     semaphore: Option<EndpointSemaphore>,
     labels: Labels,
+    write_terminator: Option<SerialMessageBytes>,
+    transforms: Vec<Transform>,
+    echo: MockEcho,
+    write_delay: Option<Duration>,
+    description: Option<String>,
+    prompt: Option<String>,
 }
 
 impl MockBuilder {
@@ -68,9 +78,52 @@ impl MockBuilder {
             mock_id,
             semaphore: None,
             labels: Labels::default(),
+            write_terminator: None,
+            transforms: vec![],
+            echo: MockEcho::default(),
+            write_delay: None,
+            description: None,
+            prompt: None,
         }
     }
 
+    /// Set a human readable description.
+    pub(crate) fn set_description(mut self, description: String) -> Self {
+        self.description = Some(description);
+        self
+    }
+
+    /// What the mocked shell prints when waiting for input.
+    /// Looped back lines are split as always, this is only listed.
+    pub(crate) fn set_prompt(mut self, prompt: String) -> Self {
+        self.prompt = Some(prompt);
+        self
+    }
+
+    /// Append this to written messages not already ending with it.
+    pub(crate) fn set_write_terminator(mut self, terminator: SerialMessageBytes) -> Self {
+        self.write_terminator = Some(terminator);
+        self
+    }
+
+    /// Apply these to each message looped back from wire.
+    pub(crate) fn set_transforms(mut self, transforms: Vec<Transform>) -> Self {
+        self.transforms = transforms;
+        self
+    }
+
+    /// Send this back from wire instead of each line written as is.
+    pub(crate) fn set_echo(mut self, echo: MockEcho) -> Self {
+        self.echo = echo;
+        self
+    }
+
+    /// Wait this long before looping back each write, like a slow device would.
+    pub(crate) fn set_write_delay(mut self, delay: Duration) -> Self {
+        self.write_delay = Some(delay);
+        self
+    }
+
     /// Set the [`EndpointSemaphore`] to use.
     pub(crate) fn set_semaphore(mut self, semaphore: EndpointSemaphore) -> Self {
         self.semaphore = Some(semaphore);
@@ -89,8 +142,12 @@ impl MockBuilder {
 
         // Listen to this internally.
         // If anything appears, put it on the broadcast.
-        let (should_put_on_wire_sender, mut should_put_on_wire_receiver) =
+        let (should_put_on_wire_sender, should_put_on_wire_receiver) =
             mpsc::unbounded::<SerialMessageBytes>();
+        let mut should_put_on_wire_receiver = metrics::stamped(should_put_on_wire_receiver);
+
+        let (flush_sender, mut flush_receiver) =
+            mpsc::unbounded::<oneshot::Sender<Result<(), Error>>>();
 
         // Outsiders will be getting observing messages from this broadcast.
         // TODO: If a user sends a message with more newlines than we can fit here and they are not
@@ -101,9 +158,45 @@ impl MockBuilder {
         // We need a stream.
         let broadcast_receiver: BroadcastStream<EndpointEvent> = broadcast_receiver.into();
         let broadcast_sender_task = broadcast_sender.clone();
+        let write_terminator = self.write_terminator;
+        let transforms = self.transforms;
+        let echo = self.echo;
+        let write_delay = self.write_delay;
+        let write_latency = WriteLatency::default();
+        let write_latency_task = write_latency.clone();
 
         tokio::spawn(async move {
-            while let Some(message) = should_put_on_wire_receiver.next().await {
+            // Writes not yet looped back are what a mock has buffered,
+            // so flushing drops them, including one waiting for its write delay.
+            loop {
+                let (enqueued, message) = tokio::select! {
+                    biased;
+                    Some(done) = flush_receiver.next() => {
+                        drop_queued(&mut should_put_on_wire_receiver, done);
+                        continue;
+                    }
+                    next = should_put_on_wire_receiver.next() => match next {
+                        Some(next) => next,
+                        None => break,
+                    },
+                };
+
+                if let Some(delay) = write_delay {
+                    tokio::select! {
+                        biased;
+                        Some(done) = flush_receiver.next() => {
+                            drop_queued(&mut should_put_on_wire_receiver, done);
+                            continue;
+                        }
+                        () = tokio::time::sleep(delay) => {}
+                    }
+                }
+                write_latency_task.record(enqueued.elapsed());
+
+                let message = match &write_terminator {
+                    Some(terminator) => serial::terminated(message, terminator),
+                    None => message,
+                };
                 let message = String::from_utf8_lossy(&message);
 
                 let newlines = message.chars().filter(|c| c == &'\n').count();
@@ -116,8 +209,9 @@ impl MockBuilder {
                 // This emulates a per-line loopback on a serial port.
                 for line in message.lines() {
                     let line = line.to_owned().into_bytes();
+                    let from_wire = serial::transformed(echo.apply(line.clone()), &transforms);
 
-                    match broadcast_sender_task.send(EndpointEvent::ToWire(line.clone())) {
+                    match broadcast_sender_task.send(EndpointEvent::ToWire(line)) {
                         Ok(listeners) => {
                             trace!("Broadcasted ToWire message to {listeners} listener(s)")
                         }
@@ -126,7 +220,7 @@ impl MockBuilder {
                         }
                     }
 
-                    match broadcast_sender_task.send(EndpointEvent::FromWire(line)) {
+                    match broadcast_sender_task.send(EndpointEvent::FromWire(from_wire)) {
                         Ok(listeners) => {
                             trace!("Broadcasted FromWire message to {listeners} listener(s)")
                         }
@@ -143,26 +237,58 @@ impl MockBuilder {
 
         MockHandle {
             should_put_on_wire_sender,
+            flush_sender,
             broadcast_sender,
             id: self.mock_id,
             semaphore: self.semaphore.unwrap_or_default(),
             labels: self.labels,
+            description: self.description,
+            prompt: self.prompt,
+            write_latency,
+            created_at: chrono::Utc::now(),
         }
     }
 }
 
+/// Drop the queued messages, then tell the flush is done.
+fn drop_queued<T>(
+    queued: &mut mpsc::UnboundedReceiver<T>,
+    done: oneshot::Sender<Result<(), Error>>,
+) {
+    let mut dropped = 0;
+    while let Ok(Some(_)) = queued.try_next() {
+        dropped += 1;
+    }
+    debug!("Flushed {dropped} queued message(s)");
+
+    if done.send(Ok(())).is_err() {
+        debug!("Flushed, but nobody is waiting for the result");
+    }
+}
+
 pub(crate) struct MockHandle {
     pub(crate) id: MockId,
 
     // Used for giving out senders (via clone)
     pub(crate) should_put_on_wire_sender: mpsc::UnboundedSender<SerialMessageBytes>,
 
+    // Asks the mock to drop messages not yet looped back
+    pub(crate) flush_sender: mpsc::UnboundedSender<oneshot::Sender<Result<(), Error>>>,
+
     // Used for giving out receivers (via subscribe)
     pub(crate) broadcast_sender: broadcast::Sender<endpoint::EndpointEvent>,
 
     pub(crate) semaphore: EndpointSemaphore,
 
     pub(crate) labels: Labels,
+
+    pub(crate) description: Option<String>,
+
+    pub(crate) prompt: Option<String>,
+
+    pub(crate) write_latency: WriteLatency,
+
+    pub(crate) created_at: chrono::DateTime<chrono::Utc>,
 }
 
 #[cfg(test)]
@@ -186,6 +312,64 @@ mod tests {
         from
     }
 
+    #[tokio::test]
+    async fn write_terminator_appended_once() {
+        let mock = MockBuilder::new(MockId::new("user", "mock"))
+            .set_write_terminator(b";".to_vec())
+            .build();
+
+        let mut tx = mock.message_sender();
+        let mut rx = mock.events();
+
+        tx.send("unterminated".into()).await.unwrap();
+        assert_eq!(
+            b"unterminated;".to_vec(),
+            rx_wire_to_and_from(&mut rx).await
+        );
+
+        tx.send("terminated;".into()).await.unwrap();
+        assert_eq!(b"terminated;".to_vec(), rx_wire_to_and_from(&mut rx).await);
+    }
+
+    #[tokio::test]
+    async fn write_latency_includes_write_delay() {
+        let delay = Duration::from_millis(50);
+        let mock = MockBuilder::new(MockId::new("user", "mock"))
+            .set_write_delay(delay)
+            .build();
+
+        let mut tx = mock.message_sender();
+        let mut rx = mock.events();
+
+        tx.send("slow".into()).await.unwrap();
+        rx_wire_to_and_from(&mut rx).await;
+
+        let latency = mock.write_latency();
+        assert_eq!(latency.count, 1);
+        assert!(latency.min.unwrap() >= delay);
+    }
+
+    #[tokio::test]
+    async fn rx_flush_drops_buffered_messages() {
+        let mock = MockBuilder::new(MockId::new("user", "mock"))
+            .set_write_delay(Duration::from_millis(200))
+            .build();
+
+        let mut tx = mock.message_sender();
+        let mut rx = mock.events();
+
+        for message in ["stale 1", "stale 2", "stale 3"] {
+            tx.send(message.into()).await.unwrap();
+        }
+
+        // Let the messages be queued behind the write delay
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        mock.flush(false, true).unwrap().await.unwrap().unwrap();
+
+        tx.send("fresh".into()).await.unwrap();
+        assert_eq!(b"fresh".to_vec(), rx_wire_to_and_from(&mut rx).await);
+    }
+
     #[tokio::test]
     async fn loopback() {
         let mock = MockBuilder::new(MockId::new("user", "mock")).build();
@@ -1,14 +1,19 @@
-use async_recursion::async_recursion;
+use std::{
+    collections::HashMap, future::Future, net::SocketAddr, ops::ControlFlow, time::Duration,
+};
+
 use futures::SinkExt;
 use itertools::Itertools;
 use tokio::{
     sync::{broadcast, mpsc, oneshot},
     task::JoinHandle,
+    time::Instant,
 };
-use tracing::{debug, info, info_span, warn, Instrument};
+use tracing::{debug, error, info, info_span, warn, Instrument};
 
 use crate::{
     actions::{self, ResponseResult},
+    config::MacroStep,
     control_center::{
         self, ControlCenterHandle, EndpointController, EndpointControllerQueue, Inform,
     },
@@ -18,14 +23,117 @@ use crate::{
     error::{self, Error},
     events,
     mock::MockId,
-    serial::{SerialMessage, SerialMessageBytes},
+    serial::{Direction, SerialMessage, SerialMessageBytes, TaggedMessage},
     user::User,
+    websocket::SessionId,
 };
 
+type EndpointMessageSender = futures::channel::mpsc::UnboundedSender<SerialMessageBytes>;
+
+/// How long a deduplicating observer holds a run of identical messages
+/// before delivering it, if no other message ends the run first.
+const DEDUP_HOLD: Duration = Duration::from_secs(1);
+
+/// Identical consecutive messages a deduplicating observer has not delivered yet.
+struct Run {
+    direction: Direction,
+    message: SerialMessageBytes,
+    repeats: usize,
+    since: Instant,
+}
+
+impl Run {
+    fn new(direction: Direction, message: SerialMessageBytes) -> Self {
+        Self {
+            direction,
+            message,
+            repeats: 1,
+            since: Instant::now(),
+        }
+    }
+
+    fn into_delivery(self) -> (Direction, SerialMessageBytes, usize) {
+        (self.direction, self.message, self.repeats)
+    }
+}
+
+/// What an endpoint handler waits for next.
+enum Next {
+    Event(endpoint::EndpointEvent),
+    Lagged(u64),
+    Flush,
+    Closed,
+}
+
+/// Which of the messages an endpoint recently saw a new observation starts with.
+#[derive(Debug, Clone, Copy, Default)]
+enum Replay {
+    #[default]
+    Nothing,
+    Everything,
+    Since(chrono::DateTime<chrono::Utc>),
+}
+
+impl Replay {
+    fn new(replay: bool, since: Option<chrono::DateTime<chrono::Utc>>) -> Self {
+        match (replay, since) {
+            (_, Some(since)) => Self::Since(since),
+            (true, None) => Self::Everything,
+            (false, None) => Self::Nothing,
+        }
+    }
+
+    fn select(self, recent: Vec<control_center::RecentMessage>) -> Vec<endpoint::EndpointEvent> {
+        recent
+            .into_iter()
+            .filter(|(seen_at, _)| match self {
+                Self::Nothing => false,
+                Self::Everything => true,
+                Self::Since(since) => *seen_at > since,
+            })
+            .map(|(_, event)| event)
+            .collect()
+    }
+}
+
+/// How to observe an endpoint we wait for, once it appears.
+#[derive(Debug, Default)]
+struct DeferredObservation {
+    include_writes: bool,
+    limit: Option<usize>,
+    dedup: bool,
+    replay: Replay,
+
+    // Whether endpoint ids were compacted when the observation was asked for
+    compact: bool,
+}
+
+/// How the user connected, see [`actions::Action::WhoAmI`].
+#[derive(Debug, Clone)]
+pub(crate) struct Connection {
+    session: SessionId,
+    remote: Option<SocketAddr>,
+    connected_at: chrono::DateTime<chrono::Utc>,
+}
+
+impl Connection {
+    /// A connection made just now.
+    pub(crate) fn new(session: SessionId, remote: Option<SocketAddr>) -> Self {
+        Self {
+            session,
+            remote,
+            connected_at: chrono::Utc::now(),
+        }
+    }
+}
+
 pub(crate) struct Peer {
     // Which user does this peer represent
     user: User,
 
+    // How the user connected
+    connection: Connection,
+
     // Where to put responses to this peer's requests
     sender: mpsc::UnboundedSender<ResponseResult>,
 
@@ -41,42 +149,201 @@ pub(crate) struct Peer {
     // The handle to the control center,
     // which holds global state.
     cc_handle: ControlCenterHandle,
+
+    // Whether the user opted out of on control macros
+    skip_on_control_macros: bool,
+
+    // Whether messages of endpoints observed from now on refer to them by handle
+    compact_endpoint_ids: bool,
+
+    // The handles assigned to endpoints, which are never reused
+    endpoint_handles: HashMap<InternalEndpointId, actions::EndpointHandle>,
+
+    // Whether the server only allows observing
+    read_only: bool,
+
+    // How to observe endpoints we wait for, once they appear
+    deferred_observations: HashMap<InternalEndpointId, DeferredObservation>,
+
+    // Labels we wait for some endpoint to match before controlling it,
+    // and the timers giving up on waiting
+    deferred_controls: HashMap<Labels, JoinHandle<()>>,
+
+    // The tasks sending the user messages of each observed endpoint
+    endpoint_handlers: HashMap<InternalEndpointId, (InternalEndpointInfo, JoinHandle<()>)>,
+
+    // Endpoint handlers waiting for the user to be told about the observation,
+    // such that no message arrives before that
+    handlers_to_start: Vec<oneshot::Sender<()>>,
+
+    // Tasks waiting in a control queue on behalf of the user
+    queue_waiters: Vec<JoinHandle<()>>,
+
+    // Bumped when the user cancels its queues,
+    // such that controllers from queues waited in before that are not granted
+    queue_epoch: usize,
 }
 
 // TODO: Close this gracefully?
+#[allow(clippy::too_many_arguments)]
 async fn endpoint_handler(
     user: User,
     cc_handle: ControlCenterHandle,
     info: InternalEndpointInfo,
     mut endpoint_events: broadcast::Receiver<endpoint::EndpointEvent>,
     user_sender: mpsc::UnboundedSender<ResponseResult>,
+    peer_requests: mpsc::UnboundedSender<PeerRequest>,
+    include_writes: bool,
+    limit: Option<usize>,
+    dedup: bool,
+    handle: Option<actions::EndpointHandle>,
+    replay: Vec<endpoint::EndpointEvent>,
+    start: oneshot::Receiver<()>,
 ) {
+    if start.await.is_err() {
+        debug!("Observation was never confirmed");
+        return;
+    }
+
     info!("Starting handler for {user}+{info}");
 
-    while let Ok(event) = endpoint_events.recv().await {
-        let Some(message) = event.as_from_wire() else { continue };
+    let mut delivered = 0;
+    let mut replay = replay.into_iter();
 
-        if user_sender
-            .send(Ok(actions::Response::message(
+    // Only used when deduplicating.
+    let mut run: Option<Run> = None;
+
+    loop {
+        let next = match replay.next() {
+            Some(event) => Next::Event(event),
+            None => {
+                let held = async {
+                    match &run {
+                        Some(run) => tokio::time::sleep_until(run.since + DEDUP_HOLD).await,
+                        None => std::future::pending().await,
+                    }
+                };
+
+                tokio::select! {
+                    event = endpoint_events.recv() => match event {
+                        Ok(event) => Next::Event(event),
+                        Err(broadcast::error::RecvError::Lagged(skipped)) => Next::Lagged(skipped),
+                        Err(broadcast::error::RecvError::Closed) => Next::Closed,
+                    },
+                    _ = held => Next::Flush,
+                }
+            }
+        };
+
+        let (delivery, closed) = match next {
+            Next::Event(event) => {
+                let (direction, message) = match event {
+                    endpoint::EndpointEvent::FromWire(message) => (Direction::FromWire, message),
+                    endpoint::EndpointEvent::ToWire(message) if include_writes => {
+                        (Direction::ToWire, message)
+                    }
+                    endpoint::EndpointEvent::ToWire(_) => continue,
+                };
+
+                if !dedup {
+                    (Some((direction, message, 1)), false)
+                } else if let Some(run) = run
+                    .as_mut()
+                    .filter(|run| run.direction == direction && run.message == message)
+                {
+                    run.repeats += 1;
+                    continue;
+                } else {
+                    // A different message ends the run.
+                    (
+                        run.replace(Run::new(direction, message))
+                            .map(Run::into_delivery),
+                        false,
+                    )
+                }
+            }
+            Next::Lagged(skipped) => {
+                // Let the user know they missed something, the endpoint is still there.
+                warn!(%skipped, "Endpoint handler lagged");
+                let skipped = actions::Response::messages_skipped(info.clone().into(), skipped);
+                if user_sender.send(Ok(skipped)).is_err() {
+                    debug!("Send error");
+                    break;
+                }
+                continue;
+            }
+            Next::Flush => (run.take().map(Run::into_delivery), false),
+            Next::Closed => (run.take().map(Run::into_delivery), true),
+        };
+
+        let Some((direction, message, repeats)) = delivery else {
+            if closed {
+                tell_closed(&peer_requests, &info, actions::CloseReason::Unavailable);
+                break;
+            }
+            continue;
+        };
+
+        let response = if let (1, Some(handle)) = (repeats, handle) {
+            actions::Response::compact_message(handle, direction, message.clone())
+        } else if repeats == 1 {
+            actions::Response::message(info.clone().into(), direction, message.clone())
+        } else {
+            actions::Response::repeated_message(
                 info.clone().into(),
+                direction,
                 message.clone(),
-            )))
-            .is_err()
-        {
+                repeats,
+            )
+        };
+
+        if user_sender.send(Ok(response)).is_err() {
             debug!("Send error");
             break;
         }
 
-        cc_handle.inform(Inform::UserReceivedMessage((
-            user.clone(),
-            info.clone(),
-            SerialMessage::new_lossy(message),
-        )))
+        // Writes are already told about by the user doing them.
+        if direction == Direction::FromWire {
+            cc_handle.inform(Inform::UserMessage((
+                user.clone(),
+                TaggedMessage::new(info.clone(), direction, message),
+            )))
+        }
+
+        delivered += 1;
+        if limit == Some(delivered) {
+            debug!(%delivered, "Observe limit reached");
+            cc_handle.inform(Inform::ObserveLimitReached((user.clone(), info.clone())));
+            tell_closed(&peer_requests, &info, actions::CloseReason::LimitReached);
+            break;
+        }
+
+        if closed {
+            tell_closed(&peer_requests, &info, actions::CloseReason::Unavailable);
+            break;
+        }
     }
 
     info!("Endpoint {info} closed")
 }
 
+/// Have the peer tell the user no more messages follow from the endpoint.
+fn tell_closed(
+    peer_requests: &mpsc::UnboundedSender<PeerRequest>,
+    info: &InternalEndpointInfo,
+    reason: actions::CloseReason,
+) {
+    if peer_requests
+        .send(PeerRequest::InternalAction(PeerAction::EndpointClosed((
+            info.clone(),
+            reason,
+        ))))
+        .is_err()
+    {
+        debug!("Peer gone before the endpoint closed");
+    }
+}
+
 // TODO: Close this gracefully?
 async fn event_handler(
     mut events: broadcast::Receiver<events::TimestampedEvent>,
@@ -84,7 +351,17 @@ async fn event_handler(
 ) {
     info!("Starting user event handler");
 
-    while let Ok(event) = events.recv().await {
+    loop {
+        let event = match events.recv().await {
+            Ok(event) => event,
+            Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                // Let the user know they missed something instead of silently carrying on.
+                warn!(%skipped, "User event handler lagged");
+                events::TimestampedEvent::new_general_event(events::general::Event::Lagged(skipped))
+            }
+            Err(broadcast::error::RecvError::Closed) => break,
+        };
+
         if user_sender
             .send(Ok(actions::Response::Async(actions::Async::Event(event))))
             .is_err()
@@ -97,14 +374,119 @@ async fn event_handler(
     debug!("User event handler closed")
 }
 
+async fn endpoint_events_handler(
+    endpoint: LabelledEndpointId,
+    mut events: broadcast::Receiver<events::TimestampedEvent>,
+    user_sender: mpsc::UnboundedSender<ResponseResult>,
+) {
+    info!("Starting endpoint event handler for {endpoint}");
+
+    loop {
+        let event = match events.recv().await {
+            Ok(event) => match events::endpoint::TimestampedEndpointEvent::from_event(&event) {
+                Some(event) if event.endpoint == endpoint => event,
+                _ => continue,
+            },
+            Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                // Let the user know they missed something instead of silently carrying on.
+                // There is no telling how many of the skipped events were of this endpoint.
+                warn!(%skipped, "Endpoint event handler lagged");
+                events::endpoint::TimestampedEndpointEvent {
+                    endpoint: endpoint.clone(),
+                    event: events::endpoint::Event::Lagged(skipped),
+                    timestamp: chrono::Utc::now(),
+                }
+            }
+            Err(broadcast::error::RecvError::Closed) => break,
+        };
+
+        if user_sender
+            .send(Ok(actions::Response::endpoint_event(event)))
+            .is_err()
+        {
+            debug!("Send error");
+            break;
+        }
+    }
+
+    debug!("Endpoint event handler for {endpoint} closed")
+}
+
+async fn messages_handler(
+    endpoints: Vec<LabelledEndpointId>,
+    mut events: broadcast::Receiver<events::TimestampedEvent>,
+    user_sender: mpsc::UnboundedSender<ResponseResult>,
+) {
+    info!(
+        "Starting messages handler for {} endpoint(s)",
+        endpoints.len()
+    );
+
+    loop {
+        let response = match events.recv().await {
+            Ok(event) => match events::message::TimestampedMessage::from_event(&event) {
+                Some(message) if endpoints.contains(&message.endpoint) => {
+                    actions::Response::traffic(message)
+                }
+                _ => continue,
+            },
+            Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                // Let the user know they missed something instead of silently carrying on.
+                // The skipped messages may be of any of the endpoints, so the user is told as for events.
+                warn!(%skipped, "Messages handler lagged");
+                actions::Response::Async(actions::Async::Event(
+                    events::TimestampedEvent::new_general_event(events::general::Event::Lagged(
+                        skipped,
+                    )),
+                ))
+            }
+            Err(broadcast::error::RecvError::Closed) => break,
+        };
+
+        if user_sender.send(Ok(response)).is_err() {
+            debug!("Send error");
+            break;
+        }
+    }
+
+    debug!("Messages handler closed")
+}
+
 #[derive(Debug)]
 pub(crate) enum PeerAction {
     /// An outbox we're waiting for is now ready.
     ControllerReady {
         context: control_center::ControlContext,
         controller: EndpointController,
+
+        /// The queue epoch it was waited for in, `None` if control was transferred to us.
+        queue_epoch: Option<usize>,
     },
 
+    /// An observed endpoint will send no more messages, and why.
+    EndpointClosed((InternalEndpointInfo, actions::CloseReason)),
+
+    /// An endpoint the user deferred observing now exists, and is observed.
+    DeferredObserverReady(
+        (
+            InternalEndpointInfo,
+            broadcast::Receiver<endpoint::EndpointEvent>,
+            Vec<control_center::RecentMessage>,
+        ),
+    ),
+
+    /// An endpoint matching the labels the user deferred controlling now exists.
+    DeferredControlReady(Labels),
+
+    /// No endpoint matching the labels the user deferred controlling appeared in time.
+    DeferredControlTimedOut(Labels),
+
+    /// An admin sent a notice about an endpoint the user observes.
+    Notice((InternalEndpointInfo, String)),
+
+    /// An admin disabled an endpoint the user controls, releasing it.
+    ControlRevoked(InternalEndpointInfo),
+
     /// Shut down the peer, cleaning up as necessary.
     Shutdown,
 }
@@ -117,31 +499,74 @@ pub(crate) enum PeerRequest {
 
 pub(crate) struct PeerHandle {
     pub(crate) requests: mpsc::UnboundedSender<PeerRequest>,
-    pub(crate) join_handle: JoinHandle<()>,
+
+    // The supervisor of the peer task, failing if the peer panicked
+    pub(crate) join_handle: JoinHandle<Result<(), Error>>,
+}
+
+/// What the user is told if their peer panicked.
+fn peer_panicked() -> Error {
+    Error::InternalIssue(
+        "The server ran into a problem handling this connection and is closing it".into(),
+    )
 }
 
 impl PeerHandle {
     pub(crate) fn new(
         user: User,
+        connection: Connection,
         sender: mpsc::UnboundedSender<ResponseResult>,
         cc_handle: ControlCenterHandle,
+        read_only: bool,
     ) -> Self {
         let (peer_requests_sender, peer_requests_receiver) = mpsc::unbounded_channel();
 
         let mut peer = Peer::new(
             user,
-            sender,
+            sender.clone(),
             peer_requests_sender.clone(),
             peer_requests_receiver,
             cc_handle,
         );
+        peer.connection = connection;
+        peer.read_only = read_only;
 
-        let peer_handle =
-            tokio::spawn(async move { peer.run().await }.instrument(info_span!("Peer")));
+        Self::supervised(
+            peer_requests_sender,
+            sender,
+            async move { peer.run().await },
+        )
+    }
+
+    /// Spawn the peer, and a supervisor telling the user if the peer panics.
+    /// Otherwise the user would wait forever for a response.
+    fn supervised<F>(
+        requests: mpsc::UnboundedSender<PeerRequest>,
+        sender: mpsc::UnboundedSender<ResponseResult>,
+        peer: F,
+    ) -> Self
+    where
+        F: Future<Output = ()> + Send + 'static,
+    {
+        let peer = tokio::spawn(peer.instrument(info_span!("Peer")));
+
+        let join_handle = tokio::spawn(
+            async move {
+                let Err(e) = peer.await else {
+                    return Ok(());
+                };
+                error!("Peer task failed: {e:?}");
+
+                // The user may be gone already.
+                let _ = sender.send(Err(peer_panicked()));
+                Err(peer_panicked())
+            }
+            .instrument(info_span!("Peer supervisor")),
+        );
 
         Self {
-            requests: peer_requests_sender,
-            join_handle: peer_handle,
+            requests,
+            join_handle,
         }
     }
 
@@ -156,15 +581,27 @@ impl PeerHandle {
         self.send_peer_request(PeerRequest::UserAction(request));
     }
 
-    pub(crate) async fn shutdown(self) {
+    /// Resolves when the peer no longer takes requests, e.g. because it panicked.
+    pub(crate) async fn closed(&self) {
+        self.requests.closed().await
+    }
+
+    /// Shut the peer down.
+    /// Fails if the peer panicked, in which case the user has been told.
+    pub(crate) async fn shutdown(self) -> Result<(), Error> {
         debug!("Shutting down");
         self.send_peer_request(PeerRequest::InternalAction(PeerAction::Shutdown));
 
-        if let Err(e) = self.join_handle.await {
-            warn!("Sent request to peer for shutting down, but it seems to have panicked: {e:?}")
-        }
+        let result = match self.join_handle.await {
+            Ok(result) => result,
+            Err(e) => {
+                warn!("Sent request to peer for shutting down, but its supervisor failed: {e:?}");
+                Err(peer_panicked())
+            }
+        };
 
         debug!("Shutdown complete");
+        result
     }
 }
 
@@ -178,26 +615,58 @@ impl Peer {
     ) -> Self {
         Self {
             user,
+            connection: Connection::new(SessionId::next(), None),
             sender,
             controllers: vec![],
             cc_handle,
             peer_requests_receiver,
             peer_requests_sender,
+            skip_on_control_macros: false,
+            compact_endpoint_ids: false,
+            endpoint_handles: HashMap::new(),
+            read_only: false,
+            deferred_observations: HashMap::new(),
+            deferred_controls: HashMap::new(),
+            endpoint_handlers: HashMap::new(),
+            handlers_to_start: vec![],
+            queue_waiters: vec![],
+            queue_epoch: 0,
+        }
+    }
+
+    /// Send the user a response.
+    /// Breaks if the user can no longer be reached and the peer should stop.
+    fn respond(&self, response: ResponseResult) -> ControlFlow<()> {
+        if self.sender.send(response).is_ok() {
+            return ControlFlow::Continue(());
         }
+
+        // Let go of what the user held right away,
+        // instead of waiting for the connection to be found closed.
+        warn!("The user can no longer be responded to, leaving right away");
+        self.cc_handle
+            .inform(control_center::Inform::UserLeft(self.user.clone()));
+        ControlFlow::Break(())
     }
 
     async fn run(&mut self) {
         self.cc_handle
             .inform(control_center::Inform::UserArrived(self.user.clone()));
+        self.cc_handle
+            .inform(control_center::Inform::UserReachable {
+                user: self.user.clone(),
+                requests: self.peer_requests_sender.clone(),
+            });
 
         while let Some(peer_request) = self.peer_requests_receiver.recv().await {
             match peer_request {
                 PeerRequest::UserAction(action) => {
                     let span = info_span!("Action", %action);
                     let response = self.do_user_action(action).instrument(span).await;
-                    self.sender
-                        .send(response)
-                        .expect("If we're alive it means the websocket connection should be up");
+                    if self.respond(response).is_break() {
+                        break;
+                    }
+                    self.start_handlers();
                 }
                 PeerRequest::InternalAction(PeerAction::Shutdown) => {
                     debug!("Shutting down peer");
@@ -206,42 +675,384 @@ impl Peer {
 
                     break;
                 }
+                PeerRequest::InternalAction(PeerAction::EndpointClosed((info, reason))) => {
+                    // Both the control center and the endpoint handler may tell,
+                    // but the user is only told once.
+                    let Some((_, handler)) = self.endpoint_handlers.remove(&info.id) else {
+                        debug!(%info, %reason, "Endpoint closed, but no longer observed");
+                        continue;
+                    };
+                    handler.abort();
+
+                    debug!(%info, %reason, "Observed endpoint closed");
+
+                    if self
+                        .respond(Ok(actions::Response::endpoint_closed(info.into(), reason)))
+                        .is_break()
+                    {
+                        break;
+                    }
+                }
+                PeerRequest::InternalAction(PeerAction::DeferredObserverReady((
+                    info,
+                    endpoint,
+                    recent,
+                ))) => {
+                    debug!(%info, "Deferred observation ready");
+
+                    let deferred = self
+                        .deferred_observations
+                        .remove(&info.id)
+                        .unwrap_or_default();
+                    self.spawn_endpoint_handler(
+                        info.clone(),
+                        endpoint,
+                        deferred.include_writes,
+                        deferred.limit,
+                        deferred.dedup,
+                        deferred.compact,
+                        deferred.replay.select(recent),
+                    );
+
+                    if self
+                        .respond(Ok(actions::Response::observing(info.into())))
+                        .is_break()
+                    {
+                        break;
+                    }
+                    self.start_handlers();
+                }
+                PeerRequest::InternalAction(PeerAction::Notice((info, text))) => {
+                    // The observation may have ended since the notice was sent.
+                    if !self.endpoint_handlers.contains_key(&info.id) {
+                        debug!(%info, "Notice of an endpoint no longer observed");
+                        continue;
+                    }
+
+                    if self
+                        .respond(Ok(actions::Response::notice(info.into(), text)))
+                        .is_break()
+                    {
+                        break;
+                    }
+                }
+                PeerRequest::InternalAction(PeerAction::ControlRevoked(info)) => {
+                    // The user may have released it since it was disabled.
+                    let Some((info, _)) = self.controlled_endpoint_sender(&info.id) else {
+                        debug!(%info, "Control revoked, but no longer controlled");
+                        continue;
+                    };
+
+                    let response = self
+                        .give_up_control(info.clone())
+                        .await
+                        .map(actions::Response::control_revoked);
+                    info!(%info, "Control revoked");
+
+                    if self.respond(response).is_break() {
+                        break;
+                    }
+                }
+                PeerRequest::InternalAction(PeerAction::DeferredControlReady(labels)) => {
+                    let Some(timer) = self.deferred_controls.remove(&labels) else {
+                        debug!(%labels, "Deferred control ready, but no longer waited for");
+                        continue;
+                    };
+                    timer.abort();
+
+                    debug!(%labels, "Deferred control ready");
+
+                    let response = self.control_any(labels).await;
+                    if self.respond(response).is_break() {
+                        break;
+                    }
+                }
+                PeerRequest::InternalAction(PeerAction::DeferredControlTimedOut(labels)) => {
+                    if self.deferred_controls.remove(&labels).is_none() {
+                        continue;
+                    }
+
+                    debug!(%labels, "Deferred control timed out");
+                    self.cc_handle
+                        .inform(control_center::Inform::DeferredControlGaveUp((
+                            self.user.clone(),
+                            labels.clone(),
+                        )));
+
+                    if self
+                        .respond(Err(Error::NoMatchingEndpoints(labels)))
+                        .is_break()
+                    {
+                        break;
+                    }
+                }
                 PeerRequest::InternalAction(PeerAction::ControllerReady {
                     controller,
                     context,
+                    queue_epoch,
                 }) => {
-                    let granted_ids = self.add_endpoint_controller(controller, context);
+                    if matches!(queue_epoch, Some(epoch) if epoch != self.queue_epoch) {
+                        // The user stopped waiting before we got it, so give it up right away.
+                        debug!(%context, "Controller from a cancelled queue");
+                        continue;
+                    }
 
-                    self.sender
-                        .send(Ok(actions::Response::control_granted(granted_ids)))
-                        .expect("If we're alive it means the websocket connection should be up")
+                    let granted_ids = self.add_endpoint_controller(controller, context).await;
+
+                    if self
+                        .respond(Ok(actions::Response::control_granted(granted_ids)))
+                        .is_break()
+                    {
+                        break;
+                    }
                 }
             }
         }
     }
 
-    async fn observe(&mut self, id: InternalEndpointId) -> ResponseResult {
+    #[allow(clippy::too_many_arguments)]
+    async fn observe(
+        &mut self,
+        id: InternalEndpointId,
+        include_writes: bool,
+        idempotent: bool,
+        limit: Option<usize>,
+        deferred: bool,
+        replay: Replay,
+        dedup: bool,
+    ) -> ResponseResult {
+        if limit == Some(0) {
+            return Err(Error::BadUsage(
+                "An observe limit must allow at least one message".into(),
+            ));
+        }
+
+        let action = if deferred {
+            control_center::Action::ObserveDeferred { id, idempotent }
+        } else if idempotent {
+            control_center::Action::ObserveIdempotent(id)
+        } else {
+            control_center::Action::Observe(id)
+        };
+
+        self.start_observing(action, include_writes, limit, replay, dedup)
+            .await
+    }
+
+    async fn observe_weak(&mut self, id: InternalEndpointId) -> ResponseResult {
+        self.start_observing(
+            control_center::Action::ObserveWeak(id),
+            false,
+            None,
+            Replay::Nothing,
+            false,
+        )
+        .await
+    }
+
+    async fn observe_exclusive(&mut self, id: InternalEndpointId) -> ResponseResult {
+        self.start_observing(
+            control_center::Action::ObserveExclusive(id),
+            false,
+            None,
+            Replay::Nothing,
+            false,
+        )
+        .await
+    }
+
+    async fn observe_any(&mut self, labels: Labels) -> ResponseResult {
+        self.start_observing(
+            control_center::Action::ObserveAny(labels),
+            false,
+            None,
+            Replay::Nothing,
+            false,
+        )
+        .await
+    }
+
+    async fn start_observing(
+        &mut self,
+        action: control_center::Action,
+        include_writes: bool,
+        limit: Option<usize>,
+        replay: Replay,
+        dedup: bool,
+    ) -> ResponseResult {
         match self
             .cc_handle
-            .perform_action(self.user.clone(), control_center::Action::Observe(id))
+            .perform_action(self.user.clone(), action)
             .await
         {
-            Ok(control_center::ControlCenterResponse::EndpointObserver((info, endpoint))) => {
-                let span = info_span!("Endpoint Handler", %info);
+            Ok(control_center::ControlCenterResponse::EndpointObserver((
+                info,
+                endpoint,
+                recent,
+            ))) => {
+                let replay = replay.select(recent);
+                self.spawn_endpoint_handler(
+                    info.clone(),
+                    endpoint,
+                    include_writes,
+                    limit,
+                    dedup,
+                    self.compact_endpoint_ids,
+                    replay,
+                );
 
-                tokio::spawn(
-                    endpoint_handler(
-                        self.user.clone(),
-                        self.cc_handle.clone(),
-                        info.clone(),
+                Ok(actions::Response::observing(LabelledEndpointId::from(info)))
+            }
+            Ok(control_center::ControlCenterResponse::CompositeObserver((
+                composite,
+                observers,
+            ))) => {
+                let mut ports = vec![];
+                for (info, endpoint, recent) in observers {
+                    let replay = replay.select(recent);
+                    ports.push(LabelledEndpointId::from(info.clone()));
+                    self.spawn_endpoint_handler(
+                        info,
                         endpoint,
-                        self.sender.clone(),
-                    )
-                    .instrument(span),
-                );
+                        include_writes,
+                        limit,
+                        dedup,
+                        self.compact_endpoint_ids,
+                        replay,
+                    );
+                }
+
+                Ok(actions::Response::observing_composite(
+                    LabelledEndpointId::from(composite),
+                    ports,
+                ))
+            }
+            Ok(control_center::ControlCenterResponse::AnyObserver((labels, observers))) => {
+                let mut endpoints = vec![];
+                for (info, endpoint, recent) in observers {
+                    let replay = replay.select(recent);
+                    endpoints.push(LabelledEndpointId::from(info.clone()));
+                    self.spawn_endpoint_handler(
+                        info,
+                        endpoint,
+                        include_writes,
+                        limit,
+                        dedup,
+                        self.compact_endpoint_ids,
+                        replay,
+                    );
+                }
 
+                Ok(actions::Response::observing_any(labels, endpoints))
+            }
+            Ok(control_center::ControlCenterResponse::AlreadyObserving(info)) => {
+                debug!(%info, "Already observing");
                 Ok(actions::Response::observing(LabelledEndpointId::from(info)))
             }
+            Ok(control_center::ControlCenterResponse::ObservationDeferred(id)) => {
+                debug!(%id, "Observation deferred");
+                self.deferred_observations.insert(
+                    id.clone(),
+                    DeferredObservation {
+                        include_writes,
+                        limit,
+                        dedup,
+                        replay,
+                        compact: self.compact_endpoint_ids,
+                    },
+                );
+                Ok(actions::Response::observation_deferred(id.into()))
+            }
+            Ok(_) => {
+                unreachable!()
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    fn spawn_endpoint_handler(
+        &mut self,
+        info: InternalEndpointInfo,
+        endpoint: broadcast::Receiver<endpoint::EndpointEvent>,
+        include_writes: bool,
+        limit: Option<usize>,
+        dedup: bool,
+        compact: bool,
+        replay: Vec<endpoint::EndpointEvent>,
+    ) {
+        let span = info_span!("Endpoint Handler", %info);
+
+        let (start_tx, start_rx) = oneshot::channel();
+        self.handlers_to_start.push(start_tx);
+
+        let handle = compact.then(|| self.endpoint_handle(&info));
+
+        let observed = info.clone();
+        let handler = tokio::spawn(
+            endpoint_handler(
+                self.user.clone(),
+                self.cc_handle.clone(),
+                info,
+                endpoint,
+                self.sender.clone(),
+                self.peer_requests_sender.clone(),
+                include_writes,
+                limit,
+                dedup,
+                handle,
+                replay,
+                start_rx,
+            )
+            .instrument(span),
+        );
+        self.endpoint_handlers
+            .insert(observed.id.clone(), (observed, handler));
+    }
+
+    // The handle of the endpoint, assigning one and telling the user about it if there is none yet.
+    fn endpoint_handle(&mut self, info: &InternalEndpointInfo) -> actions::EndpointHandle {
+        if let Some(handle) = self.endpoint_handles.get(&info.id) {
+            return *handle;
+        }
+
+        let handle = actions::EndpointHandle(self.endpoint_handles.len() as u32);
+        self.endpoint_handles.insert(info.id.clone(), handle);
+
+        // Sent ahead of the observing response, so before any message refers to the handle.
+        if self
+            .sender
+            .send(Ok(actions::Response::endpoint_handle(
+                handle,
+                info.clone().into(),
+            )))
+            .is_err()
+        {
+            debug!(%handle, "Could not tell the user about the handle");
+        }
+
+        handle
+    }
+
+    async fn unobserve(&mut self, endpoint: EndpointId) -> ResponseResult {
+        let id = self.id_to_internal(endpoint.clone());
+
+        // Nothing more is sent of the endpoint after the user is told it is not observed.
+        if let Some((_, handler)) = self.endpoint_handlers.remove(&id) {
+            handler.abort();
+        }
+        self.deferred_observations.remove(&id);
+
+        match self
+            .cc_handle
+            .perform_action(self.user.clone(), control_center::Action::Unobserve(id))
+            .await
+        {
+            Ok(control_center::ControlCenterResponse::Unobserved(info)) => {
+                Ok(actions::Response::unobserved(
+                    info.map(Into::into)
+                        .unwrap_or_else(|| LabelledEndpointId::new(&endpoint)),
+                ))
+            }
             Ok(_) => {
                 unreachable!()
             }
@@ -249,15 +1060,24 @@ impl Peer {
         }
     }
 
+    // Let handlers send messages, now that the user knows what they observe.
+    fn start_handlers(&mut self) {
+        for start in self.handlers_to_start.drain(..) {
+            // The handler may have gone already.
+            let _ = start.send(());
+        }
+    }
+
     fn mock_id(&self, mock: &str) -> MockId {
         MockId::new(&self.user.name, mock)
     }
 
-    fn add_endpoint_controller(
+    // Hold on to the controller and let the control center know, without running any macros.
+    fn keep_endpoint_controller(
         &mut self,
         controller: EndpointController,
         mut context: control_center::ControlContext,
-    ) -> Vec<LabelledEndpointId> {
+    ) -> Vec<InternalEndpointInfo> {
         let infos = controller.endpoints.keys().cloned().collect_vec();
         context.got_control = Some(infos.clone());
 
@@ -268,21 +1088,42 @@ impl Peer {
             });
 
         self.controllers.push(controller);
-        // Log them in their internal representation
-        info!(?infos, "Control granted");
+        infos
+    }
+
+    async fn add_endpoint_controller(
+        &mut self,
+        controller: EndpointController,
+        context: control_center::ControlContext,
+    ) -> Vec<LabelledEndpointId> {
+        let infos = self.keep_endpoint_controller(controller, context);
+        // Log them in their internal representation
+        info!(?infos, "Control granted");
+
+        if !self.skip_on_control_macros {
+            for info in &infos {
+                if let Err(e) = self.run_on_control_macros(info).await {
+                    warn!(%info, ?e, "Could not run on control macros");
+                }
+            }
+        }
 
         // Now convert to the user/external representation
         infos.into_iter().map(Into::into).collect()
     }
 
     fn spawn_endpoint_controller_queue_waiter(
-        &self,
+        &mut self,
         queue: oneshot::Receiver<EndpointController>,
         context: control_center::ControlContext,
     ) {
         let peer_reply_sender = self.peer_requests_sender.clone();
+        let queue_epoch = Some(self.queue_epoch);
+
+        // Forget about the waiters which are done.
+        self.queue_waiters.retain(|waiter| !waiter.is_finished());
 
-        tokio::spawn(
+        let waiter = tokio::spawn(
             async move {
                 match queue.await {
                     Ok(controller) => {
@@ -291,6 +1132,7 @@ impl Peer {
                             .send(PeerRequest::InternalAction(PeerAction::ControllerReady {
                                 controller,
                                 context,
+                                queue_epoch,
                             }))
                             .is_err()
                         {
@@ -304,6 +1146,7 @@ impl Peer {
             }
             .in_current_span(), // Same as peer, which shows user
         );
+        self.queue_waiters.push(waiter);
     }
 
     async fn handle_control_response(
@@ -317,17 +1160,18 @@ impl Peer {
 
         match inner {
             control_center::AvailableOrBusyEndpointController::Available(controller) => {
-                let granted_ids = self.add_endpoint_controller(controller, context);
+                let granted_ids = self.add_endpoint_controller(controller, context).await;
                 Ok(actions::Response::control_granted(granted_ids))
             }
             control_center::AvailableOrBusyEndpointController::Busy(EndpointControllerQueue {
                 inner: queue,
-                endpoints,
+                queued_on,
+                not_queued_on,
+                ..
             }) => {
                 self.spawn_endpoint_controller_queue_waiter(queue, context);
 
-                let endpoints = endpoints.into_iter().map(Into::into).collect();
-                Ok(actions::Response::control_queue(endpoints))
+                Ok(actions::Response::control_queue(queued_on, not_queued_on))
             }
         }
     }
@@ -353,40 +1197,640 @@ impl Peer {
         self.handle_control_response(response).await
     }
 
+    async fn control_any_when_available(
+        &mut self,
+        labels: Labels,
+        timeout: Duration,
+    ) -> ResponseResult {
+        let response = self
+            .cc_handle
+            .perform_action(
+                self.user.clone(),
+                control_center::Action::ControlAnyDeferred(labels),
+            )
+            .await;
+
+        match response {
+            Ok(control_center::ControlCenterResponse::ControlDeferred(labels)) => {
+                debug!(%labels, ?timeout, "Control deferred");
+
+                let peer_requests = self.peer_requests_sender.clone();
+                let timed_out = labels.clone();
+                let timer = tokio::spawn(async move {
+                    tokio::time::sleep(timeout).await;
+
+                    // The user may have left in the meantime.
+                    let _ = peer_requests.send(PeerRequest::InternalAction(
+                        PeerAction::DeferredControlTimedOut(timed_out),
+                    ));
+                });
+                self.deferred_controls.insert(labels.clone(), timer);
+
+                Ok(actions::Response::control_deferred(labels))
+            }
+            response => self.handle_control_response(response).await,
+        }
+    }
+
     fn id_to_internal(&self, endpoint: EndpointId) -> InternalEndpointId {
         match endpoint {
             EndpointId::Tty(tty) => InternalEndpointId::Tty(tty),
             EndpointId::Mock(mock) => InternalEndpointId::Mock(self.mock_id(&mock)),
+            EndpointId::Pty(name) => InternalEndpointId::Pty(name),
+            EndpointId::Composite(name) => InternalEndpointId::Composite(name),
         }
     }
 
-    async fn write(&mut self, endpoint: EndpointId, message: SerialMessageBytes) -> ResponseResult {
+    /// The info and message sender of the given endpoint if this peer controls it.
+    fn controlled_endpoint_sender(
+        &self,
+        id: &InternalEndpointId,
+    ) -> Option<(InternalEndpointInfo, EndpointMessageSender)> {
+        self.controllers
+            .iter()
+            .flat_map(|controller| controller.endpoints.iter())
+            .find(|(info, _)| &info.id == id)
+            .map(|(info, sender)| (info.clone(), sender.clone()))
+    }
+
+    async fn put_on_wire(
+        &mut self,
+        info: InternalEndpointInfo,
+        sender: &mut EndpointMessageSender,
+        message: SerialMessageBytes,
+    ) -> Result<(), Error> {
+        if let Err(e) = sender.send(message.clone()).await {
+            warn!(?e, %info, "Write to closed endpoint dropped");
+
+            self.cc_handle.inform(control_center::Inform::WriteDropped((
+                self.user.clone(),
+                TaggedMessage::new(info.clone(), Direction::ToWire, message),
+            )));
+
+            return Err(Error::EndpointClosed {
+                endpoint: info.id.into(),
+            });
+        }
+
+        self.cc_handle.inform(control_center::Inform::UserMessage((
+            self.user.clone(),
+            TaggedMessage::new(info, Direction::ToWire, message),
+        )));
+
+        Ok(())
+    }
+
+    // The endpoint to write to, if the user may write to it.
+    fn writable_endpoint_sender(
+        &self,
+        endpoint: EndpointId,
+    ) -> Result<(InternalEndpointInfo, EndpointMessageSender), Error> {
+        if endpoint.as_composite().is_some() {
+            return Err(Error::BadUsage(format!(
+                "`{endpoint}` is a composite, write to one of its ports instead"
+            )));
+        }
+
+        let id = self.id_to_internal(endpoint.clone());
+
+        self.controlled_endpoint_sender(&id)
+            .ok_or(Error::NotControlling { endpoint })
+    }
+
+    async fn write(
+        &mut self,
+        endpoint: EndpointId,
+        message: SerialMessageBytes,
+        correlation_id: Option<String>,
+    ) -> ResponseResult {
+        let (info, mut sender) = self.writable_endpoint_sender(endpoint)?;
+
+        self.put_on_wire(info, &mut sender, message).await?;
+
+        Ok(actions::Response::write_ok(correlation_id))
+    }
+
+    fn can_write(&self, endpoint: EndpointId) -> ResponseResult {
+        let (info, _) = self.writable_endpoint_sender(endpoint)?;
+
+        Ok(actions::Response::write_allowed(info.into()))
+    }
+
+    async fn run_macro_steps(
+        &mut self,
+        info: &InternalEndpointInfo,
+        sender: &mut EndpointMessageSender,
+        steps: Vec<MacroStep>,
+    ) -> Result<(), Error> {
+        for step in steps {
+            match step {
+                MacroStep::Line(line) => {
+                    self.put_on_wire(info.clone(), sender, format!("{line}\n").into_bytes())
+                        .await?
+                }
+                MacroStep::Bytes(bytes) => self.put_on_wire(info.clone(), sender, bytes).await?,
+                MacroStep::DelayMs(ms) => tokio::time::sleep(Duration::from_millis(ms)).await,
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn run_on_control_macros(&mut self, info: &InternalEndpointInfo) -> Result<(), Error> {
+        // A composite has no wire of its own, its ports run their own macros.
+        if let InternalEndpointId::Composite(_) = &info.id {
+            return Ok(());
+        }
+
+        let macros = match self
+            .cc_handle
+            .perform_action(
+                self.user.clone(),
+                control_center::Action::GetOnControlMacros(info.id.clone()),
+            )
+            .await
+        {
+            Ok(control_center::ControlCenterResponse::Macros(macros)) => macros,
+            Ok(_) => {
+                unreachable!()
+            }
+            Err(e) => return Err(e),
+        };
+
+        let (info, mut sender) = self
+            .controlled_endpoint_sender(&info.id)
+            .expect("Control was just granted");
+
+        for on_control in macros {
+            debug!(name = %on_control.name, %info, "Running on control macro");
+            self.run_macro_steps(&info, &mut sender, on_control.steps)
+                .await?;
+        }
+
+        Ok(())
+    }
+
+    fn skip_on_control_macros(&mut self, skip: bool) -> ResponseResult {
+        self.skip_on_control_macros = skip;
+
+        Ok(actions::Response::skipping_on_control_macros(skip))
+    }
+
+    fn compact_endpoint_ids(&mut self, compact: bool) -> ResponseResult {
+        self.compact_endpoint_ids = compact;
+
+        Ok(actions::Response::compacting_endpoint_ids(compact))
+    }
+
+    async fn transfer_control(&mut self, endpoint: EndpointId, to_user: String) -> ResponseResult {
         let user_id = endpoint.clone();
         let id = self.id_to_internal(endpoint);
 
-        let sender = match self
+        let (info, _) = self
+            .controlled_endpoint_sender(&id)
+            .ok_or_else(|| Error::NoPermit(format!("transfer control of {user_id}")))?;
+
+        let (context, requests) = match self
+            .cc_handle
+            .perform_action(
+                self.user.clone(),
+                control_center::Action::TransferControl {
+                    id,
+                    to_user: to_user.clone(),
+                },
+            )
+            .await
+        {
+            Ok(control_center::ControlCenterResponse::TransferTo { context, requests }) => {
+                (context, requests)
+            }
+            Ok(_) => {
+                unreachable!()
+            }
+            Err(e) => return Err(e),
+        };
+
+        let index = self
             .controllers
-            .iter_mut()
-            .flat_map(|controller| controller.endpoints.iter_mut())
-            .find(|(info, _)| info.id == id)
-            .map(|(_, sender)| sender)
+            .iter()
+            .position(|controller| controller.endpoints.contains_key(&info))
+            .expect("Control was checked above");
+        let controller = self.controllers.remove(index);
+
+        if let Err(mpsc::error::SendError(request)) =
+            requests.send(PeerRequest::InternalAction(PeerAction::ControllerReady {
+                controller,
+                context,
+                queue_epoch: None,
+            }))
         {
-            Some(sender) => Ok(sender),
-            None => Err(error::Error::NoPermit(format!("write {user_id}"))),
-        }?;
+            // The other user left in the meantime, so take control back.
+            // This user never lost the endpoints, so on control macros are not run again.
+            warn!(%to_user, "User left before control could be transferred");
+            if let PeerRequest::InternalAction(PeerAction::ControllerReady {
+                controller,
+                context,
+                ..
+            }) = request
+            {
+                self.keep_endpoint_controller(controller, context);
+            }
 
-        sender.send(message.clone()).await.map_err(|e| {
-            Error::InternalIssue(format!("SendError {e:?} to endpoint with id {id}. The endpoint is dead but should be alive."))
-        })?;
+            return Err(Error::NoSuchUser(to_user));
+        }
 
-        self.cc_handle
-            .inform(control_center::Inform::UserSentMessage((
+        info!(%info, %to_user, "Control transferred");
+
+        Ok(actions::Response::control_transferred(info.into(), to_user))
+    }
+
+    async fn release(&mut self, endpoint: EndpointId) -> ResponseResult {
+        let user_id = endpoint.clone();
+        let id = self.id_to_internal(endpoint);
+
+        let (info, _) = self
+            .controlled_endpoint_sender(&id)
+            .ok_or_else(|| Error::NoPermit(format!("release {user_id}")))?;
+
+        let released = self.give_up_control(info.clone()).await?;
+
+        info!(%info, "Control released");
+
+        Ok(actions::Response::released(released))
+    }
+
+    /// Release the controller of the controlled endpoint,
+    /// returning every endpoint released along with it.
+    async fn give_up_control(
+        &mut self,
+        info: InternalEndpointInfo,
+    ) -> Result<Vec<LabelledEndpointId>, Error> {
+        match self
+            .cc_handle
+            .perform_action(
                 self.user.clone(),
-                id,
-                String::from_utf8_lossy(&message).into(),
-            )));
+                control_center::Action::Release(info.id.clone()),
+            )
+            .await
+        {
+            Ok(control_center::ControlCenterResponse::Released) => {}
+            Ok(_) => {
+                unreachable!()
+            }
+            Err(e) => return Err(e),
+        };
+
+        let index = self
+            .controllers
+            .iter()
+            .position(|controller| controller.endpoints.contains_key(&info))
+            .expect("Callers check for control first");
+
+        let controller = self.controllers.remove(index);
+        let released = controller
+            .endpoints
+            .keys()
+            .cloned()
+            .map(Into::into)
+            .collect();
+
+        // Gives up the permit, letting the next in queue have it.
+        drop(controller);
+
+        Ok(released)
+    }
+
+    async fn cancel_queue(&mut self) -> ResponseResult {
+        // Dropping what the waiters wait for lets the queues give up their places.
+        for waiter in self.queue_waiters.drain(..) {
+            waiter.abort();
+        }
+        self.queue_epoch += 1;
+
+        match self
+            .cc_handle
+            .perform_action(self.user.clone(), control_center::Action::CancelQueue)
+            .await
+        {
+            Ok(control_center::ControlCenterResponse::QueueCancelled(infos)) => {
+                info!(?infos, "Queue cancelled");
+                Ok(actions::Response::queue_cancelled(
+                    infos.into_iter().map(Into::into).collect(),
+                ))
+            }
+            Ok(_) => {
+                unreachable!()
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    async fn recent_messages(&mut self, endpoint: EndpointId, count: usize) -> ResponseResult {
+        let id = self.id_to_internal(endpoint);
+
+        match self
+            .cc_handle
+            .perform_action(
+                self.user.clone(),
+                control_center::Action::RecentMessages { id, count },
+            )
+            .await
+        {
+            Ok(control_center::ControlCenterResponse::RecentMessages((info, messages))) => {
+                Ok(actions::Response::recent_messages(
+                    info.into(),
+                    messages.iter().map(SerialMessage::new_lossy).collect(),
+                ))
+            }
+            Ok(_) => {
+                unreachable!()
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    async fn ping_endpoint(
+        &mut self,
+        endpoint: EndpointId,
+        probe: SerialMessageBytes,
+        expect: SerialMessageBytes,
+        timeout: Duration,
+    ) -> ResponseResult {
+        let (info, mut sender) = self.writable_endpoint_sender(endpoint.clone())?;
+
+        // Listen before probing, such that a quick answer is not missed.
+        let mut events = match self
+            .cc_handle
+            .perform_action(
+                self.user.clone(),
+                control_center::Action::Listen(info.id.clone()),
+            )
+            .await
+        {
+            Ok(control_center::ControlCenterResponse::Listener(events)) => events,
+            Ok(_) => {
+                unreachable!()
+            }
+            Err(e) => return Err(e),
+        };
+
+        let probed_at = Instant::now();
+        self.put_on_wire(info.clone(), &mut sender, probe).await?;
+
+        let answer = async {
+            loop {
+                match events.recv().await {
+                    Ok(endpoint::EndpointEvent::FromWire(message)) if message == expect => {
+                        return Ok(())
+                    }
+                    Ok(_) | Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                    Err(broadcast::error::RecvError::Closed) => {
+                        return Err(Error::EndpointClosed {
+                            endpoint: endpoint.clone(),
+                        })
+                    }
+                }
+            }
+        };
+
+        tokio::time::timeout(timeout, answer).await.map_err(|_| {
+            Error::Timeout(format!(
+                "{endpoint} did not answer the ping within {timeout:?}"
+            ))
+        })??;
 
-        Ok(actions::Response::write_ok())
+        Ok(actions::Response::pong(info.into(), probed_at.elapsed()))
+    }
+
+    async fn notice(&mut self, endpoint: EndpointId, text: String) -> ResponseResult {
+        let id = self.id_to_internal(endpoint);
+
+        match self
+            .cc_handle
+            .perform_action(
+                self.user.clone(),
+                control_center::Action::Notice { id, text },
+            )
+            .await
+        {
+            Ok(control_center::ControlCenterResponse::NoticeSent((info, observers))) => {
+                Ok(actions::Response::notice_sent(info.into(), observers))
+            }
+            Ok(_) => {
+                unreachable!()
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    async fn set_endpoint_enabled(
+        &mut self,
+        endpoint: EndpointId,
+        enabled: bool,
+        release_holders: bool,
+    ) -> ResponseResult {
+        let id = self.id_to_internal(endpoint);
+
+        match self
+            .cc_handle
+            .perform_action(
+                self.user.clone(),
+                control_center::Action::SetEnabled {
+                    id,
+                    enabled,
+                    release_holders,
+                },
+            )
+            .await
+        {
+            Ok(control_center::ControlCenterResponse::EnabledSet((info, enabled))) => {
+                Ok(actions::Response::endpoint_enabled(info.into(), enabled))
+            }
+            Ok(_) => {
+                unreachable!()
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    async fn list_labels(&mut self) -> ResponseResult {
+        match self
+            .cc_handle
+            .perform_action(self.user.clone(), control_center::Action::ListLabels)
+            .await
+        {
+            Ok(control_center::ControlCenterResponse::Labels(labels)) => {
+                Ok(actions::Response::labels(labels))
+            }
+            Ok(_) => {
+                unreachable!()
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    fn who_am_i(&self) -> ResponseResult {
+        let sorted = |ids: Vec<LabelledEndpointId>| -> Vec<LabelledEndpointId> {
+            ids.into_iter()
+                .sorted_by_key(|id| id.id.to_string())
+                .collect()
+        };
+
+        Ok(actions::Response::connection(actions::ConnectionInfo {
+            session: self.connection.session.into(),
+            user: self.user.clone(),
+            remote: self.connection.remote.map(|remote| remote.to_string()),
+            connected_at: self.connection.connected_at,
+            observing: sorted(
+                self.endpoint_handlers
+                    .values()
+                    .map(|(info, _)| info.clone().into())
+                    .collect(),
+            ),
+            controlling: sorted(
+                self.controllers
+                    .iter()
+                    .flat_map(|controller| controller.endpoints.keys())
+                    .map(|info| info.clone().into())
+                    .collect(),
+            ),
+        }))
+    }
+
+    async fn list_endpoints(&mut self) -> ResponseResult {
+        match self
+            .cc_handle
+            .perform_action(self.user.clone(), control_center::Action::ListEndpoints)
+            .await
+        {
+            Ok(control_center::ControlCenterResponse::Endpoints(endpoints)) => {
+                Ok(actions::Response::endpoints(endpoints))
+            }
+            Ok(_) => {
+                unreachable!()
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    async fn run_macro(&mut self, endpoint: EndpointId, name: String) -> ResponseResult {
+        let user_id = endpoint.clone();
+        let id = self.id_to_internal(endpoint);
+
+        let (info, mut sender) = self
+            .controlled_endpoint_sender(&id)
+            .ok_or_else(|| Error::NoPermit(format!("run macro {name} on {user_id}")))?;
+
+        let steps = match self
+            .cc_handle
+            .perform_action(
+                self.user.clone(),
+                control_center::Action::GetMacro {
+                    id,
+                    name: name.clone(),
+                },
+            )
+            .await
+        {
+            Ok(control_center::ControlCenterResponse::Macro(found)) => found.steps,
+            Ok(_) => {
+                unreachable!()
+            }
+            Err(e) => return Err(e),
+        };
+
+        debug!(%name, %info, "Running macro");
+        self.run_macro_steps(&info, &mut sender, steps).await?;
+
+        Ok(actions::Response::macro_done(info.into(), name))
+    }
+
+    /// The info of the given endpoint if this peer controls it.
+    fn controlled_endpoint_info(&self, id: &InternalEndpointId) -> Option<InternalEndpointInfo> {
+        self.controllers
+            .iter()
+            .flat_map(|controller| controller.endpoints.keys())
+            .find(|info| &info.id == id)
+            .cloned()
+    }
+
+    async fn reconfigure(
+        &mut self,
+        endpoint: EndpointId,
+        baud: Option<u32>,
+        flow_control: Option<serialport::FlowControl>,
+    ) -> ResponseResult {
+        let user_id = endpoint.clone();
+        let id = self.id_to_internal(endpoint);
+
+        let info = self
+            .controlled_endpoint_info(&id)
+            .ok_or_else(|| Error::NoPermit(format!("reconfigure {user_id}")))?;
+
+        match self
+            .cc_handle
+            .perform_action(
+                self.user.clone(),
+                control_center::Action::Reconfigure {
+                    id,
+                    baud,
+                    flow_control,
+                },
+            )
+            .await
+        {
+            Ok(control_center::ControlCenterResponse::Reconfiguring(done)) => {
+                done.await.map_err(|_| {
+                    Error::InternalIssue(format!(
+                        "The endpoint {user_id} stopped before it was reconfigured"
+                    ))
+                })??;
+
+                Ok(actions::Response::reconfigured(info.into()))
+            }
+            Ok(_) => {
+                unreachable!()
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    async fn flush(&mut self, endpoint: EndpointId, tx: bool, rx: bool) -> ResponseResult {
+        if !tx && !rx {
+            return Err(Error::BadUsage(
+                "A flush must discard at least one of tx and rx".into(),
+            ));
+        }
+
+        let user_id = endpoint.clone();
+        let id = self.id_to_internal(endpoint);
+
+        let info = self
+            .controlled_endpoint_info(&id)
+            .ok_or_else(|| Error::NoPermit(format!("flush {user_id}")))?;
+
+        match self
+            .cc_handle
+            .perform_action(
+                self.user.clone(),
+                control_center::Action::Flush { id, tx, rx },
+            )
+            .await
+        {
+            Ok(control_center::ControlCenterResponse::Flushing(done)) => {
+                done.await.map_err(|_| {
+                    Error::InternalIssue(format!(
+                        "The endpoint {user_id} stopped before it was flushed"
+                    ))
+                })??;
+
+                Ok(actions::Response::flushed(info.into()))
+            }
+            Ok(_) => {
+                unreachable!()
+            }
+            Err(e) => Err(e),
+        }
     }
 
     async fn observe_events(&mut self) -> ResponseResult {
@@ -409,19 +1853,483 @@ impl Peer {
         }
     }
 
-    #[async_recursion]
+    async fn observe_endpoint_events(&mut self, id: InternalEndpointId) -> ResponseResult {
+        match self
+            .cc_handle
+            .perform_action(
+                self.user.clone(),
+                control_center::Action::SubscribeToEndpointEvents(id),
+            )
+            .await
+        {
+            Ok(control_center::ControlCenterResponse::EndpointEventObserver((info, receiver))) => {
+                let endpoint = LabelledEndpointId::from(info);
+                let span = info_span!("Endpoint Event Handler", %endpoint);
+
+                tokio::spawn(
+                    endpoint_events_handler(endpoint.clone(), receiver, self.sender.clone())
+                        .instrument(span),
+                );
+
+                Ok(actions::Response::observing_endpoint_events(endpoint))
+            }
+            Ok(_) => {
+                unreachable!()
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    async fn observe_messages(&mut self, ids: Vec<InternalEndpointId>) -> ResponseResult {
+        match self
+            .cc_handle
+            .perform_action(
+                self.user.clone(),
+                control_center::Action::SubscribeToMessages(ids),
+            )
+            .await
+        {
+            Ok(control_center::ControlCenterResponse::MessageObserver((infos, receiver))) => {
+                let endpoints: Vec<LabelledEndpointId> =
+                    infos.into_iter().map(LabelledEndpointId::from).collect();
+                let span = info_span!("Messages Handler");
+
+                tokio::spawn(
+                    messages_handler(endpoints.clone(), receiver, self.sender.clone())
+                        .instrument(span),
+                );
+
+                Ok(actions::Response::observing_messages(endpoints))
+            }
+            Ok(_) => {
+                unreachable!()
+            }
+            Err(e) => Err(e),
+        }
+    }
+
     async fn do_user_action(&mut self, action: actions::Action) -> ResponseResult {
         info!("client requested action: {action}");
 
-        match action {
-            actions::Action::Observe(id) => self.observe(self.id_to_internal(id)).await,
+        let correlation_id = action.correlation_id().map(ToOwned::to_owned);
+
+        if self.read_only && action.controls_endpoints() {
+            return Err(
+                Error::ReadOnlyServer(action.to_string()).correlated(correlation_id.as_deref())
+            );
+        }
+
+        // One correlation id tags the error, that of the outermost action, as the user sees one layer.
+        let response = match action.uncorrelated() {
+            actions::Action::Observe {
+                endpoint,
+                include_writes,
+                idempotent,
+                limit,
+                deferred,
+                replay,
+                since,
+                dedup,
+            } => {
+                self.observe(
+                    self.id_to_internal(endpoint),
+                    include_writes,
+                    idempotent,
+                    limit,
+                    deferred,
+                    Replay::new(replay, since),
+                    dedup,
+                )
+                .await
+            }
+            actions::Action::ObserveWeak(id) => self.observe_weak(self.id_to_internal(id)).await,
+            actions::Action::ObserveExclusive(id) => {
+                self.observe_exclusive(self.id_to_internal(id)).await
+            }
+            actions::Action::ObserveAny(labels) => self.observe_any(labels).await,
+            actions::Action::Unobserve(id) => self.unobserve(id).await,
             actions::Action::Control(id) => self.control(self.id_to_internal(id)).await,
             actions::Action::ControlAny(labels) => self.control_any(labels).await,
-            actions::Action::Write((endpoint, message)) => {
-                self.write(endpoint, message.into_bytes()).await
+            actions::Action::ControlAnyWhenAvailable { labels, timeout } => {
+                self.control_any_when_available(labels, timeout).await
+            }
+            actions::Action::Write {
+                endpoint,
+                message,
+                correlation_id,
+            } => {
+                self.write(endpoint, message.into_bytes(), correlation_id)
+                    .await
             }
-            actions::Action::WriteBytes((endpoint, bytes)) => self.write(endpoint, bytes).await,
+            actions::Action::WriteBytes {
+                endpoint,
+                bytes,
+                correlation_id,
+            } => self.write(endpoint, bytes, correlation_id).await,
+            actions::Action::CanWrite(endpoint) => self.can_write(endpoint),
             actions::Action::ObserveEvents => self.observe_events().await,
+            actions::Action::ObserveEndpointEvents(id) => {
+                self.observe_endpoint_events(self.id_to_internal(id)).await
+            }
+            actions::Action::ObserveMessages(ids) => {
+                let ids = ids.into_iter().map(|id| self.id_to_internal(id)).collect();
+                self.observe_messages(ids).await
+            }
+            actions::Action::Reconfigure {
+                endpoint,
+                baud,
+                flow_control,
+            } => self.reconfigure(endpoint, baud, flow_control).await,
+            actions::Action::Flush { endpoint, tx, rx } => self.flush(endpoint, tx, rx).await,
+            actions::Action::RunMacro { endpoint, name } => self.run_macro(endpoint, name).await,
+            actions::Action::SkipOnControlMacros(skip) => self.skip_on_control_macros(skip),
+            actions::Action::CompactEndpointIds(compact) => self.compact_endpoint_ids(compact),
+            actions::Action::ListEndpoints => self.list_endpoints().await,
+            actions::Action::ListLabels => self.list_labels().await,
+            actions::Action::TransferControl { endpoint, to_user } => {
+                self.transfer_control(endpoint, to_user).await
+            }
+            actions::Action::Release(endpoint) => self.release(endpoint).await,
+            actions::Action::CancelQueue => self.cancel_queue().await,
+            actions::Action::RecentMessages { endpoint, count } => {
+                self.recent_messages(endpoint, count).await
+            }
+            actions::Action::Notice { endpoint, text } => self.notice(endpoint, text).await,
+            actions::Action::SetEndpointEnabled {
+                endpoint,
+                enabled,
+                release_holders,
+            } => {
+                self.set_endpoint_enabled(endpoint, enabled, release_holders)
+                    .await
+            }
+            actions::Action::PingEndpoint {
+                endpoint,
+                probe,
+                expect,
+                timeout,
+            } => self.ping_endpoint(endpoint, probe, expect, timeout).await,
+            actions::Action::ServerTime => Ok(actions::Response::server_time(chrono::Utc::now())),
+            actions::Action::WhoAmI => self.who_am_i(),
+            actions::Action::Correlated { .. } => unreachable!("Unwrapped above"),
+        };
+
+        response.map_err(|error| error.correlated(correlation_id.as_deref()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::channel::mpsc as futures_mpsc;
+
+    use super::*;
+    use crate::config::Config;
+
+    #[tokio::test]
+    async fn write_to_closed_endpoint_is_an_error() {
+        let mut cc_handle = ControlCenterHandle::new(&Config::default());
+        let user = User::new("writer");
+        cc_handle.inform(Inform::UserArrived(user.clone()));
+
+        let Ok(control_center::ControlCenterResponse::EventObserver(mut events)) = cc_handle
+            .perform_action(user.clone(), control_center::Action::SubscribeToEvents)
+            .await
+        else {
+            panic!("Should be able to subscribe to events");
+        };
+
+        let (sender, _responses) = mpsc::unbounded_channel();
+        let (peer_requests_sender, peer_requests_receiver) = mpsc::unbounded_channel();
+        let mut peer = Peer::new(
+            user,
+            sender,
+            peer_requests_sender,
+            peer_requests_receiver,
+            cc_handle,
+        );
+
+        // The endpoint's end of the channel is gone, like a torn down endpoint's would be
+        let (mut endpoint_sender, endpoint_receiver) = futures_mpsc::unbounded();
+        drop(endpoint_receiver);
+
+        let id = EndpointId::mock("torn-down");
+        let info = InternalEndpointInfo::new(
+            InternalEndpointId::Mock(MockId::new("writer", "torn-down")),
+            Labels::default(),
+        );
+
+        let result = peer
+            .put_on_wire(info.clone(), &mut endpoint_sender, b"hello".to_vec())
+            .await;
+        assert_eq!(result, Err(Error::EndpointClosed { endpoint: id }));
+
+        loop {
+            let event = events.recv().await.expect("Should see the dropped write");
+            if let events::Event::User(events::user::UserEvent {
+                event: events::user::Event::WriteDropped((dropped, message)),
+                ..
+            }) = event.inner
+            {
+                assert_eq!(dropped, info);
+                assert_eq!(message, b"hello".to_vec());
+                break;
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn deferred_observation_starts_as_it_was_asked_for() {
+        let cc_handle = ControlCenterHandle::new(&Config::default());
+        let user = User::new("patient");
+        let info = InternalEndpointInfo::new(
+            InternalEndpointId::Mock(MockId::new("patient", "late")),
+            Labels::default(),
+        );
+
+        let (sender, mut responses) = mpsc::unbounded_channel();
+        let (peer_requests_sender, peer_requests_receiver) = mpsc::unbounded_channel();
+        let mut peer = Peer::new(
+            user,
+            sender,
+            peer_requests_sender.clone(),
+            peer_requests_receiver,
+            cc_handle,
+        );
+        peer.deferred_observations.insert(
+            info.id.clone(),
+            DeferredObservation {
+                replay: Replay::Everything,
+                compact: true,
+                ..Default::default()
+            },
+        );
+        tokio::spawn(async move { peer.run().await });
+
+        let (_endpoint_sender, endpoint_events) = broadcast::channel(2);
+        let recent = vec![(
+            chrono::Utc::now(),
+            endpoint::EndpointEvent::FromWire("earlier".into()),
+        )];
+        peer_requests_sender
+            .send(PeerRequest::InternalAction(
+                PeerAction::DeferredObserverReady((info.clone(), endpoint_events, recent)),
+            ))
+            .expect("The peer should be running");
+
+        let handle = actions::EndpointHandle(0);
+        for expected in [
+            actions::Response::endpoint_handle(handle, info.clone().into()),
+            actions::Response::observing(info.clone().into()),
+            actions::Response::compact_message(handle, Direction::FromWire, "earlier".into()),
+        ] {
+            let response = tokio::time::timeout(Duration::from_secs(5), responses.recv())
+                .await
+                .expect("The observer should be told")
+                .expect("The peer should be running");
+            assert_eq!(response, Ok(expected));
+        }
+    }
+
+    #[tokio::test]
+    async fn lagging_observer_is_told_and_keeps_observing() {
+        let cc_handle = ControlCenterHandle::new(&Config::default());
+        let info = InternalEndpointInfo::new(
+            InternalEndpointId::Mock(MockId::new("slow", "chatty")),
+            Labels::default(),
+        );
+
+        // Room for two messages, so the first three of five are skipped
+        let (endpoint_sender, endpoint_events) = broadcast::channel(2);
+        for message in ["1", "2", "3", "4", "5"] {
+            endpoint_sender
+                .send(endpoint::EndpointEvent::FromWire(message.into()))
+                .unwrap();
         }
+
+        let (sender, mut responses) = mpsc::unbounded_channel();
+        let (peer_requests, _peer_requests_receiver) = mpsc::unbounded_channel();
+        let (start, start_rx) = oneshot::channel();
+        tokio::spawn(endpoint_handler(
+            User::new("slow"),
+            cc_handle,
+            info.clone(),
+            endpoint_events,
+            sender,
+            peer_requests,
+            false,
+            None,
+            false,
+            None,
+            vec![],
+            start_rx,
+        ));
+        start.send(()).unwrap();
+
+        let message = |message: &str| {
+            actions::Response::message(info.clone().into(), Direction::FromWire, message.into())
+        };
+        for expected in [
+            actions::Response::messages_skipped(info.clone().into(), 3),
+            message("4"),
+            message("5"),
+        ] {
+            let response = tokio::time::timeout(Duration::from_secs(5), responses.recv())
+                .await
+                .expect("The observer should be told")
+                .expect("The handler should be running");
+            assert_eq!(response, Ok(expected));
+        }
+    }
+
+    #[tokio::test]
+    async fn lagging_endpoint_event_observer_is_told() {
+        let endpoint = LabelledEndpointId::new(&EndpointId::mock("flapping"));
+
+        // Room for two events, so the first three of five are skipped
+        let (event_sender, events) = broadcast::channel(2);
+        for _ in 0..5 {
+            event_sender
+                .send(events::TimestampedEvent::new_general_event(
+                    events::general::Event::EndpointIdle(endpoint.clone()),
+                ))
+                .unwrap();
+        }
+
+        let (sender, mut responses) = mpsc::unbounded_channel();
+        tokio::spawn(endpoint_events_handler(endpoint.clone(), events, sender));
+
+        for expected in [
+            events::endpoint::Event::Lagged(3),
+            events::endpoint::Event::Idle,
+            events::endpoint::Event::Idle,
+        ] {
+            let response = tokio::time::timeout(Duration::from_secs(5), responses.recv())
+                .await
+                .expect("The observer should be told")
+                .expect("The handler should be running");
+            let Ok(actions::Response::Async(actions::Async::EndpointEvent(event))) = response
+            else {
+                panic!("Expected an endpoint event, got {response:?}");
+            };
+            assert_eq!(event.endpoint, endpoint);
+            assert_eq!(event.event, expected);
+        }
+    }
+
+    #[tokio::test]
+    async fn lagging_message_observer_is_told() {
+        let info = InternalEndpointInfo::new(
+            InternalEndpointId::Mock(MockId::new("slow", "busy")),
+            Labels::default(),
+        );
+        let endpoint = LabelledEndpointId::from(info.clone());
+
+        // Room for two messages, so the first three of five are skipped
+        let (event_sender, events) = broadcast::channel(2);
+        for message in ["1", "2", "3", "4", "5"] {
+            event_sender
+                .send(events::TimestampedEvent::new_general_event(
+                    events::general::Event::MessageReceived((info.clone(), message.into())),
+                ))
+                .unwrap();
+        }
+
+        let (sender, mut responses) = mpsc::unbounded_channel();
+        tokio::spawn(messages_handler(vec![endpoint.clone()], events, sender));
+
+        let mut received = vec![];
+        for _ in 0..3 {
+            let response = tokio::time::timeout(Duration::from_secs(5), responses.recv())
+                .await
+                .expect("The observer should be told")
+                .expect("The handler should be running");
+            received.push(response);
+        }
+
+        let Ok(actions::Response::Async(actions::Async::Event(event))) = &received[0] else {
+            panic!("Expected an event, got {:?}", received[0]);
+        };
+        assert_eq!(
+            event.inner,
+            events::Event::General(events::general::Event::Lagged(3))
+        );
+
+        for (response, expected) in received[1..].iter().zip(["4", "5"]) {
+            let Ok(actions::Response::Async(actions::Async::Traffic(message))) = response else {
+                panic!("Expected traffic, got {response:?}");
+            };
+            assert_eq!(message.endpoint, endpoint);
+            assert_eq!(message.message, expected.as_bytes());
+        }
+    }
+
+    #[tokio::test]
+    async fn peer_leaves_when_user_can_no_longer_be_responded_to() {
+        let mut cc_handle = ControlCenterHandle::new(&Config::default());
+        let observer = User::new("observer");
+        cc_handle.inform(Inform::UserArrived(observer.clone()));
+
+        let Ok(control_center::ControlCenterResponse::EventObserver(mut events)) = cc_handle
+            .perform_action(observer, control_center::Action::SubscribeToEvents)
+            .await
+        else {
+            panic!("Should be able to subscribe to events");
+        };
+
+        let user = User::new("flaky");
+        let (sender, responses) = mpsc::unbounded_channel();
+        let (peer_requests_sender, peer_requests_receiver) = mpsc::unbounded_channel();
+        let mut peer = Peer::new(
+            user.clone(),
+            sender,
+            peer_requests_sender.clone(),
+            peer_requests_receiver,
+            cc_handle,
+        );
+        let peer = tokio::spawn(async move { peer.run().await });
+
+        // The connection errored, so nobody reads responses anymore
+        drop(responses);
+        peer_requests_sender
+            .send(PeerRequest::UserAction(actions::Action::list_endpoints()))
+            .expect("The peer should be running");
+
+        tokio::time::timeout(Duration::from_secs(5), peer)
+            .await
+            .expect("The peer should stop by itself")
+            .expect("The peer should not panic");
+
+        loop {
+            let event = events.recv().await.expect("Should see the user leave");
+            if let events::Event::User(events::user::UserEvent {
+                user: left,
+                event: events::user::Event::Disconnected,
+            }) = event.inner
+            {
+                assert_eq!(left, user);
+                break;
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn panicking_peer_is_an_error_for_the_user() {
+        let (sender, mut responses) = mpsc::unbounded_channel();
+        let (requests, mut requests_receiver) = mpsc::unbounded_channel();
+
+        let peer_handle = PeerHandle::supervised(requests, sender, async move {
+            // Stands in for an `expect` failing while handling an action
+            let _action = requests_receiver.recv().await;
+            panic!("Injected panic");
+        });
+        peer_handle.send(actions::Action::list_endpoints());
+
+        let response = tokio::time::timeout(Duration::from_secs(5), responses.recv())
+            .await
+            .expect("The user should be told instead of left waiting");
+        assert_eq!(response, Some(Err(peer_panicked())));
+
+        peer_handle.closed().await;
+        assert_eq!(peer_handle.shutdown().await, Err(peer_panicked()));
     }
 }
@@ -1,18 +1,29 @@
 use std::fmt::Display;
 
+use chrono::{DateTime, Utc};
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
+use crate::endpoint::{EndpointEvent, InternalEndpointInfo};
+
 /// Serial port related errors.
 pub(crate) mod error;
 
 /// The serial port structure.
 pub(crate) mod serial_port;
 
+/// Where serial ports are opened from.
+pub(crate) mod backend;
+
+/// Pseudo-terminals in place of serial ports.
+#[cfg(unix)]
+pub(crate) mod pty;
+
 /// Codecs for encoding/decoding messages to/from wire.
 pub(crate) mod codecs;
 
 /// The message data type used for serial.
-#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Deserialize, Serialize)]
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Deserialize, Serialize, JsonSchema)]
 pub struct SerialMessage(String);
 
 impl SerialMessage {
@@ -21,6 +32,17 @@ impl SerialMessage {
         Self(String::from_utf8_lossy(bytes.as_ref()).to_string())
     }
 
+    /// Create a serial message from bytes in the given encoding,
+    /// replacing any malformed sequences.
+    pub fn decode<B: AsRef<[u8]>>(bytes: B, encoding: &'static encoding_rs::Encoding) -> Self {
+        Self(
+            encoding
+                .decode_without_bom_handling(bytes.as_ref())
+                .0
+                .into_owned(),
+        )
+    }
+
     /// Turn the message (utf8) into bytes.
     pub fn into_bytes(self) -> Vec<u8> {
         self.0.into_bytes()
@@ -48,3 +70,223 @@ impl Display for SerialMessage {
 
 /// The message data type used for serial bytes.
 pub type SerialMessageBytes = Vec<u8>;
+
+/// Append the terminator to the message, unless the message already ends with it.
+pub(crate) fn terminated(mut message: SerialMessageBytes, terminator: &[u8]) -> SerialMessageBytes {
+    if !message.ends_with(terminator) {
+        message.extend_from_slice(terminator);
+    }
+
+    message
+}
+
+/// The wire settings a serial port is using, as read back after applying them.
+/// The OS or the device may not honor a requested baud exactly.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize, JsonSchema)]
+pub struct PortSettings {
+    /// The baud rate.
+    pub baud: u32,
+
+    /// The flow control.
+    #[schemars(with = "FlowControlSchema")]
+    pub flow_control: serialport::FlowControl,
+}
+
+/// Describes [`serialport::FlowControl`] in the JSON schema of the wire types,
+/// since it does not describe itself.
+#[derive(JsonSchema)]
+#[schemars(rename = "FlowControl")]
+#[allow(dead_code)]
+pub(crate) enum FlowControlSchema {
+    None,
+    Software,
+    Hardware,
+}
+
+/// A built-in transformation of messages received from wire.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+pub enum Transform {
+    /// Remove ANSI escape sequences, such as colors and cursor movement.
+    StripAnsi,
+
+    /// Remove leading and trailing whitespace.
+    Trim,
+}
+
+impl Transform {
+    fn apply(&self, message: SerialMessageBytes) -> SerialMessageBytes {
+        match self {
+            Transform::StripAnsi => strip_ansi(&message),
+            Transform::Trim => {
+                let start = message
+                    .iter()
+                    .position(|b| !b.is_ascii_whitespace())
+                    .unwrap_or(message.len());
+                let end = message
+                    .iter()
+                    .rposition(|b| !b.is_ascii_whitespace())
+                    .map_or(start, |last| last + 1);
+
+                message[start..end].to_vec()
+            }
+        }
+    }
+}
+
+/// Apply each transform in order.
+pub(crate) fn transformed(
+    message: SerialMessageBytes,
+    transforms: &[Transform],
+) -> SerialMessageBytes {
+    transforms
+        .iter()
+        .fold(message, |message, transform| transform.apply(message))
+}
+
+/// What a mock sends back from wire for each line written to it.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+pub enum MockEcho {
+    /// The line as it was written.
+    #[default]
+    Echo,
+
+    /// The line with ASCII letters uppercased.
+    Uppercase,
+
+    /// The line followed by `*` and its CRC-16/CCITT-FALSE as four uppercase hex digits,
+    /// e.g. `hello*D26E`.
+    AppendCrc,
+}
+
+impl MockEcho {
+    pub(crate) fn apply(&self, line: SerialMessageBytes) -> SerialMessageBytes {
+        match self {
+            MockEcho::Echo => line,
+            MockEcho::Uppercase => line.to_ascii_uppercase(),
+            MockEcho::AppendCrc => {
+                let crc = format!("*{:04X}", crc16_ccitt_false(&line));
+                [line, crc.into_bytes()].concat()
+            }
+        }
+    }
+}
+
+fn crc16_ccitt_false(bytes: &[u8]) -> u16 {
+    bytes.iter().fold(0xFFFF, |crc, byte| {
+        (0..8).fold(crc ^ (u16::from(*byte) << 8), |crc, _| {
+            if crc & 0x8000 != 0 {
+                (crc << 1) ^ 0x1021
+            } else {
+                crc << 1
+            }
+        })
+    })
+}
+
+const ESC: u8 = 0x1b;
+const BEL: u8 = 0x07;
+
+fn strip_ansi(message: &[u8]) -> SerialMessageBytes {
+    let mut stripped = Vec::with_capacity(message.len());
+    let mut bytes = message.iter().copied().peekable();
+
+    while let Some(byte) = bytes.next() {
+        if byte != ESC {
+            stripped.push(byte);
+            continue;
+        }
+
+        match bytes.next() {
+            // Control sequence: parameters, then a final byte in `@..=~`.
+            Some(b'[') => {
+                for byte in bytes.by_ref() {
+                    if (0x40..=0x7e).contains(&byte) {
+                        break;
+                    }
+                }
+            }
+            // Operating system command: ends with BEL or `ESC \`.
+            Some(b']') => {
+                while let Some(byte) = bytes.next() {
+                    if byte == BEL {
+                        break;
+                    }
+                    if byte == ESC && bytes.peek() == Some(&b'\\') {
+                        bytes.next();
+                        break;
+                    }
+                }
+            }
+            // Any other escape is a single character.
+            _ => {}
+        }
+    }
+
+    stripped
+}
+
+/// Which way a message travelled, as seen from the endpoint.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash, Deserialize, Serialize, JsonSchema)]
+pub enum Direction {
+    /// The message was put on wire.
+    ToWire,
+
+    /// The message was received from wire.
+    #[default]
+    FromWire,
+}
+
+impl Display for Direction {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Direction::ToWire => write!(f, "to wire"),
+            Direction::FromWire => write!(f, "from wire"),
+        }
+    }
+}
+
+/// A message along with where, which way, and when it was seen.
+/// This is what is passed around internally so that consumers
+/// do not have to piece the context back together.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct TaggedMessage {
+    pub(crate) endpoint: InternalEndpointInfo,
+    pub(crate) direction: Direction,
+    pub(crate) bytes: SerialMessageBytes,
+    pub(crate) timestamp: DateTime<Utc>,
+}
+
+impl TaggedMessage {
+    /// Tag a message seen just now.
+    pub(crate) fn new(
+        endpoint: InternalEndpointInfo,
+        direction: Direction,
+        bytes: SerialMessageBytes,
+    ) -> Self {
+        Self {
+            endpoint,
+            direction,
+            bytes,
+            timestamp: Utc::now(),
+        }
+    }
+
+    /// Tag the message of an endpoint event.
+    pub(crate) fn from_event(endpoint: InternalEndpointInfo, event: EndpointEvent) -> Self {
+        match event {
+            EndpointEvent::ToWire(bytes) => Self::new(endpoint, Direction::ToWire, bytes),
+            EndpointEvent::FromWire(bytes) => Self::new(endpoint, Direction::FromWire, bytes),
+        }
+    }
+
+    /// The message as (lossy) utf8.
+    pub(crate) fn message(&self) -> SerialMessage {
+        SerialMessage::new_lossy(&self.bytes)
+    }
+}
+
+impl Display for TaggedMessage {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} {} {}", self.message(), self.direction, self.endpoint)
+    }
+}
@@ -0,0 +1,194 @@
+use tokio::io::{AsyncRead, AsyncWrite};
+
+use crate::{endpoint::InternalEndpointId, error::Error, serial::PortSettings};
+
+/// Opens and configures the ports a [`super::serial_port::SerialPortBuilder`] builds upon.
+///
+/// The real backend talks to the OS, see [`super::serial_port::TokioSerial`].
+/// Tests may use [`NullSerial`] instead, which needs no hardware.
+pub(crate) trait SerialBackend {
+    /// An open port.
+    type Port: AsyncRead + AsyncWrite + Unpin + Send + 'static;
+
+    /// Open the port at the given path.
+    fn open(
+        &self,
+        path: &str,
+        baud: u32,
+        flow_control: serialport::FlowControl,
+    ) -> Result<Self::Port, Error>;
+
+    /// The settings the port actually uses.
+    /// Warns about any which differ from what was requested.
+    fn settings(
+        port: &Self::Port,
+        requested_baud: Option<u32>,
+        requested_flow_control: Option<serialport::FlowControl>,
+    ) -> Result<PortSettings, Error>;
+
+    /// Change the settings of an open port.
+    /// Settings which are `None` are left as they are.
+    fn reconfigure(
+        port: &mut Self::Port,
+        baud: Option<u32>,
+        flow_control: Option<serialport::FlowControl>,
+    ) -> Result<PortSettings, Error>;
+
+    /// Discard what the OS has buffered for the port.
+    fn clear(port: &mut Self::Port, buffer: serialport::ClearBuffer) -> Result<(), Error>;
+
+    /// The id of an endpoint opened at the given path.
+    fn endpoint_id(path: &str) -> InternalEndpointId {
+        InternalEndpointId::Tty(path.into())
+    }
+
+    /// Where the other end of the port can be opened, if anywhere.
+    fn device(_port: &Self::Port) -> Option<String> {
+        None
+    }
+}
+
+#[cfg(test)]
+pub(crate) use null::NullSerial;
+
+#[cfg(test)]
+mod null {
+    use std::{
+        collections::VecDeque,
+        io,
+        pin::Pin,
+        sync::Mutex,
+        task::{Context, Poll},
+    };
+
+    use tokio::io::{AsyncRead, AsyncWrite, DuplexStream, ReadBuf};
+
+    use super::SerialBackend;
+    use crate::{error::Error, serial::PortSettings};
+
+    /// A backend handing out in-memory ports.
+    /// What the port sees on wire is the other end of a [`DuplexStream`].
+    #[derive(Debug, Default)]
+    pub(crate) struct NullSerial {
+        ports: Mutex<VecDeque<(DuplexStream, Option<io::Error>)>>,
+    }
+
+    impl NullSerial {
+        /// Make a port available, returning its other end.
+        /// Ports are handed out in the order they were added.
+        pub(crate) fn add_port(&self) -> DuplexStream {
+            self.push_port(None)
+        }
+
+        /// Like [`Self::add_port`], but the first read from the port fails
+        /// as if what was on wire could not be read.
+        pub(crate) fn add_failing_port(&self) -> DuplexStream {
+            self.push_port(Some(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "Garbled on wire",
+            )))
+        }
+
+        fn push_port(&self, read_error: Option<io::Error>) -> DuplexStream {
+            let (port, wire) = tokio::io::duplex(1024);
+            self.ports
+                .lock()
+                .expect("Ports lock should not be poisoned")
+                .push_back((port, read_error));
+            wire
+        }
+    }
+
+    /// An in-memory port.
+    #[derive(Debug)]
+    pub(crate) struct NullPort {
+        stream: DuplexStream,
+        settings: PortSettings,
+        read_error: Option<io::Error>,
+    }
+
+    impl SerialBackend for NullSerial {
+        type Port = NullPort;
+
+        fn open(
+            &self,
+            path: &str,
+            baud: u32,
+            flow_control: serialport::FlowControl,
+        ) -> Result<Self::Port, Error> {
+            let (stream, read_error) = self
+                .ports
+                .lock()
+                .expect("Ports lock should not be poisoned")
+                .pop_front()
+                .ok_or_else(|| Error::InternalIssue(format!("No null port left for {path}")))?;
+
+            Ok(NullPort {
+                stream,
+                settings: PortSettings { baud, flow_control },
+                read_error,
+            })
+        }
+
+        fn settings(
+            port: &Self::Port,
+            _requested_baud: Option<u32>,
+            _requested_flow_control: Option<serialport::FlowControl>,
+        ) -> Result<PortSettings, Error> {
+            Ok(port.settings)
+        }
+
+        fn reconfigure(
+            port: &mut Self::Port,
+            baud: Option<u32>,
+            flow_control: Option<serialport::FlowControl>,
+        ) -> Result<PortSettings, Error> {
+            if let Some(baud) = baud {
+                port.settings.baud = baud;
+            }
+            if let Some(flow_control) = flow_control {
+                port.settings.flow_control = flow_control;
+            }
+
+            Ok(port.settings)
+        }
+
+        fn clear(_port: &mut Self::Port, _buffer: serialport::ClearBuffer) -> Result<(), Error> {
+            // Nothing is buffered besides the duplex stream itself.
+            Ok(())
+        }
+    }
+
+    impl AsyncRead for NullPort {
+        fn poll_read(
+            self: Pin<&mut Self>,
+            cx: &mut Context<'_>,
+            buf: &mut ReadBuf<'_>,
+        ) -> Poll<io::Result<()>> {
+            let this = self.get_mut();
+
+            match this.read_error.take() {
+                Some(e) => Poll::Ready(Err(e)),
+                None => Pin::new(&mut this.stream).poll_read(cx, buf),
+            }
+        }
+    }
+
+    impl AsyncWrite for NullPort {
+        fn poll_write(
+            self: Pin<&mut Self>,
+            cx: &mut Context<'_>,
+            buf: &[u8],
+        ) -> Poll<io::Result<usize>> {
+            Pin::new(&mut self.get_mut().stream).poll_write(cx, buf)
+        }
+
+        fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+            Pin::new(&mut self.get_mut().stream).poll_flush(cx)
+        }
+
+        fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+            Pin::new(&mut self.get_mut().stream).poll_shutdown(cx)
+        }
+    }
+}
@@ -1,7 +1,7 @@
 use bytes::{Buf, BytesMut};
 use tokio_util::codec::{Decoder, Encoder};
 
-use crate::serial::error::SerialPortError;
+use crate::{metrics::FramingStats, serial::error::SerialPortError};
 
 /// This codec has a configurable delimiter character for reading,
 /// and optionally adds a character to each line it encodes.
@@ -17,6 +17,19 @@ pub struct LinesCodec {
     /// If provided, which byte to append when writing (encoding) messages.
     /// If `None`, forwards the data as-is.
     write_delimiter: Option<u8>,
+
+    /// If provided, frames longer than this are dropped instead of yielded.
+    max_length: Option<usize>,
+
+    /// Whether we are dropping the rest of an oversized frame.
+    discarding: bool,
+
+    /// If provided, a buffer ending with these bytes is yielded as a frame
+    /// even though no delimiter follows, since a shell waits at its prompt.
+    prompt: Option<Vec<u8>>,
+
+    /// Where oversized frames and dropped bytes are counted.
+    framing: FramingStats,
 }
 
 impl LinesCodec {
@@ -26,8 +39,47 @@ impl LinesCodec {
             cursor: 0,
             read_delimiter,
             write_delimiter,
+            max_length: None,
+            discarding: false,
+            prompt: None,
+            framing: FramingStats::default(),
         }
     }
+
+    /// Drop frames longer than this many bytes.
+    pub(crate) fn set_max_length(mut self, max_length: usize) -> Self {
+        self.max_length = Some(max_length);
+        self
+    }
+
+    /// Yield what has been read as soon as it ends with this prompt.
+    /// An empty prompt is ignored.
+    pub(crate) fn set_prompt(mut self, prompt: Vec<u8>) -> Self {
+        self.prompt = (!prompt.is_empty()).then_some(prompt);
+        self
+    }
+
+    /// Count oversized frames and dropped bytes here.
+    pub(crate) fn set_framing_stats(mut self, framing: FramingStats) -> Self {
+        self.framing = framing;
+        self
+    }
+
+    /// Forget about any partial frame, since the buffer it was in has been cleared.
+    pub(crate) fn reset(&mut self) {
+        self.cursor = 0;
+        self.discarding = false;
+    }
+
+    fn is_oversized(&self, length: usize) -> bool {
+        self.max_length.is_some_and(|max| length > max)
+    }
+
+    fn ends_with_prompt(&self, buffer: &[u8]) -> bool {
+        self.prompt
+            .as_ref()
+            .is_some_and(|prompt| buffer.ends_with(prompt))
+    }
 }
 
 impl Default for LinesCodec {
@@ -53,6 +105,19 @@ impl Decoder for LinesCodec {
             // Next time we need to start over.
             self.cursor = 0;
 
+            if self.discarding || self.is_oversized(actual_position) {
+                if !self.discarding {
+                    self.framing.oversized_frame();
+                }
+                self.discarding = false;
+                self.framing.dropped(actual_position);
+
+                // Drop the frame along with its delimiter,
+                // and look for the next frame in what is left.
+                src.advance(actual_position + 1);
+                return self.decode(src);
+            }
+
             // Split at the delimiter, getting a slice of the bytes before it.
             let line = src.split_to(actual_position);
 
@@ -60,6 +125,23 @@ impl Decoder for LinesCodec {
             src.advance(1);
 
             Ok(Some(line[..].to_vec()))
+        } else if self.discarding || self.is_oversized(read_to) {
+            // The frame is too long already, so there is no point in keeping it around.
+            if !self.discarding {
+                self.framing.oversized_frame();
+                self.discarding = true;
+            }
+            self.framing.dropped(read_to);
+
+            src.clear();
+            self.cursor = 0;
+
+            Ok(None)
+        } else if self.ends_with_prompt(&src[..read_to]) {
+            // The other end waits for input, so no delimiter is coming.
+            self.cursor = 0;
+
+            Ok(Some(src.split_to(read_to)[..].to_vec()))
         } else {
             // We did not find a full frame.
             // The next time we are called the same buffer `src` will be provided to us (same starting point),
@@ -0,0 +1,112 @@
+use std::{
+    io,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tokio_serial::{SerialPort, SerialStream};
+
+use crate::{
+    endpoint::InternalEndpointId,
+    error::Error,
+    serial::{backend::SerialBackend, serial_port::TokioSerial, PortSettings},
+};
+
+/// The backend creating pseudo-terminals instead of opening existing ports.
+///
+/// The server reads and writes the master side.
+/// The slave side is kept open, such that others may open it by its path
+/// and act as the device on the other end.
+#[derive(Debug, Default, Clone, Copy)]
+pub(crate) struct Pty;
+
+/// Both ends of a pseudo-terminal.
+#[derive(Debug)]
+pub(crate) struct PtyPort {
+    master: SerialStream,
+
+    /// The pty goes away if nobody has the slave side open,
+    /// so keep it open even if nobody else does.
+    slave: SerialStream,
+}
+
+impl SerialBackend for Pty {
+    type Port = PtyPort;
+
+    fn open(
+        &self,
+        name: &str,
+        baud: u32,
+        flow_control: serialport::FlowControl,
+    ) -> Result<Self::Port, Error> {
+        let problem =
+            |e| Error::InternalIssue(format!("Could not create pty {name}, problem: {e:#?}"));
+
+        let (mut master, mut slave) = SerialStream::pair().map_err(problem)?;
+
+        // Others need to be able to open the slave side too.
+        slave.set_exclusive(false).map_err(problem)?;
+
+        master.set_baud_rate(baud).map_err(problem)?;
+        master.set_flow_control(flow_control).map_err(problem)?;
+
+        Ok(PtyPort { master, slave })
+    }
+
+    fn settings(
+        port: &Self::Port,
+        requested_baud: Option<u32>,
+        requested_flow_control: Option<serialport::FlowControl>,
+    ) -> Result<PortSettings, Error> {
+        TokioSerial::settings(&port.master, requested_baud, requested_flow_control)
+    }
+
+    fn reconfigure(
+        port: &mut Self::Port,
+        baud: Option<u32>,
+        flow_control: Option<serialport::FlowControl>,
+    ) -> Result<PortSettings, Error> {
+        TokioSerial::reconfigure(&mut port.master, baud, flow_control)
+    }
+
+    fn clear(port: &mut Self::Port, buffer: serialport::ClearBuffer) -> Result<(), Error> {
+        TokioSerial::clear(&mut port.master, buffer)
+    }
+
+    fn endpoint_id(name: &str) -> InternalEndpointId {
+        InternalEndpointId::Pty(name.into())
+    }
+
+    fn device(port: &Self::Port) -> Option<String> {
+        port.slave.name()
+    }
+}
+
+impl AsyncRead for PtyPort {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().master).poll_read(cx, buf)
+    }
+}
+
+impl AsyncWrite for PtyPort {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.get_mut().master).poll_write(cx, buf)
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().master).poll_flush(cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().master).poll_shutdown(cx)
+    }
+}
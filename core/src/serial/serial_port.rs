@@ -1,20 +1,81 @@
 #![allow(dead_code)] // TODO: Cleanup this module
 
+use std::sync::{Arc, Mutex};
+
 use futures::{
     channel::mpsc::{self, UnboundedSender},
     SinkExt, StreamExt,
 };
-use tokio::{sync::broadcast, task::JoinHandle};
-use tokio_serial::SerialPortBuilderExt;
-use tokio_util::codec::Decoder;
-use tracing::{error, info, info_span, trace, warn, Instrument};
+use tokio::{
+    sync::{broadcast, oneshot},
+    task::JoinHandle,
+};
+use tokio_serial::{SerialPort, SerialPortBuilderExt, SerialStream};
+use tokio_util::codec::Framed;
+use tracing::{debug, error, info, info_span, trace, warn, Instrument};
 
 use crate::{
-    endpoint::{self, EndpointSemaphore, Label, Labels},
+    endpoint::{self, EndpointSemaphore, InternalEndpointId, Label, Labels},
     error::Error,
-    serial::{codecs::lines::LinesCodec, error::SerialPortError, SerialMessageBytes},
+    metrics::{self, FramingStats, WriteLatency},
+    serial::{
+        self, backend::SerialBackend, codecs::lines::LinesCodec, PortSettings, SerialMessageBytes,
+        Transform,
+    },
 };
 
+/// How many bytes are read from a port at once, unless configured otherwise.
+const DEFAULT_READ_BUFFER_SIZE: usize = 8 * 1024;
+
+/// Lines longer than this are dropped, since the port is likely sending garbage.
+const MAX_LINE_LENGTH: usize = 64 * 1024;
+
+/// The backend opening real serial ports through the OS.
+#[derive(Debug, Default, Clone, Copy)]
+pub(crate) struct TokioSerial;
+
+impl SerialBackend for TokioSerial {
+    type Port = SerialStream;
+
+    fn open(
+        &self,
+        path: &str,
+        baud: u32,
+        flow_control: serialport::FlowControl,
+    ) -> Result<Self::Port, Error> {
+        tokio_serial::new(path, baud)
+            .data_bits(tokio_serial::DataBits::Eight)
+            .parity(tokio_serial::Parity::None)
+            .stop_bits(tokio_serial::StopBits::One)
+            .flow_control(flow_control)
+            .open_native_async()
+            .map_err(|e| {
+                Error::InternalIssue(format!("Could not open port at {path}, problem: {e:#?}"))
+            })
+    }
+
+    fn settings(
+        port: &Self::Port,
+        requested_baud: Option<u32>,
+        requested_flow_control: Option<serialport::FlowControl>,
+    ) -> Result<PortSettings, Error> {
+        read_back(port, requested_baud, requested_flow_control)
+    }
+
+    fn reconfigure(
+        port: &mut Self::Port,
+        baud: Option<u32>,
+        flow_control: Option<serialport::FlowControl>,
+    ) -> Result<PortSettings, Error> {
+        reconfigure(port, baud, flow_control)
+    }
+
+    fn clear(port: &mut Self::Port, buffer: serialport::ClearBuffer) -> Result<(), Error> {
+        SerialPort::clear(port, buffer)
+            .map_err(|e| Error::InternalIssue(format!("Could not clear {buffer:?}, problem: {e}")))
+    }
+}
+
 /// Builder for a [`SerialPortHandle`].
 #[derive(Debug, Default)]
 pub struct SerialPortBuilder {
@@ -24,6 +85,12 @@ pub struct SerialPortBuilder {
     line_codec: Option<LinesCodec>,
     semaphore: Option<EndpointSemaphore>,
     labels: Labels,
+    write_terminator: Option<SerialMessageBytes>,
+    transforms: Vec<Transform>,
+    description: Option<String>,
+    prompt: Option<String>,
+    read_buffer_size: Option<usize>,
+    pty: bool,
 }
 
 impl SerialPortBuilder {
@@ -36,6 +103,15 @@ impl SerialPortBuilder {
         }
     }
 
+    /// Start a new builder for a pseudo-terminal with the given name,
+    /// which is created instead of opened.
+    pub(crate) fn new_pty(name: &str) -> Self {
+        Self {
+            pty: true,
+            ..Self::new(name)
+        }
+    }
+
     /// Set the [`EndpointSemaphore`] to use.
     pub(crate) fn set_semaphore(mut self, semaphore: EndpointSemaphore) -> Self {
         self.semaphore = Some(semaphore);
@@ -48,6 +124,39 @@ impl SerialPortBuilder {
         self
     }
 
+    /// Append this to written messages not already ending with it.
+    pub(crate) fn set_write_terminator(mut self, terminator: SerialMessageBytes) -> Self {
+        self.write_terminator = Some(terminator);
+        self
+    }
+
+    /// Apply these to each message received from wire.
+    pub(crate) fn set_transforms(mut self, transforms: Vec<Transform>) -> Self {
+        self.transforms = transforms;
+        self
+    }
+
+    /// Set a human readable description.
+    pub(crate) fn set_description(mut self, description: String) -> Self {
+        self.description = Some(description);
+        self
+    }
+
+    /// What the other end prints when waiting for input.
+    /// Lines ending with this are passed on without waiting for a delimiter.
+    pub(crate) fn set_prompt(mut self, prompt: String) -> Self {
+        self.prompt = Some(prompt);
+        self
+    }
+
+    /// Size the buffer the port is read into, i.e. how much is read at once.
+    /// Lines may span several reads, so this does not affect where lines are split.
+    /// Will use 8 KiB if not set.
+    pub(crate) fn set_read_buffer_size(mut self, size: usize) -> Self {
+        self.read_buffer_size = Some(size);
+        self
+    }
+
     /// Set the [LinesCodec] to use.
     /// Will be ignored if [set_string_codec] has been called (so don't use both).
     pub(crate) fn set_line_codec(mut self, codec: LinesCodec) -> Self {
@@ -56,43 +165,67 @@ impl SerialPortBuilder {
     }
 
     pub(crate) fn build(self) -> Result<SerialPortHandle, Error> {
+        if self.pty {
+            self.build_pty()
+        } else {
+            self.build_with(&TokioSerial)
+        }
+    }
+
+    #[cfg(unix)]
+    fn build_pty(self) -> Result<SerialPortHandle, Error> {
+        self.build_with(&serial::pty::Pty)
+    }
+
+    #[cfg(not(unix))]
+    fn build_pty(self) -> Result<SerialPortHandle, Error> {
+        Err(Error::BadConfig(format!(
+            "The pty {} can only be created on unix",
+            self.path
+        )))
+    }
+
+    /// Build, opening the port through the given backend.
+    pub(crate) fn build_with<B: SerialBackend + 'static>(
+        self,
+        backend: &B,
+    ) -> Result<SerialPortHandle, Error> {
         let baud = self.baud.unwrap_or(115_200) as u32;
         let flow_control = self.flow_control.unwrap_or(serialport::FlowControl::None);
 
         info!(%self.path, %baud, ?flow_control, "Starting serial port handler");
 
-        let serial_stream = tokio_serial::new(&self.path, baud)
-            .data_bits(tokio_serial::DataBits::Eight)
-            .parity(tokio_serial::Parity::None)
-            .stop_bits(tokio_serial::StopBits::One)
-            .flow_control(flow_control)
-            .open_native_async()
-            .map_err(|e| {
-                Error::InternalIssue(format!(
-                    "Could not open port at {}, problem: {e:#?}",
-                    self.path
-                ))
-            })?;
+        let serial_stream = backend.open(&self.path, baud, flow_control)?;
+
+        let settings = B::settings(&serial_stream, Some(baud), Some(flow_control))?;
+        let device = B::device(&serial_stream);
+        info!(?settings, ?device, "Opened serial port");
+        let settings = Arc::new(Mutex::new(settings));
+        let settings_task = settings.clone();
+
+        let framing = FramingStats::default();
+        let framing_task = framing.clone();
 
         let codec = if let Some(line_codec) = self.line_codec {
             line_codec
         } else {
-            LinesCodec::default()
+            LinesCodec::default().set_max_length(MAX_LINE_LENGTH)
+        }
+        .set_framing_stats(framing.clone());
+        let codec = match &self.prompt {
+            Some(prompt) => codec.set_prompt(prompt.clone().into_bytes()),
+            None => codec,
         };
 
-        // Sink: Send things (to serial port), stream: receive things (from serial port)
-        let (mut sink, stream) = codec.framed(serial_stream).split();
+        let read_buffer_size = self.read_buffer_size.unwrap_or(DEFAULT_READ_BUFFER_SIZE);
+        debug!(%read_buffer_size, "Framing port");
+        let mut framed = Framed::with_capacity(serial_stream, codec, read_buffer_size);
 
-        enum Event {
-            PleasePutThisOnWire(SerialMessageBytes),
-            ThisCameFromWire(Result<SerialMessageBytes, SerialPortError>),
-        }
-
-        let stream = stream.map(Event::ThisCameFromWire);
+        let (should_put_on_wire_sender, should_put_on_wire_receiver) =
+            mpsc::unbounded::<SerialMessageBytes>();
+        let mut should_put_on_wire_receiver = metrics::stamped(should_put_on_wire_receiver);
 
-        let (should_put_on_wire_sender, should_put_on_wire_receiver) = mpsc::unbounded();
-        let should_put_on_wire_receiver =
-            should_put_on_wire_receiver.map(Event::PleasePutThisOnWire);
+        let (control_sender, mut control_receiver) = mpsc::unbounded::<SerialPortControl>();
 
         // Outsiders will be getting observing messages from this broadcast.
         let (broadcast_sender, broadcast_receiver) = broadcast::channel(1024);
@@ -100,53 +233,86 @@ impl SerialPortBuilder {
         let broadcast_sender_task = broadcast_sender.clone();
 
         let tty_span = info_span!("tty", %self.path);
+        let write_terminator = self.write_terminator;
+        let transforms = self.transforms;
+        let write_latency = WriteLatency::default();
+        let write_latency_task = write_latency.clone();
 
         let handle = tokio::spawn(
             async move {
-                let mut events = futures::stream::select(stream, should_put_on_wire_receiver);
-
                 loop {
-                    match events.select_next_some().await {
-                        Event::PleasePutThisOnWire(message) => match sink.send(message.clone()).await {
-                            Ok(()) => {
+                    tokio::select! {
+                        from_wire = framed.next() => match from_wire {
+                            Some(Ok(message)) => {
+                                trace!(
+                                    "Message from port: `{:?}`",
+                                    &message[..message.len().min(32)]
+                                );
+
+                                let message = serial::transformed(message, &transforms);
+
                                 match broadcast_sender_task
-                                    .send(endpoint::EndpointEvent::ToWire(message))
+                                    .send(endpoint::EndpointEvent::FromWire(message))
                                 {
                                     Ok(listeners) => {
-                                        trace!("Broadcasted ToWire message to {listeners} listener(s)")
+                                        trace!("Broadcasted FromWire to {listeners} listener(s)")
                                     }
                                     Err(e) => {
                                         warn!("Send error in broadcast: {e:?}")
                                     }
                                 }
-
-                                continue;
                             }
-                            Err(e) => {
-                                error!(?e, "Serial port error in send, exiting");
+                            Some(Err(e)) => {
+                                framing_task.decode_error();
+                                error!(?e, "Serial port error, exiting");
+                                break;
+                            }
+                            None => {
+                                error!("Serial port stream ended, exiting");
                                 break;
                             }
                         },
-                        Event::ThisCameFromWire(Ok(message)) => {
-                            trace!(
-                                "Message from port: `{:?}`",
-                                &message[..message.len().min(32)]
-                            );
-
-                            match broadcast_sender_task
-                                .send(endpoint::EndpointEvent::FromWire(message))
-                            {
-                                Ok(listeners) => {
-                                    trace!("Broadcasted FromWire to {listeners} listener(s)")
+                        Some((enqueued, message)) = should_put_on_wire_receiver.next() => {
+                            let message = match &write_terminator {
+                                Some(terminator) => serial::terminated(message, terminator),
+                                None => message,
+                            };
+
+                            match framed.send(message.clone()).await {
+                                Ok(()) => {
+                                    write_latency_task.record(enqueued.elapsed());
+
+                                    match broadcast_sender_task
+                                        .send(endpoint::EndpointEvent::ToWire(message))
+                                    {
+                                        Ok(listeners) => {
+                                            trace!("Broadcasted ToWire message to {listeners} listener(s)")
+                                        }
+                                        Err(e) => {
+                                            warn!("Send error in broadcast: {e:?}")
+                                        }
+                                    }
                                 }
                                 Err(e) => {
-                                    warn!("Send error in broadcast: {e:?}")
+                                    error!(?e, "Serial port error in send, exiting");
+                                    break;
                                 }
                             }
                         }
-                        Event::ThisCameFromWire(Err(e)) => {
-                            error!(?e, "Serial port error, exiting");
-                            break;
+                        Some(control) = control_receiver.next() => {
+                            if let SerialPortControl::Flush { tx, rx, .. } = &control {
+                                // Besides what the OS buffers, drop what has not reached it yet.
+                                if *tx {
+                                    while let Ok(Some(_)) = should_put_on_wire_receiver.try_next() {}
+                                }
+                                if *rx {
+                                    framing_task.dropped(framed.read_buffer().len());
+                                    framed.read_buffer_mut().clear();
+                                    framed.codec_mut().reset();
+                                }
+                            }
+
+                            handle_control::<B>(framed.get_mut(), control, &settings_task);
                         }
                     }
                 }
@@ -158,12 +324,20 @@ impl SerialPortBuilder {
         );
 
         Ok(SerialPortHandle {
-            tty: self.path,
+            id: B::endpoint_id(&self.path),
+            device,
             handle,
             serial_tx: should_put_on_wire_sender,
+            control_tx: control_sender,
             broadcast_tx: broadcast_sender,
             semaphore: self.semaphore.unwrap_or_default(),
             labels: self.labels,
+            description: self.description,
+            prompt: self.prompt,
+            write_latency,
+            framing,
+            settings,
+            opened_at: chrono::Utc::now(),
         })
     }
 
@@ -178,11 +352,401 @@ impl SerialPortBuilder {
     }
 }
 
+/// Requests for the serial port task which are not about putting messages on the wire.
+#[derive(Debug)]
+pub(crate) enum SerialPortControl {
+    /// Change the settings of the open port.
+    /// Settings which are `None` are left as they are.
+    Reconfigure {
+        baud: Option<u32>,
+        flow_control: Option<serialport::FlowControl>,
+        reply: oneshot::Sender<Result<(), Error>>,
+    },
+
+    /// Discard what is buffered for the wire (`tx`) and/or from the wire (`rx`).
+    Flush {
+        tx: bool,
+        rx: bool,
+        reply: oneshot::Sender<Result<(), Error>>,
+    },
+}
+
+/// Read back the settings the port actually uses.
+/// Warns about any which differ from what was requested.
+fn read_back<P: SerialPort>(
+    port: &P,
+    requested_baud: Option<u32>,
+    requested_flow_control: Option<serialport::FlowControl>,
+) -> Result<PortSettings, Error> {
+    let problem = |e| Error::InternalIssue(format!("Could not read back port settings: {e}"));
+
+    let settings = PortSettings {
+        baud: port.baud_rate().map_err(problem)?,
+        flow_control: port.flow_control().map_err(problem)?,
+    };
+
+    if let Some(requested) = requested_baud.filter(|&baud| baud != settings.baud) {
+        warn!(
+            requested,
+            actual = settings.baud,
+            "Port did not honor the requested baud"
+        );
+    }
+
+    if let Some(requested) = requested_flow_control.filter(|&fc| fc != settings.flow_control) {
+        warn!(
+            ?requested,
+            actual = ?settings.flow_control,
+            "Port did not honor the requested flow control"
+        );
+    }
+
+    Ok(settings)
+}
+
+fn reconfigure(
+    port: &mut SerialStream,
+    baud: Option<u32>,
+    flow_control: Option<serialport::FlowControl>,
+) -> Result<PortSettings, Error> {
+    if let Some(baud) = baud {
+        port.set_baud_rate(baud).map_err(|e| {
+            Error::InternalIssue(format!("Could not set baud {baud}, problem: {e}"))
+        })?;
+    }
+
+    if let Some(flow_control) = flow_control {
+        port.set_flow_control(flow_control).map_err(|e| {
+            Error::InternalIssue(format!(
+                "Could not set flow control {flow_control:?}, problem: {e}"
+            ))
+        })?;
+    }
+
+    read_back(&*port, baud, flow_control)
+}
+
+fn handle_control<B: SerialBackend>(
+    port: &mut B::Port,
+    control: SerialPortControl,
+    settings: &Mutex<PortSettings>,
+) {
+    match control {
+        SerialPortControl::Reconfigure {
+            baud,
+            flow_control,
+            reply,
+        } => {
+            info!(?baud, ?flow_control, "Reconfiguring");
+
+            let result = match B::reconfigure(port, baud, flow_control) {
+                Ok(actual) => {
+                    *settings
+                        .lock()
+                        .expect("Settings lock should not be poisoned") = actual;
+                    Ok(())
+                }
+                Err(e) => {
+                    warn!(%e, "Reconfiguring failed");
+                    Err(e)
+                }
+            };
+
+            if reply.send(result).is_err() {
+                debug!("Reconfigured, but nobody is waiting for the result");
+            }
+        }
+        SerialPortControl::Flush { tx, rx, reply } => {
+            info!(tx, rx, "Flushing");
+
+            let buffer = match (tx, rx) {
+                (true, true) => Some(serialport::ClearBuffer::All),
+                (true, false) => Some(serialport::ClearBuffer::Output),
+                (false, true) => Some(serialport::ClearBuffer::Input),
+                (false, false) => None,
+            };
+
+            let result = buffer.map_or(Ok(()), |buffer| B::clear(port, buffer));
+            if let Err(e) = &result {
+                warn!(%e, "Flushing failed");
+            }
+
+            if reply.send(result).is_err() {
+                debug!("Flushed, but nobody is waiting for the result");
+            }
+        }
+    }
+}
+
 pub(crate) struct SerialPortHandle {
-    pub(crate) tty: String,
+    pub(crate) id: InternalEndpointId,
+    pub(crate) device: Option<String>,
     pub(crate) handle: JoinHandle<()>,
     pub(crate) serial_tx: UnboundedSender<SerialMessageBytes>,
+    pub(crate) control_tx: UnboundedSender<SerialPortControl>,
     pub(crate) broadcast_tx: broadcast::Sender<endpoint::EndpointEvent>,
     pub(crate) semaphore: EndpointSemaphore,
     pub(crate) labels: Labels,
+    pub(crate) description: Option<String>,
+    pub(crate) prompt: Option<String>,
+    pub(crate) write_latency: WriteLatency,
+    pub(crate) framing: FramingStats,
+    pub(crate) settings: Arc<Mutex<PortSettings>>,
+    pub(crate) opened_at: chrono::DateTime<chrono::Utc>,
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use pretty_assertions::assert_eq;
+    use serialport::FlowControl;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    use super::*;
+    use crate::serial::backend::NullSerial;
+
+    const TIMEOUT: Duration = Duration::from_secs(5);
+
+    #[cfg(unix)]
+    #[test]
+    fn settings_are_read_back_from_port() {
+        let (mut port, _other_end) =
+            serialport::TTYPort::pair().expect("Should be able to open a pty pair");
+        port.set_baud_rate(9600).unwrap();
+        port.set_flow_control(FlowControl::None).unwrap();
+
+        let settings = read_back(&port, Some(9600), Some(FlowControl::None)).unwrap();
+
+        assert_eq!(
+            settings,
+            PortSettings {
+                baud: 9600,
+                flow_control: FlowControl::None
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn lines_from_wire_are_broadcast() {
+        let backend = NullSerial::default();
+        let mut wire = backend.add_port();
+
+        let handle = SerialPortBuilder::new("null")
+            .set_transforms(vec![Transform::Trim])
+            .build_with(&backend)
+            .unwrap();
+        let mut events = handle.broadcast_tx.subscribe();
+
+        wire.write_all(b"  hello\nwor").await.unwrap();
+        wire.write_all(b"ld\n").await.unwrap();
+
+        for expected in ["hello", "world"] {
+            let event = tokio::time::timeout(TIMEOUT, events.recv())
+                .await
+                .unwrap()
+                .unwrap();
+            let endpoint::EndpointEvent::FromWire(message) = event else {
+                panic!("Expected a message from wire, got {event:?}");
+            };
+            assert_eq!(message, expected.as_bytes());
+        }
+    }
+
+    #[tokio::test]
+    async fn burst_is_split_into_lines_regardless_of_read_buffer_size() {
+        let lines = (0..500)
+            .map(|i| format!("line {i} {}", "x".repeat(i % 50)))
+            .collect::<Vec<_>>();
+        let burst = lines
+            .iter()
+            .map(|line| format!("{line}\n"))
+            .collect::<String>();
+
+        for size in [4, 64 * 1024] {
+            let backend = NullSerial::default();
+            let mut wire = backend.add_port();
+
+            let handle = SerialPortBuilder::new("null")
+                .set_read_buffer_size(size)
+                .build_with(&backend)
+                .unwrap();
+            let mut events = handle.broadcast_tx.subscribe();
+
+            // More than the wire holds, so it is read while being written.
+            let burst = burst.clone();
+            tokio::spawn(async move { wire.write_all(burst.as_bytes()).await });
+
+            for expected in &lines {
+                let event = tokio::time::timeout(TIMEOUT, events.recv())
+                    .await
+                    .unwrap()
+                    .unwrap();
+                let endpoint::EndpointEvent::FromWire(message) = event else {
+                    panic!("Expected a message from wire, got {event:?}");
+                };
+                assert_eq!(
+                    String::from_utf8_lossy(&message),
+                    expected.as_str(),
+                    "Read buffer size: {size}"
+                );
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn oversized_lines_are_dropped_and_counted() {
+        let backend = NullSerial::default();
+        let mut wire = backend.add_port();
+
+        let handle = SerialPortBuilder::new("null")
+            .set_line_codec(LinesCodec::default().set_max_length(8))
+            .build_with(&backend)
+            .unwrap();
+        let mut events = handle.broadcast_tx.subscribe();
+
+        wire.write_all(b"way too long for a line").await.unwrap();
+        wire.write_all(b" indeed\nok\n").await.unwrap();
+
+        let event = tokio::time::timeout(TIMEOUT, events.recv())
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(matches!(event, endpoint::EndpointEvent::FromWire(m) if m == b"ok"));
+
+        assert_eq!(
+            handle.framing.summary(),
+            metrics::FramingSummary {
+                decode_errors: 0,
+                oversized_frames: 1,
+                dropped_bytes: "way too long for a line indeed".len(),
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn lines_ending_in_the_prompt_are_not_held_back() {
+        let backend = NullSerial::default();
+        let mut wire = backend.add_port();
+
+        let handle = SerialPortBuilder::new("null")
+            .set_prompt("uart:~$ ".into())
+            .build_with(&backend)
+            .unwrap();
+        let mut events = handle.broadcast_tx.subscribe();
+
+        wire.write_all(b"version 1.2\nuart:~$ ").await.unwrap();
+
+        for expected in ["version 1.2", "uart:~$ "] {
+            let event = tokio::time::timeout(TIMEOUT, events.recv())
+                .await
+                .unwrap()
+                .unwrap();
+            let endpoint::EndpointEvent::FromWire(message) = event else {
+                panic!("Expected a message from wire, got {event:?}");
+            };
+            assert_eq!(String::from_utf8_lossy(&message), expected);
+        }
+    }
+
+    #[tokio::test]
+    async fn decode_errors_are_counted() {
+        let backend = NullSerial::default();
+        let _wire = backend.add_failing_port();
+
+        let handle = SerialPortBuilder::new("null").build_with(&backend).unwrap();
+        let framing = handle.framing.clone();
+        assert_eq!(framing.summary().decode_errors, 0);
+
+        // The handler gives up on the port after the error.
+        tokio::time::timeout(TIMEOUT, handle.handle)
+            .await
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(framing.summary().decode_errors, 1);
+    }
+
+    #[tokio::test]
+    async fn writes_are_terminated_and_put_on_wire() {
+        let backend = NullSerial::default();
+        let mut wire = backend.add_port();
+
+        let handle = SerialPortBuilder::new("null")
+            .set_write_terminator(b"\r\n".to_vec())
+            .build_with(&backend)
+            .unwrap();
+        let mut events = handle.broadcast_tx.subscribe();
+
+        handle.serial_tx.unbounded_send(b"hi".to_vec()).unwrap();
+
+        let mut on_wire = [0; 4];
+        tokio::time::timeout(TIMEOUT, wire.read_exact(&mut on_wire))
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(&on_wire, b"hi\r\n");
+
+        let event = tokio::time::timeout(TIMEOUT, events.recv())
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(matches!(event, endpoint::EndpointEvent::ToWire(m) if m == b"hi\r\n"));
+    }
+
+    #[tokio::test]
+    async fn reconfiguring_updates_the_read_back_settings() {
+        let backend = NullSerial::default();
+        let _wire = backend.add_port();
+
+        let handle = SerialPortBuilder::new("null").build_with(&backend).unwrap();
+        assert_eq!(handle.settings.lock().unwrap().baud, 115_200);
+
+        let (reply, result) = oneshot::channel();
+        handle
+            .control_tx
+            .unbounded_send(SerialPortControl::Reconfigure {
+                baud: Some(9600),
+                flow_control: Some(FlowControl::Hardware),
+                reply,
+            })
+            .unwrap();
+        tokio::time::timeout(TIMEOUT, result)
+            .await
+            .unwrap()
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(
+            *handle.settings.lock().unwrap(),
+            PortSettings {
+                baud: 9600,
+                flow_control: FlowControl::Hardware
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn port_going_away_ends_the_handler() {
+        let backend = NullSerial::default();
+        let wire = backend.add_port();
+
+        let handle = SerialPortBuilder::new("null").build_with(&backend).unwrap();
+        drop(wire);
+
+        tokio::time::timeout(TIMEOUT, handle.handle)
+            .await
+            .unwrap()
+            .unwrap();
+    }
+
+    #[test]
+    fn opening_a_missing_port_is_an_error() {
+        let backend = NullSerial::default();
+
+        assert!(matches!(
+            SerialPortBuilder::new("null").build_with(&backend),
+            Err(Error::InternalIssue(_))
+        ));
+    }
 }
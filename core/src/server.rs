@@ -1,33 +1,92 @@
-use std::net::SocketAddr;
+use std::{
+    net::SocketAddr,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
 
+use axum::extract::ws;
+use axum::http::StatusCode;
 use axum::routing::post;
-use axum::{response::IntoResponse, routing::get, Extension, Router};
-use tokio::sync::oneshot;
-use tracing::info;
+use axum::{response::IntoResponse, routing::get, Extension, Json, Router};
+use futures::{channel::mpsc, future, SinkExt, StreamExt};
+use serde::{Deserialize, Serialize};
+use tokio::{sync::oneshot, time::Instant};
+use tracing::{info, Instrument};
 
-use crate::{config::Config, control_center::ControlCenterHandle, websocket};
+use crate::{
+    client::ClientHandle,
+    config::Config,
+    control_center::{self, ControlCenterHandle, ControlCenterResponse},
+    error::Error,
+    peer,
+    user::User,
+    websocket,
+};
 use semver::Version;
 use tower::ServiceBuilder;
 
 /// The default port to run the server on.
 pub const DEFAULT_PORT: u16 = 3123;
 
+/// How long the health check waits for the control center before reporting it is not ready.
+const HEALTH_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Whether the server is ready to serve users.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+pub enum HealthStatus {
+    /// The control center is not answering yet.
+    Starting,
+
+    /// The control center answers requests.
+    Ready,
+}
+
+/// What the `/health` route answers with.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct Health {
+    /// Whether the server is ready.
+    pub status: HealthStatus,
+
+    /// How long the server has been running.
+    pub uptime: Duration,
+
+    /// How many endpoints the server knows of.
+    /// Zero until ready.
+    pub endpoints: usize,
+}
+
+/// What the server serves besides the websocket.
+pub(crate) const OTHER_ROUTES: [&str; 5] = ["/config", "/version", "/metrics", "/health", "/exit"];
+
+/// When the server started.
+#[derive(Debug, Clone, Copy)]
+struct Started(Instant);
+
 async fn run(config: Config, port: Option<u16>, allocated_port: Option<oneshot::Sender<u16>>) {
     config.validate().expect("Configuration must be valid");
 
+    let started = Started(Instant::now());
     let cc_handle = ControlCenterHandle::new(&config);
 
     let app = Router::new()
-        .route("/client", get(websocket::ws_handler))
+        .route(config.websocket_path(), get(websocket::ws_handler))
         .route("/config", get(show_config))
         .route("/version", get(show_version))
+        .route("/metrics", get(show_metrics))
+        .route("/health", get(show_health))
         .route("/exit", post(exit_server))
         .layer(
             ServiceBuilder::new()
                 // Each websocket needs to be able to reach the control center
                 .layer(Extension(cc_handle))
                 // The serial-keel config should be known to the web server
-                .layer(Extension(config.clone())),
+                .layer(Extension(config.clone()))
+                // Usernames chosen by connected clients
+                .layer(Extension(websocket::Usernames::default()))
+                .layer(Extension(started)),
         );
 
     let addr = SocketAddr::from(([0, 0, 0, 0], port.unwrap_or(0)));
@@ -57,6 +116,107 @@ pub async fn run_on_port(config: Config, port: u16) {
     run(config, Some(port), None).await
 }
 
+/// A server living in this process, which does not listen on any port.
+///
+/// Clients connect to it directly, without any networking in between.
+/// Useful for tests and tools which want to drive endpoints themselves.
+#[derive(Debug, Clone)]
+pub struct InProcess {
+    cc_handle: ControlCenterHandle,
+    config: Config,
+
+    /// How many clients have connected, used to name them.
+    connected: Arc<AtomicUsize>,
+}
+
+impl InProcess {
+    /// Start a server using the given configuration.
+    ///
+    /// Must be called from within a tokio runtime.
+    pub fn new(config: Config) -> Result<Self, Error> {
+        config.validate()?;
+
+        Ok(Self {
+            cc_handle: ControlCenterHandle::new(&config),
+            config,
+            connected: Arc::default(),
+        })
+    }
+
+    /// Connect a new client to the server.
+    ///
+    /// Users are named `in-process-<n>`, in the order they connected.
+    pub async fn connect(&self) -> Result<ClientHandle, Error> {
+        let n = self.connected.fetch_add(1, Ordering::Relaxed);
+        let user = User::new(&format!("in-process-{n}"));
+
+        let (client_tx, server_rx) = mpsc::unbounded();
+        let (server_tx, client_rx) = mpsc::unbounded();
+
+        let cc_handle = self.cc_handle.clone();
+        let config = self.config.clone();
+        let session = websocket::SessionId::next();
+        let span = websocket::connection_span(&user, None, session);
+        let server_user = user.clone();
+        tokio::spawn(
+            async move {
+                websocket::handle_connection(
+                    server_tx,
+                    server_rx.map(Ok),
+                    server_user,
+                    peer::Connection::new(session, None),
+                    cc_handle,
+                    &config,
+                )
+                .await
+            }
+            .instrument(span),
+        );
+
+        let sink = client_tx
+            .sink_map_err(|_| tungstenite::Error::ConnectionClosed)
+            .with(|message: tungstenite::Message| {
+                future::ready(Ok::<_, tungstenite::Error>(to_server_message(message)))
+            });
+        let stream = client_rx.map(|message| Ok(to_client_message(message)));
+
+        ClientHandle::from_frames(Box::pin(sink), stream.boxed(), Some(user)).await
+    }
+}
+
+/// A message from an in-process client, as the server would have received it.
+fn to_server_message(message: tungstenite::Message) -> ws::Message {
+    match message {
+        tungstenite::Message::Text(text) => ws::Message::Text(text),
+        tungstenite::Message::Binary(bytes) => ws::Message::Binary(bytes),
+        tungstenite::Message::Ping(bytes) => ws::Message::Ping(bytes),
+        tungstenite::Message::Pong(bytes) => ws::Message::Pong(bytes),
+        tungstenite::Message::Close(frame) => {
+            ws::Message::Close(frame.map(|frame| ws::CloseFrame {
+                code: frame.code.into(),
+                reason: frame.reason,
+            }))
+        }
+        tungstenite::Message::Frame(frame) => ws::Message::Binary(frame.into_data()),
+    }
+}
+
+/// A message to an in-process client, as it would have received it.
+fn to_client_message(message: ws::Message) -> tungstenite::Message {
+    match message {
+        ws::Message::Text(text) => tungstenite::Message::Text(text),
+        ws::Message::Binary(bytes) => tungstenite::Message::Binary(bytes),
+        ws::Message::Ping(bytes) => tungstenite::Message::Ping(bytes),
+        ws::Message::Pong(bytes) => tungstenite::Message::Pong(bytes),
+        ws::Message::Close(frame) => {
+            tungstenite::Message::Close(frame.map(|frame| tungstenite::protocol::CloseFrame {
+                code: frame.code.into(),
+                reason: frame.reason,
+            }))
+        }
+    }
+}
+
 #[cfg(unix)]
 async fn exit_server() {
     use nix::sys::signal;
@@ -75,6 +235,49 @@ async fn show_config(Extension(config): Extension<Config>) -> impl IntoResponse
     config.serialize_pretty()
 }
 
+async fn show_metrics(
+    Extension(mut cc_handle): Extension<ControlCenterHandle>,
+) -> impl IntoResponse {
+    match cc_handle
+        .perform_action(User::new("metrics"), control_center::Action::Metrics)
+        .await
+    {
+        Ok(ControlCenterResponse::Metrics(metrics)) => {
+            serde_json::to_string_pretty(&metrics).unwrap()
+        }
+        Ok(_) => unreachable!(),
+        Err(e) => e.to_string(),
+    }
+}
+
+async fn show_health(
+    Extension(mut cc_handle): Extension<ControlCenterHandle>,
+    Extension(Started(started)): Extension<Started>,
+) -> impl IntoResponse {
+    let endpoints = tokio::time::timeout(
+        HEALTH_TIMEOUT,
+        cc_handle.perform_action(User::new("health"), control_center::Action::ListEndpoints),
+    )
+    .await;
+
+    let (code, status, endpoints) = match endpoints {
+        Ok(Ok(ControlCenterResponse::Endpoints(endpoints))) => {
+            (StatusCode::OK, HealthStatus::Ready, endpoints.len())
+        }
+        Ok(Ok(_)) => unreachable!(),
+        Ok(Err(_)) | Err(_) => (StatusCode::SERVICE_UNAVAILABLE, HealthStatus::Starting, 0),
+    };
+
+    (
+        code,
+        Json(Health {
+            status,
+            uptime: started.elapsed(),
+            endpoints,
+        }),
+    )
+}
+
 async fn show_version() -> impl IntoResponse {
     let version = Version::parse(env!("CARGO_PKG_VERSION"))
         .expect("The server version should always be semver parsable!");
@@ -1,13 +1,26 @@
 use std::fmt::Display;
 use std::sync::Arc;
 
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
+use crate::error::Error;
+
+/// The longest username a client may choose.
+pub const MAX_USERNAME_LEN: usize = 64;
+
 /// A user of the serial keel server.
-#[derive(Debug, Clone, Hash, PartialEq, Eq, Serialize, Deserialize, PartialOrd, Ord)]
+#[derive(
+    Debug, Clone, Hash, PartialEq, Eq, Serialize, Deserialize, PartialOrd, Ord, JsonSchema,
+)]
 pub struct User {
     /// The user's name.
     pub name: Arc<String>,
+
+    /// A tag the user chose for its session when connecting, if any.
+    /// Helps telling apart which session did what in the event log.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub session_tag: Option<Arc<String>>,
 }
 
 impl User {
@@ -15,12 +28,53 @@ impl User {
     pub fn new(name: &str) -> Self {
         Self {
             name: Arc::new(name.into()),
+            session_tag: None,
+        }
+    }
+
+    /// Check that a name a client chose for itself is acceptable.
+    ///
+    /// It must be non-empty, at most [`MAX_USERNAME_LEN`] characters,
+    /// and only contain ASCII letters, digits, `-`, `_` and `.`.
+    pub fn validate_name(name: &str) -> Result<(), Error> {
+        if name.is_empty() {
+            return Err(Error::InvalidUsername("The name is empty".into()));
+        }
+
+        if name.len() > MAX_USERNAME_LEN {
+            return Err(Error::InvalidUsername(format!(
+                "The name is {} characters, the maximum is {MAX_USERNAME_LEN}",
+                name.len()
+            )));
         }
+
+        if let Some(bad) = name
+            .chars()
+            .find(|c| !(c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.')))
+        {
+            return Err(Error::InvalidUsername(format!(
+                "The name `{name}` contains `{bad}`, only ASCII letters, digits, `-`, `_` and `.` are allowed"
+            )));
+        }
+
+        Ok(())
+    }
+
+    /// Tag this user's session.
+    pub fn with_session_tag(mut self, tag: &str) -> Self {
+        self.session_tag = Some(Arc::new(tag.into()));
+        self
     }
 }
 
 impl Display for User {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{}", self.name)
+        write!(f, "{}", self.name)?;
+
+        if let Some(tag) = &self.session_tag {
+            write!(f, " ({tag})")?;
+        }
+
+        Ok(())
     }
 }
@@ -1,27 +1,140 @@
-use std::net::SocketAddr;
+use std::{
+    collections::HashSet,
+    fmt::Display,
+    net::SocketAddr,
+    ops::ControlFlow,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+};
 
 use axum::{
     extract::{
         ws::{Message, WebSocket},
         ConnectInfo, WebSocketUpgrade,
     },
+    http::HeaderMap,
     response::IntoResponse,
     Extension, TypedHeader,
 };
 use futures::stream::Stream;
 use futures::{sink::Sink, SinkExt, StreamExt};
-use tokio::sync::mpsc;
-use tracing::{debug, info, info_span, trace, warn, Instrument};
+use tokio::sync::{mpsc, oneshot};
+use tracing::{debug, error, field, info, info_span, trace, warn, Instrument, Span};
 
 use crate::{
-    actions::ResponseResult, control_center::ControlCenterHandle, error, peer, user::User,
+    actions::{self, ResponseResult},
+    config::Config,
+    control_center::ControlCenterHandle,
+    error, peer,
+    user::User,
 };
 
+/// The names connected clients chose for themselves.
+/// Users are told apart by name, so a name may only be used by one client at a time.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Usernames(Arc<Mutex<HashSet<String>>>);
+
+impl Usernames {
+    /// Take the given name until the returned claim is dropped.
+    fn claim(&self, name: &str) -> Result<UsernameClaim, error::Error> {
+        User::validate_name(name)?;
+
+        if !self
+            .0
+            .lock()
+            .expect("Usernames lock should not be poisoned")
+            .insert(name.to_owned())
+        {
+            return Err(error::Error::InvalidUsername(format!(
+                "`{name}` is already connected"
+            )));
+        }
+
+        Ok(UsernameClaim {
+            usernames: self.clone(),
+            name: name.to_owned(),
+        })
+    }
+}
+
+/// A name in use by a connected client.
+#[derive(Debug)]
+struct UsernameClaim {
+    usernames: Usernames,
+    name: String,
+}
+
+impl Drop for UsernameClaim {
+    fn drop(&mut self) {
+        self.usernames
+            .0
+            .lock()
+            .expect("Usernames lock should not be poisoned")
+            .remove(&self.name);
+    }
+}
+
+/// Tells connections apart in the logs, even ones of the same user.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct SessionId(u64);
+
+impl SessionId {
+    /// The id of a new connection, unique for as long as the server runs.
+    pub(crate) fn next() -> Self {
+        static NEXT: AtomicU64 = AtomicU64::new(0);
+        Self(NEXT.fetch_add(1, Ordering::Relaxed))
+    }
+}
+
+impl From<SessionId> for u64 {
+    fn from(session: SessionId) -> Self {
+        session.0
+    }
+}
+
+impl Display for SessionId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+/// The span a connection is served in, for as long as it lasts.
+/// Everything logged on behalf of the connection carries its fields.
+pub(crate) fn connection_span(user: &User, remote: Option<SocketAddr>, session: SessionId) -> Span {
+    let span = info_span!("User", %user, %session, remote = field::Empty);
+    if let Some(remote) = remote {
+        span.record("remote", field::display(remote));
+    }
+
+    span
+}
+
+/// Claim the username the client asked for, if any.
+fn claim_username(
+    headers: &HeaderMap,
+    usernames: &Usernames,
+) -> Result<Option<UsernameClaim>, error::Error> {
+    let Some(name) = headers.get(actions::USERNAME_HEADER) else {
+        return Ok(None);
+    };
+
+    let name = name
+        .to_str()
+        .map_err(|_| error::Error::InvalidUsername("The name is not valid visible ASCII".into()))?;
+
+    usernames.claim(name).map(Some)
+}
+
 pub(crate) async fn ws_handler(
     ws: WebSocketUpgrade,
     user_agent: Option<TypedHeader<headers::UserAgent>>,
     ConnectInfo(addr): ConnectInfo<SocketAddr>,
     Extension(cc_handle): Extension<ControlCenterHandle>,
+    Extension(config): Extension<Config>,
+    Extension(usernames): Extension<Usernames>,
+    headers: HeaderMap,
 ) -> impl IntoResponse {
     if let Some(TypedHeader(user_agent)) = user_agent {
         info!("`{}`@`{addr}` connected", user_agent.as_str());
@@ -29,103 +142,477 @@ pub(crate) async fn ws_handler(
 
     debug!("Client connecting");
 
+    let admission = check_protocol(&headers).and_then(|()| claim_username(&headers, &usernames));
+    let session_tag = headers
+        .get(actions::SESSION_TAG_HEADER)
+        .and_then(|tag| tag.to_str().ok())
+        .map(str::to_owned);
+
     ws.on_upgrade(move |socket| {
-        let user = User::new(&addr.to_string());
+        let mut user = match &admission {
+            Ok(Some(claim)) => User::new(&claim.name),
+            _ => User::new(&addr.to_string()),
+        };
+        if let Some(tag) = session_tag {
+            user = user.with_session_tag(&tag);
+        }
 
-        let span = info_span!("User", %user);
+        let session = SessionId::next();
+        let span = connection_span(&user, Some(addr), session);
+        let connection = peer::Connection::new(session, Some(addr));
 
-        handle_websocket(socket, user, cc_handle).instrument(span)
+        async move {
+            match admission {
+                // The name is in use until the client leaves.
+                Ok(_claim) => handle_websocket(socket, user, connection, cc_handle, &config).await,
+                Err(e) => reject(socket, e).await,
+            }
+        }
+        .instrument(span)
     })
 }
 
+/// Check the protocol version the client asked for, if any.
+fn check_protocol(headers: &HeaderMap) -> Result<(), error::Error> {
+    let Some(version) = headers.get(actions::PROTOCOL_VERSION_HEADER) else {
+        return Ok(());
+    };
+
+    let server = actions::PROTOCOL_VERSION;
+
+    match version.to_str().ok().and_then(|v| v.parse::<u32>().ok()) {
+        Some(client) if client == server => Ok(()),
+        Some(client) => Err(error::Error::IncompatibleProtocol(format!(
+            "The client speaks protocol version {client}, the server speaks {server}"
+        ))),
+        None => Err(error::Error::IncompatibleProtocol(format!(
+            "The client protocol version `{version:?}` is not understood, the server speaks {server}"
+        ))),
+    }
+}
+
+/// Tell the client why it is not welcome, then close the connection.
+async fn reject(mut websocket: WebSocket, error: error::Error) {
+    info!("Rejecting client: {error}");
+
+    let response: ResponseResult = Err(error);
+    let response = serde_json::to_string(&response).expect("Serialize should work");
+
+    if websocket.send(Message::Text(response)).await.is_err() {
+        debug!("client disconnected");
+        return;
+    }
+
+    if let Err(e) = websocket.close().await {
+        debug!("Close error: {e:?}");
+    }
+}
+
+/// Tell the client about a problem with what it sent.
+/// Breaks if the connection is no longer written to, i.e. the client is gone.
+fn respond(
+    sender: &mpsc::UnboundedSender<ResponseResult>,
+    response: ResponseResult,
+) -> ControlFlow<()> {
+    if sender.send(response).is_ok() {
+        ControlFlow::Continue(())
+    } else {
+        debug!("client can no longer be responded to");
+        ControlFlow::Break(())
+    }
+}
+
+/// Hand the request to the peer, unless it writes more than allowed.
+fn forward(
+    request: actions::Action,
+    peer_handle: &peer::PeerHandle,
+    sender: &mpsc::UnboundedSender<ResponseResult>,
+    max_message_bytes: Option<usize>,
+) -> ControlFlow<()> {
+    match (request.message_len(), max_message_bytes) {
+        (Some(size), Some(max)) if size > max => respond(
+            sender,
+            Err(error::Error::MessageTooLarge { size, max }.correlated(request.correlation_id())),
+        ),
+        _ => {
+            peer_handle.send(request);
+            ControlFlow::Continue(())
+        }
+    }
+}
+
 pub(crate) async fn read<S>(
     mut receiver: S,
     sender: mpsc::UnboundedSender<ResponseResult>,
     peer_handle: peer::PeerHandle,
-) where
+    binary_responses: Arc<AtomicBool>,
+    max_message_bytes: Option<usize>,
+) -> Result<(), error::Error>
+where
     S: Unpin,
     S: Stream<Item = Result<Message, axum::Error>>,
 {
-    while let Some(Ok(msg)) = receiver.next().await {
-        match msg {
+    loop {
+        let msg = tokio::select! {
+            msg = receiver.next() => match msg {
+                Some(Ok(msg)) => msg,
+                _ => break,
+            },
+            () = peer_handle.closed() => {
+                error!("The peer stopped taking requests");
+                break;
+            }
+        };
+
+        let handled = match msg {
             Message::Text(request_text) => {
                 trace!(%request_text, "peer request");
                 match serde_json::from_str(&request_text) {
-                    Ok(request) => peer_handle.send(request),
-                    Err(e) => {
-                        sender
-                            .send(Err(error::Error::BadJson {
-                                request: request_text,
-                                problem: e.to_string(),
-                            }))
-                            .expect("Sender should be alive");
-                    }
+                    Ok(request) => forward(request, &peer_handle, &sender, max_message_bytes),
+                    Err(e) => respond(
+                        &sender,
+                        Err(error::Error::BadJson {
+                            request: request_text,
+                            problem: e.to_string(),
+                        }),
+                    ),
                 }
             }
-            Message::Binary(_) => {
-                debug!("client sent binary data");
+            Message::Binary(request_bytes) => {
+                trace!(len = request_bytes.len(), "peer binary request");
+
+                // The client has shown it speaks binary, so answer in kind from now on.
+                binary_responses.store(true, Ordering::Relaxed);
+
+                match actions::Action::deserialize_binary(&request_bytes) {
+                    Ok(request) => forward(request, &peer_handle, &sender, max_message_bytes),
+                    Err(e) => respond(&sender, Err(e)),
+                }
             }
             Message::Ping(_) => {
                 debug!("socket ping");
+                ControlFlow::Continue(())
             }
             Message::Pong(_) => {
                 debug!("socket pong");
+                ControlFlow::Continue(())
             }
-            Message::Close(_) => {
-                debug!("client disconnected");
+            Message::Close(frame) => {
+                // No need to wait for the stream to end,
+                // so that anything the user held is released right away.
+                info!(?frame, "client disconnected cleanly");
+                break;
             }
+        };
+
+        // Shutting the peer down below lets the control center clean up after the user.
+        if handled.is_break() {
+            break;
         }
     }
 
     // Async drop?
-    peer_handle.shutdown().await;
+    let result = peer_handle.shutdown().await;
 
     debug!("no more stuff");
+    result
 }
 
 pub(crate) async fn write(
     mut sender: impl Sink<Message> + Unpin,
     mut receiver: mpsc::UnboundedReceiver<ResponseResult>,
+    binary_responses: Arc<AtomicBool>,
+    mut close: oneshot::Receiver<()>,
 ) {
-    while let Some(response) = receiver.recv().await {
+    let to_message = |response: &ResponseResult| {
+        if binary_responses.load(Ordering::Relaxed) {
+            Message::Binary(actions::serialize_response_binary(response))
+        } else {
+            Message::Text(serde_json::to_string(response).expect("Serialize should work"))
+        }
+    };
+
+    let mut may_close = true;
+
+    loop {
+        let response = tokio::select! {
+            response = receiver.recv() => match response {
+                Some(response) => response,
+                None => return,
+            },
+            closing = &mut close, if may_close => match closing {
+                Ok(()) => break,
+                Err(_) => {
+                    may_close = false;
+                    continue;
+                }
+            },
+        };
+
         match &response {
             Ok(r) => debug!("Response: <{r}>"),
             Err(e) => info!("Error response: <{e}>"),
         }
 
-        let response = serde_json::to_string(&response).expect("Serialize should work");
-
-        if sender.send(Message::Text(response)).await.is_err() {
+        if sender.send(to_message(&response)).await.is_err() {
             debug!("client disconnected");
             return;
         }
         debug!("Reply flushed");
     }
+
+    // Flush what is left, then close the connection.
+    while let Ok(response) = receiver.try_recv() {
+        if sender.send(to_message(&response)).await.is_err() {
+            debug!("client disconnected");
+            return;
+        }
+    }
+
+    if sender.close().await.is_err() {
+        debug!("client disconnected");
+    }
 }
 
 pub(crate) async fn handle_websocket(
     websocket: WebSocket,
     user: User,
+    connection: peer::Connection,
     cc_handle: ControlCenterHandle,
+    config: &Config,
 ) {
     let (stream_sender, stream_receiver) = websocket.split();
+    handle_connection(
+        stream_sender,
+        stream_receiver,
+        user,
+        connection,
+        cc_handle,
+        config,
+    )
+    .await
+}
+
+/// Serve a user over any connection carrying websocket messages.
+pub(crate) async fn handle_connection<Tx, Rx>(
+    stream_sender: Tx,
+    stream_receiver: Rx,
+    user: User,
+    connection: peer::Connection,
+    cc_handle: ControlCenterHandle,
+    config: &Config,
+) where
+    Tx: Sink<Message> + Unpin + Send + 'static,
+    Rx: Stream<Item = Result<Message, axum::Error>> + Unpin + Send + 'static,
+{
     let (response_sender, response_receiver) = mpsc::unbounded_channel::<ResponseResult>();
 
-    let peer_handle = peer::PeerHandle::new(user, response_sender.clone(), cc_handle);
+    // Sent before anything else can be, so clients may rely on it coming first.
+    response_sender
+        .send(Ok(actions::Response::handshake()))
+        .expect("The receiver is alive");
+
+    if !config.banner.is_empty() {
+        response_sender
+            .send(Ok(actions::Response::banner(config.banner.clone())))
+            .expect("The receiver is alive");
+    }
+
+    let peer_handle = peer::PeerHandle::new(
+        user,
+        connection,
+        response_sender.clone(),
+        cc_handle,
+        config.read_only,
+    );
+
+    // Responses are json in text frames until the client sends something binary.
+    let binary_responses = Arc::new(AtomicBool::new(false));
 
     let read_handle = tokio::spawn(
-        read(stream_receiver, response_sender, peer_handle).instrument(info_span!("Read")),
+        read(
+            stream_receiver,
+            response_sender,
+            peer_handle,
+            binary_responses.clone(),
+            config.max_message_bytes,
+        )
+        .instrument(info_span!("Read")),
+    );
+    let (close_tx, close_rx) = oneshot::channel();
+    let mut write_handle = tokio::spawn(
+        write(stream_sender, response_receiver, binary_responses, close_rx)
+            .instrument(info_span!("Write")),
     );
-    let write_handle =
-        tokio::spawn(write(stream_sender, response_receiver).instrument(info_span!("Write")));
 
     match read_handle.await {
-        Ok(()) => debug!("Read task joined"),
+        Ok(Ok(())) => debug!("Read task joined"),
+        Ok(Err(e)) => {
+            error!("Closing the connection: {e}");
+
+            // The user has been told why, make sure it gets through.
+            if close_tx.send(()).is_ok() {
+                if let Err(e) = (&mut write_handle).await {
+                    warn!("Write task join error: {e:?}");
+                }
+            }
+        }
         Err(e) => warn!("Read task join error: {e:?}"),
     }
 
     debug!("Aborting write task");
-    // This ensures the underlying TCP connection gets closed,
+    // This ensures the underlying connection gets closed,
     // which signals the peer that the session is over.
     write_handle.abort();
 }
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashMap, time::Duration};
+
+    use tracing::{field::Visit, span, Subscriber};
+    use tracing_subscriber::{layer::Context, prelude::*, registry::LookupSpan, Layer};
+
+    use super::*;
+    use crate::{
+        control_center::{self, ControlCenterResponse, Inform},
+        endpoint::EndpointId,
+        events,
+    };
+
+    /// Collects the name and value of fields.
+    struct Fields<'a>(&'a mut Vec<(String, String)>);
+
+    impl Visit for Fields<'_> {
+        fn record_debug(&mut self, field: &field::Field, value: &dyn std::fmt::Debug) {
+            self.0.push((field.name().to_owned(), format!("{value:?}")));
+        }
+    }
+
+    /// Captures the fields of the spans each log record was made in.
+    #[derive(Clone, Default)]
+    struct Captured {
+        spans: Arc<Mutex<HashMap<span::Id, Vec<(String, String)>>>>,
+        records: Arc<Mutex<Vec<Vec<(String, String)>>>>,
+    }
+
+    impl<S: Subscriber + for<'a> LookupSpan<'a>> Layer<S> for Captured {
+        fn on_new_span(&self, attrs: &span::Attributes<'_>, id: &span::Id, _ctx: Context<'_, S>) {
+            let mut fields = vec![];
+            attrs.record(&mut Fields(&mut fields));
+            self.spans.lock().unwrap().insert(id.clone(), fields);
+        }
+
+        fn on_record(&self, id: &span::Id, values: &span::Record<'_>, _ctx: Context<'_, S>) {
+            if let Some(fields) = self.spans.lock().unwrap().get_mut(id) {
+                values.record(&mut Fields(fields));
+            }
+        }
+
+        fn on_event(&self, event: &tracing::Event<'_>, ctx: Context<'_, S>) {
+            let spans = self.spans.lock().unwrap();
+            let fields = ctx
+                .event_scope(event)
+                .into_iter()
+                .flatten()
+                .flat_map(|span| spans.get(&span.id()).cloned().unwrap_or_default())
+                .collect();
+            self.records.lock().unwrap().push(fields);
+        }
+    }
+
+    #[test]
+    fn logs_of_a_connection_carry_its_session() {
+        let captured = Captured::default();
+        let subscriber = tracing_subscriber::registry().with(captured.clone());
+
+        let session = SessionId::next();
+        let remote: SocketAddr = "127.0.0.1:4321".parse().unwrap();
+
+        tracing::subscriber::with_default(subscriber, || {
+            let span = connection_span(&User::new("logged"), Some(remote), session);
+            let _entered = span.enter();
+
+            // Like the tasks reading and writing the connection.
+            info_span!("Read").in_scope(|| info!("Hello"));
+        });
+
+        let records = captured.records.lock().unwrap();
+        assert_eq!(records.len(), 1);
+
+        for field in [
+            ("session".to_owned(), session.to_string()),
+            ("remote".to_owned(), remote.to_string()),
+            ("user".to_owned(), "logged".to_owned()),
+        ] {
+            assert!(
+                records[0].contains(&field),
+                "{field:?} should be among {:?}",
+                records[0]
+            );
+        }
+
+        assert_ne!(SessionId::next(), session);
+    }
+
+    #[tokio::test]
+    async fn disconnect_racing_actions_ends_the_session_cleanly() {
+        let mut cc_handle = ControlCenterHandle::new(&Config::default());
+        let observer = User::new("observer");
+        cc_handle.inform(Inform::UserArrived(observer.clone()));
+
+        let Ok(ControlCenterResponse::EventObserver(mut events)) = cc_handle
+            .perform_action(observer, control_center::Action::SubscribeToEvents)
+            .await
+        else {
+            panic!("Should be able to subscribe to events");
+        };
+
+        let user = User::new("racing");
+        let (sender, responses) = mpsc::unbounded_channel();
+        let peer_handle = peer::PeerHandle::new(
+            user.clone(),
+            peer::Connection::new(SessionId::next(), None),
+            sender.clone(),
+            cc_handle,
+            false,
+        );
+
+        // The connection went away while these were on their way,
+        // and the client never hangs up properly.
+        drop(responses);
+        let requests = futures::stream::iter([
+            Ok(Message::Text(
+                actions::Action::control(&EndpointId::mock("raced")).serialize(),
+            )),
+            Ok(Message::Text(actions::Action::list_endpoints().serialize())),
+        ])
+        .chain(futures::stream::pending());
+
+        let result = tokio::time::timeout(
+            Duration::from_secs(5),
+            read(
+                requests,
+                sender,
+                peer_handle,
+                Arc::new(AtomicBool::new(false)),
+                None,
+            ),
+        )
+        .await
+        .expect("Reading should stop once the client cannot be responded to");
+        assert_eq!(result, Ok(()));
+
+        loop {
+            let event = events.recv().await.expect("Should see the user leave");
+            if let events::Event::User(events::user::UserEvent {
+                user: left,
+                event: events::user::Event::Disconnected,
+            }) = event.inner
+            {
+                assert_eq!(left, user);
+                break;
+            }
+        }
+    }
+}
@@ -0,0 +1,122 @@
+mod common;
+
+// Feature: The configured mocks are only controllable by name if endpoints are shared
+#[cfg(feature = "mocks-share-endpoints")]
+mod access {
+    use color_eyre::Result;
+    use serial_keel::{
+        client::ClientHandle,
+        config::{Config, ConfigEndpoint, Group},
+        endpoint::EndpointId,
+        error::Error,
+    };
+
+    use super::common::*;
+
+    fn restricted_config() -> Config {
+        let mut config = Config::default();
+        config.endpoints.push(ConfigEndpoint {
+            labels: "acl-allow".into(),
+            allow_users: Some(vec!["alice".into()]),
+            ..EndpointId::mock("acl-allowed").into()
+        });
+        config.endpoints.push(ConfigEndpoint {
+            labels: "acl-deny".into(),
+            deny_users: vec!["mallory".into()],
+            ..EndpointId::mock("acl-denied").into()
+        });
+        config.endpoints.push(EndpointId::mock("acl-open").into());
+        config.groups.push(Group {
+            allow_users: Some(vec!["alice".into()]),
+            ..Group::new_with_labels(&["acl-group"], vec![EndpointId::mock("acl-group-mock")])
+        });
+        config
+    }
+
+    #[tokio::test]
+    async fn allowed_user_may_control() -> Result<()> {
+        let port = start_server_with_config(restricted_config()).await;
+
+        let mut alice = ClientHandle::new_with_username("localhost", port, "alice").await?;
+        alice.control(&EndpointId::mock("acl-allowed")).await?;
+        alice.control(&EndpointId::mock("acl-denied")).await?;
+        alice.control_any(&["acl-group"]).await?;
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn denied_user_may_not_control() -> Result<()> {
+        let port = start_server_with_config(restricted_config()).await;
+
+        let mut mallory = ClientHandle::new_with_username("localhost", port, "mallory").await?;
+        for id in ["acl-allowed", "acl-denied"] {
+            let response = mallory.control(&EndpointId::mock(id)).await;
+            assert!(
+                matches!(response, Err(Error::AccessDenied(_))),
+                "Expected access to {id} denied, got {response:?}"
+            );
+        }
+
+        for label in ["acl-allow", "acl-deny", "acl-group"] {
+            let response = mallory.control_any(&[label]).await;
+            assert!(
+                matches!(response, Err(Error::AccessDenied(_))),
+                "Expected access to {label} denied, got {response:?}"
+            );
+        }
+
+        // Observing is not restricted.
+        mallory.observe(&EndpointId::mock("acl-allowed")).await?;
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn unrestricted_endpoint_may_be_controlled_by_anyone() -> Result<()> {
+        let port = start_server_with_config(restricted_config()).await;
+
+        let mut mallory = ClientHandle::new_with_username("localhost", port, "mallory").await?;
+        mallory.control(&EndpointId::mock("acl-open")).await?;
+
+        let mut anonymous = ClientHandle::new("localhost", port).await?;
+        anonymous.control(&EndpointId::mock("acl-denied")).await?;
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn denied_group_member_denies_its_siblings() -> Result<()> {
+        let mut config = Config::default();
+        config.groups.push(Group {
+            endpoints: vec![
+                ConfigEndpoint {
+                    deny_users: vec!["mallory".into()],
+                    ..EndpointId::mock("acl-sibling-denied").into()
+                },
+                EndpointId::mock("acl-sibling-open").into(),
+            ],
+            ..Group::new_with_labels(&["acl-siblings"], vec![])
+        });
+        let port = start_server_with_config(config).await;
+
+        // Control of the open sibling would be control of the whole group
+        let mut mallory = ClientHandle::new_with_username("localhost", port, "mallory").await?;
+        let response = mallory.control(&EndpointId::mock("acl-sibling-open")).await;
+        assert!(
+            matches!(response, Err(Error::AccessDenied(_))),
+            "Expected access to the group denied, got {response:?}"
+        );
+
+        let response = mallory.control_any(&["acl-siblings"]).await;
+        assert!(
+            matches!(response, Err(Error::AccessDenied(_))),
+            "Expected access to the group denied, got {response:?}"
+        );
+
+        let mut alice = ClientHandle::new_with_username("localhost", port, "alice").await?;
+        alice.control(&EndpointId::mock("acl-sibling-open")).await?;
+
+        Ok(())
+    }
+}
@@ -1,9 +1,15 @@
 use color_eyre::Result;
-use common::{send_receive, start_server_and_connect};
+use common::{
+    connect_raw, connect_raw_with_protocol, receive, send_receive, send_receive_binary,
+    start_server, start_server_and_connect, start_server_with_config,
+};
 use serial_keel::{
-    actions::{Action, Response},
+    actions::{Action, Async, Capability, Response, Sync, PROTOCOL_VERSION},
+    client::ClientHandle,
+    config::Config,
     endpoint::EndpointId,
     error::Error,
+    serial::SerialMessage,
 };
 
 mod common;
@@ -67,14 +73,205 @@ async fn observe_mock_and_write_is_bad_no_control() -> Result<()> {
 
     let id = EndpointId::mock("some-mock");
 
-    let request = Action::Observe(id.clone()).serialize();
+    let request = Action::observe(&id).serialize();
     let response = send_receive(&mut client, request).await??;
     assert_observing!(response);
 
     let request = Action::write(&id, "Hi there".into()).serialize();
     let response = send_receive(&mut client, request).await?;
 
-    assert_result_error!(response, Error::NoPermit(_));
+    assert_result_error!(response, Error::NotControlling { .. });
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn observe_mock_and_write_bytes_is_bad_no_control() -> Result<()> {
+    let mut client = start_server_and_connect().await?;
+
+    let id = EndpointId::mock("some-bytes-mock");
+
+    let request = Action::observe(&id).serialize();
+    let response = send_receive(&mut client, request).await??;
+    assert_observing!(response);
+
+    let request = Action::write_bytes(&id, vec![0xde, 0xad]).serialize();
+    let response = send_receive(&mut client, request).await?;
+
+    assert_eq!(response, Err(Error::NotControlling { endpoint: id }));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn handshake_is_received_first() -> Result<()> {
+    let mut client = connect_raw(start_server().await).await?;
+
+    let response = receive(&mut client).await??;
+    let Response::Async(Async::Handshake(info)) = response else {
+        panic!("Expected a handshake first, got {response:?}");
+    };
+
+    assert_eq!(info.version.to_string(), env!("CARGO_PKG_VERSION"));
+    assert!(info.supports(Capability::Macros));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn banner_is_received_right_after_handshake() -> Result<()> {
+    let port = start_server_with_config(Config {
+        banner: "Maintenance at 18:00".into(),
+        ..Default::default()
+    })
+    .await;
+    let mut client = connect_raw(port).await?;
+
+    let response = receive(&mut client).await??;
+    assert!(matches!(response, Response::Async(Async::Handshake(_))));
+
+    let response = receive(&mut client).await??;
+    assert_eq!(
+        response,
+        Response::Async(Async::Banner("Maintenance at 18:00".into()))
+    );
+
+    let mut client = ClientHandle::new("localhost", port).await?;
+    client.list_endpoints().await?;
+    assert_eq!(client.banner().as_deref(), Some("Maintenance at 18:00"));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn empty_banner_is_not_sent() -> Result<()> {
+    let port = start_server().await;
+    let mut client = connect_raw(port).await?;
+
+    let response = receive(&mut client).await??;
+    assert!(matches!(response, Response::Async(Async::Handshake(_))));
+
+    let response = send_receive(&mut client, Action::list_endpoints().serialize()).await??;
+    assert!(matches!(response, Response::Sync(Sync::Endpoints(_))));
+
+    let mut client = ClientHandle::new("localhost", port).await?;
+    client.list_endpoints().await?;
+    assert_eq!(client.banner(), None);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn client_knows_server_capabilities() -> Result<()> {
+    let port = start_server().await;
+    let client = ClientHandle::new("localhost", port).await?;
+
+    assert!(client.server_capabilities().contains(&Capability::Macros));
+    assert_eq!(
+        client.server_info().version.to_string(),
+        env!("CARGO_PKG_VERSION")
+    );
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn matching_protocol_version_is_accepted() -> Result<()> {
+    let port = start_server().await;
+
+    let mut raw = connect_raw_with_protocol(port, &PROTOCOL_VERSION.to_string()).await?;
+    let response = receive(&mut raw).await??;
+    let Response::Async(Async::Handshake(info)) = response else {
+        panic!("Expected a handshake, got {response:?}");
+    };
+    assert_eq!(info.protocol, PROTOCOL_VERSION);
+
+    let client = ClientHandle::new("localhost", port).await?;
+    assert_eq!(client.server_info().protocol, PROTOCOL_VERSION);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn mismatching_protocol_version_is_rejected() -> Result<()> {
+    let port = start_server().await;
+
+    let mut client = connect_raw_with_protocol(port, &(PROTOCOL_VERSION + 1).to_string()).await?;
+    let response = receive(&mut client).await?;
+    assert_result_error!(response, Error::IncompatibleProtocol(_));
+
+    // The server hangs up after telling us why.
+    assert!(receive(&mut client).await.is_err());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn unparsable_protocol_version_is_rejected() -> Result<()> {
+    let port = start_server().await;
+
+    let mut client = connect_raw_with_protocol(port, "one").await?;
+    let response = receive(&mut client).await?;
+    assert_result_error!(response, Error::IncompatibleProtocol(_));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn binary_frames_round_trip() -> Result<()> {
+    let mut client = start_server_and_connect().await?;
+
+    let id = EndpointId::mock("binary-frames");
+    let response = send_receive_binary(&mut client, Action::observe(&id)).await??;
+    assert_observing!(response);
+
+    // Errors are binary too.
+    let response = send_receive_binary(&mut client, Action::observe(&id)).await?;
+    assert_result_error!(response, Error::SuperfluousRequest(_));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn client_can_use_binary_frames() -> Result<()> {
+    let port = start_server().await;
+    let mut client = ClientHandle::new("localhost", port).await?;
+    assert!(client
+        .server_capabilities()
+        .contains(&Capability::BinaryFrames));
+
+    client.use_binary_frames()?;
+
+    let mut writer = client.control_mock("binary-client").await?;
+    let mut reader = client.observe_mock("binary-client").await?;
+
+    writer.write([0xde, 0xad, 0xbe, 0xef, b'\n']).await?;
+    assert_eq!(
+        reader.next_message().await?,
+        SerialMessage::new_lossy([0xde, 0xad, 0xbe, 0xef])
+    );
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn binary_frames_keep_optional_fields_apart() -> Result<()> {
+    let port = start_server().await;
+
+    // The session tag is left out when there is none, which must not shift the fields after it.
+    let mut untagged = ClientHandle::new("localhost", port).await?;
+    untagged.use_binary_frames()?;
+    let info = untagged.who_am_i().await?;
+    assert_eq!(info.user.session_tag, None);
+
+    let mut tagged = ClientHandle::new_with_session_tag("localhost", port, "binary-ci").await?;
+    tagged.use_binary_frames()?;
+    let info = tagged.who_am_i().await?;
+    assert_eq!(
+        info.user.session_tag.as_deref().map(String::as_str),
+        Some("binary-ci")
+    );
+    assert!(info.remote.is_some());
 
     Ok(())
 }
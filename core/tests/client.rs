@@ -1,7 +1,15 @@
+use std::time::Duration;
+
 use color_eyre::Result;
 use common::*;
-use serial_keel::client::ClientHandle;
+use futures::StreamExt;
+use serial_keel::actions::ListedEndpoint;
+use serial_keel::client::{BlockingClient, ClientHandle, ControlTransition, ControlledEndpoint};
+use serial_keel::config::{Config, ConfigEndpoint};
 use serial_keel::endpoint::{EndpointId, LabelledEndpointId};
+use serial_keel::error::Error;
+use serial_keel::events::{general, Event};
+use tokio::time::timeout;
 use tracing::debug;
 
 mod common;
@@ -49,3 +57,640 @@ async fn can_use_mock() -> Result<()> {
 
     Ok(())
 }
+
+#[tokio::test]
+async fn observe_many_merges_endpoints() -> Result<()> {
+    let port = start_server().await;
+
+    let mut client = ClientHandle::new("localhost", port).await?;
+
+    let mut writer_a = client.control_mock("merge-a").await?;
+    let mut writer_b = client.control_mock("merge-b").await?;
+
+    let id_a = EndpointId::mock("merge-a");
+    let id_b = EndpointId::mock("merge-b");
+
+    let mut merged = client.observe_many(&[id_a.clone(), id_b.clone()]).await?;
+    assert_eq!(merged.endpoint_ids().len(), 2);
+
+    writer_a.write("a1\n").await?;
+    writer_b.write("b1\n").await?;
+    writer_a.write("a2\n").await?;
+
+    let mut received = vec![];
+    for _ in 0..3 {
+        let (id, message) = merged.next_message().await?;
+        received.push((EndpointId::from(id), message.as_str().to_owned()));
+    }
+
+    // Arrival order across endpoints is not guaranteed, but it is within one.
+    let from_a = received
+        .iter()
+        .filter(|(id, _)| id == &id_a)
+        .map(|(_, message)| message.as_str())
+        .collect::<Vec<_>>();
+    let from_b = received
+        .iter()
+        .filter(|(id, _)| id == &id_b)
+        .map(|(_, message)| message.as_str())
+        .collect::<Vec<_>>();
+
+    assert_eq!(from_a, vec!["a1", "a2"]);
+    assert_eq!(from_b, vec!["b1"]);
+
+    Ok(())
+}
+
+#[test]
+fn blocking_client_loopback() -> Result<()> {
+    // The blocking client brings its own runtime,
+    // so the server needs one of its own outside of it.
+    let server_runtime = tokio::runtime::Runtime::new()?;
+    let port = server_runtime.block_on(start_server());
+
+    let mut client = BlockingClient::new("localhost", port)?;
+
+    let mut writer = client.control_mock("blocking")?;
+    let mut reader = client.observe_mock("blocking")?;
+
+    client.write(&mut writer, "Hello\nblocking")?;
+
+    assert_eq!(client.next_message(&mut reader)?.as_str(), "Hello");
+    assert_eq!(client.next_message(&mut reader)?.as_str(), "blocking");
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn write_hex() -> Result<()> {
+    let port = start_server().await;
+
+    let mut client = ClientHandle::new("localhost", port).await?;
+
+    let mut writer = client.control_mock("hex").await?;
+    let mut reader = client.observe_mock("hex").await?;
+
+    // "Hello\n", mixing separators.
+    writer.write_hex("48 65 6c:6c 6F\t0a").await?;
+    assert_eq!(reader.next_message().await?.as_str(), "Hello");
+
+    // Separators are optional.
+    writer.write_hex("776f726c640a").await?;
+    assert_eq!(reader.next_message().await?.as_str(), "world");
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn write_hex_malformed_is_bad() -> Result<()> {
+    let port = start_server().await;
+
+    let mut client = ClientHandle::new("localhost", port).await?;
+    let mut writer = client.control_mock("bad-hex").await?;
+
+    for malformed in ["DE AD BX", "DE A", "0xDE", "+F", "", " : "] {
+        let result = writer.write_hex(malformed).await;
+        assert!(
+            matches!(result, Err(Error::BadUsage(_))),
+            "`{malformed}` gave {result:?}"
+        );
+    }
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn tee_to_file() -> Result<()> {
+    let port = start_server().await;
+
+    let mut client = ClientHandle::new("localhost", port).await?;
+
+    let mut writer = client.control_mock("tee").await?;
+    let mut reader = client.observe_mock("tee").await?;
+
+    let path = std::env::temp_dir().join(format!("serial-keel-tee-{}.log", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+
+    let tee = reader.tee_to_file(&path, false).await?;
+
+    writer.write("first\nsecond\n").await?;
+
+    // The reader still sees the messages.
+    assert_eq!(reader.next_message().await?.as_str(), "first");
+    assert_eq!(reader.next_message().await?.as_str(), "second");
+
+    tee.stop().await?;
+
+    writer.write("not teed\n").await?;
+    assert_eq!(reader.next_message().await?.as_str(), "not teed");
+
+    assert_eq!(std::fs::read_to_string(&path)?, "first\nsecond\n");
+    std::fs::remove_file(&path)?;
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn write_from_reader() -> Result<()> {
+    let port = start_server().await;
+
+    let mut client = ClientHandle::new("localhost", port).await?;
+
+    let mut writer = client.control_mock("streamed").await?;
+    let mut reader = client.observe_mock("streamed").await?;
+
+    let path = std::env::temp_dir().join(format!("serial-keel-stream-{}.txt", std::process::id()));
+    let lines = 100;
+    let contents = (0..lines)
+        .map(|i| format!("line {i:03}\n"))
+        .collect::<String>();
+    std::fs::write(&path, &contents)?;
+
+    // The mock loops back each write line by line,
+    // so keep chunks on line boundaries (each line is 9 bytes).
+    let file = tokio::fs::File::open(&path).await?;
+    let written = writer.write_from_reader(file, 9 * 4).await?;
+    assert_eq!(written, contents.len());
+
+    for i in 0..lines {
+        assert_eq!(
+            reader.next_message().await?.as_str(),
+            format!("line {i:03}")
+        );
+    }
+
+    std::fs::remove_file(&path)?;
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn forward_stream_to_line_sink() -> Result<()> {
+    let port = start_server().await;
+
+    let mut client = ClientHandle::new("localhost", port).await?;
+
+    let writer = client.control_mock("forwarded").await?;
+    let mut reader = client.observe_mock("forwarded").await?;
+
+    let lines = ["one", "two", "three"];
+    futures::stream::iter(lines.map(|line| Ok::<_, Error>(line.to_string())))
+        .forward(writer.into_line_sink())
+        .await?;
+
+    for line in lines {
+        assert_eq!(reader.next_message().await?.as_str(), line);
+    }
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn drain_empties_reader() -> Result<()> {
+    let port = start_server().await;
+
+    let mut client = ClientHandle::new("localhost", port).await?;
+
+    let mut writer = client.control_mock("drained").await?;
+    let mut reader = client.observe_mock("drained").await?;
+
+    writer.write("first\nsecond\nthird").await?;
+
+    // Let the messages arrive
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    let drained = reader.drain();
+    assert_eq!(
+        drained.iter().map(|m| m.as_str()).collect::<Vec<_>>(),
+        vec!["first", "second", "third"]
+    );
+
+    assert!(reader.drain().is_empty());
+    assert!(
+        timeout(Duration::from_millis(100), reader.next_message())
+            .await
+            .is_err(),
+        "Nothing should be left after draining"
+    );
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn controlled_endpoint_write_read_release() -> Result<()> {
+    let port = start_server().await;
+
+    let mut client = ClientHandle::new("localhost", port).await?;
+    let id = EndpointId::mock("controlled");
+
+    let mut endpoint = client.control_and_observe(&id).await?;
+    assert_eq!(endpoint.endpoint_id(), &LabelledEndpointId::new(&id));
+
+    endpoint.write_line("ping").await?;
+    assert_eq!(endpoint.next_message().await?.as_str(), "ping");
+
+    // The mock echoes, so the response is the request itself
+    assert_eq!(endpoint.request_response("pong").await?.as_str(), "pong");
+
+    let _reader = endpoint.release(&mut client).await?;
+
+    // Control would be a superfluous request had it not been released
+    let _writer = client.control(&id).await?;
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn compact_endpoint_ids_are_resolved_by_the_client() -> Result<()> {
+    let port = start_server().await;
+
+    let mut client = ClientHandle::new("localhost", port).await?;
+    client.compact_endpoint_ids(true).await?;
+
+    let first = EndpointId::mock("compact-first");
+    let second = EndpointId::mock("compact-second");
+    let mut first_endpoint = client.control_and_observe(&first).await?;
+    let mut second_endpoint = client.control_and_observe(&second).await?;
+
+    second_endpoint.write_line("to second").await?;
+    first_endpoint.write_line("to first").await?;
+
+    assert_eq!(first_endpoint.next_message().await?.as_str(), "to first");
+    assert_eq!(second_endpoint.next_message().await?.as_str(), "to second");
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn can_write_when_controlling() -> Result<()> {
+    let port = start_server().await;
+
+    let mut client = ClientHandle::new("localhost", port).await?;
+    let id = EndpointId::mock("dry-run");
+
+    let mut writer = client.control(&id).await?;
+    let mut reader = client.observe_mock("dry-run").await?;
+
+    client.can_write(&id).await?;
+
+    // Nothing was put on wire by checking, so the first message seen is the real write
+    writer.write("real").await?;
+    assert_eq!(reader.next_message().await?.as_str(), "real");
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn can_not_write_when_not_controlling() -> Result<()> {
+    let port = start_server().await;
+
+    let mut client = ClientHandle::new("localhost", port).await?;
+    let _reader = client.observe_mock("not-mine").await?;
+
+    let result = client.can_write(&EndpointId::mock("not-mine")).await;
+    assert!(matches!(result, Err(Error::NotControlling { .. })));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn control_transition_granted_for_own_user() -> Result<()> {
+    let port = start_server().await;
+
+    let mut client = ClientHandle::new("localhost", port).await?;
+    let mut transitions = Box::pin(client.control_transitions().await?);
+
+    let _writer = client.control_mock("transitions").await?;
+
+    let transition = timeout(Duration::from_secs(5), transitions.next()).await?;
+    assert_eq!(
+        transition,
+        Some(ControlTransition::Granted(vec![LabelledEndpointId::new(
+            &EndpointId::mock("transitions")
+        )]))
+    );
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn dropping_reader_frees_transient_mock() -> Result<()> {
+    let port = start_server().await;
+    let mut client = ClientHandle::new("localhost", port).await?;
+
+    let is_listed = |endpoints: &[ListedEndpoint], name: &str| {
+        endpoints
+            .iter()
+            .any(|listed| matches!(&listed.id.id, EndpointId::Mock(mock) if mock.ends_with(name)))
+    };
+
+    let dropped = client.observe_mock("dropped").await?;
+    let closed = client.observe_mock("closed").await?;
+    let endpoints = client.list_endpoints().await?;
+    assert!(is_listed(&endpoints, "dropped"));
+    assert!(is_listed(&endpoints, "closed"));
+
+    // The server handles actions in order, so the mocks are gone before listing again
+    drop(dropped);
+    closed.close();
+    let endpoints = client.list_endpoints().await?;
+    assert!(!is_listed(&endpoints, "dropped"));
+    assert!(!is_listed(&endpoints, "closed"));
+
+    // Observing again works as usual
+    let mut reader = client.observe_mock("dropped").await?;
+    let mut writer = client.control_mock("dropped").await?;
+    writer.write("hello").await?;
+    assert_eq!(reader.next_message().await?.as_str(), "hello");
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn server_time_is_close_to_now() -> Result<()> {
+    let port = start_server().await;
+    let mut client = ClientHandle::new("localhost", port).await?;
+
+    let before = chrono::Utc::now();
+    let server_time = client.server_time().await?;
+    let after = chrono::Utc::now();
+
+    // Same host, so the clocks agree
+    assert!(before <= server_time && server_time <= after);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn who_am_i_shows_the_connection_and_what_it_holds() -> Result<()> {
+    let port = start_server().await;
+    let before = chrono::Utc::now();
+    let mut client = ClientHandle::new_with_username("localhost", port, "inspected").await?;
+
+    let writer = client.control_mock("who-am-i").await?;
+
+    let info = client.who_am_i().await?;
+    assert_eq!(info.user.name.as_str(), "inspected");
+    assert_eq!(info.controlling, vec![writer.endpoint_id().clone()]);
+    assert!(info.observing.is_empty());
+    assert!(info.remote.is_some());
+    assert!(before <= info.connected_at && info.connected_at <= chrono::Utc::now());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn write_to_single_matching_label() -> Result<()> {
+    let mut config = Config::default();
+    config.endpoints.push(ConfigEndpoint {
+        labels: "dut".into(),
+        ..EndpointId::mock("labelled-dut").into()
+    });
+    config.endpoints.push(ConfigEndpoint {
+        labels: "spare".into(),
+        ..EndpointId::mock("labelled-spare").into()
+    });
+    let port = start_server_with_config(config).await;
+
+    let mut client = ClientHandle::new("localhost", port).await?;
+    let mut events = client.observe_events().await?;
+
+    let _writers = client.control_any(&["dut"]).await?;
+    client.write_to_label("dut", "hello\n").await?;
+
+    let sent = async {
+        loop {
+            if let Event::General(general::Event::MessageSent((info, message))) =
+                events.next_event().await.inner
+            {
+                return (info, message);
+            }
+        }
+    };
+    let (info, message) = timeout(Duration::from_secs(5), sent).await?;
+    assert_eq!(
+        LabelledEndpointId::from(info).id,
+        EndpointId::mock("labelled-dut")
+    );
+    assert_eq!(message, b"hello\n".to_vec());
+
+    // Not controlled, so there is nothing to write to.
+    let response = client.write_to_label("spare", "hello\n").await;
+    assert!(matches!(response, Err(Error::BadUsage(_))));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn callback_is_called_per_message_until_stopped() -> Result<()> {
+    let port = start_server().await;
+
+    let mut client = ClientHandle::new("localhost", port).await?;
+
+    let mut writer = client.control_mock("called-back").await?;
+
+    let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+    let callback = client
+        .observe_with_callback(&EndpointId::mock("called-back"), move |message| {
+            let _ = tx.send(message);
+        })
+        .await?;
+
+    writer.write("first\nsecond").await?;
+
+    for expected in ["first", "second"] {
+        let message = timeout(Duration::from_secs(5), rx.recv()).await?;
+        assert_eq!(
+            message.map(|m| m.as_str().to_owned()),
+            Some(expected.into())
+        );
+    }
+
+    assert!(!callback.is_finished());
+    callback.stop();
+    writer.write("third").await?;
+
+    // The callback (owning the sender) is gone, and never saw the last message.
+    let message = timeout(Duration::from_secs(5), rx.recv()).await?;
+    assert_eq!(message, None);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn connect_on_custom_websocket_path() -> Result<()> {
+    let port = start_server_with_config(Config {
+        websocket_path: Some("/serial-keel/client".into()),
+        ..Default::default()
+    })
+    .await;
+
+    let mut client = ClientHandle::new_with_path("localhost", port, "/serial-keel/client").await?;
+    client.list_endpoints().await?;
+
+    // Nothing is served at the default path.
+    assert!(ClientHandle::new("localhost", port).await.is_err());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn observe_any_observes_every_matching_endpoint() -> Result<()> {
+    let mut config = Config::default();
+    for name in ["watched-1", "watched-2"] {
+        config.endpoints.push(ConfigEndpoint {
+            labels: "watched".into(),
+            ..EndpointId::mock(name).into()
+        });
+    }
+    config.endpoints.push(ConfigEndpoint {
+        labels: "unwatched".into(),
+        ..EndpointId::mock("unwatched").into()
+    });
+    let port = start_server_with_config(config).await;
+
+    let mut observer = ClientHandle::new("localhost", port).await?;
+    let mut readers = observer.observe_any(&["watched"]).await?;
+
+    let observed = readers
+        .iter()
+        .map(|reader| reader.endpoint_id().id.clone())
+        .collect::<Vec<_>>();
+    assert_eq!(
+        observed,
+        vec![EndpointId::mock("watched-1"), EndpointId::mock("watched-2")]
+    );
+
+    // Each writer tells which endpoint it wrote to.
+    // They are kept, such that control of neither is released.
+    let mut controller = ClientHandle::new("localhost", port).await?;
+    let mut writers = vec![];
+    for _ in 0..2 {
+        let mut writer = controller.control_any(&["watched"]).await?.0.remove(0);
+        let line = format!("{}\n", writer.endpoint_id().id);
+        writer.write(line).await?;
+        writers.push(writer);
+    }
+
+    for reader in &mut readers {
+        let message = timeout(Duration::from_secs(5), reader.next_message()).await??;
+        assert_eq!(message.as_str(), reader.endpoint_id().id.to_string());
+    }
+
+    let response = observer.observe_any(&["nothing-has-this"]).await;
+    assert!(matches!(response, Err(Error::NoMatchingEndpoints(_))));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn controlling_a_non_existent_tty_is_no_such_endpoint() -> Result<()> {
+    let port = start_server().await;
+    let mut client = ClientHandle::new("localhost", port).await?;
+
+    let response = client.control_tty("/dev/ttyDOES_NOT_EXIST").await;
+    assert!(
+        matches!(response, Err(Error::NoSuchEndpoint(_))),
+        "Expected no such endpoint, got {response:?}"
+    );
+
+    // The client is still usable afterwards.
+    client.control_mock("after-bogus-tty").await?;
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn listed_endpoints_count_bytes_either_way() -> Result<()> {
+    let mut config = Config::default();
+    config.endpoints.push(ConfigEndpoint {
+        labels: "counted".into(),
+        ..EndpointId::mock("counted").into()
+    });
+    let port = start_server_with_config(config).await;
+
+    let mut client = ClientHandle::new("localhost", port).await?;
+    let mut writer = client.control_any(&["counted"]).await?.0.remove(0);
+
+    // No newlines, so the mock echoes each message back whole
+    for message in ["hello", "world!"] {
+        writer.write(message).await?;
+    }
+
+    // The endpoint's task counts, so the totals may lag behind the writes
+    let counted = async {
+        loop {
+            let listed = client.list_endpoints().await?;
+            let bytes = listed
+                .iter()
+                .find(|listed| listed.id.id == EndpointId::mock("counted"))
+                .expect("The configured mock should be listed")
+                .bytes;
+
+            if bytes.sent == 11 && bytes.received == 11 {
+                return Ok::<_, Error>(());
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    };
+    timeout(Duration::from_secs(5), counted).await??;
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn responses_are_read_until_the_prompt() -> Result<()> {
+    let mut config = Config::default();
+    config.endpoints.push(ConfigEndpoint {
+        labels: "shell".into(),
+        prompt: Some("$ ".into()),
+        ..EndpointId::mock("shell").into()
+    });
+    let port = start_server_with_config(config).await;
+
+    let mut client = ClientHandle::new("localhost", port).await?;
+
+    let listed = client.list_endpoints().await?;
+    assert_eq!(listed[0].prompt.as_deref(), Some("$ "));
+
+    let reader = client.observe_any(&["shell"]).await?.remove(0);
+    let writer = client.control_any(&["shell"]).await?.0.remove(0);
+    let mut shell = ControlledEndpoint::new(writer, reader)?;
+
+    // The mock echoes line by line, so this plays the part of a shell answering
+    let response = shell
+        .request_response_until_prompt("status\nuptime 5s\nload 0.1\n$ ", "$ ")
+        .await?;
+    assert_eq!(
+        response
+            .iter()
+            .map(|message| message.as_str())
+            .collect::<Vec<_>>(),
+        vec!["status", "uptime 5s", "load 0.1"]
+    );
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn close_tells_about_failed_pending_writes() -> Result<()> {
+    let port = start_server_with_config(Config {
+        max_message_bytes: Some(4),
+        ..Default::default()
+    })
+    .await;
+
+    let mut client = ClientHandle::new("localhost", port).await?;
+    let mut writer = client.control_mock("closing-with-failure").await?;
+
+    // Only queued, nothing awaits the responses
+    writer.write("too large\n").await?;
+    writer.write("ok\n").await?;
+
+    assert_eq!(
+        client.close().await,
+        Err(Error::MessageTooLarge { size: 10, max: 4 })
+    );
+
+    Ok(())
+}
@@ -13,7 +13,7 @@ use serial_keel::{
 use tokio::net::TcpStream;
 use tokio::sync::oneshot;
 use tokio::time::timeout;
-use tokio_tungstenite::{MaybeTlsStream, WebSocketStream};
+use tokio_tungstenite::{tungstenite::client::IntoClientRequest, MaybeTlsStream, WebSocketStream};
 use tracing::info;
 
 #[macro_export]
@@ -40,15 +40,23 @@ macro_rules! assert_queued {
     ($response:ident) => {
         assert!(matches!(
             $response,
-            Response::Sync(serial_keel::actions::Sync::ControlQueue(_))
+            Response::Sync(serial_keel::actions::Sync::ControlQueue { .. })
         ));
     };
 
     ($response:ident, $lid:ident) => {
-        assert_eq!(
-            $response,
-            Response::Sync(serial_keel::actions::Sync::ControlQueue(vec![$lid.clone()]))
-        );
+        match &$response {
+            Response::Sync(serial_keel::actions::Sync::ControlQueue { queued_on, .. }) => {
+                assert_eq!(
+                    queued_on
+                        .iter()
+                        .map(|queued| &queued.endpoint)
+                        .collect::<Vec<_>>(),
+                    vec![&$lid]
+                )
+            }
+            other => panic!("Expected to be queued, got {other:?}"),
+        }
     };
 }
 
@@ -83,15 +91,11 @@ pub async fn start_server_with_config(config: Config) -> u16 {
 }
 
 pub async fn start_server_with_group(group: Group) -> u16 {
-    start_server_with_config(Config {
-        groups: vec![group],
-        endpoints: vec![],
-        ignore_unavailable_endpoints: false,
-    })
-    .await
+    start_server_with_config(Config::builder().add_group(group).build()).await
 }
 
-pub async fn connect(port: u16) -> Result<WebSocketStream<MaybeTlsStream<TcpStream>>> {
+/// Connect without consuming the handshake the server starts with.
+pub async fn connect_raw(port: u16) -> Result<WebSocketStream<MaybeTlsStream<TcpStream>>> {
     info!("Connecting to server on port {port}");
     let (stream, http_response) =
         tokio_tungstenite::connect_async(format!("ws://127.0.0.1:{port}/client")).await?;
@@ -101,6 +105,35 @@ pub async fn connect(port: u16) -> Result<WebSocketStream<MaybeTlsStream<TcpStre
     Ok(stream)
 }
 
+/// Connect claiming to speak the given protocol version, without consuming the first response.
+pub async fn connect_raw_with_protocol(
+    port: u16,
+    version: &str,
+) -> Result<WebSocketStream<MaybeTlsStream<TcpStream>>> {
+    let mut request = format!("ws://127.0.0.1:{port}/client").into_client_request()?;
+    request
+        .headers_mut()
+        .insert(actions::PROTOCOL_VERSION_HEADER, version.parse()?);
+
+    let (stream, http_response) = tokio_tungstenite::connect_async(request).await?;
+
+    assert_eq!(http_response.status(), StatusCode::SWITCHING_PROTOCOLS);
+
+    Ok(stream)
+}
+
+pub async fn connect(port: u16) -> Result<WebSocketStream<MaybeTlsStream<TcpStream>>> {
+    let mut stream = connect_raw(port).await?;
+
+    let handshake = receive(&mut stream).await??;
+    assert!(matches!(
+        handshake,
+        actions::Response::Async(actions::Async::Handshake(_))
+    ));
+
+    Ok(stream)
+}
+
 pub async fn start_server_and_connect() -> Result<WebSocketStream<MaybeTlsStream<TcpStream>>> {
     let port = start_server().await;
     connect(port).await
@@ -119,10 +152,44 @@ pub async fn receive(
     Ok(response)
 }
 
+/// Like [`receive`], but for binary frames.
+pub async fn receive_binary(
+    client: &mut WebSocketStream<MaybeTlsStream<TcpStream>>,
+) -> Result<actions::ResponseResult> {
+    let response = timeout(Duration::from_secs(5), client.next())
+        .await?
+        .ok_or_else(|| color_eyre::eyre::eyre!("Stream closed"))??;
+
+    let tungstenite::Message::Binary(bytes) = response else {
+        color_eyre::eyre::bail!("Expected a binary frame, got {response:?}");
+    };
+
+    Ok(actions::deserialize_response_binary(&bytes)?)
+}
+
+/// Send without waiting for the response, e.g. to pipeline requests.
+pub async fn send(
+    client: &mut WebSocketStream<MaybeTlsStream<TcpStream>>,
+    to_send: String,
+) -> Result<()> {
+    client.send(tungstenite::Message::Text(to_send)).await?;
+    Ok(())
+}
+
 pub async fn send_receive(
     client: &mut WebSocketStream<MaybeTlsStream<TcpStream>>,
     to_send: String,
 ) -> Result<actions::ResponseResult> {
-    client.send(tungstenite::Message::Text(to_send)).await?;
+    send(client, to_send).await?;
     receive(client).await
 }
+
+pub async fn send_receive_binary(
+    client: &mut WebSocketStream<MaybeTlsStream<TcpStream>>,
+    action: actions::Action,
+) -> Result<actions::ResponseResult> {
+    client
+        .send(tungstenite::Message::Binary(action.serialize_binary()))
+        .await?;
+    receive_binary(client).await
+}
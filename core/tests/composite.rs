@@ -0,0 +1,124 @@
+mod common;
+
+// Feature: The ports of a configured composite are only reachable by name if mock endpoints are shared
+#[cfg(feature = "mocks-share-endpoints")]
+mod composite {
+    use color_eyre::Result;
+    use pretty_assertions::assert_eq;
+    use serial_keel::{
+        actions::{Action, Response},
+        client::ClientHandle,
+        config::{Composite, Config, ConfigEndpoint},
+        endpoint::{EndpointId, LabelledEndpointId},
+        error::Error,
+    };
+
+    use super::common::*;
+    use crate::{assert_granted, assert_queued, assert_result_error};
+
+    /// A composite of two mock ports, named after the composite.
+    fn composite_config(name: &str) -> Config {
+        Config::builder()
+            .add_composite(Composite::new(
+                name,
+                vec![
+                    EndpointId::mock(&format!("{name}-bootloader")),
+                    EndpointId::mock(&format!("{name}-app")),
+                ],
+            ))
+            .build()
+    }
+
+    #[tokio::test]
+    async fn observing_a_composite_merges_its_ports() -> Result<()> {
+        let port = start_server_with_config(composite_config("dut")).await;
+        let bootloader = LabelledEndpointId::new(&EndpointId::mock("dut-bootloader"));
+        let app = LabelledEndpointId::new(&EndpointId::mock("dut-app"));
+
+        let mut client = ClientHandle::new("localhost", port).await?;
+        let mut reader = client.observe_composite("dut").await?;
+        assert_eq!(reader.endpoint_ids(), &[bootloader.clone(), app.clone()]);
+
+        let mut writers = client.control_composite("dut").await?;
+        assert_eq!(writers.0.len(), 2);
+
+        for (id, message) in [(&bootloader, "mcuboot"), (&app, "hello from app")] {
+            let writer = writers
+                .0
+                .iter_mut()
+                .find(|writer| writer.endpoint_id() == id)
+                .expect("Each port should have a writer");
+            writer.write(format!("{message}\n")).await?;
+
+            let (from, received) = reader.next_message().await?;
+            assert_eq!(&from, id);
+            assert_eq!(received.as_str(), message);
+        }
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn writes_must_go_to_a_port() -> Result<()> {
+        let id = EndpointId::composite("write-dut");
+        let port = start_server_with_config(composite_config("write-dut")).await;
+
+        let mut client = connect(port).await?;
+        let response = send_receive(&mut client, Action::control(&id).serialize()).await??;
+        assert_granted!(response);
+
+        let response =
+            send_receive(&mut client, Action::write(&id, "reset".into()).serialize()).await?;
+        assert_result_error!(response, Error::BadUsage(_));
+
+        let response = send_receive(
+            &mut client,
+            Action::write(&EndpointId::mock("write-dut-app"), "reset".into()).serialize(),
+        )
+        .await??;
+        assert_eq!(response, Response::example_write_ok());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn controlling_a_composite_controls_each_port() -> Result<()> {
+        let port = start_server_with_config(composite_config("queue-dut")).await;
+
+        let mut client_1 = ClientHandle::new("localhost", port).await?;
+        let _writers = client_1.control_composite("queue-dut").await?;
+
+        let mut client_2 = connect(port).await?;
+        let response = send_receive(
+            &mut client_2,
+            Action::control(&EndpointId::mock("queue-dut-bootloader")).serialize(),
+        )
+        .await??;
+        assert_queued!(response);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn composite_is_denied_if_any_port_is() -> Result<()> {
+        let mut composite = Composite::new("acl-dut", vec![EndpointId::mock("acl-dut-bootloader")]);
+        composite.ports.push(ConfigEndpoint {
+            deny_users: vec!["mallory".into()],
+            ..EndpointId::mock("acl-dut-app").into()
+        });
+        let config = Config::builder().add_composite(composite).build();
+        let port = start_server_with_config(config).await;
+
+        let mut mallory = ClientHandle::new_with_username("localhost", port, "mallory").await?;
+        let response = mallory.control_composite("acl-dut").await;
+        assert!(
+            matches!(response, Err(Error::AccessDenied(_))),
+            "Expected access to the composite denied, got {response:?}"
+        );
+
+        let mut alice = ClientHandle::new_with_username("localhost", port, "alice").await?;
+        alice.control_composite("acl-dut").await?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,140 @@
+mod common;
+
+// Feature: The configured mock is only reachable by name if endpoints are shared
+#[cfg(feature = "mocks-share-endpoints")]
+mod enabling {
+    use std::time::Duration;
+
+    use color_eyre::Result;
+    use pretty_assertions::assert_eq;
+    use serial_keel::{
+        actions::{Action, Async, Response},
+        client::ClientHandle,
+        config::{Config, ConfigEndpoint},
+        endpoint::{EndpointId, LabelledEndpointId},
+        error::Error,
+    };
+
+    use super::common::*;
+    use tokio::time::timeout;
+
+    use crate::{assert_granted, assert_result_error};
+
+    async fn start_maintained_mock(name: &str) -> Result<(u16, ClientHandle, EndpointId)> {
+        let id = EndpointId::mock(name);
+
+        let mut config = Config {
+            admins: vec!["operator".into()],
+            ..Default::default()
+        };
+        config.endpoints.push(id.clone().into());
+        let port = start_server_with_config(config).await;
+
+        let operator = ClientHandle::new_with_username("localhost", port, "operator").await?;
+
+        Ok((port, operator, id))
+    }
+
+    #[tokio::test]
+    async fn disabled_endpoint_refuses_control_until_enabled() -> Result<()> {
+        let (port, mut operator, id) = start_maintained_mock("maintained").await?;
+        let lid = LabelledEndpointId::new(&id);
+
+        operator.set_endpoint_enabled(&id, false, false).await?;
+
+        let listed = operator.list_endpoints().await?;
+        assert!(listed
+            .iter()
+            .any(|listed| listed.id == lid && listed.disabled));
+
+        let mut user = connect(port).await?;
+        let response = send_receive(&mut user, Action::control(&id).serialize()).await?;
+        assert_result_error!(response, Error::EndpointDisabled(_));
+
+        let response = send_receive(&mut user, Action::observe(&id).serialize()).await?;
+        assert_result_error!(response, Error::EndpointDisabled(_));
+
+        operator.set_endpoint_enabled(&id, true, false).await?;
+
+        let response = send_receive(&mut user, Action::control(&id).serialize()).await??;
+        assert_granted!(response, lid);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn disabling_may_release_holders() -> Result<()> {
+        let (port, mut operator, id) = start_maintained_mock("taken-out").await?;
+        let lid = LabelledEndpointId::new(&id);
+
+        let mut user = connect(port).await?;
+        let response = send_receive(&mut user, Action::control(&id).serialize()).await??;
+        assert_granted!(response, lid);
+
+        operator.set_endpoint_enabled(&id, false, true).await?;
+
+        let response = receive(&mut user).await??;
+        assert_eq!(
+            response,
+            Response::Async(Async::ControlRevoked(vec![lid.clone()]))
+        );
+
+        let response =
+            send_receive(&mut user, Action::write(&id, "hello".into()).serialize()).await?;
+        assert_result_error!(response, Error::NotControlling { .. });
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn revoked_endpoint_is_not_written_to_by_label() -> Result<()> {
+        let id = EndpointId::mock("revoked-by-label");
+        let mut config = Config {
+            admins: vec!["operator".into()],
+            ..Default::default()
+        };
+        config.endpoints.push(ConfigEndpoint {
+            labels: "dut".into(),
+            ..id.clone().into()
+        });
+        let port = start_server_with_config(config).await;
+
+        let mut operator = ClientHandle::new_with_username("localhost", port, "operator").await?;
+        let mut user = ClientHandle::new("localhost", port).await?;
+
+        let _writers = user.control_any(&["dut"]).await?;
+        user.write_to_label("dut", "hello\n").await?;
+
+        operator.set_endpoint_enabled(&id, false, true).await?;
+
+        // The revocation arrives on its own, so until then writes are refused by the server.
+        let forgotten = async {
+            loop {
+                if let Err(Error::BadUsage(_)) = user.write_to_label("dut", "hello\n").await {
+                    return;
+                }
+            }
+        };
+        timeout(Duration::from_secs(5), forgotten).await?;
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn only_admins_enable_or_disable() -> Result<()> {
+        let (port, _operator, id) = start_maintained_mock("guarded").await?;
+
+        let mut user = connect(port).await?;
+        let response = send_receive(
+            &mut user,
+            Action::set_endpoint_enabled(&id, false, false).serialize(),
+        )
+        .await?;
+        assert_result_error!(response, Error::AccessDenied(_));
+
+        let response = send_receive(&mut user, Action::control(&id).serialize()).await??;
+        assert_granted!(response);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,97 @@
+mod common;
+
+// Feature: The configured mock is only observable by name if endpoints are shared
+#[cfg(feature = "mocks-share-endpoints")]
+mod endpoint_events {
+    use std::time::Duration;
+
+    use color_eyre::Result;
+    use pretty_assertions::assert_eq;
+    use serial_keel::{
+        client::ClientHandle,
+        config::{Config, ConfigEndpoint},
+        endpoint::EndpointId,
+        error::Error,
+        events::endpoint::Event,
+    };
+
+    use super::common::*;
+
+    fn idle_config(name: &str, label: &str) -> Config {
+        let mut config = Config::default();
+        config.endpoints.push(ConfigEndpoint {
+            labels: label.into(),
+            idle_message_timeout: Some(Duration::from_millis(200)),
+            ..EndpointId::mock(name).into()
+        });
+        config
+    }
+
+    #[tokio::test]
+    async fn observed_endpoint_goes_idle() -> Result<()> {
+        let id = EndpointId::mock("idle-mock");
+        let port = start_server_with_config(idle_config("idle-mock", "idle-label")).await;
+
+        let mut client = ClientHandle::new("localhost", port).await?;
+        let mut writers = client.control_any(&["idle-label"]).await?;
+        let writer = &mut writers.0[0];
+
+        let mut reader = client.observe_endpoint_events(&id).await?;
+        assert_eq!(reader.endpoint_id().id, id);
+
+        writer.write("hello\n").await?;
+
+        // The endpoint may already have gone idle (and then active by the write) before
+        // observing started, so wait for it to go idle after the write.
+        let idle = async {
+            loop {
+                let event = reader.next_event().await?;
+                assert_eq!(event.endpoint.id, id);
+                if event.event == Event::Idle {
+                    return Ok::<_, Error>(());
+                }
+            }
+        };
+        tokio::time::timeout(Duration::from_secs(5), idle).await??;
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn observing_endpoint_events_twice_is_superfluous() -> Result<()> {
+        let id = EndpointId::mock("twice-mock");
+        let port = start_server_with_config(idle_config("twice-mock", "twice-label")).await;
+
+        let mut client = ClientHandle::new("localhost", port).await?;
+        let _reader = client.observe_endpoint_events(&id).await?;
+
+        let response = client.observe_endpoint_events(&id).await;
+        assert!(matches!(response, Err(Error::SuperfluousRequest(_))));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn first_message_is_only_reported_once() -> Result<()> {
+        let id = EndpointId::mock("first-mock");
+        let mut config = Config::default();
+        config.endpoints.push(id.clone().into());
+        let port = start_server_with_config(config).await;
+
+        let mut client = ClientHandle::new("localhost", port).await?;
+        let mut writer = client.control(&id).await?;
+        let mut reader = client.observe_endpoint_events(&id).await?;
+
+        writer.write("hello\n").await?;
+        writer.write("again\n").await?;
+
+        let event = tokio::time::timeout(Duration::from_secs(5), reader.next_event()).await??;
+        assert_eq!(event.endpoint.id, id);
+        assert_eq!(event.event, Event::FirstMessage);
+
+        let next = tokio::time::timeout(Duration::from_millis(200), reader.next_event()).await;
+        assert!(next.is_err(), "No other event expected, got {next:?}");
+
+        Ok(())
+    }
+}
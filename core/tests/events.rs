@@ -3,11 +3,19 @@ mod common;
 use color_eyre::Result;
 use common::*;
 use serial_keel::{
-    client::ClientHandle,
+    actions::Action,
+    client::{ClientHandle, EventReader, UserEventReader},
+    config::{Config, ConfigEndpoint},
+    endpoint::EndpointId,
+    error::Error,
     events::user,
     events::Event,
     events::{general, TimestampedEvent},
+    serial::MockEcho,
+    user::User,
 };
+use std::time::Duration;
+use tokio_tungstenite::MaybeTlsStream;
 use tracing::{debug, info};
 
 macro_rules! assert_next_user_event {
@@ -46,7 +54,7 @@ async fn user_events() -> Result<()> {
     assert_next_user_event!(reader, user::Event::Connected);
 
     info!("Client will ask to observe mock");
-    some_client.observe_mock("john").await?;
+    let _reader = some_client.observe_mock("john").await?;
     info!("OK, checking event..");
     assert_next_user_event!(reader, user::Event::Observing(_));
 
@@ -179,3 +187,254 @@ async fn endpoints_general_events() -> Result<()> {
 
     Ok(())
 }
+
+#[tokio::test]
+async fn received_message_event_has_endpoint_and_direction() -> Result<()> {
+    let port = start_server().await;
+
+    let mut client = ClientHandle::new("localhost", port).await?;
+
+    let mut writer = client.control_mock("tagged").await?;
+    let _endpoint_reader = client.observe_mock("tagged").await?;
+
+    let mut event_reader = client.observe_events().await?;
+
+    writer.write(b"Hello tags\n").await?;
+
+    // The sent message events may arrive first, skip past them.
+    loop {
+        let event = event_reader.next_event().await;
+
+        match event.inner {
+            Event::User(user::UserEvent {
+                user: _,
+                event: user::Event::MessageSent((info, message)),
+            }) => {
+                assert_eq!(EndpointId::from(info.id), EndpointId::mock("tagged"));
+                assert_eq!(message, b"Hello tags\n");
+            }
+            Event::User(user::UserEvent {
+                user: _,
+                event: user::Event::MessageReceived((info, message)),
+            }) => {
+                assert_eq!(EndpointId::from(info.id), EndpointId::mock("tagged"));
+                assert_eq!(message, b"Hello tags");
+                break;
+            }
+            _ => continue,
+        }
+    }
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn binary_message_event_is_unchanged() -> Result<()> {
+    let port = start_server().await;
+
+    let mut client = ClientHandle::new("localhost", port).await?;
+
+    let mut writer = client.control_mock("binary").await?;
+    let mut event_reader = client.observe_events().await?;
+
+    // Not valid utf8.
+    let binary = vec![0xde, 0xad, 0xbe, 0xef, 0x00, 0xff];
+    writer.write(&binary).await?;
+
+    loop {
+        if let Event::User(user::UserEvent {
+            user: _,
+            event: user::Event::MessageSent((info, message)),
+        }) = event_reader.next_event().await.inner
+        {
+            assert_eq!(EndpointId::from(info.id), EndpointId::mock("binary"));
+            assert_eq!(message, binary);
+            break;
+        }
+    }
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn stalled_event_subscriber_does_not_crash_server() -> Result<()> {
+    let port = start_server().await;
+
+    // Subscribes to events, then never reads anything.
+    let mut stalled = connect(port).await?;
+    send_receive(&mut stalled, Action::ObserveEvents.serialize()).await??;
+
+    let mut client = ClientHandle::new("localhost", port).await?;
+    let mut writer = client.control_mock("flood").await?;
+
+    // Each line gives rise to several events, well beyond what the broadcast channel holds.
+    let flood = "flooding the event subscribers\n".repeat(1000);
+    writer.write(flood).await?;
+
+    // The server should still be responsive.
+    let mut other_client = ClientHandle::new("localhost", port).await?;
+    let _writer = other_client.control_mock("still-alive").await?;
+
+    drop(stalled);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn wait_for_connected_from_specific_user() -> Result<()> {
+    let port = start_server().await;
+
+    let mut observer = ClientHandle::new("localhost", port).await?;
+    let mut reader = observer.observe_events().await?;
+
+    let _first = connect(port).await?;
+    let second = connect(port).await?;
+
+    // The server names users by their address.
+    let second_name = match second.get_ref() {
+        MaybeTlsStream::Plain(stream) => stream.local_addr()?.to_string(),
+        _ => unreachable!("Tests connect without TLS"),
+    };
+
+    let is_second_connecting = |event: &TimestampedEvent| {
+        matches!(
+            &event.inner,
+            Event::User(user::UserEvent {
+                user,
+                event: user::Event::Connected,
+            }) if user.name.as_str() == second_name
+        )
+    };
+
+    let event = reader
+        .wait_for(is_second_connecting, Duration::from_secs(5))
+        .await?;
+    assert!(is_second_connecting(&event));
+
+    // It only connects once.
+    let result = reader
+        .wait_for(is_second_connecting, Duration::from_millis(100))
+        .await;
+    assert!(matches!(result, Err(Error::Timeout(_))));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn session_tag_shows_up_in_events() -> Result<()> {
+    let port = start_server().await;
+
+    let mut observer = ClientHandle::new("localhost", port).await?;
+    let mut reader = observer.observe_events().await?;
+
+    let mut tagged = ClientHandle::new_with_session_tag("localhost", port, "nightly-ci").await?;
+
+    let event = reader.next_event().await;
+    let Event::User(user::UserEvent {
+        user,
+        event: user::Event::Connected,
+    }) = event.inner
+    else {
+        panic!("Expected a connected event, got {event:?}");
+    };
+    assert_eq!(
+        user.session_tag.as_deref().map(String::as_str),
+        Some("nightly-ci")
+    );
+    assert_eq!(Some(&user), tagged.user());
+
+    tagged.observe_mock("tagged").await?;
+    let event = reader.next_event().await;
+    let Event::User(user::UserEvent { user, .. }) = event.inner else {
+        panic!("Expected a user event, got {event:?}");
+    };
+    assert_eq!(
+        user.session_tag.as_deref().map(String::as_str),
+        Some("nightly-ci")
+    );
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn silent_endpoint_goes_idle_then_active() -> Result<()> {
+    let label = "idle-label";
+    let mut config = Config::default();
+    config.endpoints.push(ConfigEndpoint {
+        id: EndpointId::mock("idle-mock"),
+        labels: label.into(),
+        flow_control: None,
+        baud: None,
+        idle_message_timeout: Some(Duration::from_millis(200)),
+        write_terminator: None,
+        transforms: vec![],
+        write_delay: None,
+        echo: MockEcho::Echo,
+        shared_control: false,
+        allow_users: None,
+        deny_users: vec![],
+        description: None,
+        read_buffer_size: None,
+        charset: None,
+        prompt: None,
+    });
+    let port = start_server_with_config(config).await;
+
+    let mut client = ClientHandle::new("localhost", port).await?;
+    let mut event_reader = client.observe_events().await?;
+    let mut writers = client.control_any(&[label]).await?;
+    let writer = &mut writers.0[0];
+
+    let idle = |event: &TimestampedEvent| {
+        matches!(&event.inner, Event::General(general::Event::EndpointIdle(e))
+            if e.id == EndpointId::mock("idle-mock"))
+    };
+    let active = |event: &TimestampedEvent| {
+        matches!(&event.inner, Event::General(general::Event::EndpointActive(e))
+            if e.id == EndpointId::mock("idle-mock"))
+    };
+
+    // Talk, then go silent until the timeout passes.
+    writer.write("hello\n").await?;
+    event_reader.wait_for(idle, Duration::from_secs(5)).await?;
+
+    // Talking again brings it back.
+    writer.write("anyone there?\n").await?;
+    event_reader
+        .wait_for(active, Duration::from_secs(5))
+        .await?;
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn user_event_reader_leaves_out_general_events() -> Result<()> {
+    let (events, rx) = futures::channel::mpsc::unbounded();
+    let mut reader = UserEventReader::new(EventReader::new(rx));
+
+    let user = User::new("reader");
+    for event in [
+        TimestampedEvent::new_general_event(general::Event::Lagged(3)),
+        TimestampedEvent::new_user_event(&user, user::Event::Connected),
+        TimestampedEvent::new_general_event(general::Event::Lagged(5)),
+        TimestampedEvent::new_general_event(general::Event::Lagged(7)),
+        TimestampedEvent::new_user_event(&user, user::Event::Disconnected),
+        TimestampedEvent::new_general_event(general::Event::Lagged(9)),
+    ] {
+        events.unbounded_send(event)?;
+    }
+
+    let event = reader.next_user_event().await;
+    assert_eq!(event.user, user);
+    assert_eq!(event.event, user::Event::Connected);
+
+    let event = reader
+        .try_next_user_event()
+        .expect("A user event is waiting");
+    assert_eq!(event.event, user::Event::Disconnected);
+
+    // Only a general event is left.
+    assert!(reader.try_next_user_event().is_none());
+
+    Ok(())
+}
@@ -0,0 +1,35 @@
+use color_eyre::Result;
+use pretty_assertions::assert_eq;
+use serial_keel::{
+    actions::{Action, Response, ResponseResult},
+    error::Error,
+};
+
+#[test]
+fn example_actions_round_trip() -> Result<()> {
+    for action in Action::examples() {
+        let json = serde_json::to_string(&action)?;
+        let again: Action = serde_json::from_str(&json)?;
+
+        assert_eq!(serde_json::to_string(&again)?, json, "`{action}` changed");
+    }
+
+    Ok(())
+}
+
+#[test]
+fn example_responses_round_trip() -> Result<()> {
+    let responses = Response::examples()
+        .into_iter()
+        .map(Ok)
+        .chain(Error::examples().into_iter().map(Err));
+
+    for response in responses {
+        let json = serde_json::to_string(&response)?;
+        let again: ResponseResult = serde_json::from_str(&json)?;
+
+        assert_eq!(again, response);
+    }
+
+    Ok(())
+}
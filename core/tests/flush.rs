@@ -0,0 +1,56 @@
+mod common;
+
+use color_eyre::Result;
+use common::*;
+use serial_keel::{
+    actions::{Action, Response},
+    client::ClientHandle,
+    endpoint::EndpointId,
+    error::Error,
+};
+
+#[tokio::test]
+async fn flush_without_control_is_bad() -> Result<()> {
+    let mut client = start_server_and_connect().await?;
+
+    let id = EndpointId::mock("flush-not-controlled");
+    let request = Action::flush(&id, true, true).serialize();
+    let response = send_receive(&mut client, request).await?;
+
+    assert_result_error!(response, Error::NoPermit(_));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn flush_nothing_is_bad() -> Result<()> {
+    let mut client = start_server_and_connect().await?;
+
+    let id = EndpointId::mock("flush-nothing");
+    let response = send_receive(&mut client, Action::control(&id).serialize()).await??;
+    assert_granted!(response);
+
+    let request = Action::flush(&id, false, false).serialize();
+    let response = send_receive(&mut client, request).await?;
+
+    assert_result_error!(response, Error::BadUsage(_));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn client_flush_controlled_mock() -> Result<()> {
+    let port = start_server().await;
+    let mut client = ClientHandle::new("localhost", port).await?;
+
+    let name = "client-flush-mock";
+    let mut writer = client.control_mock(name).await?;
+    let mut reader = client.observe_mock(name).await?;
+
+    client.flush(&EndpointId::mock(name), false, true).await?;
+
+    writer.write("after flush\n").await?;
+    assert_eq!(reader.next_message().await?.as_str(), "after flush");
+
+    Ok(())
+}
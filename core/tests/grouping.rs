@@ -6,6 +6,7 @@ mod grouping {
     use color_eyre::Result;
     use serial_keel::{
         actions::{self, Action, Response},
+        client::ClientHandle,
         endpoint::{EndpointId, LabelledEndpointId},
         error::Error,
     };
@@ -61,9 +62,11 @@ mod grouping {
         let mut client_2 = connect(port).await?;
 
         match send_receive(&mut client_2, Action::control(&m2).serialize()).await?? {
-            Response::Sync(actions::Sync::ControlQueue(queue)) => {
-                assert!(queue.contains(&lm1));
-                assert!(queue.contains(&lm2));
+            Response::Sync(actions::Sync::ControlQueue {
+                queued_on: queue, ..
+            }) => {
+                assert!(queue.iter().any(|queued| queued.endpoint == lm1));
+                assert!(queue.iter().any(|queued| queued.endpoint == lm2));
             }
             _ => unreachable!(),
         }
@@ -71,6 +74,46 @@ mod grouping {
         Ok(())
     }
 
+    #[tokio::test]
+    async fn queued_on_busy_group_reports_holder() -> Result<()> {
+        let m1 = EndpointId::mock("Mock41");
+        let m2 = EndpointId::mock("Mock42");
+
+        let port = start_server_with_group(vec![m1.clone(), m2.clone()].into()).await;
+
+        let mut holder = ClientHandle::new("localhost", port).await?;
+        let _writers = holder.control_mock("Mock41").await?;
+        let holder_name = holder
+            .user()
+            .expect("Should know own name")
+            .name
+            .to_string();
+
+        // Kept alive so that each client is queued behind the previous ones
+        let mut queued_clients = vec![];
+
+        for position in 0..2 {
+            let mut client = connect(port).await?;
+
+            match send_receive(&mut client, Action::control(&m2).serialize()).await?? {
+                Response::Sync(actions::Sync::ControlQueue {
+                    queued_on: queue, ..
+                }) => {
+                    assert_eq!(queue.len(), 2);
+                    for queued in queue {
+                        assert_eq!(queued.controller.as_ref(), Some(&holder_name));
+                        assert_eq!(queued.position, position);
+                    }
+                }
+                _ => unreachable!(),
+            }
+
+            queued_clients.push(client);
+        }
+
+        Ok(())
+    }
+
     #[tokio::test]
     async fn control_group_then_drop_advances_queue() -> Result<()> {
         let m1 = EndpointId::mock("Mock31");
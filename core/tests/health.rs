@@ -0,0 +1,36 @@
+use color_eyre::Result;
+use common::*;
+use serial_keel::server::{Health, HealthStatus};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpStream,
+};
+
+mod common;
+
+#[tokio::test]
+async fn health_is_ready_once_running() -> Result<()> {
+    let port = start_server().await;
+
+    let mut stream = TcpStream::connect(("127.0.0.1", port)).await?;
+    stream
+        .write_all(b"GET /health HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n")
+        .await?;
+
+    let mut response = String::new();
+    stream.read_to_string(&mut response).await?;
+
+    let (head, body) = response
+        .split_once("\r\n\r\n")
+        .expect("The response should have a head and a body");
+    assert!(
+        head.starts_with("HTTP/1.1 200"),
+        "Unexpected response: {head}"
+    );
+
+    let health: Health = serde_json::from_str(body)?;
+    assert_eq!(health.status, HealthStatus::Ready);
+    assert_eq!(health.endpoints, 0);
+
+    Ok(())
+}
@@ -0,0 +1,34 @@
+use color_eyre::Result;
+use pretty_assertions::assert_eq;
+use serial_keel::{config::Config, server::InProcess, user::User};
+
+#[tokio::test]
+async fn control_mock_in_process() -> Result<()> {
+    let server = InProcess::new(Config::default())?;
+
+    let mut client = server.connect().await?;
+    assert_eq!(client.user(), Some(&User::new("in-process-0")));
+
+    let mut reader = client.observe_mock("in-process-mock").await?;
+    let mut writer = client.control_mock("in-process-mock").await?;
+
+    writer.write("hello\nworld").await?;
+
+    assert_eq!(reader.next_message().await?.as_str(), "hello");
+    assert_eq!(reader.next_message().await?.as_str(), "world");
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn in_process_clients_are_told_apart() -> Result<()> {
+    let server = InProcess::new(Config::default())?;
+
+    let first = server.connect().await?;
+    let second = server.connect().await?;
+
+    assert_eq!(first.user(), Some(&User::new("in-process-0")));
+    assert_eq!(second.user(), Some(&User::new("in-process-1")));
+
+    Ok(())
+}
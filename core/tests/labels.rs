@@ -1,11 +1,14 @@
 mod common;
 
+use std::time::Duration;
+
 use color_eyre::Result;
 use serial_keel::{
     actions::{self, Action, Response},
     config::{Config, ConfigEndpoint, Group},
     endpoint::{EndpointId, Label, LabelledEndpointId, Labels},
     error::Error,
+    serial::MockEcho,
 };
 
 use common::*;
@@ -29,6 +32,19 @@ async fn can_control_label() -> Result<()> {
         id: EndpointId::Mock("Mock1".into()),
         labels: label.into(),
         flow_control: None,
+        baud: None,
+        idle_message_timeout: None,
+        write_terminator: None,
+        transforms: vec![],
+        write_delay: None,
+        echo: MockEcho::Echo,
+        shared_control: false,
+        allow_users: None,
+        deny_users: vec![],
+        description: None,
+        read_buffer_size: None,
+        charset: None,
+        prompt: None,
     });
 
     let mut client = connect(start_server_with_config(config).await).await?;
@@ -48,11 +64,37 @@ async fn two_labelled_endpoints_and_two_users_means_no_queue() -> Result<()> {
         id: EndpointId::Mock("Mock1".into()),
         labels: label.into(),
         flow_control: None,
+        baud: None,
+        idle_message_timeout: None,
+        write_terminator: None,
+        transforms: vec![],
+        write_delay: None,
+        echo: MockEcho::Echo,
+        shared_control: false,
+        allow_users: None,
+        deny_users: vec![],
+        description: None,
+        read_buffer_size: None,
+        charset: None,
+        prompt: None,
     });
     config.endpoints.push(ConfigEndpoint {
         id: EndpointId::Mock("Mock2".into()),
         labels: label.into(),
         flow_control: None,
+        baud: None,
+        idle_message_timeout: None,
+        write_terminator: None,
+        transforms: vec![],
+        write_delay: None,
+        echo: MockEcho::Echo,
+        shared_control: false,
+        allow_users: None,
+        deny_users: vec![],
+        description: None,
+        read_buffer_size: None,
+        charset: None,
+        prompt: None,
     });
 
     let port = start_server_with_config(config).await;
@@ -77,11 +119,37 @@ async fn two_labelled_endpoints_and_one_user() -> Result<()> {
         id: EndpointId::Mock("Mock1".into()),
         labels: label.into(),
         flow_control: None,
+        baud: None,
+        idle_message_timeout: None,
+        write_terminator: None,
+        transforms: vec![],
+        write_delay: None,
+        echo: MockEcho::Echo,
+        shared_control: false,
+        allow_users: None,
+        deny_users: vec![],
+        description: None,
+        read_buffer_size: None,
+        charset: None,
+        prompt: None,
     });
     config.endpoints.push(ConfigEndpoint {
         id: EndpointId::Mock("Mock2".into()),
         labels: label.into(),
         flow_control: None,
+        baud: None,
+        idle_message_timeout: None,
+        write_terminator: None,
+        transforms: vec![],
+        write_delay: None,
+        echo: MockEcho::Echo,
+        shared_control: false,
+        allow_users: None,
+        deny_users: vec![],
+        description: None,
+        read_buffer_size: None,
+        charset: None,
+        prompt: None,
     });
 
     let port = start_server_with_config(config).await;
@@ -107,6 +175,19 @@ async fn two_labelled_endpoints_can_still_use_specific_names() -> Result<()> {
         id: mock1.clone(),
         labels: Labels::from_iter([&label]),
         flow_control: None,
+        baud: None,
+        idle_message_timeout: None,
+        write_terminator: None,
+        transforms: vec![],
+        write_delay: None,
+        echo: MockEcho::Echo,
+        shared_control: false,
+        allow_users: None,
+        deny_users: vec![],
+        description: None,
+        read_buffer_size: None,
+        charset: None,
+        prompt: None,
     });
     let lmock1 = LabelledEndpointId {
         id: mock1.clone(),
@@ -118,6 +199,19 @@ async fn two_labelled_endpoints_can_still_use_specific_names() -> Result<()> {
         id: mock2.clone(),
         labels: label.into(),
         flow_control: None,
+        baud: None,
+        idle_message_timeout: None,
+        write_terminator: None,
+        transforms: vec![],
+        write_delay: None,
+        echo: MockEcho::Echo,
+        shared_control: false,
+        allow_users: None,
+        deny_users: vec![],
+        description: None,
+        read_buffer_size: None,
+        charset: None,
+        prompt: None,
     });
 
     let port = start_server_with_config(config).await;
@@ -148,11 +242,37 @@ async fn can_control_different_labels() -> Result<()> {
         id: EndpointId::Mock("ccdl-Mock1".into()),
         labels: label_1.into(),
         flow_control: None,
+        baud: None,
+        idle_message_timeout: None,
+        write_terminator: None,
+        transforms: vec![],
+        write_delay: None,
+        echo: MockEcho::Echo,
+        shared_control: false,
+        allow_users: None,
+        deny_users: vec![],
+        description: None,
+        read_buffer_size: None,
+        charset: None,
+        prompt: None,
     });
     config.endpoints.push(ConfigEndpoint {
         id: EndpointId::Mock("ccdl-Mock2".into()),
         labels: label_2.into(),
         flow_control: None,
+        baud: None,
+        idle_message_timeout: None,
+        write_terminator: None,
+        transforms: vec![],
+        write_delay: None,
+        echo: MockEcho::Echo,
+        shared_control: false,
+        allow_users: None,
+        deny_users: vec![],
+        description: None,
+        read_buffer_size: None,
+        charset: None,
+        prompt: None,
     });
 
     let port = start_server_with_config(config).await;
@@ -176,6 +296,19 @@ async fn granted_labelled_endpoint_is_freed_when_user_drops() -> Result<()> {
         id: EndpointId::Mock("sd".into()),
         labels: Labels::from_iter([label]),
         flow_control: None,
+        baud: None,
+        idle_message_timeout: None,
+        write_terminator: None,
+        transforms: vec![],
+        write_delay: None,
+        echo: MockEcho::Echo,
+        shared_control: false,
+        allow_users: None,
+        deny_users: vec![],
+        description: None,
+        read_buffer_size: None,
+        charset: None,
+        prompt: None,
     });
     let port = start_server_with_config(config).await;
     let mut client_1 = connect(port).await?;
@@ -206,7 +339,24 @@ async fn user_is_informed_of_endpoint_labels() -> Result<()> {
             id: EndpointId::mock("glmock"),
             labels: Labels::from_iter([endpoint_label.clone()]),
             flow_control: None,
+            baud: None,
+            idle_message_timeout: None,
+            write_terminator: None,
+            transforms: vec![],
+            write_delay: None,
+            echo: MockEcho::Echo,
+            shared_control: false,
+            allow_users: None,
+            deny_users: vec![],
+            description: None,
+            read_buffer_size: None,
+            charset: None,
+            prompt: None,
         }],
+        description: None,
+        name: None,
+        allow_users: None,
+        deny_users: vec![],
     });
 
     let port = start_server_with_config(config).await;
@@ -242,6 +392,19 @@ async fn multiple_label_endpoint_is_found_via_subset() -> Result<()> {
         id: EndpointId::Mock("MockManyLabels".into()),
         labels: Labels::from_iter([label_1, label_2]),
         flow_control: None,
+        baud: None,
+        idle_message_timeout: None,
+        write_terminator: None,
+        transforms: vec![],
+        write_delay: None,
+        echo: MockEcho::Echo,
+        shared_control: false,
+        allow_users: None,
+        deny_users: vec![],
+        description: None,
+        read_buffer_size: None,
+        charset: None,
+        prompt: None,
     });
 
     let mut client = connect(start_server_with_config(config).await).await?;
@@ -263,6 +426,19 @@ async fn multiple_label_endpoint_is_found_via_equal_set() -> Result<()> {
         id: EndpointId::Mock("MockManyLabels2".into()),
         labels: Labels::from_iter([label_1, label_2]),
         flow_control: None,
+        baud: None,
+        idle_message_timeout: None,
+        write_terminator: None,
+        transforms: vec![],
+        write_delay: None,
+        echo: MockEcho::Echo,
+        shared_control: false,
+        allow_users: None,
+        deny_users: vec![],
+        description: None,
+        read_buffer_size: None,
+        charset: None,
+        prompt: None,
     });
 
     let mut client = connect(start_server_with_config(config).await).await?;
@@ -288,6 +464,19 @@ async fn single_label_endpoint_is_not_matched_via_superset() -> Result<()> {
         id: EndpointId::Mock("MockManyLabels3".into()),
         labels: label_2.into(),
         flow_control: None,
+        baud: None,
+        idle_message_timeout: None,
+        write_terminator: None,
+        transforms: vec![],
+        write_delay: None,
+        echo: MockEcho::Echo,
+        shared_control: false,
+        allow_users: None,
+        deny_users: vec![],
+        description: None,
+        read_buffer_size: None,
+        charset: None,
+        prompt: None,
     });
 
     let mut client = connect(start_server_with_config(config).await).await?;
@@ -301,3 +490,111 @@ async fn single_label_endpoint_is_not_matched_via_superset() -> Result<()> {
 
     Ok(())
 }
+
+#[tokio::test]
+async fn default_labels_are_carried_alongside_specific_labels() -> Result<()> {
+    let mut config = Config {
+        default_labels: Labels::from_iter(["lab=oslo", "env=ci"]),
+        ..Default::default()
+    };
+    let specific = "dl-specific";
+
+    config.endpoints.push(ConfigEndpoint {
+        labels: specific.into(),
+        ..EndpointId::mock("dl-mock").into()
+    });
+
+    let mut client = connect(start_server_with_config(config).await).await?;
+
+    for labels in [["lab=oslo"], ["env=ci"], [specific]] {
+        match send_receive(&mut client, Action::control_any(&labels).serialize()).await?? {
+            Response::Sync(actions::Sync::ControlGranted(granted)) => {
+                let grant = &granted[0];
+
+                for label in ["lab=oslo", "env=ci", specific] {
+                    assert!(
+                        grant.labels.iter().any(|l| l.0 == label),
+                        "`{label}` should be among {}",
+                        grant.labels
+                    );
+                }
+
+                send_receive(&mut client, Action::release(&grant.id).serialize()).await??;
+            }
+            response => panic!("Expected control granted, got {response:?}"),
+        };
+    }
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn control_when_available_with_a_match_is_granted_right_away() -> Result<()> {
+    let mut config = Config::default();
+    let label = "wa-present";
+
+    config.endpoints.push(ConfigEndpoint {
+        labels: label.into(),
+        ..EndpointId::mock("wa-mock").into()
+    });
+
+    let mut client = connect(start_server_with_config(config).await).await?;
+
+    let response = send_receive(
+        &mut client,
+        Action::control_any_when_available(&[label], Duration::from_secs(10)).serialize(),
+    )
+    .await??;
+    assert_granted!(response);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn control_when_available_gives_up_after_timeout() -> Result<()> {
+    let mut client = start_server_and_connect().await?;
+    let timeout = Duration::from_millis(200);
+
+    let response = send_receive(
+        &mut client,
+        Action::control_any_when_available(&["wa-absent"], timeout).serialize(),
+    )
+    .await??;
+    assert_eq!(
+        response,
+        Response::Sync(actions::Sync::ControlDeferred(Labels::from_iter([
+            "wa-absent"
+        ])))
+    );
+
+    let before = std::time::Instant::now();
+    let response = receive(&mut client).await?;
+    assert_result_error!(response, Error::NoMatchingEndpoints(_));
+    assert!(before.elapsed() >= timeout / 2);
+
+    Ok(())
+}
+
+#[test]
+fn labels_given_in_any_order_are_equal_and_serialize_the_same() -> Result<()> {
+    let one = Labels::from_iter(["env=ci", "blue-device", "lab=oslo"]);
+    let other = Labels::from_iter(["lab=oslo", "env=ci", "blue-device"]);
+
+    assert_eq!(one, other);
+    assert_eq!(serde_json::to_string(&one)?, serde_json::to_string(&other)?);
+    assert_eq!(one.to_string(), other.to_string());
+
+    let mut pushed = Labels::default();
+    for label in ["lab=oslo", "blue-device", "env=ci"] {
+        pushed.push(label);
+    }
+    assert_eq!(pushed, one);
+
+    let deserialized: Labels = serde_json::from_str(r#"["lab=oslo", "env=ci", "blue-device"]"#)?;
+    assert_eq!(deserialized, one);
+
+    assert!(one.is_superset(&Labels::from_iter(["lab=oslo", "blue-device"])));
+    assert!(!one.is_superset(&Labels::from_iter(["lab=bergen"])));
+
+    Ok(())
+}
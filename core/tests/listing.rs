@@ -0,0 +1,139 @@
+mod common;
+
+use std::time::Duration;
+
+use color_eyre::Result;
+use common::*;
+use serial_keel::{
+    actions::{ListedEndpoint, ListedLabel},
+    client::ClientHandle,
+    config::{Config, ConfigEndpoint, Group},
+    endpoint::{EndpointId, Label},
+};
+
+fn description_of<'a>(endpoints: &'a [ListedEndpoint], name: &str) -> Option<&'a str> {
+    endpoints
+        .iter()
+        .find(|listed| matches!(&listed.id.id, EndpointId::Mock(mock) if mock.ends_with(name)))
+        .unwrap_or_else(|| panic!("Endpoint {name} should be listed"))
+        .description
+        .as_deref()
+}
+
+#[tokio::test]
+async fn descriptions_are_listed() -> Result<()> {
+    let mut config = Config::default();
+
+    config.endpoints.push(ConfigEndpoint {
+        description: Some("Bench A".into()),
+        ..EndpointId::mock("listed-lone").into()
+    });
+    config
+        .endpoints
+        .push(EndpointId::mock("listed-plain").into());
+
+    let mut group =
+        Group::new_with_labels(&["listed-group"], vec![EndpointId::mock("listed-member")]);
+    group.description = Some("Rack 3".into());
+    group.endpoints.push(ConfigEndpoint {
+        description: Some("Rack 3, slot 2".into()),
+        ..EndpointId::mock("listed-override").into()
+    });
+    config.groups.push(group);
+
+    let port = start_server_with_config(config).await;
+    let mut client = ClientHandle::new("localhost", port).await?;
+
+    let endpoints = client.list_endpoints().await?;
+    assert_eq!(endpoints.len(), 4);
+
+    assert_eq!(description_of(&endpoints, "listed-lone"), Some("Bench A"));
+    assert_eq!(description_of(&endpoints, "listed-plain"), None);
+    assert_eq!(description_of(&endpoints, "listed-member"), Some("Rack 3"));
+    assert_eq!(
+        description_of(&endpoints, "listed-override"),
+        Some("Rack 3, slot 2")
+    );
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn config_mock_is_listed_before_any_client_connects() -> Result<()> {
+    let mut config = Config::default();
+    config
+        .endpoints
+        .push(EndpointId::mock("listed-eagerly").into());
+
+    let port = start_server_with_config(config).await;
+
+    // Nobody has connected, so nobody could have had the mock created on demand.
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    let before_connecting = chrono::Utc::now();
+
+    let mut client = ClientHandle::new("localhost", port).await?;
+    let endpoints = client.list_endpoints().await?;
+
+    let listed = endpoints
+        .iter()
+        .find(|listed| matches!(&listed.id.id, EndpointId::Mock(mock) if mock.ends_with("listed-eagerly")))
+        .expect("The config mock should be listed");
+    assert!(listed.opened_at < before_connecting);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn fresh_mock_reports_recent_open() -> Result<()> {
+    let port = start_server().await;
+    let mut client = ClientHandle::new("localhost", port).await?;
+
+    let before = chrono::Utc::now();
+    let _reader = client.observe_mock("fresh-mock").await?;
+
+    let endpoints = client.list_endpoints().await?;
+    let listed = endpoints
+        .iter()
+        .find(|listed| matches!(&listed.id.id, EndpointId::Mock(mock) if mock.ends_with("fresh-mock")))
+        .expect("The mock should be listed");
+
+    let since_open = (chrono::Utc::now() - listed.opened_at).to_std()?;
+    assert!(listed.opened_at >= before);
+    assert!(since_open < Duration::from_secs(5));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn distinct_labels_are_listed_with_counts() -> Result<()> {
+    let mut config = Config::default();
+
+    config.endpoints.push(ConfigEndpoint {
+        labels: "nrf52".into(),
+        ..EndpointId::mock("labels-lone").into()
+    });
+    config
+        .endpoints
+        .push(EndpointId::mock("labels-plain").into());
+
+    let mut group = Group::new_with_labels(&["rack"], vec![EndpointId::mock("labels-member")]);
+    group.endpoints.push(ConfigEndpoint {
+        labels: "nrf52".into(),
+        ..EndpointId::mock("labels-nrf-member").into()
+    });
+    config.groups.push(group);
+
+    let port = start_server_with_config(config).await;
+    let mut client = ClientHandle::new("localhost", port).await?;
+
+    let listed = |label: &str, endpoints| ListedLabel {
+        label: Label::new(label),
+        endpoints,
+    };
+    assert_eq!(
+        client.list_labels().await?,
+        vec![listed("nrf52", 2), listed("rack", 2)]
+    );
+
+    Ok(())
+}
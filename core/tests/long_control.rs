@@ -0,0 +1,70 @@
+mod common;
+
+use std::time::Duration;
+
+use color_eyre::Result;
+use common::*;
+use pretty_assertions::assert_eq;
+use serial_keel::{
+    client::ClientHandle,
+    config::Config,
+    endpoint::EndpointId,
+    events::{general, Event},
+    user::User,
+};
+
+#[tokio::test]
+async fn long_control_is_reported_once() -> Result<()> {
+    let warn_after = Duration::from_millis(200);
+    let port = start_server_with_config(Config {
+        control_warn_after: Some(warn_after),
+        ..Default::default()
+    })
+    .await;
+
+    let mut observer = ClientHandle::new("localhost", port).await?;
+    let mut reader = observer.observe_events().await?;
+
+    let mut hog = ClientHandle::new_with_username("localhost", port, "hog").await?;
+    let mut writer = hog.control_mock("hogged").await?;
+
+    let long_control = async {
+        loop {
+            let event = reader.next_event().await;
+            if let Event::General(general::Event::LongControl {
+                user,
+                endpoint,
+                held_for,
+            }) = event.inner
+            {
+                return (user, endpoint, held_for);
+            }
+        }
+    };
+    let (user, endpoint, held_for) =
+        tokio::time::timeout(Duration::from_secs(5), long_control).await?;
+
+    assert_eq!(user, User::new("hog"));
+    assert_eq!(endpoint.id, EndpointId::mock("hogged"));
+    assert!(held_for >= warn_after, "Reported after only {held_for:?}");
+
+    // Still in control.
+    writer.write("mine").await?;
+
+    let again = async {
+        loop {
+            let event = reader.next_event().await;
+            if matches!(
+                event.inner,
+                Event::General(general::Event::LongControl { .. })
+            ) {
+                break;
+            }
+        }
+    };
+    assert!(tokio::time::timeout(Duration::from_millis(500), again)
+        .await
+        .is_err());
+
+    Ok(())
+}
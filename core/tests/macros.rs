@@ -0,0 +1,186 @@
+mod common;
+
+use color_eyre::Result;
+use common::*;
+use serial_keel::{
+    actions::{Action, Response},
+    client::ClientHandle,
+    config::{Config, ConfigEndpoint, Macro, MacroStep, MacroTarget},
+    endpoint::EndpointId,
+    error::Error,
+    serial::MockEcho,
+};
+
+fn two_line_macro(target: MacroTarget) -> Macro {
+    Macro {
+        name: "init".into(),
+        target,
+        steps: vec![
+            MacroStep::Line("first".into()),
+            MacroStep::DelayMs(10),
+            MacroStep::Line("second".into()),
+        ],
+        on_control: false,
+    }
+}
+
+#[cfg(feature = "mocks-share-endpoints")]
+fn on_control_macro(name: &str) -> Macro {
+    Macro {
+        name: "reset".into(),
+        target: MacroTarget::Endpoint(EndpointId::mock(name)),
+        steps: vec![MacroStep::Line("reset".into())],
+        on_control: true,
+    }
+}
+
+#[tokio::test]
+async fn run_two_line_macro() -> Result<()> {
+    let name = "macro-mock";
+    let config = Config {
+        macros: vec![two_line_macro(MacroTarget::Endpoint(EndpointId::mock(
+            name,
+        )))],
+        ..Default::default()
+    };
+
+    let port = start_server_with_config(config).await;
+    let mut client = ClientHandle::new("localhost", port).await?;
+
+    let mut writer = client.control_mock(name).await?;
+    let mut reader = client.observe_mock(name).await?;
+
+    writer.run_macro("init").await?;
+
+    assert_eq!(reader.next_message().await?.as_str(), "first");
+    assert_eq!(reader.next_message().await?.as_str(), "second");
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn run_labelled_macro() -> Result<()> {
+    let label = "macro-label";
+
+    let mut config = Config {
+        macros: vec![two_line_macro(MacroTarget::Labels(label.into()))],
+        ..Default::default()
+    };
+    config.endpoints.push(ConfigEndpoint {
+        id: EndpointId::mock("labelled-macro-mock"),
+        labels: label.into(),
+        flow_control: None,
+        baud: None,
+        idle_message_timeout: None,
+        write_terminator: None,
+        transforms: vec![],
+        write_delay: None,
+        echo: MockEcho::Echo,
+        shared_control: false,
+        allow_users: None,
+        deny_users: vec![],
+        description: None,
+        read_buffer_size: None,
+        charset: None,
+        prompt: None,
+    });
+
+    let mut client = connect(start_server_with_config(config).await).await?;
+
+    let response = send_receive(&mut client, Action::control_any(&[label]).serialize()).await??;
+    assert_granted!(response);
+
+    let id = EndpointId::mock("labelled-macro-mock");
+    let request = Action::run_macro(&id, "init").serialize();
+    let response = send_receive(&mut client, request).await??;
+
+    assert!(matches!(
+        response,
+        Response::Sync(serial_keel::actions::Sync::MacroDone(_))
+    ));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn unknown_macro_is_bad() -> Result<()> {
+    let mut client = start_server_and_connect().await?;
+
+    let id = EndpointId::mock("no-macros-here");
+    let response = send_receive(&mut client, Action::control(&id).serialize()).await??;
+    assert_granted!(response);
+
+    let request = Action::run_macro(&id, "init").serialize();
+    let response = send_receive(&mut client, request).await?;
+
+    assert_result_error!(response, Error::BadUsage(_));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn macro_without_control_is_bad() -> Result<()> {
+    let name = "uncontrolled-macro-mock";
+    let config = Config {
+        macros: vec![two_line_macro(MacroTarget::Endpoint(EndpointId::mock(
+            name,
+        )))],
+        ..Default::default()
+    };
+
+    let mut client = connect(start_server_with_config(config).await).await?;
+
+    let request = Action::run_macro(&EndpointId::mock(name), "init").serialize();
+    let response = send_receive(&mut client, request).await?;
+
+    assert_result_error!(response, Error::NoPermit(_));
+
+    Ok(())
+}
+
+#[cfg(feature = "mocks-share-endpoints")]
+#[tokio::test]
+async fn on_control_macro_runs_when_granted() -> Result<()> {
+    let name = "on-control-mock";
+    let config = Config {
+        macros: vec![on_control_macro(name)],
+        ..Default::default()
+    };
+
+    let port = start_server_with_config(config).await;
+
+    let mut observer = ClientHandle::new("localhost", port).await?;
+    let mut reader = observer.observe_mock(name).await?;
+
+    let mut controller = ClientHandle::new("localhost", port).await?;
+    let _writer = controller.control_mock(name).await?;
+
+    assert_eq!(reader.next_message().await?.as_str(), "reset");
+
+    Ok(())
+}
+
+#[cfg(feature = "mocks-share-endpoints")]
+#[tokio::test]
+async fn on_control_macro_can_be_skipped() -> Result<()> {
+    let name = "skip-on-control-mock";
+    let config = Config {
+        macros: vec![on_control_macro(name)],
+        ..Default::default()
+    };
+
+    let port = start_server_with_config(config).await;
+
+    let mut observer = ClientHandle::new("localhost", port).await?;
+    let mut reader = observer.observe_mock(name).await?;
+
+    let mut controller = ClientHandle::new("localhost", port).await?;
+    controller.skip_on_control_macros(true).await?;
+    let mut writer = controller.control_mock(name).await?;
+
+    // If the macro ran, its output would come first.
+    writer.write("not reset\n").await?;
+    assert_eq!(reader.next_message().await?.as_str(), "not reset");
+
+    Ok(())
+}
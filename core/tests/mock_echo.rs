@@ -0,0 +1,53 @@
+mod common;
+
+// Feature: The configured mock is only reachable by name if endpoints are shared
+#[cfg(feature = "mocks-share-endpoints")]
+mod mock_echo {
+    use color_eyre::Result;
+    use pretty_assertions::assert_eq;
+    use serial_keel::{
+        client::ClientHandle,
+        config::{Config, ConfigEndpoint},
+        endpoint::EndpointId,
+        serial::MockEcho,
+    };
+
+    use super::common::*;
+
+    async fn echoed(name: &str, echo: MockEcho, written: &str) -> Result<String> {
+        let id = EndpointId::mock(name);
+
+        let mut config = Config::default();
+        config.endpoints.push(ConfigEndpoint {
+            echo,
+            ..id.clone().into()
+        });
+        let port = start_server_with_config(config).await;
+
+        let mut client = ClientHandle::new("localhost", port).await?;
+        let mut endpoint = client.control_and_observe(&id).await?;
+        let echoed = endpoint.request_response(written).await?;
+
+        Ok(echoed.as_str().to_owned())
+    }
+
+    #[tokio::test]
+    async fn uppercase_mock_echoes_uppercase() -> Result<()> {
+        assert_eq!(
+            echoed("upper-mock", MockEcho::Uppercase, "hello there").await?,
+            "HELLO THERE"
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn crc_mock_appends_checksum() -> Result<()> {
+        assert_eq!(
+            echoed("crc-mock", MockEcho::AppendCrc, "hello").await?,
+            "hello*D26E"
+        );
+
+        Ok(())
+    }
+}
@@ -0,0 +1,54 @@
+mod common;
+
+use color_eyre::Result;
+use common::*;
+use pretty_assertions::assert_eq;
+use serial_keel::{actions::CloseReason, client::ClientHandle, endpoint::EndpointId, error::Error};
+
+#[tokio::test]
+async fn limited_observe_ends_after_limit() -> Result<()> {
+    let id = EndpointId::mock("banner");
+    let port = start_server().await;
+
+    let mut client = ClientHandle::new("localhost", port).await?;
+    let mut reader = client.observe_limited(&id, 3).await?;
+    let mut writer = client.control(&id).await?;
+
+    for line in ["one", "two", "three", "four", "five"] {
+        writer.write(line).await?;
+    }
+
+    for expected in ["one", "two", "three"] {
+        assert_eq!(reader.next_message().await?.as_str(), expected);
+    }
+
+    let response = reader.next_message().await;
+    assert_eq!(
+        response,
+        Err(Error::ObservationEnded {
+            endpoint: id.clone(),
+            reason: CloseReason::LimitReached
+        }),
+        "The reader should have ended"
+    );
+
+    // The endpoint may be observed again afterwards
+    let mut reader = client.observe(&id).await?;
+    writer.write("six").await?;
+    assert_eq!(reader.next_message().await?.as_str(), "six");
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn zero_observe_limit_is_bad_usage() -> Result<()> {
+    let port = start_server().await;
+
+    let mut client = ClientHandle::new("localhost", port).await?;
+    let response = client
+        .observe_limited(&EndpointId::mock("nothing"), 0)
+        .await;
+    assert!(matches!(response, Err(Error::BadUsage(_))));
+
+    Ok(())
+}
@@ -0,0 +1,649 @@
+mod common;
+
+// Feature: Observers can only see another user's mock if endpoints are shared
+#[cfg(feature = "mocks-share-endpoints")]
+mod observing {
+    use std::time::Duration;
+
+    use color_eyre::Result;
+    use pretty_assertions::assert_eq;
+    use serial_keel::{
+        actions::{Action, Async, CloseReason, Response, Sync},
+        client::{ClientHandle, ReadItem},
+        config::Config,
+        endpoint::{EndpointId, LabelledEndpointId},
+        error::Error,
+        serial::Direction,
+    };
+
+    use super::common::*;
+    use crate::{assert_granted, assert_observing, assert_result_error};
+
+    #[tokio::test]
+    async fn weak_observer_does_not_keep_mock_alive() -> Result<()> {
+        let id = EndpointId::mock("weakly-observed");
+        let lid = LabelledEndpointId::new(&id);
+
+        let port = start_server().await;
+
+        let mut user = connect(port).await?;
+        let response = send_receive(&mut user, Action::control(&id).serialize()).await??;
+        assert_granted!(response, lid);
+
+        let mut dashboard = connect(port).await?;
+        let response =
+            send_receive(&mut dashboard, Action::observe_weak(&id).serialize()).await??;
+        assert_observing!(response);
+
+        drop(user);
+
+        let response = receive(&mut dashboard).await??;
+        assert_eq!(
+            response,
+            Response::Async(Async::EndpointClosed((lid, CloseReason::Unavailable)))
+        );
+
+        let response = send_receive(&mut dashboard, Action::observe_weak(&id).serialize()).await?;
+        assert_result_error!(response, Error::NoSuchEndpoint(_));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn weak_observer_is_told_why_observation_ended() -> Result<()> {
+        let id = EndpointId::mock("weakly-observed-reason");
+
+        let port = start_server().await;
+
+        let mut user = ClientHandle::new("localhost", port).await?;
+        user.control(&id).await?;
+
+        let mut dashboard = ClientHandle::new("localhost", port).await?;
+        let mut reader = dashboard.observe_weak(&id).await?;
+
+        drop(user);
+
+        assert_eq!(
+            reader.next_message().await,
+            Err(Error::ObservationEnded {
+                endpoint: id,
+                reason: CloseReason::Unavailable
+            })
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn observer_including_writes_sees_other_users_writes() -> Result<()> {
+        let id = EndpointId::mock("audited");
+        let lid = LabelledEndpointId::new(&id);
+
+        let port = start_server().await;
+
+        let mut user = connect(port).await?;
+        let response = send_receive(&mut user, Action::control(&id).serialize()).await??;
+        assert_granted!(response, lid);
+
+        let mut monitor = connect(port).await?;
+        let response = send_receive(
+            &mut monitor,
+            Action::observe_including_writes(&id).serialize(),
+        )
+        .await??;
+        assert_observing!(response);
+
+        let response =
+            send_receive(&mut user, Action::write(&id, "hello".into()).serialize()).await??;
+        assert_eq!(response, Response::example_write_ok());
+
+        // The mock loops the write back, so it is seen both ways
+        for direction in [Direction::ToWire, Direction::FromWire] {
+            let response = receive(&mut monitor).await??;
+            assert_eq!(
+                response,
+                Response::Async(Async::Message {
+                    endpoint: lid.clone(),
+                    direction,
+                    message: "hello".into(),
+                })
+            );
+        }
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn message_observer_sees_only_the_listed_endpoints() -> Result<()> {
+        let listed = EndpointId::mock("traffic-listed");
+        let unlisted = EndpointId::mock("traffic-unlisted");
+        let lid = LabelledEndpointId::new(&listed);
+
+        let port = start_server().await;
+
+        let mut user = connect(port).await?;
+        for id in [&listed, &unlisted] {
+            let response = send_receive(&mut user, Action::control(id).serialize()).await??;
+            assert_granted!(response, LabelledEndpointId::new(id));
+        }
+
+        let mut monitor = connect(port).await?;
+        let response = send_receive(
+            &mut monitor,
+            Action::observe_messages(&[listed.clone()]).serialize(),
+        )
+        .await??;
+        assert_eq!(
+            response,
+            Response::Sync(Sync::ObservingMessages(vec![lid.clone()]))
+        );
+
+        for (id, message) in [(&unlisted, "to-unlisted"), (&listed, "to-listed")] {
+            let response =
+                send_receive(&mut user, Action::write(id, message.into()).serialize()).await??;
+            assert_eq!(response, Response::example_write_ok());
+        }
+
+        // The mock loops the write back, so it is seen both ways
+        for direction in [Direction::ToWire, Direction::FromWire] {
+            let response = receive(&mut monitor).await??;
+            let Response::Async(Async::Traffic(traffic)) = response else {
+                panic!("Expected traffic, got {response:?}");
+            };
+            assert_eq!(traffic.endpoint, lid);
+            assert_eq!(traffic.direction, direction);
+            assert_eq!(traffic.message, b"to-listed");
+        }
+
+        // Endpoints must exist to have their messages observed
+        let response = send_receive(
+            &mut monitor,
+            Action::observe_messages(&[EndpointId::mock("traffic-missing")]).serialize(),
+        )
+        .await?;
+        assert_result_error!(response, Error::NoSuchEndpoint(_));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn idempotent_reobserve_is_confirmed() -> Result<()> {
+        let id = EndpointId::mock("toggled");
+        let lid = LabelledEndpointId::new(&id);
+
+        let port = start_server().await;
+
+        let mut user = connect(port).await?;
+        let response = send_receive(&mut user, Action::control(&id).serialize()).await??;
+        assert_granted!(response, lid);
+
+        let mut dashboard = connect(port).await?;
+        for _ in 0..2 {
+            let response =
+                send_receive(&mut dashboard, Action::observe_idempotent(&id).serialize()).await??;
+            assert_eq!(response, Response::Sync(Sync::Observing(lid.clone())));
+        }
+
+        // Strictness is kept for those not opting in
+        let response = send_receive(&mut dashboard, Action::observe(&id).serialize()).await?;
+        assert_result_error!(response, Error::SuperfluousRequest(_));
+
+        let response =
+            send_receive(&mut user, Action::write(&id, "hello".into()).serialize()).await??;
+        assert_eq!(response, Response::example_write_ok());
+
+        let response = receive(&mut dashboard).await??;
+        assert_eq!(
+            response,
+            Response::Async(Async::Message {
+                endpoint: lid.clone(),
+                direction: Direction::FromWire,
+                message: "hello".into(),
+            })
+        );
+
+        // Observing twice does not mean receiving twice
+        let response =
+            tokio::time::timeout(Duration::from_millis(100), receive(&mut dashboard)).await;
+        assert!(response.is_err(), "Got a duplicate: {response:?}");
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn idempotent_reobserve_keeps_the_client_reader() -> Result<()> {
+        let id = EndpointId::mock("toggled-through-client");
+
+        let port = start_server().await;
+
+        let mut user = ClientHandle::new("localhost", port).await?;
+        let mut writer = user.control(&id).await?;
+
+        let mut dashboard = ClientHandle::new("localhost", port).await?;
+        let mut reader = dashboard
+            .observe_idempotent(&id)
+            .await?
+            .expect("Not observed yet");
+        assert!(dashboard.observe_idempotent(&id).await?.is_none());
+
+        writer.write("hello").await?;
+        assert_eq!(reader.next_message().await?.as_str(), "hello");
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn deferred_observe_starts_when_mock_is_created() -> Result<()> {
+        let id = EndpointId::mock("not-yet");
+        let lid = LabelledEndpointId::new(&id);
+
+        let port = start_server().await;
+
+        let mut waiting = connect(port).await?;
+        let response =
+            send_receive(&mut waiting, Action::observe_deferred(&id).serialize()).await??;
+        assert_eq!(
+            response,
+            Response::Sync(Sync::ObservationDeferred(id.clone()))
+        );
+
+        // Controlling the mock creates it
+        let mut user = connect(port).await?;
+        let response = send_receive(&mut user, Action::control(&id).serialize()).await??;
+        assert_granted!(response, lid);
+
+        let response = receive(&mut waiting).await??;
+        assert_eq!(response, Response::Sync(Sync::Observing(lid.clone())));
+
+        let response =
+            send_receive(&mut user, Action::write(&id, "hello".into()).serialize()).await??;
+        assert_eq!(response, Response::example_write_ok());
+
+        let response = receive(&mut waiting).await??;
+        assert_eq!(
+            response,
+            Response::Async(Async::Message {
+                endpoint: lid.clone(),
+                direction: Direction::FromWire,
+                message: "hello".into(),
+            })
+        );
+
+        // Once the endpoint exists, deferring makes no difference
+        let mut late = connect(port).await?;
+        let response = send_receive(&mut late, Action::observe_deferred(&id).serialize()).await??;
+        assert_eq!(response, Response::Sync(Sync::Observing(lid)));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn exclusive_observer_keeps_others_out() -> Result<()> {
+        let id = EndpointId::mock("sensitive");
+        let lid = LabelledEndpointId::new(&id);
+
+        let port = start_server().await;
+
+        let mut holder = connect(port).await?;
+        let response =
+            send_receive(&mut holder, Action::observe_exclusive(&id).serialize()).await??;
+        assert_eq!(response, Response::Sync(Sync::Observing(lid.clone())));
+
+        let mut other = connect(port).await?;
+        for action in [
+            Action::observe(&id),
+            Action::observe_weak(&id),
+            Action::observe_exclusive(&id),
+            Action::observe_endpoint_events(&id),
+        ] {
+            let response = send_receive(&mut other, action.serialize()).await?;
+            assert_result_error!(response, Error::EndpointBusy(_));
+        }
+
+        // Neither may the answer to a ping be listened for
+        let response = send_receive(&mut other, Action::control(&id).serialize()).await??;
+        assert_granted!(response);
+        let response = send_receive(
+            &mut other,
+            Action::ping_endpoint(&id, b"AT", b"AT", Duration::from_secs(1)).serialize(),
+        )
+        .await?;
+        assert_result_error!(response, Error::EndpointBusy(_));
+
+        // Once the holder leaves, others may observe again
+        drop(holder);
+        tokio::time::sleep(Duration::from_millis(100)).await;
+
+        let response = send_receive(&mut other, Action::observe(&id).serialize()).await??;
+        assert_eq!(response, Response::Sync(Sync::Observing(lid)));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn exclusive_observe_fails_if_already_observed() -> Result<()> {
+        let id = EndpointId::mock("already-watched");
+
+        let port = start_server().await;
+
+        let mut watcher = connect(port).await?;
+        let response = send_receive(&mut watcher, Action::observe(&id).serialize()).await??;
+        assert_observing!(response);
+
+        let mut other = connect(port).await?;
+        let response = send_receive(&mut other, Action::observe_exclusive(&id).serialize()).await?;
+        assert_result_error!(response, Error::EndpointBusy(_));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn replaying_observer_sees_recent_messages_first() -> Result<()> {
+        let id = EndpointId::mock("replayed");
+        let lid = LabelledEndpointId::new(&id);
+
+        let message = |message: &str| {
+            Response::Async(Async::Message {
+                endpoint: lid.clone(),
+                direction: Direction::FromWire,
+                message: message.into(),
+            })
+        };
+
+        let port = start_server().await;
+
+        let mut user = connect(port).await?;
+        let response = send_receive(&mut user, Action::control(&id).serialize()).await??;
+        assert_granted!(response, lid);
+
+        for line in ["first", "second"] {
+            let response =
+                send_receive(&mut user, Action::write(&id, line.into()).serialize()).await??;
+            assert_eq!(response, Response::example_write_ok());
+        }
+
+        // Let the loopback reach the server before anyone observes
+        tokio::time::sleep(Duration::from_millis(100)).await;
+
+        let mut replaying = connect(port).await?;
+        let response =
+            send_receive(&mut replaying, Action::observe_replayed(&id).serialize()).await??;
+        assert_observing!(response);
+
+        let mut live = connect(port).await?;
+        let response = send_receive(&mut live, Action::observe(&id).serialize()).await??;
+        assert_observing!(response);
+
+        let response =
+            send_receive(&mut user, Action::write(&id, "third".into()).serialize()).await??;
+        assert_eq!(response, Response::example_write_ok());
+
+        for line in ["first", "second", "third"] {
+            assert_eq!(receive(&mut replaying).await??, message(line));
+        }
+
+        // Starting at now means the earlier lines are never seen
+        assert_eq!(receive(&mut live).await??, message("third"));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn observer_since_a_cutoff_sees_only_newer_recent_messages() -> Result<()> {
+        let id = EndpointId::mock("replayed-since");
+        let lid = LabelledEndpointId::new(&id);
+
+        let message = |message: &str| {
+            Response::Async(Async::Message {
+                endpoint: lid.clone(),
+                direction: Direction::FromWire,
+                message: message.into(),
+            })
+        };
+
+        let port = start_server().await;
+
+        let mut user = connect(port).await?;
+        let response = send_receive(&mut user, Action::control(&id).serialize()).await??;
+        assert_granted!(response, lid);
+
+        let write = |line: &'static str| Action::write(&id, line.into()).serialize();
+
+        for line in ["old", "older"] {
+            assert_eq!(
+                send_receive(&mut user, write(line)).await??,
+                Response::example_write_ok()
+            );
+        }
+
+        // Let the loopback reach the server before the cutoff, and the next ones after it
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        let cutoff = chrono::Utc::now();
+        tokio::time::sleep(Duration::from_millis(100)).await;
+
+        for line in ["new", "newer"] {
+            assert_eq!(
+                send_receive(&mut user, write(line)).await??,
+                Response::example_write_ok()
+            );
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+
+        let mut catching_up = connect(port).await?;
+        let response = send_receive(
+            &mut catching_up,
+            Action::observe_since(&id, cutoff).serialize(),
+        )
+        .await??;
+        assert_observing!(response);
+
+        assert_eq!(
+            send_receive(&mut user, write("live")).await??,
+            Response::example_write_ok()
+        );
+
+        for line in ["new", "newer", "live"] {
+            assert_eq!(receive(&mut catching_up).await??, message(line));
+        }
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn recent_messages_are_the_tail_of_what_was_received() -> Result<()> {
+        let id = EndpointId::mock("recently-written");
+        let lid = LabelledEndpointId::new(&id);
+
+        let port = start_server().await;
+
+        let mut user = connect(port).await?;
+        let response = send_receive(&mut user, Action::control(&id).serialize()).await??;
+        assert_granted!(response, lid);
+
+        for line in ["first", "second", "third", "fourth"] {
+            let response =
+                send_receive(&mut user, Action::write(&id, line.into()).serialize()).await??;
+            assert_eq!(response, Response::example_write_ok());
+        }
+
+        // Let the loopback reach the server before asking
+        tokio::time::sleep(Duration::from_millis(100)).await;
+
+        // Neither control nor observing is needed
+        let mut other = connect(port).await?;
+        let response =
+            send_receive(&mut other, Action::recent_messages(&id, 2).serialize()).await??;
+        assert_eq!(
+            response,
+            Response::Sync(Sync::RecentMessages((
+                lid.clone(),
+                vec!["third".into(), "fourth".into()]
+            )))
+        );
+
+        // Asking for more than what was received gives everything
+        let response =
+            send_receive(&mut other, Action::recent_messages(&id, 100).serialize()).await??;
+        assert_eq!(
+            response,
+            Response::Sync(Sync::RecentMessages((
+                lid,
+                vec![
+                    "first".into(),
+                    "second".into(),
+                    "third".into(),
+                    "fourth".into()
+                ]
+            )))
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn deduplicating_observer_sees_repeats_collapsed() -> Result<()> {
+        let id = EndpointId::mock("deduplicated");
+        let lid = LabelledEndpointId::new(&id);
+
+        let port = start_server().await;
+
+        let mut user = connect(port).await?;
+        let response = send_receive(&mut user, Action::control(&id).serialize()).await??;
+        assert_granted!(response, lid);
+
+        let mut observer = connect(port).await?;
+        let response =
+            send_receive(&mut observer, Action::observe_deduplicated(&id).serialize()).await??;
+        assert_observing!(response);
+
+        for line in ["status", "status", "status", "changed", "status"] {
+            let response =
+                send_receive(&mut user, Action::write(&id, line.into()).serialize()).await??;
+            assert_eq!(response, Response::example_write_ok());
+        }
+
+        assert_eq!(
+            receive(&mut observer).await??,
+            Response::Async(Async::RepeatedMessage {
+                endpoint: lid.clone(),
+                direction: Direction::FromWire,
+                message: "status".into(),
+                repeats: 3,
+            })
+        );
+
+        // The changed line ends the run, so the next status line starts counting anew
+        for line in ["changed", "status"] {
+            assert_eq!(
+                receive(&mut observer).await??,
+                Response::Async(Async::Message {
+                    endpoint: lid.clone(),
+                    direction: Direction::FromWire,
+                    message: line.into(),
+                })
+            );
+        }
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn observers_see_notices_apart_from_device_messages() -> Result<()> {
+        let id = EndpointId::mock("noticed");
+        let lid = LabelledEndpointId::new(&id);
+
+        let port = start_server_with_config(Config {
+            admins: vec!["operator".into()],
+            ..Default::default()
+        })
+        .await;
+
+        let mut observer = connect(port).await?;
+        let response = send_receive(&mut observer, Action::observe(&id).serialize()).await??;
+        assert_observing!(response);
+
+        let mut operator = ClientHandle::new_with_username("localhost", port, "operator").await?;
+        assert_eq!(operator.notice(&id, "Board will reset").await?, 1);
+
+        let response = receive(&mut observer).await??;
+        assert_eq!(
+            response,
+            Response::Async(Async::Notice {
+                endpoint: lid,
+                text: "Board will reset".into(),
+            })
+        );
+
+        // Only admins may send notices
+        let mut other = ClientHandle::new("localhost", port).await?;
+        let response = other.notice(&id, "Board will reset").await;
+        assert!(matches!(response, Err(Error::AccessDenied(_))));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn endpoint_reader_keeps_the_repeat_count() -> Result<()> {
+        let id = EndpointId::mock("deduplicated-through-client");
+
+        let port = start_server().await;
+
+        let mut user = ClientHandle::new("localhost", port).await?;
+        let mut writer = user.control(&id).await?;
+
+        let mut observer = ClientHandle::new("localhost", port).await?;
+        let mut reader = observer.observe_deduplicated(&id).await?;
+
+        for line in ["status", "status", "status", "changed"] {
+            writer.write(line).await?;
+        }
+
+        assert_eq!(
+            reader.next_item().await?,
+            ReadItem::Repeated {
+                message: "status".into(),
+                repeats: 3
+            }
+        );
+        assert_eq!(
+            reader.next_item().await?,
+            ReadItem::Message("changed".into())
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn endpoint_reader_yields_notices_apart_from_device_messages() -> Result<()> {
+        let id = EndpointId::mock("noticed-through-client");
+
+        let port = start_server_with_config(Config {
+            admins: vec!["operator".into()],
+            ..Default::default()
+        })
+        .await;
+
+        let mut user = ClientHandle::new("localhost", port).await?;
+        let mut writer = user.control(&id).await?;
+
+        let mut observer = ClientHandle::new("localhost", port).await?;
+        let mut reader = observer.observe(&id).await?;
+
+        let mut operator = ClientHandle::new_with_username("localhost", port, "operator").await?;
+        assert_eq!(operator.notice(&id, "Board will reset").await?, 1);
+        assert_eq!(
+            reader.next_item().await?,
+            ReadItem::Notice("Board will reset".into())
+        );
+
+        writer.write("hello").await?;
+        assert_eq!(reader.next_item().await?, ReadItem::Message("hello".into()));
+
+        // Plain message reads only see what the device said
+        operator.notice(&id, "Board is back").await?;
+        writer.write("world").await?;
+        assert_eq!(reader.next_message().await?.as_str(), "world");
+
+        Ok(())
+    }
+}
@@ -0,0 +1,75 @@
+mod common;
+
+// Feature: The configured mock is only reachable by name if endpoints are shared
+#[cfg(feature = "mocks-share-endpoints")]
+mod ping {
+    use std::time::Duration;
+
+    use color_eyre::Result;
+    use serial_keel::{
+        client::ClientHandle,
+        config::{Config, ConfigEndpoint},
+        endpoint::EndpointId,
+        error::Error,
+        serial::MockEcho,
+    };
+
+    use super::common::*;
+
+    const TIMEOUT: Duration = Duration::from_secs(1);
+
+    async fn start_uppercase_mock(name: &str) -> Result<(ClientHandle, EndpointId)> {
+        let id = EndpointId::mock(name);
+
+        let mut config = Config::default();
+        config.endpoints.push(ConfigEndpoint {
+            echo: MockEcho::Uppercase,
+            ..id.clone().into()
+        });
+        let port = start_server_with_config(config).await;
+
+        let client = ClientHandle::new("localhost", port).await?;
+
+        Ok((client, id))
+    }
+
+    #[tokio::test]
+    async fn mock_answering_the_probe_pongs() -> Result<()> {
+        let (mut client, id) = start_uppercase_mock("pinged-mock").await?;
+        let _writer = client.control(&id).await?;
+
+        let round_trip = client.ping_endpoint(&id, b"ping", b"PING", TIMEOUT).await?;
+        assert!(round_trip < TIMEOUT);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn mock_not_answering_as_expected_times_out() -> Result<()> {
+        let (mut client, id) = start_uppercase_mock("unanswering-mock").await?;
+        let _writer = client.control(&id).await?;
+
+        let response = client
+            .ping_endpoint(&id, b"ping", b"ping", Duration::from_millis(100))
+            .await;
+        assert!(
+            matches!(response, Err(Error::Timeout(_))),
+            "Expected a timeout, got {response:?}"
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn ping_needs_control() -> Result<()> {
+        let (mut client, id) = start_uppercase_mock("uncontrolled-mock").await?;
+
+        let response = client.ping_endpoint(&id, b"ping", b"PING", TIMEOUT).await;
+        assert!(
+            matches!(response, Err(Error::NotControlling { .. })),
+            "Expected not controlling, got {response:?}"
+        );
+
+        Ok(())
+    }
+}
@@ -0,0 +1,43 @@
+mod common;
+
+// Feature: Pseudo-terminals are only created on unix
+#[cfg(unix)]
+mod pty {
+    use std::io::Write;
+
+    use color_eyre::Result;
+    use pretty_assertions::assert_eq;
+    use serial_keel::{client::ClientHandle, config::Config, endpoint::EndpointId};
+
+    use super::common::*;
+
+    #[tokio::test]
+    async fn writes_to_the_pty_are_observed_as_lines() -> Result<()> {
+        let id = EndpointId::pty("pty-test");
+        let mut config = Config::default();
+        config.endpoints.push(id.clone().into());
+        let port = start_server_with_config(config).await;
+
+        let mut client = ClientHandle::new("localhost", port).await?;
+        let listed = client
+            .list_endpoints()
+            .await?
+            .into_iter()
+            .find(|endpoint| endpoint.id.id == id)
+            .expect("The pty should be listed");
+        let device = listed
+            .device
+            .expect("A pty should tell where it can be opened");
+
+        let mut reader = client.observe(&id).await?;
+
+        let mut other_end = serialport::new(&device, 115_200).open()?;
+        other_end.write_all(b"hello\nwor")?;
+        other_end.write_all(b"ld\n")?;
+
+        assert_eq!(reader.next_message().await?.as_str(), "hello");
+        assert_eq!(reader.next_message().await?.as_str(), "world");
+
+        Ok(())
+    }
+}
@@ -31,12 +31,18 @@ mod queuing {
     use color_eyre::Result;
     use pretty_assertions::assert_eq;
     use serial_keel::{
-        actions::{Action, Response},
+        actions::{Action, Async, Response},
+        client::{ClientHandle, EventReader},
+        config::{Config, ConfigEndpoint, Group},
         endpoint::{EndpointId, LabelledEndpointId},
+        error::Error,
+        events::{general, user, Event},
+        serial::Direction,
+        user::User,
     };
 
     use super::common::*;
-    use crate::{assert_granted, assert_queued};
+    use crate::{assert_granted, assert_observing, assert_queued};
 
     #[tokio::test]
     async fn second_user_is_queued() -> Result<()> {
@@ -98,6 +104,39 @@ mod queuing {
         Ok(())
     }
 
+    #[tokio::test]
+    async fn second_user_gets_access_promptly_after_first_user_closes() -> Result<()> {
+        // Shared data
+        let id = EndpointId::Mock("queue-then-close".into());
+        let request = Action::control(&id).serialize();
+
+        let port = start_server().await;
+
+        // Client 1
+        let mut client_1 = connect(port).await?;
+        let response = send_receive(&mut client_1, request.clone()).await??;
+
+        let lid = LabelledEndpointId::new(&id);
+        assert_granted!(response, lid);
+
+        // Client 2
+        let mut client_2 = connect(port).await?;
+        let response = send_receive(&mut client_2, request.clone()).await??;
+
+        assert_queued!(response, lid);
+
+        // Client 1 sends a close frame, but keeps the connection around
+        client_1.close(None).await?;
+
+        let response =
+            tokio::time::timeout(Duration::from_secs(1), receive(&mut client_2)).await???;
+        assert_granted!(response, lid);
+
+        drop(client_1);
+
+        Ok(())
+    }
+
     #[tokio::test]
     async fn three_users_control_granted_in_order() -> Result<()> {
         // Shared data
@@ -181,4 +220,335 @@ mod queuing {
 
         Ok(())
     }
+
+    async fn next_queue(reader: &mut EventReader, lid: &LabelledEndpointId) -> Vec<User> {
+        loop {
+            if let Event::General(general::Event::QueueUpdated {
+                endpoint,
+                ordered_users,
+            }) = reader.next_event().await.inner
+            {
+                if &endpoint == lid {
+                    return ordered_users;
+                }
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn queue_updates_are_ordered_snapshots() -> Result<()> {
+        let id = EndpointId::Mock("queue-snapshots".into());
+        let lid = LabelledEndpointId::new(&id);
+        let request = Action::control(&id).serialize();
+
+        let port = start_server().await;
+
+        let mut observer = ClientHandle::new("localhost", port).await?;
+        let mut events = observer.observe_events().await?;
+
+        let mut client_1 = connect(port).await?;
+        let response = send_receive(&mut client_1, request.clone()).await??;
+        assert_granted!(response, lid);
+
+        let mut client_2 = connect(port).await?;
+        let response = send_receive(&mut client_2, request.clone()).await??;
+        assert_queued!(response, lid);
+        let queue = next_queue(&mut events, &lid).await;
+        assert_eq!(queue.len(), 1);
+        let user_2 = queue[0].clone();
+
+        let mut client_3 = connect(port).await?;
+        let response = send_receive(&mut client_3, request.clone()).await??;
+        assert_queued!(response, lid);
+        let queue = next_queue(&mut events, &lid).await;
+        assert_eq!(queue.len(), 2);
+        assert_eq!(queue[0], user_2);
+        let user_3 = queue[1].clone();
+
+        let mut client_4 = connect(port).await?;
+        let response = send_receive(&mut client_4, request).await??;
+        assert_queued!(response, lid);
+        let queue = next_queue(&mut events, &lid).await;
+        assert_eq!(queue.len(), 3);
+        assert_eq!(queue[..2], [user_2.clone(), user_3]);
+        let user_4 = queue[2].clone();
+
+        // Leaving the queue keeps the order of the rest
+        drop(client_3);
+        assert_eq!(
+            next_queue(&mut events, &lid).await,
+            [user_2.clone(), user_4.clone()]
+        );
+
+        // The first in queue gets control, and leaves the queue
+        drop(client_1);
+        let response = receive(&mut client_2).await??;
+        assert_granted!(response, lid);
+        assert_eq!(next_queue(&mut events, &lid).await, [user_4.clone()]);
+
+        drop(client_2);
+        let response = receive(&mut client_4).await??;
+        assert_granted!(response, lid);
+        assert_eq!(next_queue(&mut events, &lid).await, Vec::<User>::new());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn two_users_write_to_shared_control_mock() -> Result<()> {
+        let id = EndpointId::mock("shared-control");
+        let lid = LabelledEndpointId::new(&id);
+
+        let port = start_server_with_config(Config {
+            endpoints: vec![ConfigEndpoint {
+                shared_control: true,
+                ..id.clone().into()
+            }],
+            ..Default::default()
+        })
+        .await;
+        let request = Action::control(&id).serialize();
+
+        let mut client_1 = connect(port).await?;
+        let response = send_receive(&mut client_1, request.clone()).await??;
+        assert_granted!(response, lid);
+
+        // No queue, both are in control
+        let mut client_2 = connect(port).await?;
+        let response = send_receive(&mut client_2, request).await??;
+        assert_granted!(response, lid);
+
+        let response = send_receive(&mut client_1, Action::observe(&id).serialize()).await??;
+        assert_observing!(response);
+
+        for (writer, message) in [
+            (&mut client_2, "from client 2"),
+            (&mut client_1, "from client 1"),
+        ] {
+            let response =
+                send_receive(writer, Action::write(&id, message.into()).serialize()).await??;
+            assert_eq!(response, Response::example_write_ok());
+        }
+
+        for expected in ["from client 2", "from client 1"] {
+            let response = receive(&mut client_1).await??;
+            assert_eq!(
+                response,
+                Response::Async(Async::Message {
+                    endpoint: lid.clone(),
+                    direction: Direction::FromWire,
+                    message: expected.into(),
+                })
+            );
+        }
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn endpoint_is_free_right_after_write_then_release() -> Result<()> {
+        let id = EndpointId::mock("write-then-release");
+        let port = start_server().await;
+
+        let mut client_1 = ClientHandle::new("localhost", port).await?;
+        let mut client_2 = ClientHandle::new("localhost", port).await?;
+        let mut reader = client_2.observe(&id).await?;
+
+        client_1.control_write_release(&id, "reboot\n").await?;
+        assert_eq!(reader.next_message().await?.as_str(), "reboot");
+
+        // Had client 1 still held the endpoint, client 2 would be queued.
+        let _writer = tokio::time::timeout(Duration::from_secs(1), client_2.control(&id)).await??;
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn labelled_endpoint_is_free_right_after_write_then_release() -> Result<()> {
+        let id = EndpointId::mock("labelled-write-then-release");
+        let mut config = Config::default();
+        config.endpoints.push(ConfigEndpoint {
+            labels: "write-then-release".into(),
+            ..id.clone().into()
+        });
+        let port = start_server_with_config(config).await;
+
+        let mut client_1 = ClientHandle::new("localhost", port).await?;
+        let mut client_2 = ClientHandle::new("localhost", port).await?;
+        let mut reader = client_2.observe(&id).await?;
+
+        client_1
+            .control_any_write_release(&["write-then-release"], "reboot\n")
+            .await?;
+        assert_eq!(reader.next_message().await?.as_str(), "reboot");
+
+        let _writers = tokio::time::timeout(
+            Duration::from_secs(1),
+            client_2.control_any(&["write-then-release"]),
+        )
+        .await??;
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn group_is_free_right_after_write_then_release() -> Result<()> {
+        let id = EndpointId::mock("group-write-then-release");
+        let mut config = Config::default();
+        config.groups.push(Group::new_with_labels(
+            &["group-write-then-release"],
+            vec![
+                id.clone(),
+                EndpointId::mock("group-write-then-release-sibling"),
+            ],
+        ));
+        let port = start_server_with_config(config).await;
+
+        let mut client_1 = ClientHandle::new("localhost", port).await?;
+        let mut client_2 = ClientHandle::new("localhost", port).await?;
+        let mut reader = client_2.observe(&id).await?;
+
+        client_1
+            .control_any_write_release(&["group-write-then-release"], "reboot\n")
+            .await?;
+        assert_eq!(reader.next_message().await?.as_str(), "reboot");
+
+        let writers = tokio::time::timeout(
+            Duration::from_secs(1),
+            client_2.control_any(&["group-write-then-release"]),
+        )
+        .await??;
+        assert_eq!(writers.0.len(), 2);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn control_outcome_tells_whether_queued() -> Result<()> {
+        let id = EndpointId::mock("contended");
+        let mut config = Config::default();
+        config.endpoints.push(ConfigEndpoint {
+            labels: "contended".into(),
+            ..id.into()
+        });
+        let port = start_server_with_config(config).await;
+
+        let mut client_1 = ClientHandle::new("localhost", port).await?;
+        let mut client_2 = ClientHandle::new("localhost", port).await?;
+
+        let mut first = client_1.control_any_outcome(&["contended"]).await?;
+        assert!(!first.was_queued);
+
+        let second = tokio::spawn(async move {
+            let outcome = client_2.control_any_outcome(&["contended"]).await;
+            (client_2, outcome)
+        });
+
+        let held = Duration::from_millis(200);
+        tokio::time::sleep(held).await;
+        client_1.release(first.writers.0.remove(0)).await?;
+
+        let (_client_2, second) = second.await?;
+        let second = second?;
+        assert!(second.was_queued);
+        assert!(second.waited >= held);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn queued_control_any_tells_about_endpoints_not_queued_for() -> Result<()> {
+        let held = EndpointId::mock("mixed-held");
+        let busy = EndpointId::mock("mixed-busy");
+
+        let mut config = Config::default();
+        for id in [&held, &busy] {
+            config.endpoints.push(ConfigEndpoint {
+                labels: "mixed".into(),
+                ..id.clone().into()
+            });
+        }
+        let port = start_server_with_config(config).await;
+
+        let mut client_1 = ClientHandle::new("localhost", port).await?;
+        let mut client_2 = ClientHandle::new("localhost", port).await?;
+
+        let _held_writer = client_1.control(&held).await?;
+        let busy_writer = client_2.control(&busy).await?;
+
+        let waiter = tokio::spawn(async move {
+            let outcome = client_1.control_any_outcome(&["mixed"]).await;
+            (client_1, outcome)
+        });
+
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        client_2.release(busy_writer).await?;
+
+        let (_client_1, outcome) = waiter.await?;
+        let outcome = outcome?;
+        assert!(outcome.was_queued);
+
+        let [not_queued_on] = outcome.not_queued_on.as_slice() else {
+            panic!(
+                "Expected one endpoint not queued for, got {:?}",
+                outcome.not_queued_on
+            );
+        };
+        assert_eq!(not_queued_on.endpoint.id, held);
+        assert!(matches!(not_queued_on.error, Error::SuperfluousRequest(_)));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn dropped_control_any_future_leaves_the_queue() -> Result<()> {
+        let id = EndpointId::mock("given-up-on");
+        let mut config = Config::default();
+        config.endpoints.push(ConfigEndpoint {
+            labels: "given-up-on".into(),
+            ..id.into()
+        });
+        let port = start_server_with_config(config).await;
+
+        let mut client_1 = ClientHandle::new("localhost", port).await?;
+        let mut client_2 = ClientHandle::new("localhost", port).await?;
+        let quitter = client_2.user().cloned().expect("Should know own name");
+
+        let mut observer = ClientHandle::new("localhost", port).await?;
+        let mut events = observer.observe_events().await?;
+
+        let mut writers = client_1.control_any(&["given-up-on"]).await?;
+
+        tokio::select! {
+            _ = client_2.control_any_future(&["given-up-on"]) => {
+                panic!("Client 1 is in control, so client 2 should be queued")
+            }
+            _ = tokio::time::sleep(Duration::from_millis(200)) => {}
+        }
+
+        events
+            .wait_for(
+                |event| {
+                    matches!(
+                        &event.inner,
+                        Event::User(user::UserEvent {
+                            user: left,
+                            event: user::Event::NoLongerInQueueOf(_),
+                        }) if left == &quitter
+                    )
+                },
+                Duration::from_secs(5),
+            )
+            .await?;
+
+        // Had client 2 still been queued, it would already have control when asking again.
+        client_1.release(writers.0.remove(0)).await?;
+        let _writers = tokio::time::timeout(
+            Duration::from_secs(1),
+            client_2.control_any(&["given-up-on"]),
+        )
+        .await??;
+
+        Ok(())
+    }
 }
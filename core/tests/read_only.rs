@@ -0,0 +1,68 @@
+use color_eyre::Result;
+use common::{connect, send_receive, start_server_with_config};
+use serial_keel::{
+    actions::{Action, Response, Sync},
+    config::Config,
+    endpoint::EndpointId,
+    error::Error,
+};
+
+mod common;
+
+async fn start_read_only_server() -> u16 {
+    start_server_with_config(Config {
+        read_only: true,
+        ..Default::default()
+    })
+    .await
+}
+
+#[tokio::test]
+async fn read_only_server_allows_observing() -> Result<()> {
+    let port = start_read_only_server().await;
+    let mut client = connect(port).await?;
+
+    let response = send_receive(
+        &mut client,
+        Action::observe(&EndpointId::mock("watched")).serialize(),
+    )
+    .await??;
+    assert_observing!(response);
+
+    let response = send_receive(&mut client, Action::ObserveEvents.serialize()).await??;
+    assert!(matches!(response, Response::Sync(Sync::ObservingEvents)));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn read_only_server_rejects_control_and_writes() -> Result<()> {
+    let port = start_read_only_server().await;
+    let mut client = connect(port).await?;
+    let id = EndpointId::mock("untouchable");
+
+    for action in [
+        Action::control(&id),
+        Action::control_any(&["untouchable"]),
+        Action::write(&id, "hello".into()),
+    ] {
+        let response = send_receive(&mut client, action.serialize()).await?;
+        assert!(
+            matches!(response, Err(Error::ReadOnlyServer(_))),
+            "Expected {action} to be rejected, got {response:?}"
+        );
+    }
+
+    // Wrapping the action does not get it past the check
+    let response = send_receive(
+        &mut client,
+        Action::control(&id).correlated("sneaky").serialize(),
+    )
+    .await?;
+    assert!(
+        matches!(&response, Err(Error::Correlated { error, .. }) if matches!(**error, Error::ReadOnlyServer(_))),
+        "Expected the correlated control to be rejected, got {response:?}"
+    );
+
+    Ok(())
+}
@@ -0,0 +1,60 @@
+mod common;
+
+use color_eyre::Result;
+use common::*;
+use serial_keel::{
+    actions::{Action, Response},
+    client::ClientHandle,
+    endpoint::EndpointId,
+    error::Error,
+};
+
+#[tokio::test]
+async fn reconfigure_without_control_is_bad() -> Result<()> {
+    let mut client = start_server_and_connect().await?;
+
+    let id = EndpointId::tty("/dev/ttyNotControlled");
+    let request = Action::reconfigure(&id, Some(57_600), None).serialize();
+    let response = send_receive(&mut client, request).await?;
+
+    assert_result_error!(response, Error::NoPermit(_));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn reconfigure_mock_is_bad() -> Result<()> {
+    let mut client = start_server_and_connect().await?;
+
+    let id = EndpointId::mock("reconfigure-mock");
+    let response = send_receive(&mut client, Action::control(&id).serialize()).await??;
+    assert_granted!(response);
+
+    let request = Action::reconfigure(&id, Some(57_600), None).serialize();
+    let response = send_receive(&mut client, request).await?;
+
+    assert_result_error!(response, Error::BadUsage(_));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn client_reconfigure_mock_is_bad() -> Result<()> {
+    let port = start_server().await;
+    let mut client = ClientHandle::new("localhost", port).await?;
+
+    let name = "client-reconfigure-mock";
+    let _writer = client.control_mock(name).await?;
+
+    let response = client
+        .reconfigure(
+            &EndpointId::mock(name),
+            Some(57_600),
+            Some(serialport::FlowControl::Hardware),
+        )
+        .await;
+
+    assert_result_error!(response, Error::BadUsage(_));
+
+    Ok(())
+}
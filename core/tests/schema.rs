@@ -0,0 +1,19 @@
+use color_eyre::Result;
+use serial_keel::actions;
+
+#[test]
+fn schema_describes_actions_and_responses() -> Result<()> {
+    let schema = actions::schema();
+
+    for name in ["Action", "Response", "Error"] {
+        assert!(
+            schema.definitions.contains_key(name),
+            "`{name}` should be described by the schema"
+        );
+    }
+
+    let action = serde_json::to_string(&schema.definitions["Action"])?;
+    assert!(action.contains("\"ControlAny\""));
+
+    Ok(())
+}
@@ -0,0 +1,57 @@
+mod common;
+
+use std::time::Duration;
+
+use color_eyre::Result;
+use common::*;
+use pretty_assertions::assert_eq;
+use serial_keel::{
+    client::ClientHandle,
+    config::{Config, EventsConfig},
+    events::{general, Event},
+    logging::{self, LogLevel},
+};
+use tracing::{info, warn, Level};
+
+#[tokio::test]
+async fn warnings_are_sent_as_events() -> Result<()> {
+    logging::init(Level::WARN, None).await;
+
+    let port = start_server_with_config(Config {
+        events: EventsConfig {
+            server_logs: Some(LogLevel::Warn),
+            ..Default::default()
+        },
+        ..Default::default()
+    })
+    .await;
+
+    let mut client = ClientHandle::new("localhost", port).await?;
+    let mut reader = client.observe_events().await?;
+
+    // The server and the test share a process, so the test may log on its behalf.
+    info!(target: "server_logs_test", "Not severe enough");
+    warn!(target: "server_logs_test", code = 7, "Something is off");
+
+    let log = async {
+        loop {
+            let event = reader.next_event().await;
+            if let Event::General(general::Event::ServerLog {
+                level,
+                target,
+                message,
+            }) = event.inner
+            {
+                if target == "server_logs_test" {
+                    return (level, message);
+                }
+            }
+        }
+    };
+    let (level, message) = tokio::time::timeout(Duration::from_secs(5), log).await?;
+
+    assert_eq!(level, LogLevel::Warn);
+    assert_eq!(message, "Something is off code=7");
+
+    Ok(())
+}
@@ -0,0 +1,43 @@
+mod common;
+
+// Feature: The configured mock is only observable by name if endpoints are shared
+#[cfg(feature = "mocks-share-endpoints")]
+mod startup_macros {
+    use color_eyre::Result;
+    use pretty_assertions::assert_eq;
+    use serial_keel::{
+        client::ClientHandle,
+        config::{Config, Macro, MacroStep, MacroTarget, StartupMacro},
+        endpoint::EndpointId,
+    };
+
+    use super::common::*;
+
+    #[tokio::test]
+    async fn startup_macro_runs_on_boot() -> Result<()> {
+        let id = EndpointId::mock("boot-mock");
+
+        let mut config = Config::default();
+        config.endpoints.push(id.clone().into());
+        config.macros.push(Macro {
+            name: "reset".into(),
+            target: MacroTarget::Endpoint(id.clone()),
+            // Give the client time to start observing
+            steps: vec![MacroStep::DelayMs(300), MacroStep::Line("reset".into())],
+            on_control: false,
+        });
+        config.startup_macros.push(StartupMacro {
+            name: "reset".into(),
+            target: MacroTarget::Endpoint(id.clone()),
+        });
+
+        let port = start_server_with_config(config).await;
+
+        let mut client = ClientHandle::new("localhost", port).await?;
+        let mut reader = client.observe(&id).await?;
+
+        assert_eq!(reader.next_message().await?.as_str(), "reset");
+
+        Ok(())
+    }
+}
@@ -0,0 +1,74 @@
+mod common;
+
+use std::time::Duration;
+
+use color_eyre::Result;
+use common::*;
+use serial_keel::{
+    client::ClientHandle,
+    config::{Config, EventsConfig},
+    events::{general, Event},
+};
+
+#[tokio::test]
+async fn stats_arrive_at_configured_cadence() -> Result<()> {
+    let interval = Duration::from_millis(200);
+    let port = start_server_with_config(Config {
+        events: EventsConfig {
+            stats_interval: interval,
+            ..Default::default()
+        },
+        ..Default::default()
+    })
+    .await;
+
+    let mut client = ClientHandle::new("localhost", port).await?;
+    let mut reader = client.observe_events().await?;
+
+    let mut timestamps = vec![];
+    let stats = async {
+        while timestamps.len() < 3 {
+            let event = reader.next_event().await;
+            if let Event::General(general::Event::Stats {
+                connected_users, ..
+            }) = event.inner
+            {
+                assert_eq!(connected_users, 1);
+                timestamps.push(event.timestamp);
+            }
+        }
+    };
+    tokio::time::timeout(Duration::from_secs(5), stats).await?;
+
+    for pair in timestamps.windows(2) {
+        let between = (pair[1] - pair[0]).to_std()?;
+        assert!(
+            between > interval / 2 && between < interval * 2,
+            "Stats should arrive every {interval:?}, got {between:?} between two"
+        );
+    }
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn stats_are_off_by_default() -> Result<()> {
+    let port = start_server().await;
+
+    let mut client = ClientHandle::new("localhost", port).await?;
+    let mut reader = client.observe_events().await?;
+
+    let stats = async {
+        loop {
+            let event = reader.next_event().await;
+            if matches!(event.inner, Event::General(general::Event::Stats { .. })) {
+                break;
+            }
+        }
+    };
+    assert!(tokio::time::timeout(Duration::from_millis(500), stats)
+        .await
+        .is_err());
+
+    Ok(())
+}
@@ -0,0 +1,83 @@
+mod common;
+
+use std::{
+    io::Write,
+    sync::{Arc, Mutex},
+};
+
+use color_eyre::Result;
+use common::*;
+use pretty_assertions::assert_eq;
+use serial_keel::client::ClientHandle;
+
+/// A writer whose contents can be looked at after handing it to the client.
+#[derive(Debug, Clone, Default)]
+struct SharedBuffer(Arc<Mutex<Vec<u8>>>);
+
+impl Write for SharedBuffer {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.lock().unwrap().write(buf)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+#[tokio::test]
+async fn traffic_log_has_action_and_response() -> Result<()> {
+    let port = start_server().await;
+    let log = SharedBuffer::default();
+
+    let mut client = ClientHandle::new("localhost", port)
+        .await?
+        .with_traffic_log(log.clone());
+    client.list_endpoints().await?;
+
+    let contents = log.0.lock().unwrap().clone();
+    let entries = serde_json::Deserializer::from_slice(&contents)
+        .into_iter::<serde_json::Value>()
+        .collect::<Result<Vec<_>, _>>()?;
+
+    assert_eq!(entries.len(), 2, "Got entries: {entries:#?}");
+
+    let (outgoing, incoming) = (&entries[0], &entries[1]);
+    assert_eq!(outgoing["direction"], "outgoing");
+    assert_eq!(outgoing["message"], "ListEndpoints");
+    assert_eq!(incoming["direction"], "incoming");
+    assert!(incoming["message"]["Ok"]["Sync"]["Endpoints"].is_array());
+
+    for entry in entries {
+        assert!(entry["timestamp"].is_string());
+    }
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn close_waits_for_pending_writes() -> Result<()> {
+    let port = start_server().await;
+    let log = SharedBuffer::default();
+
+    let mut client = ClientHandle::new("localhost", port)
+        .await?
+        .with_traffic_log(log.clone());
+    let mut writer = client.control_mock("closing").await?;
+
+    // Only queued, nothing awaits the response
+    writer.write("bye\n").await?;
+    client.close().await?;
+
+    let contents = log.0.lock().unwrap().clone();
+    let entries = serde_json::Deserializer::from_slice(&contents)
+        .into_iter::<serde_json::Value>()
+        .collect::<Result<Vec<_>, _>>()?;
+
+    assert!(
+        entries.iter().any(|entry| entry["direction"] == "incoming"
+            && entry["message"]["Ok"]["Sync"]["WriteOk"].is_object()),
+        "Got entries: {entries:#?}"
+    );
+
+    Ok(())
+}
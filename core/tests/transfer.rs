@@ -0,0 +1,55 @@
+mod common;
+
+// Feature: Can't hand over control if endpoints are not shared
+#[cfg(feature = "mocks-share-endpoints")]
+mod transfer {
+    use std::time::Duration;
+
+    use color_eyre::Result;
+    use serial_keel::{client::ClientHandle, endpoint::EndpointId, error::Error, user::User};
+    use tokio::time::timeout;
+
+    use super::common::*;
+
+    #[tokio::test]
+    async fn handoff_between_two_users() -> Result<()> {
+        let id = EndpointId::mock("handoff");
+        let port = start_server().await;
+
+        let mut alice = ClientHandle::new("localhost", port).await?;
+        let mut bob = ClientHandle::new("localhost", port).await?;
+        let bob_user = bob
+            .user()
+            .expect("Plain connections know their user")
+            .clone();
+
+        let _alice_writer = alice.control_mock("handoff").await?;
+        let mut reader = bob.observe_mock("handoff").await?;
+
+        alice.transfer_control(&id, &bob_user).await?;
+
+        let mut writers =
+            timeout(Duration::from_secs(1), bob.receive_transferred_control()).await??;
+        let mut bob_writer = writers.remove(0);
+        assert!(writers.is_empty());
+
+        bob_writer.write("from bob").await?;
+        assert_eq!(reader.next_message().await?.as_str(), "from bob");
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn transfer_to_unknown_user_is_an_error() -> Result<()> {
+        let id = EndpointId::mock("handoff-nobody");
+        let port = start_server().await;
+
+        let mut alice = ClientHandle::new("localhost", port).await?;
+        let _writer = alice.control_mock("handoff-nobody").await?;
+
+        let response = alice.transfer_control(&id, &User::new("nobody")).await;
+        assert!(matches!(response, Err(Error::NoSuchUser(_))));
+
+        Ok(())
+    }
+}
@@ -0,0 +1,38 @@
+mod common;
+
+// Feature: The configured mock is only observable by name if endpoints are shared
+#[cfg(feature = "mocks-share-endpoints")]
+mod transforms {
+    use color_eyre::Result;
+    use pretty_assertions::assert_eq;
+    use serial_keel::{
+        client::ClientHandle,
+        config::{Config, ConfigEndpoint},
+        endpoint::EndpointId,
+        serial::Transform,
+    };
+
+    use super::common::*;
+
+    #[tokio::test]
+    async fn ansi_codes_are_stripped_from_observed_lines() -> Result<()> {
+        let name = "ansi-mock";
+        let mut config = Config::default();
+        config.endpoints.push(ConfigEndpoint {
+            transforms: vec![Transform::StripAnsi, Transform::Trim],
+            ..EndpointId::mock(name).into()
+        });
+        let port = start_server_with_config(config).await;
+
+        let mut client = ClientHandle::new("localhost", port).await?;
+        let mut writer = client.control_mock(name).await?;
+        let mut reader = client.observe_mock(name).await?;
+
+        writer
+            .write("\x1b[1;31mred\x1b[0m and \x1b]0;title\x07plain  ")
+            .await?;
+        assert_eq!(reader.next_message().await?.as_str(), "red and plain");
+
+        Ok(())
+    }
+}
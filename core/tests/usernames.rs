@@ -0,0 +1,68 @@
+mod common;
+
+use color_eyre::Result;
+use common::*;
+use pretty_assertions::assert_eq;
+use serial_keel::{
+    client::ClientHandle,
+    error::Error,
+    events::{user, Event},
+    user::{User, MAX_USERNAME_LEN},
+};
+
+#[tokio::test]
+async fn chosen_username_shows_up_in_events() -> Result<()> {
+    let port = start_server().await;
+
+    let mut observer = ClientHandle::new("localhost", port).await?;
+    let mut reader = observer.observe_events().await?;
+
+    let alice = ClientHandle::new_with_username("localhost", port, "alice").await?;
+    assert_eq!(alice.user(), Some(&User::new("alice")));
+
+    let event = reader.next_event().await;
+    let Event::User(user::UserEvent {
+        user,
+        event: user::Event::Connected,
+    }) = event.inner
+    else {
+        panic!("Expected a connected event, got {event:?}");
+    };
+    assert_eq!(user, User::new("alice"));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn invalid_usernames_are_rejected() -> Result<()> {
+    let port = start_server().await;
+
+    let too_long = "a".repeat(MAX_USERNAME_LEN + 1);
+
+    for name in ["", "has space", "emoji-🦀", "semi;colon", too_long.as_str()] {
+        let response = ClientHandle::new_with_username("localhost", port, name).await;
+        assert!(
+            matches!(response, Err(Error::InvalidUsername(_))),
+            "`{name}` should be rejected, got {response:?}"
+        );
+    }
+
+    let longest = "a".repeat(MAX_USERNAME_LEN);
+    for name in ["bob", "ci-runner_2.local", longest.as_str()] {
+        ClientHandle::new_with_username("localhost", port, name).await?;
+    }
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn username_in_use_is_rejected() -> Result<()> {
+    let port = start_server().await;
+
+    let _carol = ClientHandle::new_with_username("localhost", port, "carol").await?;
+
+    let response = ClientHandle::new_with_username("localhost", port, "carol").await;
+    assert!(matches!(response, Err(Error::InvalidUsername(_))));
+
+    Ok(())
+}
@@ -1,10 +1,15 @@
 use std::time::Duration;
 
 use color_eyre::Result;
-use common::{receive, send_receive, start_server_and_connect};
+use common::{
+    connect, receive, send, send_receive, start_server_and_connect, start_server_with_config,
+};
 use serial_keel::{
     actions::{self, Action, Response},
+    config::Config,
     endpoint::{EndpointId, LabelledEndpointId},
+    error::Error,
+    serial::Direction,
 };
 use tokio::net::TcpStream;
 use tokio_tungstenite::{MaybeTlsStream, WebSocketStream};
@@ -56,6 +61,7 @@ async fn can_mock_lorem_ipsum_word_at_a_time() -> Result<()> {
 
         let expected_response = Response::Async(actions::Async::Message {
             endpoint: LabelledEndpointId::new(&id),
+            direction: Direction::FromWire,
             message: word.into(),
         });
         assert_eq!(response, expected_response);
@@ -91,9 +97,172 @@ async fn can_mock_lorem_ipsum_inject_1000_words() -> Result<()> {
 
     let expected_response = Response::Async(actions::Async::Message {
         endpoint: LabelledEndpointId::new(&id),
+        direction: Direction::FromWire,
         message: words,
     });
     assert_eq!(response, expected_response);
 
     Ok(())
 }
+
+#[tokio::test]
+async fn compact_endpoint_ids_refer_to_endpoint_by_handle() -> Result<()> {
+    let mut client = start_server_and_connect().await?;
+
+    let id = EndpointId::mock("compactly-observed");
+    send_receive(&mut client, Action::control(&id).serialize()).await??;
+
+    let response =
+        send_receive(&mut client, Action::compact_endpoint_ids(true).serialize()).await??;
+    assert_eq!(response, Response::example_compacting_endpoint_ids());
+
+    // The handle is told about before the observation is confirmed
+    let response = send_receive(&mut client, Action::observe(&id).serialize()).await??;
+    assert_eq!(
+        response,
+        Response::Async(actions::Async::EndpointHandle((
+            actions::EndpointHandle(0),
+            LabelledEndpointId::new(&id)
+        )))
+    );
+    let response = receive(&mut client).await??;
+    assert_eq!(
+        response,
+        Response::Sync(actions::Sync::Observing(LabelledEndpointId::new(&id)))
+    );
+
+    send_receive(&mut client, Action::write(&id, "hello".into()).serialize()).await??;
+
+    let response = receive(&mut client).await??;
+    assert_eq!(
+        response,
+        Response::Async(actions::Async::CompactMessage {
+            handle: actions::EndpointHandle(0),
+            direction: Direction::FromWire,
+            message: "hello".into(),
+        })
+    );
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn too_large_write_is_rejected() -> Result<()> {
+    let port = start_server_with_config(Config {
+        max_message_bytes: Some(16),
+        ..Default::default()
+    })
+    .await;
+    let mut client = connect(port).await?;
+
+    let id = EndpointId::mock("size-limited");
+    send_receive(&mut client, Action::control(&id).serialize()).await??;
+
+    let request = Action::write_bytes(&id, vec![b'x'; 17]).serialize();
+    let response = send_receive(&mut client, request).await?;
+    assert_eq!(response, Err(Error::MessageTooLarge { size: 17, max: 16 }));
+
+    let request = Action::write_bytes(&id, vec![b'x'; 16]).serialize();
+    let response = send_receive(&mut client, request).await??;
+    assert_eq!(response, Response::example_write_ok());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn correlation_id_round_trips() -> Result<()> {
+    let mut client = start_server_and_connect().await?;
+
+    let id = EndpointId::mock("correlated");
+    send_receive(&mut client, Action::control(&id).serialize()).await??;
+
+    for (request, correlation_id) in [
+        (Action::write_correlated(&id, "first\n".into(), "1"), "1"),
+        (
+            Action::write_bytes_correlated(&id, b"second\n".to_vec(), "two"),
+            "two",
+        ),
+    ] {
+        let response = send_receive(&mut client, request.serialize()).await??;
+        assert_eq!(
+            response,
+            Response::Sync(actions::Sync::WriteOk {
+                correlation_id: Some(correlation_id.into())
+            })
+        );
+    }
+
+    // Writes without one are answered without one.
+    let request = Action::write(&id, "third\n".into()).serialize();
+    let response = send_receive(&mut client, request).await??;
+    assert_eq!(response, Response::example_write_ok());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn pipelined_failures_carry_correlation_id() -> Result<()> {
+    let mut client = start_server_and_connect().await?;
+
+    let controlled = EndpointId::mock("attributed");
+    let observed = EndpointId::mock("only-observed");
+    send_receive(&mut client, Action::control(&controlled).serialize()).await??;
+    send_receive(&mut client, Action::observe(&observed).serialize()).await??;
+
+    for request in [
+        Action::write_correlated(&controlled, "fine\n".into(), "good"),
+        Action::write_correlated(&observed, "rejected\n".into(), "bad"),
+        Action::write(&observed, "rejected too\n".into()).correlated("wrapped"),
+    ] {
+        send(&mut client, request.serialize()).await?;
+    }
+
+    let response = receive(&mut client).await??;
+    assert_eq!(
+        response,
+        Response::Sync(actions::Sync::WriteOk {
+            correlation_id: Some("good".into())
+        })
+    );
+
+    for correlation_id in ["bad", "wrapped"] {
+        let response = receive(&mut client).await?;
+        assert_eq!(
+            response,
+            Err(Error::Correlated {
+                correlation_id: correlation_id.into(),
+                error: Box::new(Error::NotControlling {
+                    endpoint: observed.clone()
+                }),
+            })
+        );
+    }
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn nested_correlation_ids_tag_errors_once() -> Result<()> {
+    let mut client = start_server_and_connect().await?;
+
+    let observed = EndpointId::mock("nested-correlated");
+    send_receive(&mut client, Action::observe(&observed).serialize()).await??;
+
+    let request = Action::write_correlated(&observed, "rejected\n".into(), "inner")
+        .correlated("middle")
+        .correlated("outer");
+    let response = send_receive(&mut client, request.serialize()).await?;
+
+    // The outermost id wins, and the client sees a single layer of correlation
+    assert_eq!(
+        response,
+        Err(Error::Correlated {
+            correlation_id: "outer".into(),
+            error: Box::new(Error::NotControlling {
+                endpoint: observed.clone()
+            }),
+        })
+    );
+
+    Ok(())
+}
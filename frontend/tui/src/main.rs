@@ -6,8 +6,9 @@ use crossterm::{
 };
 use enum_iterator::Sequence;
 use serial_keel::{
-    client::{modname::UserEvent, EventReader},
+    client::UserEventReader,
     endpoint::{EndpointId, InternalEndpointInfo},
+    events::user::{self, TimestampedUserEvent},
     serial::SerialMessage,
     user::User,
 };
@@ -34,6 +35,16 @@ enum Tab {
     Server,
 }
 
+/// How key presses are interpreted.
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum InputMode {
+    /// Keys navigate.
+    Normal,
+
+    /// Keys are typed into the users filter.
+    FilteringUsers,
+}
+
 impl Tab {
     fn index(&self) -> usize {
         match self {
@@ -120,10 +131,50 @@ impl Timestamp {
     fn span_relative_to_ms(&self, newer: Timestamp) -> Span {
         let ms = self.ms_difference(newer);
 
-        Span::styled(format!("[{ms:8}ms]"), Style::default().fg(Color::DarkGray))
+        Span::styled(format_relative_ms(ms), Style::default().fg(Color::DarkGray))
+    }
+}
+
+const MS_PER_HOUR: i64 = 60 * 60 * 1000;
+
+/// Format a span of milliseconds such that columns stay aligned.
+///
+/// Spans of an hour or more are shown as `h:mm:ss.mmm`.
+/// Negative spans (events arriving out of order) are shown as zero.
+fn format_relative_ms(ms: i64) -> String {
+    let ms = ms.max(0);
+
+    if ms < MS_PER_HOUR {
+        format!("[{ms:9}ms]")
+    } else {
+        let hours = ms / MS_PER_HOUR;
+        let minutes = (ms / 60_000) % 60;
+        let seconds = (ms / 1000) % 60;
+        let millis = ms % 1000;
+
+        format!(
+            "[{:>11}]",
+            format!("{hours}:{minutes:02}:{seconds:02}.{millis:03}")
+        )
     }
 }
 
+/// The style of an event line, such that kinds of events stand out.
+fn style_for(event: &user::Event) -> Style {
+    let color = match event {
+        user::Event::InControlOf(_) => Color::Green,
+        user::Event::NoLongerInControlOf(_) | user::Event::NoLongerInQueueOf(_) => Color::Red,
+        user::Event::Observing(_) | user::Event::NoLongerObserving(_) => Color::Cyan,
+        user::Event::MessageSent(_) | user::Event::MessageReceived(_) => Color::Gray,
+        user::Event::Connected
+        | user::Event::Disconnected
+        | user::Event::InQueueFor(_)
+        | user::Event::WriteDropped(_) => Color::White,
+    };
+
+    Style::default().fg(color)
+}
+
 #[derive(Default, Clone, PartialEq, Eq, PartialOrd, Ord)]
 struct ActiveInactive<T> {
     inner: T,
@@ -354,7 +405,7 @@ impl UserMessage {
 
 struct UserState {
     first_event_timestamp: Option<Timestamp>,
-    events: Vec<(serial_keel::client::modname::Event, DateTime<Utc>)>,
+    events: Vec<(user::Event, DateTime<Utc>)>,
 
     // If yes, display messages,
     // else show the list of raw events.
@@ -382,48 +433,47 @@ impl UserState {
         }
     }
 
-    fn add_event(
-        &mut self,
-        event: serial_keel::client::modname::Event,
-        timestamp: chrono::DateTime<Utc>,
-    ) {
+    fn add_event(&mut self, event: user::Event, timestamp: chrono::DateTime<Utc>) {
         if self.events.is_empty() {
             self.first_event_timestamp = Some(Timestamp::new(timestamp));
         }
 
-        use serial_keel::client::modname::Event;
-
         self.events.push((event.clone(), timestamp));
 
         match event {
-            modname::Event::Connected => self.connected.set_active(timestamp),
-            modname::Event::Disconnected => self.connected.set_inactive(timestamp),
+            user::Event::Connected => self.connected.set_active(timestamp),
+            user::Event::Disconnected => self.connected.set_inactive(timestamp),
 
-            modname::Event::Observing(endpoints) => self.observing.add_active(endpoints, timestamp),
+            user::Event::Observing(endpoints) => self.observing.add_active(endpoints, timestamp),
 
-            modname::Event::NoLongerObserving(endpoints) => {
+            user::Event::NoLongerObserving(endpoints) => {
                 self.observing.set_inactive_if_found(endpoints, timestamp)
             }
 
-            modname::Event::InQueueFor(endpoints) => {
-                self.queued_for.add_active(endpoints, timestamp)
-            }
-            modname::Event::InControlOf(endpoints) => {
+            user::Event::InQueueFor(endpoints) => self.queued_for.add_active(endpoints, timestamp),
+            user::Event::InControlOf(endpoints) => {
                 self.controlling.add_active(endpoints, timestamp)
             }
 
-            modname::Event::NoLongerInQueueOf(endpoints) => {
+            user::Event::NoLongerInQueueOf(endpoints) => {
                 self.queued_for.set_inactive_if_found(endpoints, timestamp)
             }
-            modname::Event::NoLongerInControlOf(endpoints) => {
+            user::Event::NoLongerInControlOf(endpoints) => {
                 self.controlling.set_inactive_if_found(endpoints, timestamp)
             }
-            modname::Event::MessageSent((info, msg)) => self
-                .messages
-                .push(UserMessage::new(msg, info, true, timestamp)),
-            modname::Event::MessageReceived((info, msg)) => self
-                .messages
-                .push(UserMessage::new(msg, info, false, timestamp)),
+            user::Event::MessageSent((info, msg)) => self.messages.push(UserMessage::new(
+                SerialMessage::new_lossy(msg),
+                info,
+                true,
+                timestamp,
+            )),
+            user::Event::MessageReceived((info, msg)) => self.messages.push(UserMessage::new(
+                SerialMessage::new_lossy(msg),
+                info,
+                false,
+                timestamp,
+            )),
+            user::Event::WriteDropped(_) => {}
         }
     }
 
@@ -532,7 +582,7 @@ impl UserState {
             .map(|(event, t)| {
                 ListItem::new(Spans::from(vec![
                     start_time.span_relative_to_ms(Timestamp::new(*t)),
-                    Span::from(format!(" {event}")),
+                    Span::styled(format!(" {event}"), style_for(event)),
                 ]))
             })
             .collect::<Vec<_>>();
@@ -590,6 +640,9 @@ struct Users {
     //TODO?
     // users: ActiveInactives<User>,
     ui_state: ListState,
+
+    // Only users with names containing this are shown
+    filter: String,
 }
 
 impl Users {
@@ -609,7 +662,7 @@ impl Users {
         self.inner.get_mut(user).unwrap()
     }
 
-    fn add_user_event(&mut self, user_event: modname::UserEvent) {
+    fn add_user_event(&mut self, user_event: TimestampedUserEvent) {
         self.inner
             .entry(user_event.user.clone())
             .or_insert_with(|| UserState::new(user_event.user))
@@ -647,20 +700,55 @@ impl Users {
         //     .into();
 
         let list_items = self
-            .inner
-            .values()
-            .map(|user_state| user_state.connected.to_list_item())
+            .users()
+            .into_iter()
+            .map(|user| self.user_state(user).connected.to_list_item())
             .collect::<Vec<_>>();
 
+        let title = if self.filter.is_empty() {
+            "Users [↑↓] [/]".to_string()
+        } else {
+            format!("Users [↑↓] [/{}]", self.filter)
+        };
+
         List::new(list_items)
-            .block(Block::default().borders(Borders::all()).title("Users [↑↓]"))
+            .block(Block::default().borders(Borders::all()).title(title))
             .highlight_style(Style::default().add_modifier(Modifier::BOLD))
 
         // todo!()
     }
 
+    /// The users matching the filter.
     fn users(&self) -> Vec<&User> {
-        self.inner.keys().collect()
+        self.inner
+            .keys()
+            .filter(|user| user.name.contains(self.filter.as_str()))
+            .collect()
+    }
+
+    fn push_filter(&mut self, c: char) {
+        let mut filter = self.filter.clone();
+        filter.push(c);
+        self.set_filter(filter);
+    }
+
+    fn pop_filter(&mut self) {
+        let mut filter = self.filter.clone();
+        filter.pop();
+        self.set_filter(filter);
+    }
+
+    /// Change the filter, keeping the selected user selected if still shown.
+    fn set_filter(&mut self, filter: String) {
+        let selected = self.selected_user().cloned();
+        self.filter = filter;
+
+        let users = self.users();
+        let i = selected
+            .and_then(|selected| users.iter().position(|user| **user == selected))
+            .or(if users.is_empty() { None } else { Some(0) });
+
+        self.ui_state.select(i);
     }
 
     fn next(&mut self) {
@@ -749,16 +837,48 @@ impl Users {
 /// Check the drawing logic for items on how to specify the highlighting style for selected items.
 struct App {
     tab: Tab,
-    raw_events: Vec<modname::UserEvent>,
+    raw_events: Vec<TimestampedUserEvent>,
     users: Users,
+
+    /// The server's message of the day, if any.
+    banner: Option<String>,
+
+    input_mode: InputMode,
 }
 
 impl App {
-    fn new() -> App {
+    fn new(banner: Option<String>) -> App {
         App {
             raw_events: vec![],
             tab: Tab::Serial,
             users: Users::default(),
+            banner,
+            input_mode: InputMode::Normal,
+        }
+    }
+
+    fn start_filtering(&mut self) {
+        match self.tab {
+            Tab::Serial | Tab::Server => {}
+            Tab::Users => {
+                self.input_mode = InputMode::FilteringUsers;
+            }
+        }
+    }
+
+    /// Handle a key press while filtering users.
+    fn filter_key(&mut self, key: KeyCode) {
+        match key {
+            KeyCode::Char(c) => self.users.push_filter(c),
+            KeyCode::Backspace => self.users.pop_filter(),
+            KeyCode::Enter => self.input_mode = InputMode::Normal,
+            KeyCode::Esc => {
+                self.users.set_filter(String::new());
+                self.input_mode = InputMode::Normal;
+            }
+            KeyCode::Down => self.up(),
+            KeyCode::Up => self.down(),
+            _ => {}
         }
     }
 
@@ -789,7 +909,7 @@ impl App {
         }
     }
 
-    fn add_user_event(&mut self, event: modname::UserEvent) {
+    fn add_user_event(&mut self, event: TimestampedUserEvent) {
         self.raw_events.push(event.clone());
         self.users.add_user_event(event);
     }
@@ -835,7 +955,9 @@ impl App {
 async fn main() -> Result<(), Box<dyn Error>> {
     // connect to server
     let mut sk_client = serial_keel::client::ClientHandle::new("localhost", 3123).await?;
-    let user_events = sk_client.observe_events().await?;
+    let user_events = sk_client.observe_user_events().await?;
+    // Known by now, since it arrives before the response of observing events.
+    let banner = sk_client.banner();
 
     // setup terminal
     enable_raw_mode()?;
@@ -846,7 +968,7 @@ async fn main() -> Result<(), Box<dyn Error>> {
 
     // create app and run it
     let tick_rate = Duration::from_millis(50);
-    let app = App::new();
+    let app = App::new(banner);
     let res = run_app(&mut terminal, app, user_events, tick_rate);
 
     // restore terminal
@@ -868,7 +990,7 @@ async fn main() -> Result<(), Box<dyn Error>> {
 fn run_app<B: Backend>(
     terminal: &mut Terminal<B>,
     mut app: App,
-    mut user_events: EventReader,
+    mut user_events: UserEventReader,
     tick_rate: Duration,
 ) -> io::Result<()> {
     let mut last_tick = Instant::now();
@@ -879,14 +1001,18 @@ fn run_app<B: Backend>(
 
         if event::poll(timeout)? {
             if let event::Event::Key(key) = event::read()? {
-                match key.code {
-                    KeyCode::Char('q') => return Ok(()),
-                    KeyCode::Left | KeyCode::Char('h') => app.previous_tab(),
-                    KeyCode::Right | KeyCode::Char('l') => app.next_tab(),
-                    KeyCode::Down => app.up(),
-                    KeyCode::Up => app.down(),
-                    KeyCode::Char('m') => app.char_m(),
-                    _ => {}
+                match app.input_mode {
+                    InputMode::Normal => match key.code {
+                        KeyCode::Char('q') => return Ok(()),
+                        KeyCode::Left | KeyCode::Char('h') => app.previous_tab(),
+                        KeyCode::Right | KeyCode::Char('l') => app.next_tab(),
+                        KeyCode::Down => app.up(),
+                        KeyCode::Up => app.down(),
+                        KeyCode::Char('m') => app.char_m(),
+                        KeyCode::Char('/') => app.start_filtering(),
+                        _ => {}
+                    },
+                    InputMode::FilteringUsers => app.filter_key(key.code),
                 }
             }
         }
@@ -894,7 +1020,7 @@ fn run_app<B: Backend>(
             last_tick = Instant::now();
         }
 
-        while let Some(user_event) = user_events.try_next_event() {
+        while let Some(user_event) = user_events.try_next_user_event() {
             app.add_user_event(user_event);
         }
     }
@@ -919,12 +1045,13 @@ fn ui<B: Backend>(f: &mut Frame<B>, app: &mut App) {
         })
         .collect();
 
+    let title = match &app.banner {
+        Some(banner) => format!("Serial Keel TUI - {banner}"),
+        None => "Serial Keel TUI".to_owned(),
+    };
+
     let tabs = Tabs::new(titles)
-        .block(
-            Block::default()
-                .borders(Borders::ALL)
-                .title("Serial Keel TUI"),
-        )
+        .block(Block::default().borders(Borders::ALL).title(title))
         .select(app.tab_index())
         .style(Style::default().fg(Color::White))
         .highlight_style(Style::default().add_modifier(Modifier::BOLD | Modifier::UNDERLINED));
@@ -933,3 +1060,34 @@ fn ui<B: Backend>(f: &mut Frame<B>, app: &mut App) {
 
     app.tab_widget(f, chunks[1]);
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn small_spans_are_shown_in_milliseconds() {
+        assert_eq!(format_relative_ms(0), "[        0ms]");
+        assert_eq!(format_relative_ms(1234), "[     1234ms]");
+        assert_eq!(format_relative_ms(MS_PER_HOUR - 1), "[  3599999ms]");
+    }
+
+    #[test]
+    fn large_spans_are_shown_in_hours() {
+        assert_eq!(format_relative_ms(MS_PER_HOUR), "[1:00:00.000]");
+        assert_eq!(
+            format_relative_ms(2 * MS_PER_HOUR + 3 * 60_000 + 4_005),
+            "[2:03:04.005]"
+        );
+        assert_eq!(
+            format_relative_ms(MS_PER_HOUR).len(),
+            format_relative_ms(0).len()
+        );
+    }
+
+    #[test]
+    fn negative_spans_are_clamped() {
+        assert_eq!(format_relative_ms(-1), format_relative_ms(0));
+        assert_eq!(format_relative_ms(i64::MIN), format_relative_ms(0));
+    }
+}
@@ -13,6 +13,6 @@ ws.on('open', function open() {
     }
 
     send({ Control: { Mock: "/dev/ttyACM0" } });
-    send({ Observe: { Mock: "/dev/ttyACM0" } });
-    send({ Write: [{ Mock: "/dev/ttyACM0" }, "Hello\nWorld\nBye!"] });
+    send({ Observe: { endpoint: { Mock: "/dev/ttyACM0" } } });
+    send({ Write: { endpoint: { Mock: "/dev/ttyACM0" }, message: "Hello\nWorld\nBye!" } });
 });
@@ -16,6 +16,10 @@ Response = Dict
 
 Message = str
 
+# The wire protocol version this client speaks, sent when connecting.
+PROTOCOL_VERSION = 3
+PROTOCOL_VERSION_HEADER = "serial-keel-protocol"
+
 
 class ReadQueue:
     queue: "asyncio.Queue[Message]"
@@ -116,10 +120,13 @@ class SerialKeelWs:
     async def write(self, endpoint: Endpoint, message: str):
         """
         Serialization format:
-            {"Write":[{"Mock":"example"},"Hi there"]}
+            {"Write":{"endpoint":{"Mock":"example"},"message":"Hi there"}}
         """
         await self._send(
-            json.dumps({"Write": [endpoint, message]}, cls=SerialKeelJSONEncoder)
+            json.dumps(
+                {"Write": {"endpoint": endpoint, "message": message}},
+                cls=SerialKeelJSONEncoder,
+            )
         )
 
     async def control(self, endpoint: Endpoint):
@@ -139,9 +146,9 @@ class SerialKeelWs:
     async def observe(self, endpoint: Endpoint):
         """
         Serialization format:
-            {"Observe":{"Mock":"example"}}
+            {"Observe":{"endpoint":{"Mock":"example"}}}
         """
-        await self._send(json.dumps({"Observe": endpoint}, cls=SerialKeelJSONEncoder))
+        await self._send(json.dumps({"Observe": {"endpoint": endpoint}}, cls=SerialKeelJSONEncoder))
 
     async def read(self) -> Response:
         response = await self._receive()
@@ -196,6 +203,8 @@ class SerialKeel:
     reader: "asyncio.Task[None]"
     timeout: float
     logger: Logger = None
+    server_info: Optional[Dict] = None
+    banner: Optional[str] = None
 
     def __init__(
         self, ws: WebSocketClientProtocol, logger: Logger, timeout: float
@@ -233,7 +242,13 @@ class SerialKeel:
             if "Ok" in response:
                 value = response["Ok"]
 
-                if "Async" in value:
+                if "Async" in value and "Handshake" in value["Async"]:
+                    self.server_info = value["Async"]["Handshake"]
+                    self.logger.debug(f"Server info: {self.server_info}")
+                elif "Async" in value and "Banner" in value["Async"]:
+                    self.banner = value["Async"]["Banner"]
+                    self.logger.info(f"Server banner: {self.banner}")
+                elif "Async" in value:
                     message = value["Async"]["Message"]["message"]
                     endpoint = value["Async"]["Message"]["endpoint"]["id"]
 
@@ -397,7 +412,7 @@ class SerialKeel:
             self.responses[MessageType.CONTROL].get(), self.timeout
         )
         self.logger.debug(f"Write response: {response}")
-        assert response == {"Sync": "WriteOk"}
+        assert "WriteOk" in response["Sync"]
 
     def endpoint_messages(self, endpoint: Endpoint) -> EndpointMessages:
         """
@@ -417,6 +432,15 @@ class SerialKeel:
             )
             raise e
 
+    def server_capabilities(self) -> List[str]:
+        """
+        The features the server supports, as told when connecting.
+        Empty if the server has not told yet.
+        """
+        if self.server_info is None:
+            return []
+        return self.server_info["capabilities"]
+
     def get_endpoint(self, endpoints: List[Endpoint], label: str) -> Endpoint:
         """
         Gets the next available endpoint that matches the label provided
@@ -443,7 +467,9 @@ class Connect:
 
     async def __aenter__(self) -> SerialKeel:
         self.logger.info(f"Connecting to `{self.uri}`")
-        self.ws = await websockets.connect(self.uri)
+        self.ws = await websockets.connect(
+            self.uri, extra_headers={PROTOCOL_VERSION_HEADER: str(PROTOCOL_VERSION)}
+        )
         self.sk = SerialKeel(self.ws, self.logger, self.timeout)
 
         return self.sk